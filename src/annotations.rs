@@ -0,0 +1,507 @@
+use std::path::Path;
+
+use eframe::egui;
+use pdf_extract::{
+    decode_text_string, Dictionary, Document, MediaBox, Object, OutputDev, OutputError,
+};
+use tracing::{debug, info};
+
+use crate::{TaggedRange, Taskmonger};
+
+/// Color families highlights are tagged by, `<name> highlight`
+const COLOR_NAMES: [(&str, [u8; 3]); 8] = [
+    ("yellow", [255, 230, 0]),
+    ("green", [80, 200, 80]),
+    ("blue", [60, 130, 255]),
+    ("red", [230, 40, 40]),
+    ("pink", [255, 120, 190]),
+    ("orange", [255, 150, 30]),
+    ("purple", [150, 80, 220]),
+    ("gray", [150, 150, 150]),
+];
+
+/// What most readers highlight with if the annotation has no color
+const DEFAULT_COLOR: [u8; 3] = [255, 230, 0];
+
+/// A char of a page's text and where it is, in the page's coordinates
+struct Glyph {
+    x: f64,
+    y: f64,
+    width: f64,
+    size: f64,
+    text: String,
+}
+
+/// Collects the chars of a page in the order the page draws them
+#[derive(Default)]
+struct Glyphs(Vec<Glyph>);
+
+impl OutputDev for Glyphs {
+    fn begin_page(
+        &mut self,
+        _page: u32,
+        _media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &pdf_extract::Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        text: &str,
+    ) -> Result<(), OutputError> {
+        // The font size on the page, as pdf-extract's own text output works it out
+        let x = font_size * (trm.m11 + trm.m21);
+        let y = font_size * (trm.m12 + trm.m22);
+        let size = (x * y).abs().sqrt();
+        self.0.push(Glyph {
+            x: trm.m31,
+            y: trm.m32,
+            width: width * size,
+            size,
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// A highlight annotation of a PDF
+struct Highlight {
+    page: u32,
+    color: [u8; 3],
+    /// Boxes of the highlighted lines as left, bottom, right and top
+    boxes: Vec<[f64; 4]>,
+    /// The comment attached to it
+    note: String,
+}
+
+impl Highlight {
+    /// The chars inside the highlight's boxes, with spaces where the page leaves gaps
+    fn text(&self, glyphs: &[Glyph]) -> String {
+        let mut text = String::new();
+        let mut last: Option<&Glyph> = None;
+        for glyph in glyphs {
+            let (x, y) = (glyph.x + glyph.width / 2.0, glyph.y + glyph.size * 0.3);
+            let inside = self.boxes.iter().any(|[left, bottom, right, top]| {
+                (*left..=*right).contains(&x) && (*bottom..=*top).contains(&y)
+            });
+            if !inside {
+                continue;
+            }
+            if let Some(last) = last {
+                let new_line = (glyph.y - last.y).abs() > glyph.size * 0.5;
+                let gap = glyph.x > last.x + last.width + glyph.size * 0.1;
+                if (new_line || gap) && !text.ends_with(' ') {
+                    text.push(' ');
+                }
+            }
+            text.push_str(&glyph.text);
+            last = Some(glyph);
+        }
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Top of the first line, to sort the highlights of a page from top to bottom
+    fn top(&self) -> f64 {
+        self.boxes.first().map_or(0.0, |b| b[3])
+    }
+
+    fn left(&self) -> f64 {
+        self.boxes.first().map_or(0.0, |b| b[0])
+    }
+}
+
+/// An annotation color, given in gray, RGB or CMYK components between 0 and 1
+fn color(components: &[f64]) -> [u8; 3] {
+    let byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match *components {
+        [gray] => [byte(gray); 3],
+        [r, g, b] => [byte(r), byte(g), byte(b)],
+        [c, m, y, k] => [
+            byte((1.0 - c) * (1.0 - k)),
+            byte((1.0 - m) * (1.0 - k)),
+            byte((1.0 - y) * (1.0 - k)),
+        ],
+        _ => DEFAULT_COLOR,
+    }
+}
+
+/// Name of the color family closest to `color`
+fn color_name(color: [u8; 3]) -> &'static str {
+    let distance = |other: [u8; 3]| -> i32 {
+        color
+            .iter()
+            .zip(other)
+            .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+            .sum()
+    };
+    COLOR_NAMES
+        .iter()
+        .min_by_key(|(_, named)| distance(*named))
+        .map_or("yellow", |(name, _)| name)
+}
+
+/// `object`, or the object it refers to
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    doc.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// The highlight an annotation dictionary describes, None for other annotations
+fn highlight(doc: &Document, page: u32, annotation: &Dictionary) -> Option<Highlight> {
+    let subtype = annotation.get(b"Subtype").and_then(Object::as_name);
+    if !subtype.is_ok_and(|subtype| subtype == b"Highlight") {
+        return None;
+    }
+    let numbers = |key: &[u8]| -> Vec<f64> {
+        annotation
+            .get(key)
+            .ok()
+            .and_then(|array| resolve(doc, array))
+            .and_then(|array| array.as_array().ok())
+            .map(|array| {
+                array
+                    .iter()
+                    .filter_map(|n| n.as_float().ok())
+                    .map(f64::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    // Quads are four corners each, in no order every writer agrees on
+    let mut boxes: Vec<[f64; 4]> = numbers(b"QuadPoints")
+        .chunks_exact(8)
+        .map(|quad| {
+            let xs = [quad[0], quad[2], quad[4], quad[6]];
+            let ys = [quad[1], quad[3], quad[5], quad[7]];
+            [
+                xs.iter().copied().fold(f64::MAX, f64::min),
+                ys.iter().copied().fold(f64::MAX, f64::min),
+                xs.iter().copied().fold(f64::MIN, f64::max),
+                ys.iter().copied().fold(f64::MIN, f64::max),
+            ]
+        })
+        .collect();
+    if boxes.is_empty() {
+        if let [left, bottom, right, top] = numbers(b"Rect")[..] {
+            boxes.push([left, bottom, right, top]);
+        }
+    }
+    let note = annotation
+        .get(b"Contents")
+        .ok()
+        .and_then(|contents| decode_text_string(contents).ok())
+        .unwrap_or_default();
+    Some(Highlight {
+        page,
+        color: color(&numbers(b"C")),
+        boxes,
+        note: note.trim().to_string(),
+    })
+}
+
+/// The highlight annotations of all pages, in page order and top to bottom on a page
+fn highlights(doc: &Document) -> Vec<Highlight> {
+    let mut highlights = vec![];
+    for (page, id) in doc.get_pages() {
+        let Ok(page_dict) = doc.get_dictionary(id) else {
+            continue;
+        };
+        let Some(annotations) = page_dict
+            .get(b"Annots")
+            .ok()
+            .and_then(|annotations| resolve(doc, annotations))
+            .and_then(|annotations| annotations.as_array().ok())
+        else {
+            continue;
+        };
+        let mut on_page: Vec<Highlight> = annotations
+            .iter()
+            .filter_map(|annotation| resolve(doc, annotation).and_then(|a| a.as_dict().ok()))
+            .filter_map(|annotation| highlight(doc, page, annotation))
+            .collect();
+        on_page.sort_by(|a, b| {
+            b.top()
+                .total_cmp(&a.top())
+                .then(a.left().total_cmp(&b.left()))
+        });
+        highlights.extend(on_page);
+    }
+    highlights
+}
+
+impl Taskmonger {
+    /// Appends `highlights` with their `texts` under a heading per page, without saving.
+    /// Returns the number added.
+    fn add_highlights(
+        &mut self,
+        title: &str,
+        highlights: &[Highlight],
+        texts: Vec<String>,
+    ) -> usize {
+        if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        self.buffer.push_str(&format!("\n## {title}\n"));
+        let mut page = None;
+        let mut count = 0;
+        for (highlight, mut text) in highlights.iter().zip(texts) {
+            if page != Some(highlight.page) {
+                page = Some(highlight.page);
+                self.buffer
+                    .push_str(&format!("\n### Page {}\n\n", highlight.page));
+            }
+            let mut note = highlight.note.as_str();
+            // Highlights on scans have no text, their comment stands in
+            if text.is_empty() {
+                text = std::mem::take(&mut note).to_string();
+            }
+            if text.is_empty() {
+                debug!(page = highlight.page, "Skipping a highlight without text");
+                continue;
+            }
+            self.buffer.push_str("- ");
+            let start = self.buffer.chars().count();
+            self.buffer.push_str(&text);
+            let end = self.buffer.chars().count();
+            self.buffer.push('\n');
+            for line in note.lines() {
+                self.buffer.push_str(&format!("  {}\n", line.trim()));
+            }
+
+            let name = format!("{} highlight", color_name(highlight.color));
+            let new = self.find_tag(&name).is_none();
+            let tag = self.import_tag(name);
+            if new {
+                if let Some(tag) = self.tags.get_mut(&tag) {
+                    tag.color = highlight.color;
+                }
+            }
+            self.tagged_ranges
+                .push(TaggedRange::new(tag.clone(), start..end));
+            self.normalize_tag_ranges(&tag);
+            count += 1;
+        }
+        count
+    }
+
+    /// Appends the highlights of a PDF under a heading per page, each tagged `<color>
+    /// highlight` with its comment below it. Returns the number of highlights.
+    pub fn import_pdf_highlights(
+        &mut self,
+        path: &Path,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let doc = Document::load(path)?;
+        if doc.is_encrypted() {
+            return Err("the PDF is encrypted".into());
+        }
+        let highlights = highlights(&doc);
+        if highlights.is_empty() {
+            return Err("no highlights in the PDF".into());
+        }
+
+        let title = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut glyphs = Glyphs::default();
+        let mut texts = vec![];
+        for (i, highlight) in highlights.iter().enumerate() {
+            if i == 0 || highlights[i - 1].page != highlight.page {
+                glyphs.0.clear();
+                pdf_extract::output_doc_page(&doc, &mut glyphs, highlight.page)?;
+            }
+            texts.push(highlight.text(&glyphs.0));
+        }
+        let count = self.add_highlights(&title, &highlights, texts);
+        info!(
+            highlights = count,
+            "Imported highlights of {}",
+            path.display()
+        );
+        let _ = self.save_to_disk();
+        Ok(count)
+    }
+
+    /// Export menu row to import the highlights of a PDF
+    pub fn pdf_highlights_ui(&mut self, ui: &mut egui::Ui) {
+        let import_id = egui::Id::new("pdf_highlights_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of a PDF")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(
+                    !import.trim().is_empty(),
+                    egui::Button::new("Import highlights"),
+                )
+                .on_hover_text("Highlighted text by page, tagged by highlight color")
+                .clicked()
+            {
+                match self.import_pdf_highlights(Path::new(import.trim())) {
+                    Ok(count) => {
+                        self.toasts.success(format!("Imported {count} highlights"));
+                        import.clear();
+                    }
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the highlights: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf_extract::dictionary;
+
+    fn numbers(numbers: &[f32]) -> Object {
+        Object::Array(numbers.iter().map(|&n| Object::Real(n)).collect())
+    }
+
+    fn glyph(x: f64, y: f64, text: &str) -> Glyph {
+        Glyph {
+            x,
+            y,
+            width: 5.0,
+            size: 10.0,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn highlight_dictionaries_give_boxes_color_and_note() {
+        let doc = Document::new();
+        let annotation = dictionary! {
+            "Subtype" => "Highlight",
+            "QuadPoints" => numbers(&[10.0, 110.0, 60.0, 110.0, 10.0, 100.0, 60.0, 100.0]),
+            "C" => numbers(&[0.0, 1.0, 0.0]),
+            "Contents" => Object::string_literal(" check this "),
+        };
+        let found = highlight(&doc, 3, &annotation).unwrap();
+        assert_eq!(found.page, 3);
+        assert_eq!(found.boxes, [[10.0, 100.0, 60.0, 110.0]]);
+        assert_eq!(found.color, [0, 255, 0]);
+        assert_eq!(found.note, "check this");
+
+        // Without quads the rectangle will do, without a color it is yellow
+        let annotation = dictionary! {
+            "Subtype" => "Highlight",
+            "Rect" => numbers(&[1.0, 2.0, 3.0, 4.0]),
+        };
+        let found = highlight(&doc, 1, &annotation).unwrap();
+        assert_eq!(found.boxes, [[1.0, 2.0, 3.0, 4.0]]);
+        assert_eq!(found.color, DEFAULT_COLOR);
+
+        let annotation = dictionary! { "Subtype" => "Link" };
+        assert!(highlight(&doc, 1, &annotation).is_none());
+    }
+
+    #[test]
+    fn highlight_text_is_the_glyphs_inside_its_boxes() {
+        let highlight = Highlight {
+            page: 1,
+            color: DEFAULT_COLOR,
+            boxes: vec![[10.0, 100.0, 40.0, 112.0], [0.0, 80.0, 20.0, 92.0]],
+            note: String::new(),
+        };
+        let glyphs = [
+            glyph(0.0, 102.0, "x"),
+            glyph(10.0, 102.0, "a"),
+            glyph(15.0, 102.0, "b"),
+            glyph(25.0, 102.0, "c"),
+            glyph(0.0, 82.0, "d"),
+            glyph(30.0, 82.0, "y"),
+        ];
+        assert_eq!(highlight.text(&glyphs), "ab c d");
+    }
+
+    #[test]
+    fn colors_are_named_by_the_closest_family() {
+        assert_eq!(color(&[0.5]), [128, 128, 128]);
+        assert_eq!(color(&[0.0, 0.0, 1.0, 0.0]), [255, 255, 0]);
+        assert_eq!(color(&[]), DEFAULT_COLOR);
+        assert_eq!(color_name([250, 240, 20]), "yellow");
+        assert_eq!(color_name([0, 0, 200]), "blue");
+        assert_eq!(color_name([128, 128, 128]), "gray");
+        assert_eq!(color_name([255, 140, 200]), "pink");
+    }
+
+    #[test]
+    fn highlights_are_added_by_page_and_tagged_by_color() {
+        let mut app = Taskmonger {
+            buffer: "notes".to_string(),
+            ..Default::default()
+        };
+        app.tags.clear();
+        let highlight = |page, color, note: &str| Highlight {
+            page,
+            color,
+            boxes: vec![],
+            note: note.to_string(),
+        };
+        let highlights = [
+            highlight(1, [255, 230, 0], "why?"),
+            highlight(1, [60, 130, 255], ""),
+            highlight(2, [255, 230, 0], "from a scan"),
+            highlight(2, [255, 230, 0], ""),
+        ];
+        let texts = ["first", "second", "", ""].map(String::from).to_vec();
+        assert_eq!(app.add_highlights("a.pdf", &highlights, texts), 3);
+        assert_eq!(
+            app.buffer,
+            "notes\n\n## a.pdf\n\n### Page 1\n\n- first\n  why?\n- second\n\n### Page 2\n\n- from a scan\n"
+        );
+        assert_eq!(
+            app.tags.keys().collect::<Vec<_>>(),
+            ["yellow highlight", "blue highlight"]
+        );
+        assert_eq!(app.tags["blue highlight"].color, [60, 130, 255]);
+        let tagged: Vec<(&str, String)> = app
+            .tagged_ranges
+            .iter()
+            .map(|tr| {
+                let text = app.buffer.chars().skip(tr.range.start);
+                (tr.tag_name.as_str(), text.take(tr.range.len()).collect())
+            })
+            .collect();
+        assert_eq!(
+            tagged,
+            [
+                ("yellow highlight", "first".to_string()),
+                ("blue highlight", "second".to_string()),
+                ("yellow highlight", "from a scan".to_string()),
+            ]
+        );
+    }
+}
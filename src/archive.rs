@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+use eframe::egui;
+use egui::RichText;
+use egui_phosphor::regular::{ARROW_COUNTER_CLOCKWISE, TRASH};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::tools::{char_to_byte_range, RangeExt};
+use crate::{TaggedRange, Taskmonger};
+
+/// A done range moved out of the active list.
+/// Entries of older state files only hold the range itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchivedRange {
+    #[serde(flatten)]
+    pub range: TaggedRange,
+    /// The range's text when it was archived
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub archived: Option<chrono::NaiveDateTime>,
+    /// The text was taken out of the buffer and goes back in on restore
+    #[serde(default)]
+    pub text_removed: bool,
+}
+
+impl Taskmonger {
+    /// Moves the ranges at `indices` into the archive, optionally cutting their text from the buffer
+    pub fn archive_ranges(&mut self, mut indices: Vec<usize>, remove_text: bool) {
+        indices.sort_unstable();
+        indices.dedup();
+        let now = chrono::Local::now().naive_local();
+        let mut cut: Vec<Range<usize>> = vec![];
+        for &i in indices.iter().rev() {
+            let tr = self.tagged_ranges.remove(i);
+            let text = self.buffer[char_to_byte_range(&self.buffer, &tr.range)].to_string();
+            if remove_text {
+                cut.push(tr.range.clone());
+            }
+            self.archive.push(ArchivedRange {
+                range: tr,
+                text,
+                archived: Some(now),
+                text_removed: remove_text,
+            });
+        }
+
+        // Overlapping ranges are cut once, back to front so earlier positions stay valid
+        cut.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = vec![];
+        for range in cut {
+            match merged.last_mut() {
+                Some(last) if last.touches(&range) => *last = last.union(&range),
+                _ => merged.push(range),
+            }
+        }
+        for range in merged.into_iter().rev() {
+            self.remove_text(range);
+        }
+        self.clean_invalid_ranges();
+        info!(count = indices.len(), remove_text, "Archived ranges");
+        let _ = self.save_to_disk();
+    }
+
+    /// Cuts the chars in `range` and the blank lines after them, moving the ranges behind it
+    fn remove_text(&mut self, range: Range<usize>) {
+        let trailing = self
+            .buffer
+            .chars()
+            .skip(range.end)
+            .take(2)
+            .take_while(|c| *c == '\n')
+            .count();
+        let range = range.start..range.end + trailing;
+        let len = range.len();
+        let bytes = char_to_byte_range(&self.buffer, &range);
+        self.buffer.replace_range(bytes, "");
+
+        let map = |p: usize| {
+            if p <= range.start {
+                p
+            } else if p >= range.end {
+                p - len
+            } else {
+                range.start
+            }
+        };
+        for tr in &mut self.tagged_ranges {
+            let moved = map(tr.range.start)..map(tr.range.end);
+            if moved != tr.range {
+                tr.range = moved;
+                tr.mark();
+            }
+        }
+        for bookmark in &mut self.bookmarks {
+            bookmark.at = map(bookmark.at);
+        }
+        for fold in &mut self.folds {
+            *fold = map(*fold);
+        }
+    }
+
+    /// Brings an archived range back. Removed text is appended to the end of the buffer.
+    fn restore_from_archive(&mut self, index: usize) {
+        let mut archived = self.archive.remove(index);
+        if archived.text_removed {
+            let separator = match self.buffer.as_str() {
+                "" => "",
+                b if b.ends_with("\n\n") => "",
+                b if b.ends_with('\n') => "\n",
+                _ => "\n\n",
+            };
+            self.buffer.push_str(separator);
+            let start = self.buffer.chars().count();
+            self.buffer.push_str(&archived.text);
+            let end = self.buffer.chars().count();
+            self.buffer.push('\n');
+            archived.range.range = start..end;
+        }
+        let tag_name = archived.range.tag_name.clone();
+        if !self.tags.contains_key(&tag_name) {
+            self.add_tag(tag_name.clone());
+        }
+        archived.range.mark();
+        self.tagged_ranges.push(archived.range);
+        self.clean_invalid_ranges();
+        self.normalize_tag_ranges(&tag_name);
+        let _ = self.save_to_disk();
+    }
+
+    /// Archived ranges, newest first, with restore and delete
+    pub fn archive_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.archive_open;
+        let done: Vec<usize> = (0..self.tagged_ranges.len())
+            .filter(|&i| self.tagged_ranges[i].done)
+            .collect();
+        let mut archive_done = false;
+        let mut restore = None;
+        let mut delete = None;
+        egui::Window::new("Archive")
+            .open(&mut open)
+            .default_width(340.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            !done.is_empty(),
+                            egui::Button::new(format!("Archive {} done range(s)", done.len())),
+                        )
+                        .clicked()
+                    {
+                        archive_done = true;
+                    }
+                    if ui
+                        .checkbox(&mut self.settings.archive_removes_text, "Remove their text")
+                        .on_hover_text(
+                            "Take the text out of the buffer too, it comes back on restore",
+                        )
+                        .changed()
+                    {
+                        let _ = self.save_to_disk();
+                    }
+                });
+                ui.separator();
+                if self.archive.is_empty() {
+                    ui.label("Nothing archived yet.");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for (i, archived) in self.archive.iter().enumerate().rev() {
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .small_button(ARROW_COUNTER_CLOCKWISE)
+                                    .on_hover_text("Restore")
+                                    .clicked()
+                                {
+                                    restore = Some(i);
+                                }
+                                if ui
+                                    .small_button(TRASH)
+                                    .on_hover_text("Delete for good")
+                                    .clicked()
+                                {
+                                    delete = Some(i);
+                                }
+                                if let Some(date) = archived.archived {
+                                    ui.label(
+                                        RichText::new(date.format("%b %d").to_string()).weak(),
+                                    );
+                                }
+                                let first_line = archived.text.lines().next().unwrap_or_default();
+                                let label =
+                                    ui.label(format!("{}: {first_line}", archived.range.tag_name));
+                                if archived.text.contains('\n') {
+                                    label.on_hover_text(&archived.text);
+                                }
+                            });
+                        }
+                    });
+            });
+        if archive_done {
+            self.archive_ranges(done, self.settings.archive_removes_text);
+        }
+        if let Some(i) = restore {
+            self.restore_from_archive(i);
+        }
+        if let Some(i) = delete {
+            self.archive.remove(i);
+            let _ = self.save_to_disk();
+        }
+        self.archive_open = open;
+    }
+}
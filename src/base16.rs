@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use eframe::egui;
+use tracing::{info, warn};
+
+use crate::tools::expand_vars;
+use crate::Taskmonger;
+
+/// How often the theme file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// base16 accent colors, red to brown
+const BASE16_ACCENTS: [&str; 8] = [
+    "base08", "base09", "base0a", "base0b", "base0c", "base0d", "base0e", "base0f",
+];
+
+/// ANSI colors of terminal themes, without black, white and the grays
+const ANSI_ACCENTS: [&str; 12] = [
+    "color1", "color2", "color3", "color4", "color5", "color6", "color9", "color10", "color11",
+    "color12", "color13", "color14",
+];
+
+/// Parses `key: value` or `key = value` lines with hex colors, as in base16 yaml schemes,
+/// Xresources and most terminal theme files. Returns the accent colors in theme order.
+fn parse_accents(text: &str) -> Vec<[u8; 3]> {
+    let mut colors = HashMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once([':', '=']) else {
+            continue;
+        };
+        let key = key.trim().trim_start_matches('*').trim_start_matches('.');
+        let value = value.trim().trim_start_matches(['"', '\'']);
+        let value = value.strip_prefix('#').unwrap_or(value);
+        let hex: String = value
+            .chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .collect();
+        let (6, Ok(rgb)) = (hex.len(), u32::from_str_radix(&hex, 16)) else {
+            continue;
+        };
+        colors.insert(
+            key.to_lowercase(),
+            [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+        );
+    }
+    let pick = |keys: &[&str]| -> Vec<[u8; 3]> {
+        keys.iter()
+            .filter_map(|key| colors.get(*key).copied())
+            .collect()
+    };
+    let accents = pick(&BASE16_ACCENTS);
+    if accents.is_empty() {
+        pick(&ANSI_ACCENTS)
+    } else {
+        accents
+    }
+}
+
+/// Keeps track of the theme file tag colors are derived from
+#[derive(Default)]
+pub struct ThemeWatch {
+    /// The file as given in the settings
+    setting: String,
+    modified: Option<SystemTime>,
+    checked: Option<Instant>,
+    /// Colors of the last successful read
+    pub accents: Vec<[u8; 3]>,
+    pub error: Option<String>,
+}
+
+impl ThemeWatch {
+    /// Rereads the file if its setting or modification time changed. Returns true if it did.
+    fn poll(
+        &mut self,
+        setting: &str,
+        interval: Duration,
+        resolve: impl FnOnce() -> PathBuf,
+    ) -> bool {
+        let setting_changed = self.setting != setting;
+        if !setting_changed && self.checked.is_some_and(|c| c.elapsed() < interval) {
+            return false;
+        }
+        self.checked = Some(Instant::now());
+        let path = resolve();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if !setting_changed && modified == self.modified {
+            return false;
+        }
+        self.setting = setting.to_string();
+        self.modified = modified;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Could not read theme file {}: {e}", path.display());
+                self.error = Some(e.to_string());
+                self.accents.clear();
+                return false;
+            }
+        };
+        self.accents = parse_accents(&text);
+        self.error = self
+            .accents
+            .is_empty()
+            .then(|| "no base16 or terminal colors found".to_string());
+        !self.accents.is_empty()
+    }
+}
+
+impl Taskmonger {
+    /// Recolors the tags whenever the configured theme file changes
+    pub fn sync_theme_colors(&mut self, ctx: &egui::Context) {
+        let setting = self.settings.base16_theme.trim();
+        if setting.is_empty() || self.safe_mode {
+            self.theme_watch = ThemeWatch::default();
+            return;
+        }
+        let interval = self.idle.poll_interval(POLL_INTERVAL);
+        let resolve = || PathBuf::from(expand_vars(setting, &Self::document_dir()).as_ref());
+        if self.theme_watch.poll(setting, interval, resolve) {
+            let accents = &self.theme_watch.accents;
+            for (i, tag) in self.tags.values_mut().enumerate() {
+                tag.color = accents[i % accents.len()];
+            }
+            info!(
+                "Derived tag colors from {setting} ({} colors)",
+                accents.len()
+            );
+            let _ = self.save_to_disk();
+        }
+        ctx.request_repaint_after(interval);
+    }
+}
@@ -0,0 +1,160 @@
+use eframe::egui;
+use egui::text::CCursor;
+use egui_phosphor::regular::{ARROW_RIGHT, BOOKMARK_SIMPLE, TRASH};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::tools::line_col;
+use crate::Taskmonger;
+
+/// Space left of the text for bookmark markers
+pub const BOOKMARK_SPACE: f32 = 10.0;
+
+/// A named position in the buffer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    /// Char index, moved along with edits before it
+    pub at: usize,
+}
+
+/// Markers for the bookmarks on screen, in the [`BOOKMARK_SPACE`] starting at `left`
+pub fn bookmark_shapes(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    bookmarks: &[Bookmark],
+    left: f32,
+) -> Vec<egui::Shape> {
+    let clip = ui.clip_rect();
+    let color = ui.visuals().selection.stroke.color;
+    bookmarks
+        .iter()
+        .filter_map(|bookmark| {
+            let rect = output
+                .galley
+                .pos_from_cursor(CCursor::new(bookmark.at))
+                .translate(output.galley_pos.to_vec2());
+            let center = egui::pos2(left + BOOKMARK_SPACE / 2.0, rect.center().y);
+            clip.contains(center)
+                .then(|| egui::Shape::circle_filled(center, 3.0, color))
+        })
+        .collect()
+}
+
+impl Taskmonger {
+    /// Drops a bookmark at the cursor, named after its line
+    pub fn add_bookmark(&mut self) {
+        let at = self.cursor;
+        let (line, _) = line_col(&self.buffer, at);
+        let text: String = self
+            .buffer
+            .split('\n')
+            .nth(line - 1)
+            .unwrap_or_default()
+            .trim()
+            .chars()
+            .take(30)
+            .collect();
+        let name = if text.is_empty() {
+            format!("Line {line}")
+        } else {
+            text
+        };
+        info!(at, name, "Added bookmark");
+        self.bookmarks.push(Bookmark { name, at });
+        self.bookmarks.sort_by_key(|b| b.at);
+        self.toasts
+            .info("Bookmark added, F2 jumps between bookmarks");
+        let _ = self.save_to_disk();
+    }
+
+    /// Jumps to the next bookmark after the cursor, or the previous one before it, wrapping around
+    pub fn jump_to_bookmark(&mut self, forward: bool) {
+        let cursor = self.cursor;
+        let target = if forward {
+            self.bookmarks
+                .iter()
+                .find(|b| b.at > cursor)
+                .or(self.bookmarks.first())
+        } else {
+            self.bookmarks
+                .iter()
+                .rev()
+                .find(|b| b.at < cursor)
+                .or(self.bookmarks.last())
+        };
+        if let Some(bookmark) = target {
+            self.jump_to = Some(bookmark.at..bookmark.at);
+        }
+    }
+
+    /// Moves bookmarks behind an edit at `inserted_at` by `shift` chars
+    pub fn shift_bookmarks(&mut self, inserted_at: i32, shift: i32) {
+        for bookmark in &mut self.bookmarks {
+            if bookmark.at as i32 >= inserted_at {
+                bookmark.at = (bookmark.at as i32 + shift).max(0) as usize;
+            }
+        }
+        let len = self.buffer.chars().count();
+        for bookmark in &mut self.bookmarks {
+            bookmark.at = bookmark.at.min(len);
+        }
+    }
+
+    /// List of bookmarks to rename, jump to and delete
+    pub fn bookmarks_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.bookmarks_open;
+        let mut jump = None;
+        let mut delete = None;
+        let mut changed = false;
+        egui::Window::new("Bookmarks")
+            .open(&mut open)
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                if ui
+                    .button(format!("{BOOKMARK_SIMPLE} Bookmark the cursor (Ctrl+B)"))
+                    .clicked()
+                {
+                    self.add_bookmark();
+                }
+                if self.bookmarks.is_empty() {
+                    ui.label("No bookmarks yet. F2 and Shift+F2 jump between them.");
+                    return;
+                }
+                ui.separator();
+                egui::Grid::new("bookmarks").num_columns(4).show(ui, |ui| {
+                    for (i, bookmark) in self.bookmarks.iter_mut().enumerate() {
+                        let (line, _) = line_col(&self.buffer, bookmark.at);
+                        ui.label(egui::RichText::new(line.to_string()).weak());
+                        changed |= ui
+                            .add(
+                                egui::TextEdit::singleline(&mut bookmark.name).desired_width(160.0),
+                            )
+                            .changed();
+                        if ui
+                            .small_button(ARROW_RIGHT)
+                            .on_hover_text("Go to")
+                            .clicked()
+                        {
+                            jump = Some(bookmark.at);
+                        }
+                        if ui.small_button(TRASH).on_hover_text("Delete").clicked() {
+                            delete = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        if let Some(at) = jump {
+            self.jump_to = Some(at..at);
+        }
+        if let Some(i) = delete {
+            self.bookmarks.remove(i);
+            changed = true;
+        }
+        if changed {
+            let _ = self.save_to_disk();
+        }
+        self.bookmarks_open = open;
+    }
+}
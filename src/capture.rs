@@ -0,0 +1,241 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use eframe::egui;
+use egui_phosphor::regular::PLUS;
+use tracing::{info, warn};
+
+use crate::{TaggedRange, Taskmonger};
+
+/// Lines captured while the main window was closed or busy, picked up by the app
+const INBOX_FILE: &str = "taskmonger_inbox.txt";
+
+const INBOX_POLL: Duration = Duration::from_secs(2);
+
+fn inbox_path() -> PathBuf {
+    PathBuf::from(INBOX_FILE)
+}
+
+fn append_to_inbox(line: &str) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(inbox_path())?;
+    writeln!(file, "{}", line.trim())
+}
+
+/// `today`, `tomorrow`, a weekday for the next one of it, or `2024-05-31`
+fn parse_due(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "today" => return Some(today),
+        "tomorrow" => return today.succ_opt(),
+        _ => {}
+    }
+    if let Ok(weekday) = text.parse::<Weekday>() {
+        let ahead =
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        // The same weekday means next week's
+        let ahead = if ahead == 0 { 7 } else { ahead };
+        return today.checked_add_days(Days::new(ahead as u64));
+    }
+    NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()
+}
+
+/// A quick added line split into its parts
+struct QuickAdd {
+    text: String,
+    tags: Vec<String>,
+    due: Option<NaiveDate>,
+}
+
+/// Splits `buy milk #errands due:friday` into the text, the tag names and the due date
+fn parse_quick_add(line: &str) -> QuickAdd {
+    let today = chrono::Local::now().date_naive();
+    let mut words = vec![];
+    let mut tags = vec![];
+    let mut due = None;
+    for word in line.split_whitespace() {
+        if let Some(tag) = word.strip_prefix('#') {
+            if !tag.is_empty() && !tag.starts_with('#') {
+                tags.push(tag.to_string());
+                continue;
+            }
+        }
+        if let Some(date) = word.strip_prefix("due:").and_then(|d| parse_due(d, today)) {
+            due = Some(date);
+            continue;
+        }
+        words.push(word);
+    }
+    QuickAdd {
+        text: words.join(" "),
+        tags,
+        due,
+    }
+}
+
+/// `taskmonger add "call dentist" --tag personal --due tomorrow`: puts the line into the inbox,
+/// which the running app picks up within seconds and a closed one on its next start
+pub fn add_from_cli(args: &[String]) -> Result<String, String> {
+    let mut words = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tag" | "-t" => {
+                let tag = args.next().ok_or("--tag needs a tag name")?;
+                // A tag is one word in the inbox line
+                words.push(format!(
+                    "#{}",
+                    tag.split_whitespace().collect::<Vec<_>>().join("-")
+                ));
+            }
+            "--due" | "-d" => {
+                let due = args.next().ok_or("--due needs a date")?;
+                let today = chrono::Local::now().date_naive();
+                // Resolved now, the line might be picked up another day
+                let date = parse_due(due, today).ok_or(format!(
+                    "Unknown date {due:?}, use today, tomorrow, a weekday or YYYY-MM-DD"
+                ))?;
+                words.push(format!("due:{date}"));
+            }
+            text => words.push(text.to_string()),
+        }
+    }
+    let line = words.join(" ");
+    if parse_quick_add(&line).text.is_empty() {
+        return Err(
+            "Nothing to add, usage: taskmonger add \"text\" [--tag TAG]... [--due DATE]"
+                .to_string(),
+        );
+    }
+    append_to_inbox(&line)
+        .map_err(|e| format!("Could not write {}: {e}", inbox_path().display()))?;
+    Ok(line)
+}
+
+impl Taskmonger {
+    /// Appends a line to the buffer and tags it with the `#tags` it names, due on its `due:` date.
+    /// Returns false if there was no text.
+    pub fn quick_add(&mut self, line: &str) -> bool {
+        let QuickAdd { text, tags, due } = parse_quick_add(line);
+        if text.is_empty() {
+            return false;
+        }
+        if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        let start = self.buffer.chars().count();
+        self.buffer.push_str(&text);
+        let end = self.buffer.chars().count();
+        self.buffer.push('\n');
+        for tag in tags {
+            let tag = self.import_tag(tag);
+            let mut tr = TaggedRange::new(tag.clone(), start..end);
+            tr.due = due;
+            self.tagged_ranges.push(tr);
+            self.normalize_tag_ranges(&tag);
+        }
+        info!("Quick added a line");
+        let _ = self.save_to_disk();
+        true
+    }
+
+    /// Takes over lines captured with `taskmonger --capture`
+    pub fn poll_inbox(&mut self, ctx: &egui::Context) {
+        let interval = self.idle.poll_interval(INBOX_POLL);
+        ctx.request_repaint_after(interval);
+        if self
+            .inbox_checked
+            .is_some_and(|checked| checked.elapsed() < interval)
+        {
+            return;
+        }
+        self.inbox_checked = Some(Instant::now());
+        // Moved aside first, so lines captured meanwhile go into a new inbox
+        // and a failing save does not add the lines twice
+        let taken = inbox_path().with_extension("taken");
+        if fs::rename(inbox_path(), &taken).is_err() {
+            return;
+        }
+        let lines = fs::read_to_string(&taken);
+        if let Err(e) = fs::remove_file(&taken) {
+            warn!("Could not remove {}: {e}", taken.display());
+        }
+        let Ok(lines) = lines else {
+            return;
+        };
+        let added = lines.lines().filter(|line| self.quick_add(line)).count();
+        if added > 0 {
+            self.toasts.info(format!("Captured {added} line(s)"));
+        }
+    }
+
+    /// Single line input above the editor, Enter adds the line
+    pub fn quick_add_ui(&mut self, ui: &mut egui::Ui) {
+        let id = egui::Id::new("quick_add");
+        if ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::K)) {
+            ui.memory_mut(|m| m.request_focus(id));
+        }
+        ui.horizontal(|ui| {
+            ui.label(PLUS);
+            let response = ui.add_enabled(
+                !self.locked,
+                egui::TextEdit::singleline(&mut self.quick_add_text)
+                    .id(id)
+                    .hint_text("Quick add, e.g. buy milk #errands due:friday (Ctrl+K)")
+                    .desired_width(f32::INFINITY),
+            );
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.quick_add_text);
+                if self.quick_add(&line) {
+                    response.request_focus();
+                }
+            }
+        });
+    }
+}
+
+/// Minimal window of `taskmonger --capture`, meant to be bound to a global shortcut
+#[derive(Default)]
+pub struct CaptureApp {
+    text: String,
+    error: Option<String>,
+}
+
+impl eframe::App for CaptureApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.text)
+                    .hint_text("buy milk #errands")
+                    .desired_width(f32::INFINITY),
+            );
+            response.request_focus();
+            let (enter, escape) = ui.input(|i| {
+                (
+                    i.key_pressed(egui::Key::Enter),
+                    i.key_pressed(egui::Key::Escape),
+                )
+            });
+            if enter && !self.text.trim().is_empty() {
+                match append_to_inbox(&self.text) {
+                    Ok(()) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                    Err(e) => {
+                        warn!("Could not write {}: {e}", inbox_path().display());
+                        self.error = Some(e.to_string());
+                    }
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            if escape {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        });
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::tools::{char_to_byte_range, format_duration, line_col};
+use crate::{Priority, RangeListFilter, StatusFilter, Taskmonger, STATE_VERSION};
+
+/// Exit codes of the command line queries, for scripts and status bars
+pub mod exit {
+    /// Nothing listed is overdue, or the checked state has no errors
+    pub const OK: i32 = 0;
+    /// Some of the listed ranges are overdue
+    pub const OVERDUE: i32 = 1;
+    pub const USAGE: i32 = 2;
+    /// The state file could not be read
+    pub const NO_STATE: i32 = 3;
+    /// `taskmonger check` found errors in the state file
+    pub const INVALID: i32 = 4;
+}
+
+const CHECK_USAGE: &str = "usage: taskmonger check [STATE_FILE] [--watch]";
+
+/// How often `taskmonger check --watch` looks for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+const STATUS_USAGE: &str = "usage: taskmonger status [--format text | json | waybar]";
+
+const LIST_USAGE: &str =
+    "usage: taskmonger list [--tag TAG] [--open | --done | --overdue] [TEXT] [--json]";
+
+/// A tagged range as `taskmonger list --json` prints it
+#[derive(Serialize)]
+struct ListItem {
+    tag: String,
+    /// First line of the range's text
+    text: String,
+    line: usize,
+    done: bool,
+    due: Option<chrono::NaiveDate>,
+    overdue: bool,
+    priority: Priority,
+    tracked_seconds: i64,
+}
+
+#[derive(Serialize)]
+struct List {
+    count: usize,
+    overdue: usize,
+    items: Vec<ListItem>,
+}
+
+/// `taskmonger list`: the tagged ranges, optionally narrowed down like in the ranges list.
+/// Returns the exit code, [`exit::OVERDUE`] if any of them is overdue.
+pub fn list(args: &[String]) -> i32 {
+    let mut filter = RangeListFilter::default();
+    let mut json = false;
+    let mut query = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--open" => filter.status = StatusFilter::Open,
+            "--done" => filter.status = StatusFilter::Done,
+            "--overdue" => filter.status = StatusFilter::Overdue,
+            "--tag" | "-t" => {
+                let Some(tag) = args.next() else {
+                    eprintln!("--tag needs a tag name\n{LIST_USAGE}");
+                    return exit::USAGE;
+                };
+                filter.tag = Some(tag.clone());
+            }
+            other if other.starts_with('-') => {
+                eprintln!("Unknown option {other}\n{LIST_USAGE}");
+                return exit::USAGE;
+            }
+            text => query.push(text),
+        }
+    }
+    filter.query = query.join(" ");
+
+    let app = match Taskmonger::load_from_disk() {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("Could not read {}: {e}", Taskmonger::save_path().display());
+            return exit::NO_STATE;
+        }
+    };
+    let today = chrono::Local::now().date_naive();
+    let len = app.buffer.chars().count();
+    let mut ranges: Vec<_> = app
+        .tagged_ranges
+        .iter()
+        .filter(|tr| tr.range.end <= len)
+        .collect();
+    ranges.sort_by_key(|tr| tr.range.start);
+    let items: Vec<ListItem> = ranges
+        .into_iter()
+        .filter_map(|tr| {
+            let text = &app.buffer[char_to_byte_range(&app.buffer, &tr.range)];
+            filter.matches(tr, text, today).then(|| ListItem {
+                tag: tr.tag_name.clone(),
+                text: text.trim().lines().next().unwrap_or_default().to_string(),
+                line: line_col(&app.buffer, tr.range.start).0,
+                done: tr.done,
+                due: tr.due,
+                overdue: tr.is_overdue(today),
+                priority: tr.priority,
+                tracked_seconds: tr.tracked_time().num_seconds(),
+            })
+        })
+        .collect();
+    let overdue = items.iter().filter(|item| item.overdue).count();
+
+    if json {
+        let list = List {
+            count: items.len(),
+            overdue,
+            items,
+        };
+        match serde_json::to_string_pretty(&list) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("{e}");
+                return exit::NO_STATE;
+            }
+        }
+    } else {
+        for item in &items {
+            let check = if item.done { "[x]" } else { "[ ]" };
+            let due = match item.due {
+                Some(due) if item.overdue => format!("overdue {due}"),
+                Some(due) => format!("due {due}"),
+                None => String::new(),
+            };
+            println!(
+                "{check} {:>5}  {:<16} {:<18} {}",
+                item.line, item.tag, due, item.text
+            );
+        }
+    }
+    if overdue > 0 {
+        exit::OVERDUE
+    } else {
+        exit::OK
+    }
+}
+
+/// The part of the state file `taskmonger status` needs, the buffer and the rest are skipped
+#[derive(Deserialize)]
+struct StatusState {
+    #[serde(default)]
+    tagged_ranges: Vec<StatusRange>,
+}
+
+#[derive(Deserialize)]
+struct StatusRange {
+    tag_name: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+    #[serde(default)]
+    timer_started: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct Timer {
+    tag: String,
+    seconds: i64,
+}
+
+#[derive(Serialize)]
+struct Status {
+    open: usize,
+    overdue: usize,
+    due_today: usize,
+    timer: Option<Timer>,
+}
+
+impl Status {
+    fn text(&self) -> String {
+        let mut parts = vec![format!("{} open", self.open)];
+        if self.overdue > 0 {
+            parts.push(format!("{} overdue", self.overdue));
+        }
+        if let Some(timer) = &self.timer {
+            parts.push(format!(
+                "{} {}",
+                timer.tag,
+                format_duration(chrono::Duration::seconds(timer.seconds))
+            ));
+        }
+        parts.join(", ")
+    }
+
+    /// A custom module's JSON for waybar, the class is what to style on
+    fn waybar(&self) -> serde_json::Value {
+        let class = if self.overdue > 0 {
+            "overdue"
+        } else if self.timer.is_some() {
+            "timer"
+        } else {
+            "ok"
+        };
+        let tooltip = format!(
+            "{} open\n{} overdue\n{} due today",
+            self.open, self.overdue, self.due_today
+        );
+        serde_json::json!({
+            "text": self.text(),
+            "tooltip": tooltip,
+            "class": class,
+            "alt": class,
+        })
+    }
+}
+
+/// `taskmonger status`: one line of open and overdue counts and the running timer, for
+/// status bars that run it every few seconds. Exits with 0 whatever the counts, bars
+/// tend to hide modules that fail.
+pub fn status(args: &[String]) -> i32 {
+    let mut format = "text";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next().map(String::as_str)) {
+            ("--format" | "-f", Some(f @ ("text" | "json" | "waybar"))) => format = f,
+            _ => {
+                eprintln!("{STATUS_USAGE}");
+                return exit::USAGE;
+            }
+        }
+    }
+
+    let state = fs::read_to_string(Taskmonger::save_path())
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str::<StatusState>(&json).map_err(|e| e.to_string()));
+    let state = match state {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Could not read {}: {e}", Taskmonger::save_path().display());
+            return exit::NO_STATE;
+        }
+    };
+    let now = chrono::Local::now().naive_local();
+    let today = now.date();
+    let open: Vec<&StatusRange> = state.tagged_ranges.iter().filter(|r| !r.done).collect();
+    let status = Status {
+        open: open.len(),
+        overdue: open
+            .iter()
+            .filter(|r| r.due.is_some_and(|d| d < today))
+            .count(),
+        due_today: open.iter().filter(|r| r.due == Some(today)).count(),
+        timer: state.tagged_ranges.iter().find_map(|r| {
+            Some(Timer {
+                tag: r.tag_name.clone(),
+                seconds: (now - r.timer_started?).num_seconds(),
+            })
+        }),
+    };
+    match format {
+        "json" => println!("{}", serde_json::json!(status)),
+        "waybar" => println!("{}", status.waybar()),
+        _ => println!("{}", status.text()),
+    }
+    exit::OK
+}
+
+/// Problems in a state file, errors first. Errors are what the app would silently repair or
+/// drop on load, warnings are merely odd.
+fn check_state(json: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let app: Taskmonger = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut errors = vec![];
+    let mut warnings = vec![];
+
+    match app.version {
+        0 => warnings.push("No schema version, written by an older taskmonger".to_string()),
+        v if v > STATE_VERSION => errors.push(format!(
+            "Schema version {v} is newer than the {STATE_VERSION} this taskmonger understands"
+        )),
+        _ => {}
+    }
+
+    let len = app.buffer.chars().count();
+    let mut keys: HashMap<(String, NaiveDateTime), usize> = HashMap::new();
+    for (i, tr) in app.tagged_ranges.iter().enumerate() {
+        let name = format!("Range {i} ({})", tr.tag_name);
+        if tr.range.start >= tr.range.end {
+            errors.push(format!("{name} is empty: {:?}", tr.range));
+        } else if tr.range.end > len {
+            errors.push(format!(
+                "{name} ends at {} past the buffer's {len} chars",
+                tr.range.end
+            ));
+        }
+        if !app.tags.contains_key(&tr.tag_name) {
+            errors.push(format!("{name} has a tag that does not exist"));
+        }
+        if let Some(first) = keys.insert(tr.key(), i) {
+            errors.push(format!(
+                "{name} has the same id as range {first}, tag and creation time"
+            ));
+        }
+    }
+
+    for name in app.tags.keys() {
+        if !app.tagged_ranges.iter().any(|tr| &tr.tag_name == name) {
+            warnings.push(format!("Tag {name} has no ranges"));
+        }
+        if let Some(first) = app.find_tag(name).filter(|first| first != name) {
+            warnings.push(format!(
+                "Tag {name} matches {first} under the tag name policy"
+            ));
+        }
+    }
+    for bookmark in &app.bookmarks {
+        if bookmark.at > len {
+            warnings.push(format!(
+                "Bookmark {} is past the end of the buffer",
+                bookmark.name
+            ));
+        }
+    }
+    Ok((errors, warnings))
+}
+
+/// Prints the report on `path`, returns the exit code
+fn check_file(path: &Path) -> i32 {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Could not read {}: {e}", path.display());
+            return exit::NO_STATE;
+        }
+    };
+    let (errors, warnings) = match check_state(&json) {
+        Ok(problems) => problems,
+        Err(e) => {
+            println!("{}: unreadable: {e}", path.display());
+            return exit::NO_STATE;
+        }
+    };
+    for error in &errors {
+        println!("error: {error}");
+    }
+    for warning in &warnings {
+        println!("warning: {warning}");
+    }
+    println!(
+        "{}: {} errors, {} warnings",
+        path.display(),
+        errors.len(),
+        warnings.len()
+    );
+    if errors.is_empty() {
+        exit::OK
+    } else {
+        exit::INVALID
+    }
+}
+
+/// `taskmonger check`: validates the state file before it is synced or committed.
+/// Exits with 1 on errors and 3 if the file can't be read, with `--watch` it checks
+/// again on every change until stopped.
+pub fn check(args: &[String]) -> i32 {
+    let mut watch = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--watch" | "-w" => watch = true,
+            other if other.starts_with('-') || path.is_some() => {
+                eprintln!("{CHECK_USAGE}");
+                return exit::USAGE;
+            }
+            other => path = Some(PathBuf::from(other)),
+        }
+    }
+    let path = path.unwrap_or_else(Taskmonger::save_path);
+    if !watch {
+        return check_file(&path);
+    }
+    let mut checked: Option<SystemTime> = None;
+    loop {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if checked.is_none() || modified != checked {
+            checked = modified;
+            println!("--- {}", chrono::Local::now().format("%H:%M:%S"));
+            check_file(&path);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Tag, TaggedRange};
+
+    fn app() -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: "one two".to_string(),
+            ..Default::default()
+        };
+        app.tags.clear();
+        app.tags.insert("work".to_string(), Tag::new([1, 2, 3]));
+        app.tagged_ranges = vec![TaggedRange::new("work".to_string(), 0..3)];
+        app
+    }
+
+    fn check(app: &Taskmonger) -> (Vec<String>, Vec<String>) {
+        check_state(&serde_json::to_string(app).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_sound_state_has_no_problems() {
+        assert_eq!(check(&app()), (vec![], vec![]));
+    }
+
+    #[test]
+    fn empty_and_out_of_bounds_ranges_are_errors() {
+        let mut app = app();
+        app.tagged_ranges
+            .push(TaggedRange::new("work".to_string(), 4..4));
+        app.tagged_ranges
+            .push(TaggedRange::new("work".to_string(), 4..8));
+        let (errors, _) = check(&app);
+        assert_eq!(
+            errors,
+            [
+                "Range 1 (work) is empty: 4..4",
+                "Range 2 (work) ends at 8 past the buffer's 7 chars",
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_of_unknown_tags_are_errors() {
+        let mut app = app();
+        app.tagged_ranges
+            .push(TaggedRange::new("gone".to_string(), 4..7));
+        let (errors, _) = check(&app);
+        assert_eq!(errors, ["Range 1 (gone) has a tag that does not exist"]);
+    }
+
+    #[test]
+    fn ranges_with_the_same_key_are_errors() {
+        let mut app = app();
+        let mut twin = app.tagged_ranges[0].clone();
+        twin.range = 4..7;
+        app.tagged_ranges.push(twin);
+        let (errors, _) = check(&app);
+        assert_eq!(
+            errors,
+            ["Range 1 (work) has the same id as range 0, tag and creation time"]
+        );
+    }
+
+    #[test]
+    fn schema_versions_newer_or_missing() {
+        let mut app = app();
+        app.version = STATE_VERSION + 1;
+        let (errors, _) = check(&app);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("Schema version {}", STATE_VERSION + 1)));
+
+        app.version = 0;
+        let (errors, warnings) = check(&app);
+        assert!(errors.is_empty());
+        assert_eq!(
+            warnings,
+            ["No schema version, written by an older taskmonger"]
+        );
+    }
+
+    #[test]
+    fn unused_and_near_duplicate_tags_are_warnings() {
+        let mut app = app();
+        app.tags.insert("Work".to_string(), Tag::new([1, 2, 3]));
+        app.settings.tag_name_policy = crate::TagNamePolicy::IgnoreCase;
+        let (errors, warnings) = check(&app);
+        assert!(errors.is_empty());
+        assert_eq!(
+            warnings,
+            [
+                "Tag Work has no ranges",
+                "Tag Work matches work under the tag name policy",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable_state_is_an_error() {
+        assert!(check_state("{ not json").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use eframe::egui;
+use egui::RichText;
+use tracing::info;
+
+use crate::tools::to_color32;
+use crate::{Tag, Taskmonger};
+
+/// A tag that came in under the name of one of ours but looking different. The incoming
+/// one is in use until the dialog decides.
+pub struct TagCollision {
+    pub name: String,
+    pub mine: Tag,
+    pub theirs: Tag,
+    /// Where the incoming tag is from, like a device or a profile
+    pub source: String,
+}
+
+/// What to do about a collision
+enum Resolution {
+    KeepMine,
+    TakeTheirs,
+    /// Ours keeps the name, theirs is added with a number after it
+    KeepBoth,
+}
+
+impl Taskmonger {
+    /// The collision if `theirs` would replace a tag of ours of another color
+    pub fn tag_collision(&self, name: &str, theirs: &Tag, source: &str) -> Option<TagCollision> {
+        let mine = self.tags.get(name)?;
+        (mine.color != theirs.color).then(|| TagCollision {
+            name: name.to_string(),
+            mine: mine.clone(),
+            theirs: theirs.clone(),
+            source: source.to_string(),
+        })
+    }
+
+    /// `name` with the first number after it that no tag matches
+    fn suffixed_tag_name(&self, name: &str) -> String {
+        (2..)
+            .map(|n| format!("{name} {n}"))
+            .find(|candidate| self.find_tag(candidate).is_none())
+            .unwrap_or_default()
+    }
+
+    fn resolve_collision(&mut self, collision: TagCollision, resolution: Resolution) {
+        let name = collision.name;
+        match resolution {
+            Resolution::KeepMine => {
+                self.tags.insert(name.clone(), collision.mine);
+            }
+            Resolution::TakeTheirs => {
+                self.tags.insert(name.clone(), collision.theirs);
+            }
+            Resolution::KeepBoth => {
+                let other = self.suffixed_tag_name(&name);
+                info!("Adding the incoming '{name}' as '{other}'");
+                self.tags.insert(name.clone(), collision.mine);
+                self.tags.insert(other, collision.theirs);
+            }
+        }
+        let _ = self.save_to_disk();
+    }
+
+    /// Asks about the first tag collision: keep ours, take theirs or keep both
+    pub fn tag_collision_window(&mut self, ctx: &egui::Context) {
+        let Some(collision) = self.tag_collisions.first() else {
+            return;
+        };
+        let mut resolution = None;
+        egui::Window::new("Tag collision")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "'{}' from {} has another color than yours.",
+                    collision.name, collision.source
+                ));
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("■ Mine").color(to_color32(collision.mine.color)));
+                    ui.label(RichText::new("■ Theirs").color(to_color32(collision.theirs.color)));
+                });
+                if self.tag_collisions.len() > 1 {
+                    ui.label(
+                        RichText::new(format!("{} more to decide", self.tag_collisions.len() - 1))
+                            .weak(),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Keep mine").clicked() {
+                        resolution = Some(Resolution::KeepMine);
+                    }
+                    if ui.button("Take theirs").clicked() {
+                        resolution = Some(Resolution::TakeTheirs);
+                    }
+                    if ui
+                        .button("Keep both")
+                        .on_hover_text(format!(
+                            "Theirs is added as '{}'",
+                            self.suffixed_tag_name(&collision.name)
+                        ))
+                        .clicked()
+                    {
+                        resolution = Some(Resolution::KeepBoth);
+                    }
+                });
+            });
+        if let Some(resolution) = resolution {
+            let collision = self.tag_collisions.remove(0);
+            self.resolve_collision(collision, resolution);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(tags: &[&str]) -> Taskmonger {
+        let mut app = Taskmonger::default();
+        app.tags.clear();
+        for name in tags {
+            app.tags.insert(name.to_string(), Tag::new([1, 2, 3]));
+        }
+        app
+    }
+
+    #[test]
+    fn only_other_colors_collide() {
+        let app = app(&["work"]);
+        assert!(app
+            .tag_collision("work", &Tag::new([1, 2, 3]), "a")
+            .is_none());
+        assert!(app
+            .tag_collision("home", &Tag::new([9, 9, 9]), "a")
+            .is_none());
+        let collision = app
+            .tag_collision("work", &Tag::new([9, 9, 9]), "a")
+            .unwrap();
+        assert_eq!(collision.mine.color, [1, 2, 3]);
+        assert_eq!(collision.theirs.color, [9, 9, 9]);
+    }
+
+    #[test]
+    fn kept_tags_get_the_first_free_number() {
+        let app = app(&["work", "Work 2"]);
+        assert_eq!(app.suffixed_tag_name("work"), "work 3");
+    }
+}
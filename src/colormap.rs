@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use egui::Color32;
+use indexmap::IndexMap;
+
+use crate::print::PrintMarker;
+use crate::tools::{char_to_byte_range, syntax_colors, to_color32, RangeExt};
+use crate::{CharMarks, Priority, Tag, TaggedRange};
+
+/// How tags show up in the editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Marking {
+    Text,
+    Background,
+    /// Gray patterns for the print preview
+    Print,
+}
+
+/// How long a step of a rebuild spread over frames may take
+const STEP: Duration = Duration::from_millis(8);
+
+/// Visible part of a range, the index of the tag it is drawn with, and whether it is
+/// drawn as background and as urgent
+type Mark = (Range<usize>, usize, bool, bool);
+
+/// How far a rebuild got
+#[derive(Default)]
+struct Rebuild {
+    /// Ranges looked at
+    ranges: usize,
+    total: usize,
+    marked: Vec<Mark>,
+    /// Interval boundaries, once all ranges are looked at
+    points: Option<Vec<usize>>,
+    /// Intervals filled
+    done: usize,
+}
+
+impl Rebuild {
+    fn progress(&self) -> f32 {
+        match &self.points {
+            None => self.ranges as f32 / self.total.max(1) as f32 / 2.0,
+            Some(points) => 0.5 + self.done as f32 / points.len().max(1) as f32 / 2.0,
+        }
+    }
+}
+
+/// Tag marks of the buffer as sorted, non-overlapping char intervals.
+/// Only rebuilt when the ranges, tags or highlighted window change.
+#[derive(Default)]
+pub struct Colormap {
+    key: Option<u64>,
+    segments: Vec<(Range<usize>, CharMarks)>,
+    rebuild: Option<Rebuild>,
+    /// Spread the next rebuild over frames, set after a large paste
+    pub in_steps: bool,
+    /// Text colors of ranges with a code language
+    syntax: HashMap<usize, Color32>,
+    /// Leave out whole-line tags, they are drawn as stripes or bars instead
+    pub skip_line_tags: bool,
+    /// Add a shade, underline or italics per tag, so tags don't rely on color alone
+    pub patterns: bool,
+}
+
+impl Colormap {
+    /// Rebuilds the intervals if any input changed. Returns true once it is done, which
+    /// in steps takes a few frames.
+    pub fn update(
+        &mut self,
+        ui: &egui::Ui,
+        buffer: &str,
+        ranges: &[TaggedRange],
+        tags: &IndexMap<String, Tag>,
+        marking: Marking,
+        window: Option<&Range<usize>>,
+    ) -> bool {
+        let key = Self::key(
+            ui,
+            buffer,
+            ranges,
+            tags,
+            marking,
+            window,
+            (self.skip_line_tags, self.patterns),
+        );
+        if self.key != Some(key) {
+            self.key = Some(key);
+            self.segments.clear();
+            self.syntax.clear();
+            self.rebuild = Some(Rebuild {
+                total: ranges.len(),
+                ..Default::default()
+            });
+        }
+        let Some(mut rebuild) = self.rebuild.take() else {
+            return false;
+        };
+        let deadline = self.in_steps.then(|| Instant::now() + STEP);
+        let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+        while let Some(tr) = ranges.get(rebuild.ranges) {
+            if out_of_time() {
+                self.rebuild = Some(rebuild);
+                return false;
+            }
+            if let Some(mark) = self.mark(ui, buffer, tr, tags, marking, window) {
+                rebuild.marked.push(mark);
+            }
+            rebuild.ranges += 1;
+        }
+        let points = rebuild.points.get_or_insert_with(|| {
+            let mut points: Vec<usize> = rebuild
+                .marked
+                .iter()
+                .flat_map(|(range, ..)| [range.start, range.end])
+                .collect();
+            points.sort_unstable();
+            points.dedup();
+            points
+        });
+        let dark_mode = ui.visuals().dark_mode;
+        while rebuild.done + 1 < points.len() {
+            if out_of_time() {
+                self.rebuild = Some(rebuild);
+                return false;
+            }
+            let segment = points[rebuild.done]..points[rebuild.done + 1];
+            rebuild.done += 1;
+            let mut marks: Option<CharMarks> = None;
+            for (range, tag_index, as_background, urgent) in &rebuild.marked {
+                if range.start <= segment.start && segment.end <= range.end {
+                    let Some((_, tag)) = tags.get_index(*tag_index) else {
+                        continue;
+                    };
+                    let marks = marks.get_or_insert_default();
+                    if marking == Marking::Print {
+                        marks.add_print(PrintMarker::for_tag(*tag_index));
+                    } else {
+                        marks.add(tag, *as_background, *urgent);
+                        if self.patterns {
+                            let marker = PrintMarker::for_tag(*tag_index);
+                            // A shade would only stripe a colored background
+                            let shade = marker.overlay(dark_mode).filter(|_| !as_background);
+                            marks.add_pattern(marker, to_color32(tag.color), shade);
+                        }
+                    }
+                }
+            }
+            if let Some(marks) = marks {
+                self.segments.push((segment, marks));
+            }
+        }
+        self.in_steps = false;
+        true
+    }
+
+    /// The part of a range that is drawn, and how. Fills in syntax colors of code.
+    fn mark(
+        &mut self,
+        ui: &egui::Ui,
+        buffer: &str,
+        tr: &TaggedRange,
+        tags: &IndexMap<String, Tag>,
+        marking: Marking,
+        window: Option<&Range<usize>>,
+    ) -> Option<Mark> {
+        let (tag_index, _, tag) = tags.get_full(&tr.tag_name)?;
+        if self.skip_line_tags && tag.whole_lines {
+            return None;
+        }
+        let mut range = tr.range.clone();
+        if let Some(window) = window {
+            if !range.intersects(window) {
+                return None;
+            }
+            // Code is highlighted as a whole, it does not parse from the middle
+            if tr.language.is_none() {
+                range = range.start.max(window.start)..range.end.min(window.end);
+            }
+        }
+        if let Some(language) = &tr.language {
+            let code = &buffer[char_to_byte_range(buffer, &range)];
+            self.syntax
+                .extend(range.clone().zip(syntax_colors(ui, code, language)));
+        }
+        // Code keeps its syntax colors, so the tag goes into the background
+        Some((
+            range,
+            tag_index,
+            marking == Marking::Background || tr.language.is_some(),
+            tr.priority == Priority::Urgent,
+        ))
+    }
+
+    /// How far a rebuild in steps is, from 0 to 1
+    pub fn progress(&self) -> Option<f32> {
+        self.rebuild.as_ref().map(Rebuild::progress)
+    }
+
+    /// Whether a rebuild in steps is still going, the intervals are incomplete until then
+    pub fn busy(&self) -> bool {
+        self.rebuild.is_some()
+    }
+
+    /// Everything the intervals depend on
+    fn key(
+        ui: &egui::Ui,
+        buffer: &str,
+        ranges: &[TaggedRange],
+        tags: &IndexMap<String, Tag>,
+        marking: Marking,
+        window: Option<&Range<usize>>,
+        flags: (bool, bool),
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for tr in ranges {
+            (&tr.tag_name, &tr.range, &tr.language, tr.priority).hash(&mut hasher);
+            // Syntax colors depend on the code itself
+            if tr.language.is_some() && tr.range.end <= buffer.len() {
+                buffer[char_to_byte_range(buffer, &tr.range)].hash(&mut hasher);
+            }
+        }
+        for (name, tag) in tags {
+            (
+                name,
+                tag.color,
+                tag.bold,
+                tag.italics,
+                tag.underline,
+                tag.strikethrough,
+                tag.background_only,
+                tag.whole_lines,
+            )
+                .hash(&mut hasher);
+        }
+        (marking, window, ui.visuals().dark_mode, flags).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Looks up chars in increasing order
+    pub fn lookup(&self) -> Lookup<'_> {
+        Lookup {
+            segments: &self.segments,
+            next: 0,
+        }
+    }
+
+    pub fn syntax(&self, index: usize) -> Option<Color32> {
+        self.syntax.get(&index).copied()
+    }
+}
+
+pub struct Lookup<'a> {
+    segments: &'a [(Range<usize>, CharMarks)],
+    next: usize,
+}
+
+impl<'a> Lookup<'a> {
+    /// Marks of the char at `index`, which must not be lower than in the previous call
+    pub fn get(&mut self, index: usize) -> Option<&'a CharMarks> {
+        while self
+            .segments
+            .get(self.next)
+            .is_some_and(|(range, _)| range.end <= index)
+        {
+            self.next += 1;
+        }
+        self.segments
+            .get(self.next)
+            .filter(|(range, _)| range.contains(&index))
+            .map(|(_, marks)| marks)
+    }
+}
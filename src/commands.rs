@@ -0,0 +1,83 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// Output beyond this is cut off so the state file stays small
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Result of running the command attached to a range
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct CommandRun {
+    /// Stdout followed by stderr
+    pub output: String,
+    /// None if the process could not be started or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub finished: chrono::NaiveDateTime,
+}
+
+impl CommandRun {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs `command` through the system shell on a background thread.
+/// The receiver yields the result once the command exits.
+pub fn spawn(command: &str, dir: &Path, ctx: egui::Context) -> Receiver<CommandRun> {
+    let (sender, receiver) = mpsc::channel();
+    let command = command.to_string();
+    let dir = dir.to_path_buf();
+    std::thread::spawn(move || {
+        debug!("Running '{command}' in {}", dir.display());
+        let result = shell(&command).current_dir(&dir).output();
+        let run = match result {
+            Ok(output) => {
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                CommandRun {
+                    output: truncate(text),
+                    exit_code: output.status.code(),
+                    finished: chrono::Local::now().naive_local(),
+                }
+            }
+            Err(e) => {
+                warn!("Could not run '{command}': {e}");
+                CommandRun {
+                    output: e.to_string(),
+                    exit_code: None,
+                    finished: chrono::Local::now().naive_local(),
+                }
+            }
+        };
+        let _ = sender.send(run);
+        ctx.request_repaint();
+    });
+    receiver
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_OUTPUT_BYTES {
+        let mut end = MAX_OUTPUT_BYTES;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[output truncated]");
+    }
+    text
+}
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use bip39::Mnemonic;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+/// Start of every encrypted file, with the version of the format
+const MAGIC: &[u8; 20] = b"taskmonger-sealed-1\n";
+
+const NONCE_LEN: usize = 24;
+
+/// Key the files in the sync folder are encrypted with. It is only stored on the devices,
+/// which pass it on as its recovery phrase.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SyncKey([u8; 32]);
+
+impl SyncKey {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// The 24 words the key can be entered with on another device
+    pub fn phrase(&self) -> String {
+        Mnemonic::from_entropy(&self.0)
+            .expect("32 bytes make a mnemonic")
+            .to_string()
+    }
+
+    pub fn from_phrase(phrase: &str) -> Result<Self, String> {
+        let words: Vec<String> = phrase
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect();
+        let mnemonic =
+            Mnemonic::parse(words.join(" ")).map_err(|e| format!("not a recovery phrase: {e}"))?;
+        <[u8; 32]>::try_from(mnemonic.to_entropy().as_slice())
+            .map(Self)
+            .map_err(|_| "a recovery phrase has 24 words".to_string())
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new((&self.0).into())
+    }
+
+    /// `plain` encrypted. The file's `name` is bound to it, so it can't be passed off as
+    /// another file.
+    pub fn seal(&self, name: &str, plain: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let payload = Payload {
+            msg: plain,
+            aad: name.as_bytes(),
+        };
+        let sealed = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .expect("encrypting into memory does not fail");
+        [MAGIC.as_slice(), &nonce, &sealed].concat()
+    }
+
+    /// The contents of a file [`Self::seal`] wrote
+    pub fn open(&self, name: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+        let data = data.strip_prefix(MAGIC).ok_or("not encrypted")?;
+        if data.len() < NONCE_LEN {
+            return Err(format!("{name} is cut off"));
+        }
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: sealed,
+            aad: name.as_bytes(),
+        };
+        self.cipher()
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| format!("{name} was encrypted with another key, or is damaged"))
+    }
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Whether the file at `path` is encrypted, only reading its start
+pub fn is_sealed_file(path: &Path) -> bool {
+    let mut start = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok_and(|_| &start == MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_files_open_with_their_key_and_name() {
+        let key = SyncKey::generate();
+        let sealed = key.seal("a.ops.json", b"{}");
+        assert!(is_sealed(&sealed));
+        assert_eq!(key.open("a.ops.json", &sealed).unwrap(), b"{}");
+        assert!(key.open("b.ops.json", &sealed).is_err());
+        assert!(SyncKey::generate().open("a.ops.json", &sealed).is_err());
+    }
+
+    #[test]
+    fn damaged_and_plain_files_do_not_open() {
+        let key = SyncKey::generate();
+        let mut sealed = key.seal("a", b"text");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(key.open("a", &sealed).is_err());
+        assert!(key.open("a", &sealed[..MAGIC.len() + 3]).is_err());
+        assert!(!is_sealed(b"{}"));
+        assert!(key.open("a", b"{}").is_err());
+    }
+
+    #[test]
+    fn the_recovery_phrase_gives_back_the_key() {
+        let key = SyncKey::generate();
+        let phrase = key.phrase();
+        assert_eq!(phrase.split(' ').count(), 24);
+        let typed = format!("  {}\n", phrase.to_uppercase().replace(' ', "   "));
+        assert!(SyncKey::from_phrase(&typed).unwrap() == key);
+    }
+
+    #[test]
+    fn wrong_phrases_are_refused() {
+        let phrase = SyncKey::generate().phrase();
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+        words.swap(0, 1);
+        words.swap(0, 2);
+        assert!(SyncKey::from_phrase("not a phrase").is_err());
+        assert!(SyncKey::from_phrase(&words[..12].join(" ")).is_err());
+    }
+}
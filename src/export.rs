@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use chrono::NaiveDate;
+
+use crate::tools::{char_to_byte_range, RangeExt};
+use crate::{Recurrence, Tag, TaggedRange};
+
+/// Calendar with a VTODO for every range that has a due date
+pub fn ics(buffer: &str, ranges: &[TaggedRange]) -> String {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut out = String::new();
+    let mut line = |text: String| fold_line(&mut out, &text);
+    line("BEGIN:VCALENDAR".into());
+    line("VERSION:2.0".into());
+    line(format!("PRODID:-//{}//EN", env!("CARGO_PKG_NAME")));
+    for tr in ranges {
+        let Some(due) = tr.due else {
+            continue;
+        };
+        let text = buffer[char_to_byte_range(buffer, &tr.range)].trim();
+        let summary = text.lines().next().unwrap_or_default();
+        line("BEGIN:VTODO".into());
+        line(format!(
+            "UID:{}-{}@{}",
+            tr.created.format("%Y%m%dT%H%M%S%f"),
+            tr.tag_name.replace(|c: char| !c.is_alphanumeric(), "-"),
+            env!("CARGO_PKG_NAME")
+        ));
+        line(format!("DTSTAMP:{stamp}"));
+        line(format!("SUMMARY:{}", escape(summary)));
+        if text.len() > summary.len() {
+            line(format!("DESCRIPTION:{}", escape(text)));
+        }
+        line(format!("CATEGORIES:{}", escape(&tr.tag_name)));
+        line(format!("DUE;VALUE=DATE:{}", due.format("%Y%m%d")));
+        if let Some(recurrence) = tr.recurrence {
+            line(format!("RRULE:{}", rrule(recurrence)));
+        }
+        line(format!(
+            "STATUS:{}",
+            if tr.done { "COMPLETED" } else { "NEEDS-ACTION" }
+        ));
+        line("END:VTODO".into());
+    }
+    line("END:VCALENDAR".into());
+    out
+}
+
+fn rrule(recurrence: Recurrence) -> String {
+    match recurrence {
+        Recurrence::Daily => "FREQ=DAILY".into(),
+        Recurrence::Weekdays => "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR".into(),
+        Recurrence::Weekly => "FREQ=WEEKLY".into(),
+        Recurrence::Monthly => "FREQ=MONTHLY".into(),
+        Recurrence::EveryDays(days) => format!("FREQ=DAILY;INTERVAL={days}"),
+    }
+}
+
+/// Escapes a TEXT value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Writes a content line, folded to at most 75 octets per line as RFC 5545 asks
+fn fold_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        width += c.len_utf8();
+        out.push(c);
+    }
+    out.push_str("\r\n");
+}
+
+/// Undirected graphviz graph of tags, linked when their ranges overlap or share a line.
+/// Edge weights count how often that happens.
+pub fn tag_graph_dot(buffer: &str, ranges: &[TaggedRange], tags: &IndexMap<String, Tag>) -> String {
+    // Char index where each line starts
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(
+            buffer
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| *c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect();
+    let line_of = |index: usize| line_starts.partition_point(|&start| start <= index) - 1;
+    let lines: Vec<std::ops::Range<usize>> = ranges
+        .iter()
+        .map(|tr| {
+            line_of(tr.range.start)..line_of(tr.range.end.saturating_sub(1).max(tr.range.start)) + 1
+        })
+        .collect();
+
+    let mut edges: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for (i, a) in ranges.iter().enumerate() {
+        for (j, b) in ranges.iter().enumerate().skip(i + 1) {
+            if a.tag_name == b.tag_name {
+                continue;
+            }
+            if a.range.intersects(&b.range) || lines[i].intersects(&lines[j]) {
+                let key = if a.tag_name < b.tag_name {
+                    (a.tag_name.as_str(), b.tag_name.as_str())
+                } else {
+                    (b.tag_name.as_str(), a.tag_name.as_str())
+                };
+                *edges.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    let mut out = String::from("graph tags {\n    node [style=filled, shape=box];\n");
+    for (name, tag) in tags {
+        let [r, g, b] = tag.color;
+        let count = ranges.iter().filter(|tr| &tr.tag_name == name).count();
+        out.push_str(&format!(
+            "    \"{}\" [fillcolor=\"#{r:02x}{g:02x}{b:02x}\", label=\"{} ({count})\"];\n",
+            quote(name),
+            quote(name)
+        ));
+    }
+    for ((a, b), weight) in edges {
+        out.push_str(&format!(
+            "    \"{}\" -- \"{}\" [weight={weight}, penwidth={}, label=\"{weight}\"];\n",
+            quote(a),
+            quote(b),
+            1 + weight.min(8)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Markdown report of open and completed ranges per tag
+pub fn digest(ranges: &[TaggedRange], tags: &IndexMap<String, Tag>, today: NaiveDate) -> String {
+    let week_start = today - chrono::Days::new(7);
+    let mut out = format!(
+        "# Weekly digest {}\n\n| Tag | Open | Overdue | Completed | Completed this week |\n|---|---|---|---|---|\n",
+        today.format("%Y-%m-%d")
+    );
+    let (mut open_total, mut done_total) = (0, 0);
+    for name in tags.keys() {
+        let of_tag: Vec<&TaggedRange> = ranges.iter().filter(|tr| &tr.tag_name == name).collect();
+        let open = of_tag.iter().filter(|tr| !tr.done).count();
+        let overdue = of_tag.iter().filter(|tr| tr.is_overdue(today)).count();
+        let done = of_tag.len() - open;
+        let done_this_week = of_tag
+            .iter()
+            .filter(|tr| tr.done && tr.modified.date() > week_start)
+            .count();
+        open_total += open;
+        done_total += done;
+        out.push_str(&format!(
+            "| {} | {open} | {overdue} | {done} | {done_this_week} |\n",
+            escape_cell(name)
+        ));
+    }
+    out.push_str(&format!(
+        "\n{open_total} open, {done_total} completed in total.\n"
+    ));
+    out
+}
+
+/// Escapes text for a markdown table cell
+fn escape_cell(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace('\n', " ")
+}
+
+#[derive(Serialize)]
+pub struct TagStats {
+    pub ranges: usize,
+    pub open: usize,
+    pub done: usize,
+    pub overdue: usize,
+    /// Share of done ranges, between 0 and 1
+    pub completion_rate: f64,
+    pub tracked_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    pub generated: chrono::NaiveDateTime,
+    pub total: TagStats,
+    pub tags: IndexMap<String, TagStats>,
+}
+
+fn tag_stats<'a>(ranges: impl Iterator<Item = &'a TaggedRange>, today: NaiveDate) -> TagStats {
+    let mut stats = TagStats {
+        ranges: 0,
+        open: 0,
+        done: 0,
+        overdue: 0,
+        completion_rate: 0.0,
+        tracked_seconds: 0,
+    };
+    for tr in ranges {
+        stats.ranges += 1;
+        if tr.done {
+            stats.done += 1;
+        } else {
+            stats.open += 1;
+        }
+        if tr.is_overdue(today) {
+            stats.overdue += 1;
+        }
+        stats.tracked_seconds += tr.tracked_time().num_seconds();
+    }
+    if stats.ranges > 0 {
+        stats.completion_rate = stats.done as f64 / stats.ranges as f64;
+    }
+    stats
+}
+
+/// Per tag counts and tracked time, for dashboards
+pub fn stats(ranges: &[TaggedRange], tags: &IndexMap<String, Tag>) -> Stats {
+    let now = chrono::Local::now().naive_local();
+    Stats {
+        generated: now,
+        total: tag_stats(ranges.iter(), now.date()),
+        tags: tags
+            .keys()
+            .map(|name| {
+                let of_tag = ranges.iter().filter(|tr| &tr.tag_name == name);
+                (name.clone(), tag_stats(of_tag, now.date()))
+            })
+            .collect(),
+    }
+}
+
+/// Escapes text for HTML element content and attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// CSS class of every tag, `tag-` and its name with anything but letters and digits as `-`.
+/// Names that end up alike get their tag index appended.
+fn tag_classes(tags: &IndexMap<String, Tag>) -> IndexMap<&str, String> {
+    let mut classes: IndexMap<&str, String> = IndexMap::new();
+    for (i, name) in tags.keys().enumerate() {
+        let slug: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        let mut class = format!("tag-{}", slug.trim_matches('-'));
+        if classes.values().any(|c| *c == class) {
+            class = format!("{class}-{i}");
+        }
+        classes.insert(name, class);
+    }
+    classes
+}
+
+/// Standalone HTML page of the buffer, with every tagged stretch in a `<span>` carrying a class
+/// per tag and the tag names as tooltip. Tag colors and styles go into the page's stylesheet.
+pub fn html(
+    title: &str,
+    buffer: &str,
+    ranges: &[TaggedRange],
+    tags: &IndexMap<String, Tag>,
+) -> String {
+    let classes = tag_classes(tags);
+    let len = buffer.chars().count();
+    let ranges: Vec<&TaggedRange> = ranges
+        .iter()
+        .filter(|tr| classes.contains_key(tr.tag_name.as_str()) && tr.range.end <= len)
+        .filter(|tr| !tr.range.is_empty())
+        .collect();
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n<style>\n", escape_html(title)));
+    out.push_str("body { font-family: sans-serif; max-width: 60em; margin: 2em auto; }\n");
+    out.push_str(".buffer { font-family: monospace; white-space: pre-wrap; line-height: 1.5; }\n");
+    out.push_str(".legend span { margin-right: 1em; padding: 0 0.3em; }\n");
+    for (name, tag) in tags {
+        let [r, g, b] = tag.color;
+        let mut rules = format!("background-color: rgba({r}, {g}, {b}, 0.35);");
+        if !tag.background_only {
+            rules.push_str(&format!(" border-bottom: 2px solid rgb({r}, {g}, {b});"));
+        }
+        if tag.bold {
+            rules.push_str(" font-weight: bold;");
+        }
+        if tag.italics {
+            rules.push_str(" font-style: italic;");
+        }
+        let decorations: Vec<&str> = [
+            (tag.underline, "underline"),
+            (tag.strikethrough, "line-through"),
+        ]
+        .into_iter()
+        .filter_map(|(on, decoration)| on.then_some(decoration))
+        .collect();
+        if !decorations.is_empty() {
+            rules.push_str(&format!(" text-decoration: {};", decorations.join(" ")));
+        }
+        out.push_str(&format!(".{} {{ {rules} }}\n", classes[name.as_str()]));
+    }
+    out.push_str("</style>\n</head>\n<body>\n<p class=\"legend\">");
+    for name in tags.keys() {
+        out.push_str(&format!(
+            "<span class=\"{}\">{}</span>",
+            classes[name.as_str()],
+            escape_html(name)
+        ));
+    }
+    out.push_str("</p>\n<div class=\"buffer\">");
+
+    // Overlapping ranges split the text into stretches covered by the same tags
+    let mut points: Vec<usize> = ranges
+        .iter()
+        .flat_map(|tr| [tr.range.start, tr.range.end])
+        .chain([0, len])
+        .collect();
+    points.sort_unstable();
+    points.dedup();
+    let mut stretches: Vec<(Vec<&str>, std::ops::Range<usize>)> = vec![];
+    for pair in points.windows(2) {
+        let segment = pair[0]..pair[1];
+        let mut names: Vec<&str> = ranges
+            .iter()
+            .filter(|tr| tr.range.start <= segment.start && segment.end <= tr.range.end)
+            .map(|tr| tr.tag_name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        // Ranges of the same tags meeting or overlapping make one span
+        match stretches.last_mut() {
+            Some((last, stretch)) if *last == names => stretch.end = segment.end,
+            _ => stretches.push((names, segment)),
+        }
+    }
+    for (names, stretch) in stretches {
+        let text = escape_html(&buffer[char_to_byte_range(buffer, &stretch)]);
+        if names.is_empty() {
+            out.push_str(&text);
+            continue;
+        }
+        let class: Vec<&str> = names.iter().map(|name| classes[*name].as_str()).collect();
+        out.push_str(&format!(
+            "<span class=\"{}\" title=\"{}\">{text}</span>",
+            class.join(" "),
+            escape_html(&names.join(", "))
+        ));
+    }
+    out.push_str("</div>\n</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(tag: &str, range: std::ops::Range<usize>) -> TaggedRange {
+        TaggedRange::new(tag.to_string(), range)
+    }
+
+    #[test]
+    fn dated_ranges_become_todos() {
+        let buffer = "pay rent, now\nsecond line\nno date";
+        let mut rent = range("home", 0..26);
+        rent.due = NaiveDate::from_ymd_opt(2024, 5, 3);
+        rent.recurrence = Some(Recurrence::Monthly);
+        rent.done = true;
+        let ics = ics(buffer, &[rent, range("home", 27..34)]);
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VTODO").count(), 1);
+        for expected in [
+            "SUMMARY:pay rent\\, now",
+            "DESCRIPTION:pay rent\\, now\\nsecond line",
+            "CATEGORIES:home",
+            "DUE;VALUE=DATE:20240503",
+            "RRULE:FREQ=MONTHLY",
+            "STATUS:COMPLETED",
+        ] {
+            assert!(lines.contains(&expected), "{expected} in {ics}");
+        }
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn long_lines_fold_at_75_octets_between_chars() {
+        let summary = "Überprüfung der Rückstände für größere Säle ".repeat(4);
+        let mut out = String::new();
+        fold_line(&mut out, &format!("SUMMARY:{summary}"));
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        // Unfolding gives back the line
+        let unfolded = out.trim_end_matches("\r\n").replace("\r\n ", "");
+        assert_eq!(unfolded, format!("SUMMARY:{summary}"));
+    }
+
+    fn tags(names: &[&str]) -> IndexMap<String, Tag> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), Tag::new([255, 0, 16])))
+            .collect()
+    }
+
+    #[test]
+    fn tags_on_the_same_line_are_linked() {
+        let buffer = "a b
+c";
+        let ranges = [
+            range("x", 0..1),
+            range("y", 2..3),
+            range("y", 0..3),
+            range("z", 4..5),
+        ];
+        let dot = tag_graph_dot(buffer, &ranges, &tags(&["x", "y", "z"]));
+        assert!(dot.contains("    \"x\" [fillcolor=\"#ff0010\", label=\"x (1)\"];\n"));
+        assert!(dot.contains("    \"y\" [fillcolor=\"#ff0010\", label=\"y (2)\"];\n"));
+        assert!(dot.contains("    \"x\" -- \"y\" [weight=2, penwidth=3, label=\"2\"];\n"));
+        assert!(!dot.contains("\"z\" --") && !dot.contains("-- \"z\""));
+    }
+
+    #[test]
+    fn quotes_in_tag_names_are_escaped() {
+        let name = r#"say "hi" \o/"#;
+        let dot = tag_graph_dot(
+            "ab",
+            &[range(name, 0..1), range("b", 1..2)],
+            &tags(&[name, "b"]),
+        );
+        assert!(dot.contains(r#"    "say \"hi\" \\o/" [fillcolor"#));
+        assert!(dot.contains(r#"    "b" -- "say \"hi\" \\o/" [weight=1"#));
+    }
+
+    #[test]
+    fn digest_counts_per_tag_and_escapes_cells() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        let mut late = range("a|b", 0..1);
+        late.due = NaiveDate::from_ymd_opt(2024, 5, 1);
+        let mut done = range("a|b", 1..2);
+        done.done = true;
+        let mut long_done = range("c", 2..3);
+        long_done.done = true;
+        long_done.modified = NaiveDate::from_ymd_opt(2024, 4, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let digest = digest(&[late, done, long_done], &tags(&["a|b", "c"]), today);
+        let lines: Vec<&str> = digest.lines().collect();
+        assert_eq!(lines[0], "# Weekly digest 2024-05-10");
+        assert_eq!(lines[4], r"| a\|b | 1 | 1 | 1 | 1 |");
+        assert_eq!(lines[5], "| c | 0 | 0 | 1 | 0 |");
+        assert_eq!(lines[7], "1 open, 2 completed in total.");
+    }
+
+    #[test]
+    fn table_cells_escape_pipes_and_line_breaks() {
+        assert_eq!(escape_cell(r"a|b\c"), r"a\|b\\c");
+        assert_eq!(escape_cell("two\nlines"), "two lines");
+    }
+
+    #[test]
+    fn html_escapes_text_and_tag_names() {
+        let html = html(
+            "<title>",
+            "a<b & \"c\"",
+            &[range("x&y", 0..3)],
+            &tags(&["x&y"]),
+        );
+        assert!(html.contains("<title>&lt;title&gt;</title>"));
+        assert!(html.contains("<span class=\"tag-x-y\">x&amp;y</span>"));
+        assert!(html.contains(
+            "<span class=\"tag-x-y\" title=\"x&amp;y\">a&lt;b</span> &amp; &quot;c&quot;</div>"
+        ));
+    }
+
+    #[test]
+    fn overlapping_tags_split_the_text_into_spans() {
+        let ranges = [range("a", 0..4), range("b", 2..6), range("a", 1..3)];
+        let html = html("t", "abcdefg", &ranges, &tags(&["a", "b"]));
+        let body = &html[html.find("<div class=\"buffer\">").unwrap()..];
+        assert_eq!(
+            body,
+            "<div class=\"buffer\">\
+             <span class=\"tag-a\" title=\"a\">ab</span>\
+             <span class=\"tag-a tag-b\" title=\"a, b\">cd</span>\
+             <span class=\"tag-b\" title=\"b\">ef</span>\
+             g</div>\n</body>\n</html>\n"
+        );
+    }
+
+    #[test]
+    fn tag_classes_stay_unique() {
+        let tags = tags(&["Work Stuff", "work-stuff", "!!"]);
+        let classes: Vec<String> = tag_classes(&tags).into_values().collect();
+        assert_eq!(classes, ["tag-work-stuff", "tag-work-stuff-1", "tag-"]);
+    }
+}
@@ -0,0 +1,44 @@
+use eframe::egui;
+use tracing::info;
+
+use crate::Taskmonger;
+
+pub fn default_focus_width() -> f32 {
+    720.0
+}
+
+/// Space left of the text column that centers it in the distraction-free mode
+pub fn focus_margin(available: f32, column: f32) -> f32 {
+    ((available - column) / 2.0).max(0.0)
+}
+
+/// Keeps the cursor's line in the middle of the editor while typing or moving it
+pub fn typewriter_scroll(ui: &egui::Ui, output: &egui::text_edit::TextEditOutput, moved: bool) {
+    let Some(range) = output.cursor_range else {
+        return;
+    };
+    if !moved || !output.response.has_focus() {
+        return;
+    }
+    let rect = output
+        .galley
+        .pos_from_cursor(range.primary)
+        .translate(output.galley_pos.to_vec2());
+    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+}
+
+impl Taskmonger {
+    /// F11 toggles the distraction-free mode: full screen, no panels, a centered text column.
+    /// The panels come back as they were on exit.
+    pub fn handle_focus_mode(&mut self, ctx: &egui::Context) {
+        if !ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::F11)) {
+            return;
+        }
+        self.focus_mode = !self.focus_mode;
+        info!(on = self.focus_mode, "Distraction-free mode");
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(self.focus_mode));
+        if self.focus_mode {
+            self.toasts.info("F11 brings the panels back");
+        }
+    }
+}
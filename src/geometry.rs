@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use egui::containers::panel::PanelState;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::split::SplitView;
+use crate::Taskmonger;
+
+/// Time the geometry has to stay put before it is saved, so dragging does not write every frame
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// Side panels by id whose width is kept, and bottom panels whose height is
+const SIDE_PANELS: [&str; 2] = ["tags_panel", "markdown_view_panel"];
+const BOTTOM_PANELS: [&str; 1] = ["scratchpad"];
+
+/// Window size and position, panel sizes and open windows of the last session
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Geometry {
+    /// In logical points, as the viewport builder takes them
+    pub inner_size: Option<[f32; 2]>,
+    pub position: Option<[f32; 2]>,
+    pub maximized: bool,
+    /// Width of side panels and height of bottom panels, by panel id
+    pub panels: BTreeMap<String, f32>,
+    /// Tool windows that were open
+    pub windows: Vec<String>,
+}
+
+impl Geometry {
+    /// The geometry in the state file, read before the app starts to size the window
+    pub fn load() -> Self {
+        #[derive(Deserialize, Default)]
+        struct State {
+            #[serde(default)]
+            settings: StateSettings,
+        }
+        #[derive(Deserialize, Default)]
+        struct StateSettings {
+            #[serde(default)]
+            geometry: Geometry,
+        }
+        fs::read_to_string(Taskmonger::save_path())
+            .ok()
+            .and_then(|json| serde_json::from_str::<State>(&json).ok())
+            .unwrap_or_default()
+            .settings
+            .geometry
+    }
+
+    /// `viewport` sized and placed like last time
+    pub fn apply(&self, mut viewport: egui::ViewportBuilder) -> egui::ViewportBuilder {
+        if let Some(size) = self.inner_size {
+            viewport = viewport.with_inner_size(size);
+        }
+        if let Some(position) = self.position {
+            viewport = viewport.with_position(position);
+        }
+        viewport.with_maximized(self.maximized)
+    }
+
+    /// Saved size of the panel, or `default`
+    pub fn panel(&self, id: &str, default: f32) -> f32 {
+        self.panels.get(id).copied().unwrap_or(default)
+    }
+
+    /// Sizes of the panels shown so far this session, the others keep their saved size
+    fn read_panels(&mut self, ctx: &egui::Context, split: Option<SplitView>) {
+        let mut read = |id: &str, key: &str, side: bool| {
+            if let Some(state) = PanelState::load(ctx, egui::Id::new(id)) {
+                let size = if side {
+                    state.rect.width()
+                } else {
+                    state.rect.height()
+                };
+                self.panels.insert(key.to_string(), size.round());
+            }
+        };
+        for id in SIDE_PANELS {
+            read(id, id, true);
+        }
+        for id in BOTTOM_PANELS {
+            read(id, id, false);
+        }
+        // Both split panels share an id, only the one on screen has a current size
+        if let Some(split) = split {
+            read("split_view", split.key(), split == SplitView::SideBySide);
+        }
+    }
+}
+
+impl Taskmonger {
+    /// Tool windows whose open state is kept across sessions, by name
+    fn remembered_windows(&mut self) -> [(&'static str, &mut bool); 5] {
+        [
+            ("outline", &mut self.outline_open),
+            ("bookmarks", &mut self.bookmarks_open),
+            ("time_summary", &mut self.time_summary_open),
+            ("rules", &mut self.rules_open),
+            ("sync", &mut self.sync_open),
+        ]
+    }
+
+    /// Opens the tool windows that were open when the app was last closed
+    pub fn restore_windows(&mut self) {
+        let windows = self.settings.geometry.windows.clone();
+        for (name, open) in self.remembered_windows() {
+            *open = windows.iter().any(|w| w == name);
+        }
+    }
+
+    /// Keeps the window and panel geometry in the settings, saved once it settles
+    pub fn remember_geometry(&mut self, ctx: &egui::Context) {
+        if self.safe_mode {
+            return;
+        }
+        let mut geometry = self.settings.geometry.clone();
+        let (inner, outer, maximized, fullscreen, minimized, dragging) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.inner_rect,
+                viewport.outer_rect,
+                viewport.maximized.unwrap_or(false),
+                viewport.fullscreen.unwrap_or(false),
+                viewport.minimized.unwrap_or(false),
+                i.pointer.any_down(),
+            )
+        });
+        // Resizing panels is only done once the mouse lets go
+        if dragging {
+            return;
+        }
+        // Full screen is the distraction-free mode, not a size to come back to
+        if !fullscreen && !minimized && !self.focus_mode {
+            geometry.maximized = maximized;
+            // Restoring a maximized window should bring back the size it had before
+            if !maximized {
+                let zoom = ctx.zoom_factor();
+                if let Some(inner) = inner {
+                    let size = inner.size() * zoom;
+                    geometry.inner_size = Some([size.x.round(), size.y.round()]);
+                }
+                if let Some(outer) = outer {
+                    let min = outer.min * zoom;
+                    geometry.position = Some([min.x.round(), min.y.round()]);
+                }
+            }
+        }
+        geometry.read_panels(ctx, self.settings.split_view);
+        geometry.windows = self
+            .remembered_windows()
+            .into_iter()
+            .filter(|(_, open)| **open)
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        if geometry != self.settings.geometry {
+            self.settings.geometry = geometry;
+            self.geometry_changed = Some(Instant::now());
+        }
+        let Some(changed) = self.geometry_changed else {
+            return;
+        };
+        let closing = ctx.input(|i| i.viewport().close_requested());
+        if changed.elapsed() >= SETTLE || closing {
+            debug!(geometry = ?self.settings.geometry, "Saving window geometry");
+            self.geometry_changed = None;
+            let _ = self.save_to_disk();
+        } else {
+            ctx.request_repaint_after(SETTLE - changed.elapsed());
+        }
+    }
+}
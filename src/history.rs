@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use eframe::egui;
+use egui::RichText;
+use egui_phosphor::regular::ARROW_COUNTER_CLOCKWISE;
+use git2::{Commit, IndexAddOption, Oid, Repository, Signature};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::sync::line_hunks;
+use crate::{Tag, TaggedRange, Taskmonger};
+
+/// The buffer as text, to read the history with git as well
+const BUFFER_FILE: &str = "buffer.md";
+
+const STATE_FILE: &str = "state.json";
+
+/// Saves are committed this long after the first of them, so typing doesn't make a
+/// commit per autosave
+const COMMIT_SECS: u64 = 30;
+
+/// Commits within this long of the first save in the last commit go into it
+const AMEND_SECS: i64 = 300;
+
+/// Revisions listed in the history window
+const LIST_LIMIT: usize = 200;
+
+/// What a revision is restored from, the rest of its state stays as it is
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Revision {
+    buffer: String,
+    tags: IndexMap<String, Tag>,
+    tagged_ranges: Vec<TaggedRange>,
+}
+
+impl Revision {
+    /// Rough size in memory
+    fn estimated_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.buffer.len()
+            + self
+                .tags
+                .keys()
+                .map(|name| name.len() + size_of::<Tag>())
+                .sum::<usize>()
+            + self
+                .tagged_ranges
+                .iter()
+                .map(TaggedRange::estimated_bytes)
+                .sum::<usize>()
+    }
+}
+
+/// Revisions read for the history window, dropping the least recently used beyond a limit
+#[derive(Default)]
+pub struct RevisionCache {
+    /// Revision, when it was last used and its estimated size, by commit
+    entries: HashMap<Oid, (Revision, u64, usize)>,
+    tick: u64,
+}
+
+impl RevisionCache {
+    /// The revision of commit `id`, read from `repo` unless it is in memory already
+    fn get(&mut self, repo: &Repository, id: Oid, limit: usize) -> Option<&Revision> {
+        self.tick += 1;
+        if !self.entries.contains_key(&id) {
+            let revision = revision(repo, &repo.find_commit(id).ok()?).ok()?;
+            let bytes = revision.estimated_bytes();
+            self.entries.insert(id, (revision, self.tick, bytes));
+            self.evict(limit);
+        }
+        let entry = self.entries.get_mut(&id)?;
+        entry.1 = self.tick;
+        Some(&entry.0)
+    }
+
+    /// Drops the least recently used revisions beyond `limit`
+    fn evict(&mut self, limit: usize) {
+        if self.entries.len() <= limit {
+            return;
+        }
+        let mut by_age: Vec<(u64, Oid)> = self
+            .entries
+            .iter()
+            .map(|(id, (_, used, _))| (*used, *id))
+            .collect();
+        by_age.sort();
+        for (_, id) in by_age.into_iter().take(self.entries.len() - limit) {
+            self.entries.remove(&id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.entries.values().map(|(_, _, bytes)| bytes).sum()
+    }
+}
+
+/// A commit as the history window lists it
+struct Entry {
+    id: Oid,
+    time: DateTime<Local>,
+    message: String,
+}
+
+fn signature() -> Result<Signature<'static>, git2::Error> {
+    Signature::now("Taskmonger", "taskmonger@localhost")
+}
+
+fn commit_time(commit: &Commit) -> DateTime<Local> {
+    DateTime::from_timestamp(commit.time().seconds(), 0)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+/// The revision a commit holds
+fn revision(repo: &Repository, commit: &Commit) -> Result<Revision, Box<dyn std::error::Error>> {
+    let entry = commit.tree()?.get_path(Path::new(STATE_FILE))?;
+    let blob = repo.find_blob(entry.id())?;
+    Ok(serde_json::from_slice(blob.content())?)
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("1 {word}")
+    } else {
+        format!("{count} {word}s")
+    }
+}
+
+/// Says what changed from `old` to `new`: lines, tags and tagged ranges
+fn describe_changes(old: Option<&Revision>, new: &Revision) -> String {
+    let Some(old) = old else {
+        return "Start the history".to_string();
+    };
+    let mut parts = vec![];
+    let old_lines: Vec<&str> = old.buffer.lines().collect();
+    let new_lines: Vec<&str> = new.buffer.lines().collect();
+    let (removed, added) = line_hunks(&old_lines, &new_lines)
+        .iter()
+        .fold((0, 0), |(removed, added), (old, new)| {
+            (removed + old.len(), added + new.len())
+        });
+    match (removed, added) {
+        (0, 0) => {}
+        (0, added) => parts.push(format!("add {}", plural(added, "line"))),
+        (removed, 0) => parts.push(format!("remove {}", plural(removed, "line"))),
+        (removed, added) if removed == added => {
+            parts.push(format!("edit {}", plural(added, "line")))
+        }
+        (removed, added) => parts.push(format!("edit lines (+{added} -{removed})")),
+    }
+    for name in new.tags.keys().filter(|name| !old.tags.contains_key(*name)) {
+        parts.push(format!("add tag '{name}'"));
+    }
+    for name in old.tags.keys().filter(|name| !new.tags.contains_key(*name)) {
+        parts.push(format!("remove tag '{name}'"));
+    }
+    let keys = |ranges: &[TaggedRange]| -> HashSet<(String, chrono::NaiveDateTime)> {
+        ranges.iter().map(TaggedRange::key).collect()
+    };
+    let (old_keys, new_keys) = (keys(&old.tagged_ranges), keys(&new.tagged_ranges));
+    let tagged = new_keys.difference(&old_keys).count();
+    let untagged = old_keys.difference(&new_keys).count();
+    if tagged > 0 {
+        parts.push(format!("tag {}", plural(tagged, "range")));
+    }
+    if untagged > 0 {
+        parts.push(format!("untag {}", plural(untagged, "range")));
+    }
+    if parts.is_empty() {
+        return "Change ranges and settings".to_string();
+    }
+    let message = parts.join(", ");
+    message[..1].to_uppercase() + &message[1..]
+}
+
+impl Taskmonger {
+    /// The git repository of the history, next to the state file
+    pub fn history_dir() -> PathBuf {
+        Self::save_path().with_file_name("history")
+    }
+
+    /// Commits the state `json` to the history, if it changed. Without a `message` it
+    /// goes into the last commit while that was started recently, with one made up from
+    /// the changes.
+    pub fn commit_history(&self, json: &str, message: Option<String>) -> Result<(), git2::Error> {
+        let dir = Self::history_dir();
+        let repo = match Repository::open(&dir) {
+            Ok(repo) => repo,
+            Err(_) => {
+                info!("Starting the history in {}", dir.display());
+                Repository::init(&dir)?
+            }
+        };
+        let io = |e: std::io::Error| git2::Error::from_str(&e.to_string());
+        fs::write(dir.join(BUFFER_FILE), &self.buffer).map_err(io)?;
+        fs::write(dir.join(STATE_FILE), json).map_err(io)?;
+        let mut index = repo.index()?;
+        index.add_all([BUFFER_FILE, STATE_FILE], IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+
+        let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        if head
+            .as_ref()
+            .is_some_and(|head| head.tree_id() == tree.id())
+        {
+            return Ok(());
+        }
+        let now = Local::now().timestamp();
+        // Amending keeps the author, whose time is when the commit was started
+        let amend = head.as_ref().filter(|head| {
+            message.is_none()
+                && now - head.author().when().seconds() < AMEND_SECS
+                && !head.message().unwrap_or_default().starts_with("Restore")
+        });
+        let parent = match amend {
+            Some(head) => head.parents().next(),
+            None => head.clone(),
+        };
+        let message = message.unwrap_or_else(|| {
+            let old = parent
+                .as_ref()
+                .and_then(|parent| revision(&repo, parent).ok());
+            let new = Revision {
+                buffer: self.buffer.clone(),
+                tags: self.tags.clone(),
+                tagged_ranges: self.tagged_ranges.clone(),
+            };
+            describe_changes(old.as_ref(), &new)
+        });
+        let signature = signature()?;
+        match amend {
+            Some(head) => {
+                head.amend(
+                    Some("HEAD"),
+                    None,
+                    Some(&signature),
+                    None,
+                    Some(&message),
+                    Some(&tree),
+                )?;
+            }
+            None => {
+                let parents: Vec<&Commit> = head.iter().collect();
+                repo.commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &message,
+                    &tree,
+                    &parents,
+                )?;
+            }
+        }
+        debug!("Committed '{message}' to the history");
+        Ok(())
+    }
+
+    /// Commits the saves since the last commit, once they are old enough or right away
+    /// with `now`
+    fn commit_saves(&self, now: bool) -> bool {
+        let Some(since) = self.history_since.get() else {
+            return false;
+        };
+        if !now && since.elapsed() < Duration::from_secs(COMMIT_SECS) {
+            return false;
+        }
+        self.history_since.set(None);
+        if !self.settings.git_history || self.safe_mode {
+            return true;
+        }
+        let committed = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                self.commit_history(&json, None)
+                    .map_err(|e| e.message().to_string())
+            });
+        if let Err(e) = committed {
+            warn!("Committing to the history failed: {e}");
+        }
+        true
+    }
+
+    /// Commits pending saves to the history when they are due or the window closes
+    pub fn flush_history(&self, ctx: &egui::Context) {
+        let closing = ctx.input(|i| i.viewport().close_requested());
+        if !self.commit_saves(closing) {
+            if let Some(since) = self.history_since.get() {
+                let wait = Duration::from_secs(COMMIT_SECS).saturating_sub(since.elapsed());
+                ctx.request_repaint_after(wait);
+            }
+        }
+    }
+
+    /// The latest revisions, newest first
+    fn history_entries(repo: &Repository) -> Result<Vec<Entry>, git2::Error> {
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        walk.take(LIST_LIMIT)
+            .map(|id| {
+                let commit = repo.find_commit(id?)?;
+                Ok(Entry {
+                    id: commit.id(),
+                    time: commit_time(&commit),
+                    message: commit.summary().unwrap_or_default().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Puts back the buffer, tags and ranges of a revision. The restore is a revision of
+    /// its own, so it can be undone from the history as well.
+    pub fn restore_revision(&mut self, id: Oid) -> Result<(), Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let repo = Repository::open(Self::history_dir())?;
+        let commit = repo.find_commit(id)?;
+        let revision = revision(&repo, &commit)?;
+        self.buffer = revision.buffer;
+        self.tags = revision.tags;
+        self.tagged_ranges = revision.tagged_ranges;
+        let len = self.buffer.chars().count();
+        self.bookmarks.retain(|bookmark| bookmark.at <= len);
+        self.folds.clear();
+        self.cursor = self.cursor.min(len);
+        self.select = Some(self.cursor..self.cursor);
+        self.clean_invalid_ranges();
+
+        let message = format!(
+            "Restore the revision of {}",
+            commit_time(&commit).format("%b %d %H:%M")
+        );
+        info!("{message} ({id})");
+        let json = serde_json::to_string_pretty(&*self)?;
+        self.commit_history(&json, Some(message))?;
+        let _ = self.save_to_disk();
+        Ok(())
+    }
+
+    /// Revisions of the history to look at and restore
+    pub fn history_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.history_open;
+        let mut restore = None;
+        egui::Window::new("History")
+            .open(&mut open)
+            .default_width(460.0)
+            .show(ctx, |ui| {
+                if !self.settings.git_history {
+                    ui.label("The history is off, the settings turn it on.");
+                    return;
+                }
+                // The list shows the latest saves as well
+                self.commit_saves(true);
+                let entries = Repository::open(Self::history_dir())
+                    .and_then(|repo| Ok((Self::history_entries(&repo)?, repo)));
+                let (entries, repo) = match entries {
+                    Ok(found) => found,
+                    Err(e) => {
+                        ui.label(format!("No history yet: {}", e.message()));
+                        return;
+                    }
+                };
+                let selected_id = egui::Id::new("history_selected");
+                let mut selected = ui
+                    .data(|d| d.get_temp::<String>(selected_id))
+                    .and_then(|id| Oid::from_str(&id).ok());
+
+                egui::ScrollArea::vertical()
+                    .id_salt("history_list")
+                    .max_height(220.0)
+                    .show(ui, |ui| {
+                        for entry in &entries {
+                            ui.horizontal(|ui| {
+                                let time = entry.time.format("%b %d %H:%M").to_string();
+                                ui.label(RichText::new(time).weak());
+                                let chosen = selected == Some(entry.id);
+                                if ui.selectable_label(chosen, &entry.message).clicked() {
+                                    selected = Some(entry.id);
+                                }
+                            });
+                        }
+                    });
+
+                let limit = self.settings.snapshot_cache_limit;
+                let preview = selected.and_then(|id| self.revisions.get(&repo, id, limit));
+                if let (Some(id), Some(preview)) = (selected, preview) {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{}, {} tags, {} ranges",
+                            plural(preview.buffer.lines().count(), "line"),
+                            preview.tags.len(),
+                            preview.tagged_ranges.len()
+                        ));
+                        if ui
+                            .add_enabled(
+                                !self.locked,
+                                egui::Button::new(format!("{ARROW_COUNTER_CLOCKWISE} Restore")),
+                            )
+                            .on_hover_text("Replace the buffer, tags and ranges with this revision")
+                            .clicked()
+                        {
+                            restore = Some(id);
+                        }
+                    });
+                    egui::ScrollArea::vertical()
+                        .id_salt("history_preview")
+                        .max_height(260.0)
+                        .show(ui, |ui| {
+                            ui.label(RichText::new(&preview.buffer).monospace());
+                        });
+                }
+                ui.data_mut(|d| {
+                    d.insert_temp(
+                        selected_id,
+                        selected.map(|id| id.to_string()).unwrap_or_default(),
+                    )
+                });
+            });
+        self.history_open = open;
+
+        if let Some(id) = restore {
+            match self.restore_revision(id) {
+                Ok(()) => self.toasts.success("Restored the revision"),
+                Err(e) => self
+                    .toasts
+                    .error(format!("Could not restore the revision: {e}")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revisions_beyond_the_limit_go_least_recently_used_first() {
+        let mut cache = RevisionCache::default();
+        let ids: Vec<Oid> = (1..=3u8)
+            .map(|n| Oid::from_bytes(&[n; 20]).unwrap())
+            .collect();
+        for (used, id) in [(1, ids[0]), (3, ids[1]), (2, ids[2])] {
+            cache.entries.insert(id, (Revision::default(), used, 10));
+        }
+        cache.evict(2);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.entries.contains_key(&ids[0]));
+        assert_eq!(cache.bytes(), 20);
+    }
+}
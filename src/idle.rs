@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use tracing::info;
+
+use crate::{Taskmonger, TimeEntry};
+
+/// How often background checks run while nobody is at the keyboard
+const IDLE_POLL: Duration = Duration::from_secs(30);
+
+/// Input activity, to pause timers while away
+pub struct Idle {
+    last_activity: Instant,
+    idle: bool,
+    /// Range whose timer was paused for being idle, by key
+    paused: Option<(String, chrono::NaiveDateTime)>,
+}
+
+impl Default for Idle {
+    fn default() -> Self {
+        Self {
+            last_activity: Instant::now(),
+            idle: false,
+            paused: None,
+        }
+    }
+}
+
+impl Idle {
+    /// `interval`, or a longer one while idle
+    pub fn poll_interval(&self, interval: Duration) -> Duration {
+        if self.idle {
+            interval.max(IDLE_POLL)
+        } else {
+            interval
+        }
+    }
+}
+
+impl Taskmonger {
+    /// Pauses the running timer after the idle time without input and resumes it on activity.
+    /// Only input to this window counts.
+    pub fn handle_idle(&mut self, ctx: &egui::Context) {
+        let active = ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving());
+        if active {
+            self.idle.last_activity = Instant::now();
+            if self.idle.idle {
+                self.idle.idle = false;
+                self.resume_idle_timer();
+            }
+            return;
+        }
+
+        let minutes = self.settings.idle_minutes;
+        if minutes == 0 || self.idle.idle {
+            return;
+        }
+        let limit = Duration::from_secs(minutes as u64 * 60);
+        let since = self.idle.last_activity.elapsed();
+        if since < limit {
+            // Wake up in time to notice, running timers repaint anyway
+            ctx.request_repaint_after(limit - since);
+            return;
+        }
+
+        self.idle.idle = true;
+        info!("Idle for {minutes} min");
+        // The time away is not tracked, the session ends with the last input
+        let away = chrono::Duration::from_std(since).unwrap_or_default();
+        let end = chrono::Local::now().naive_local() - away;
+        if let Some(tr) = self
+            .tagged_ranges
+            .iter_mut()
+            .find(|tr| tr.timer_started.is_some())
+        {
+            if let Some(start) = tr.timer_started.take() {
+                tr.time_entries.push(TimeEntry {
+                    start,
+                    end: end.max(start),
+                });
+            }
+            self.idle.paused = Some(tr.key());
+        }
+        // Nothing new gets typed while away, write what is pending
+        if self.unsaved_since.get().is_some() || self.idle.paused.is_some() {
+            let _ = self.save_to_disk();
+        }
+    }
+
+    fn resume_idle_timer(&mut self) {
+        let Some(key) = self.idle.paused.take() else {
+            return;
+        };
+        if let Some(tr) = self.tagged_ranges.iter_mut().find(|tr| tr.key() == key) {
+            tr.start_timer();
+            info!("Resumed the timer after being idle");
+            self.toasts
+                .info("Timer paused while you were away and resumed");
+            let _ = self.save_to_disk();
+        }
+    }
+}
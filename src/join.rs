@@ -0,0 +1,207 @@
+use std::ops::Range;
+
+use eframe::egui;
+use egui::RichText;
+use tracing::info;
+
+use crate::tools::char_to_byte_range;
+use crate::Taskmonger;
+
+/// Options of the "Join nearby ranges" dialog
+pub struct JoinRanges {
+    pub tag: String,
+    pub gap: usize,
+    /// Measure the gap in lines instead of characters
+    pub lines: bool,
+}
+
+impl JoinRanges {
+    pub fn new(tag: String) -> Self {
+        Self {
+            tag,
+            gap: 1,
+            lines: true,
+        }
+    }
+}
+
+impl Taskmonger {
+    /// Groups of indices into the tagged ranges that would be joined, in buffer order.
+    /// Only groups of two or more are returned.
+    fn join_plan(&self, join: &JoinRanges) -> Vec<Vec<usize>> {
+        let mut indices: Vec<usize> = (0..self.tagged_ranges.len())
+            .filter(|&i| self.tagged_ranges[i].tag_name == join.tag)
+            .collect();
+        indices.sort_by_key(|&i| self.tagged_ranges[i].range.start);
+
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let mut end = 0;
+        for i in indices {
+            let range = &self.tagged_ranges[i].range;
+            let close = match groups.last() {
+                Some(_) if range.start <= end => true,
+                Some(_) => {
+                    let between = end..range.start;
+                    if join.lines {
+                        self.buffer[char_to_byte_range(&self.buffer, &between)]
+                            .matches('\n')
+                            .count()
+                            <= join.gap
+                    } else {
+                        between.len() <= join.gap
+                    }
+                }
+                None => false,
+            };
+            match groups.last_mut() {
+                Some(group) if close => group.push(i),
+                _ => groups.push(vec![i]),
+            }
+            end = if close { end.max(range.end) } else { range.end };
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
+    fn apply_join(&mut self, plan: Vec<Vec<usize>>) {
+        let mut remove = vec![];
+        for group in &plan {
+            let (first, rest) = group.split_first().expect("groups have two or more ranges");
+            for &i in rest {
+                let other = self.tagged_ranges[i].clone();
+                self.tagged_ranges[*first].absorb(other);
+            }
+            remove.extend_from_slice(rest);
+        }
+        remove.sort_unstable();
+        for i in remove.into_iter().rev() {
+            self.tagged_ranges.remove(i);
+        }
+        info!("Joined ranges into {} range(s)", plan.len());
+        self.toasts
+            .success(format!("Joined ranges into {} range(s)", plan.len()));
+        let _ = self.save_to_disk();
+    }
+
+    /// Dialog to join the ranges of a tag lying close together, with a preview
+    pub fn join_window(&mut self, ctx: &egui::Context) {
+        let Some(join) = &self.join_ranges else {
+            return;
+        };
+        let plan = self.join_plan(join);
+        let previews: Vec<(usize, String)> = plan
+            .iter()
+            .map(|group| {
+                let hull: Range<usize> = group
+                    .iter()
+                    .map(|&i| self.tagged_ranges[i].range.clone())
+                    .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+                    .unwrap_or_default();
+                (group.len(), self.preview(&hull))
+            })
+            .collect();
+
+        let Some(join) = &mut self.join_ranges else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new(format!("Join nearby '{}' ranges", join.tag))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("At most");
+                    ui.add(egui::DragValue::new(&mut join.gap).range(0..=10_000));
+                    egui::ComboBox::from_id_salt("join_unit")
+                        .selected_text(if join.lines { "lines" } else { "characters" })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut join.lines, true, "lines");
+                            ui.selectable_value(&mut join.lines, false, "characters");
+                        });
+                    ui.label("apart");
+                });
+                ui.separator();
+                if previews.is_empty() {
+                    ui.label("No ranges are close enough to join.");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for (count, preview) in &previews {
+                            ui.label(format!("{count} ranges into one:"));
+                            ui.label(RichText::new(preview).weak());
+                        }
+                    });
+                let button = egui::Button::new(format!("Join {} group(s)", previews.len()));
+                if ui.add_enabled(!previews.is_empty(), button).clicked() {
+                    apply = true;
+                }
+            });
+        if apply {
+            self.apply_join(plan);
+            open = false;
+        }
+        if !open {
+            self.join_ranges = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaggedRange;
+
+    /// "one" to "four" on lines of their own, "two" and "three" with a blank line between
+    fn app() -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: "one\ntwo\n\nthree\nfour".to_string(),
+            ..Default::default()
+        };
+        app.tagged_ranges = vec![
+            TaggedRange::new("t".to_string(), 9..14),
+            TaggedRange::new("t".to_string(), 0..3),
+            TaggedRange::new("other".to_string(), 15..19),
+            TaggedRange::new("t".to_string(), 4..7),
+        ];
+        app
+    }
+
+    fn plan(app: &Taskmonger, gap: usize, lines: bool) -> Vec<Vec<usize>> {
+        app.join_plan(&JoinRanges {
+            tag: "t".to_string(),
+            gap,
+            lines,
+        })
+    }
+
+    #[test]
+    fn ranges_within_the_line_gap_are_grouped_in_buffer_order() {
+        let app = app();
+        assert_eq!(plan(&app, 1, true), [vec![1, 3]]);
+        assert_eq!(plan(&app, 2, true), [vec![1, 3, 0]]);
+        assert!(plan(&app, 0, true).is_empty());
+    }
+
+    #[test]
+    fn the_gap_can_be_measured_in_chars() {
+        let app = app();
+        assert_eq!(plan(&app, 1, false), [vec![1, 3]]);
+        assert_eq!(plan(&app, 2, false), [vec![1, 3, 0]]);
+    }
+
+    #[test]
+    fn overlapping_ranges_are_always_close() {
+        let mut app = app();
+        app.tagged_ranges = vec![
+            TaggedRange::new("t".to_string(), 0..9),
+            TaggedRange::new("t".to_string(), 4..7),
+            TaggedRange::new("t".to_string(), 15..19),
+        ];
+        // The gap is measured from the end of the first, longer range
+        assert_eq!(plan(&app, 0, false), [vec![0, 1]]);
+        assert_eq!(plan(&app, 6, false), [vec![0, 1, 2]]);
+    }
+}
@@ -0,0 +1,190 @@
+use std::ops::Range;
+
+use eframe::egui;
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use crate::tools::{line_starts, mix_colors, subtree, to_color32};
+use crate::Taskmonger;
+
+/// Space left of the text for the bars of whole-line tags
+pub const LINE_BAR_SPACE: f32 = 7.0;
+
+/// How ranges of whole-line tags show up in the editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineTagStyle {
+    /// Background across the full width of the editor
+    #[default]
+    Stripe,
+    /// Colored bar left of the text
+    Bar,
+    /// Char by char, like any other tag
+    Text,
+}
+
+/// 0-based line of char `at`
+pub fn line_of(starts: &[usize], at: usize) -> usize {
+    starts
+        .partition_point(|&start| start <= at)
+        .saturating_sub(1)
+}
+
+/// Chars of `lines`, without the final line break
+pub fn line_chars(starts: &[usize], total: usize, lines: &Range<usize>) -> Range<usize> {
+    let start = starts.get(lines.start).copied().unwrap_or(total);
+    let end = starts.get(lines.end).map_or(total, |next| next - 1);
+    start..end.max(start)
+}
+
+/// Stripes or bars for the rows of `output` on screen that lie in `line_tags`.
+/// Bars go into the [`LINE_BAR_SPACE`] starting at `bar_left`.
+pub fn line_tag_shapes(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    line_tags: &[(Range<usize>, Color32)],
+    style: LineTagStyle,
+    bar_left: f32,
+) -> Vec<egui::Shape> {
+    let clip = ui.clip_rect();
+    let mut shapes = vec![];
+    let mut at = 0;
+    for row in &output.galley.rows {
+        let rect = row.rect().translate(output.galley_pos.to_vec2());
+        if rect.top() > clip.bottom() {
+            break;
+        }
+        let row_start = at;
+        at += row.char_count_including_newline();
+        if rect.bottom() < clip.top() {
+            continue;
+        }
+        let colors: Vec<Color32> = line_tags
+            .iter()
+            .filter(|(range, _)| range.contains(&row_start))
+            .map(|(_, color)| *color)
+            .collect();
+        if colors.is_empty() {
+            continue;
+        }
+        let rows = rect.top()..=rect.bottom();
+        match style {
+            LineTagStyle::Stripe => {
+                let Some(color) = colors.into_iter().reduce(mix_colors) else {
+                    continue;
+                };
+                let stripe = egui::Rect::from_x_y_ranges(rect.left()..=clip.right(), rows);
+                shapes.push(egui::Shape::rect_filled(
+                    stripe,
+                    0.0,
+                    color.gamma_multiply(0.3),
+                ));
+            }
+            LineTagStyle::Bar => {
+                // Side by side when several tags share the line
+                let width = (LINE_BAR_SPACE - 3.0) / colors.len() as f32;
+                for (i, color) in colors.into_iter().enumerate() {
+                    let x = bar_left + 1.0 + i as f32 * width;
+                    let bar = egui::Rect::from_x_y_ranges(x..=x + width, rows.clone());
+                    shapes.push(egui::Shape::rect_filled(bar, 0.0, color));
+                }
+            }
+            LineTagStyle::Text => {}
+        }
+    }
+    shapes
+}
+
+impl Taskmonger {
+    /// Ranges of whole-line tags and their colors, for drawing them line by line.
+    /// Empty when they are drawn like other tags.
+    pub fn line_tag_colors(&self, style: LineTagStyle) -> Vec<(Range<usize>, Color32)> {
+        if style == LineTagStyle::Text || self.print_preview || self.colormap.busy() {
+            return vec![];
+        }
+        self.tagged_ranges
+            .iter()
+            .filter_map(|tr| {
+                let tag = self.tags.get(&tr.tag_name).filter(|t| t.whole_lines)?;
+                Some((tr.range.clone(), to_color32(tag.color)))
+            })
+            .collect()
+    }
+
+    /// Grows ranges of tags that take in sub-items over the lines below their first line
+    /// that are indented deeper, and shrinks them back when those lines are outdented
+    pub fn inherit_indented_lines(&mut self) {
+        if !self.tags.values().any(|t| t.inherit_indented) {
+            return;
+        }
+        let starts = line_starts(&self.buffer);
+        let lines: Vec<&str> = self.buffer.split('\n').collect();
+        let total = self.buffer.chars().count();
+        for tr in &mut self.tagged_ranges {
+            let inherit = self
+                .tags
+                .get(&tr.tag_name)
+                .is_some_and(|t| t.inherit_indented);
+            if !inherit || tr.range.is_empty() {
+                continue;
+            }
+            let head = line_of(&starts, tr.range.start);
+            let last = subtree(&lines, head).end - 1;
+            let head_end = line_chars(&starts, total, &(head..head + 1)).end;
+            let end = if last > head {
+                line_chars(&starts, total, &(last..last + 1)).end
+            } else {
+                tr.range.end.min(head_end)
+            };
+            if end != tr.range.end {
+                trace!(tag = tr.tag_name, before = ?tr.range, end, "Sub-items changed");
+                tr.range.end = end;
+                tr.mark();
+            }
+        }
+    }
+
+    fn has_line_tags(&self) -> bool {
+        self.tags.values().any(|t| t.whole_lines)
+    }
+
+    /// Widens ranges of whole-line tags to full lines and records their line numbers
+    pub fn snap_line_ranges(&mut self) {
+        if !self.has_line_tags() && self.tagged_ranges.iter().all(|tr| tr.lines.is_none()) {
+            return;
+        }
+        let starts = line_starts(&self.buffer);
+        let total = self.buffer.chars().count();
+        for tr in &mut self.tagged_ranges {
+            let whole_lines = self.tags.get(&tr.tag_name).is_some_and(|t| t.whole_lines);
+            if !whole_lines || tr.range.is_empty() {
+                tr.lines = None;
+                continue;
+            }
+            let lines = line_of(&starts, tr.range.start)..line_of(&starts, tr.range.end - 1) + 1;
+            let range = line_chars(&starts, total, &lines);
+            if range != tr.range {
+                trace!(tag = tr.tag_name, before = ?tr.range, after = ?range, "Snapped to lines");
+                tr.range = range;
+            }
+            tr.lines = Some(lines);
+        }
+    }
+
+    /// Puts ranges of whole-line tags back on the lines they were saved with
+    pub fn restore_line_ranges(&mut self) {
+        if !self.has_line_tags() {
+            return;
+        }
+        let starts = line_starts(&self.buffer);
+        let total = self.buffer.chars().count();
+        for tr in &mut self.tagged_ranges {
+            let whole_lines = self.tags.get(&tr.tag_name).is_some_and(|t| t.whole_lines);
+            if let (true, Some(lines)) = (whole_lines, &tr.lines) {
+                if lines.start < starts.len() {
+                    tr.range = line_chars(&starts, total, lines);
+                }
+            }
+        }
+    }
+}
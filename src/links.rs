@@ -0,0 +1,275 @@
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use eframe::egui;
+use regex::Regex;
+use tracing::info;
+
+use crate::{TaggedRange, Taskmonger};
+
+/// Folder names, links and the lists that nest them in a browser's bookmarks export
+static TOKENS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<h3[^>]*>(.*?)</h3>|<a\s([^>]*)>(.*?)</a>|<dl\b|</dl>").expect("valid regex")
+});
+static HREF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)\bhref\s*=\s*"([^"]*)""#).expect("valid regex"));
+static MARKUP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+
+/// A link of the bookmarks file and the folder it is in
+#[derive(Debug, PartialEq)]
+struct Link {
+    title: String,
+    url: String,
+    folder: Option<String>,
+}
+
+/// Text of the export's markup: tags dropped, entities decoded and whitespace collapsed
+fn text(html: &str) -> String {
+    let text = MARKUP.replace_all(html, "");
+    let mut out = String::new();
+    let mut rest = text.as_ref();
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end]);
+        let decoded = match entity {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some("nbsp") => Some(' '),
+            Some(code) if code.starts_with("#x") || code.starts_with("#X") => {
+                u32::from_str_radix(&code[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+            }
+            Some(code) if code.starts_with('#') => code[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match (decoded, entity) {
+            (Some(c), Some(entity)) => {
+                out.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The links of a Netscape bookmark file, as browsers export them, in order. The folder of
+/// a link is the innermost one it is in.
+fn parse(html: &str) -> Vec<Link> {
+    let mut links = vec![];
+    // A folder's name comes before the list of its links
+    let mut folder_name = None;
+    let mut folders: Vec<Option<String>> = vec![];
+    for token in TOKENS.captures_iter(html) {
+        if let Some(name) = token.get(1) {
+            folder_name = Some(text(name.as_str()));
+        } else if let (Some(attributes), Some(title)) = (token.get(2), token.get(3)) {
+            let Some(url) = HREF.captures(attributes.as_str()) else {
+                continue;
+            };
+            let url = text(&url[1]);
+            // Bookmarklets and separators are no reading
+            if url.is_empty() || url.starts_with("javascript:") || url.starts_with("place:") {
+                continue;
+            }
+            let title = text(title.as_str());
+            links.push(Link {
+                title: if title.is_empty() { url.clone() } else { title },
+                url,
+                folder: folders.iter().rev().find_map(|folder| folder.clone()),
+            });
+        } else if token[0].eq_ignore_ascii_case("</dl>") {
+            folders.pop();
+        } else {
+            folders.push(folder_name.take().filter(|name| !name.is_empty()));
+        }
+    }
+    links
+}
+
+/// `[title](url)`, with what would break the link escaped
+fn markdown_link(link: &Link) -> String {
+    let title = link
+        .title
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]");
+    if link.url.contains([' ', '(', ')', '<', '>']) {
+        format!("[{title}](<{}>)", link.url.replace('>', "%3E"))
+    } else {
+        format!("[{title}]({})", link.url)
+    }
+}
+
+impl Taskmonger {
+    /// Appends the links of a browser's bookmarks export as markdown list items, tagged by
+    /// their folder. Links already in the buffer are skipped, so exports can be imported
+    /// again. Returns the number of links added and skipped.
+    pub fn import_browser_bookmarks(
+        &mut self,
+        path: &Path,
+    ) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let links = parse(&fs::read_to_string(path)?);
+        if links.is_empty() {
+            return Err("no bookmarks in the file".into());
+        }
+        let mut added = 0;
+        let mut skipped = 0;
+        for link in &links {
+            if self.buffer.contains(&link.url) {
+                skipped += 1;
+                continue;
+            }
+            if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+                self.buffer.push('\n');
+            }
+            self.buffer.push_str("- ");
+            let start = self.buffer.chars().count();
+            self.buffer.push_str(&markdown_link(link));
+            let end = self.buffer.chars().count();
+            self.buffer.push('\n');
+            if let Some(folder) = &link.folder {
+                let tag = self.import_tag(folder.clone());
+                self.tagged_ranges
+                    .push(TaggedRange::new(tag.clone(), start..end));
+                self.normalize_tag_ranges(&tag);
+            }
+            added += 1;
+        }
+        info!(added, skipped, "Imported bookmarks of {}", path.display());
+        let _ = self.save_to_disk();
+        Ok((added, skipped))
+    }
+
+    /// Export menu row to import a browser's bookmarks
+    pub fn browser_bookmarks_ui(&mut self, ui: &mut egui::Ui) {
+        let import_id = egui::Id::new("browser_bookmarks_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of a bookmarks.html")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(
+                    !import.trim().is_empty(),
+                    egui::Button::new("Import bookmarks"),
+                )
+                .on_hover_text(
+                    "Browser bookmarks as links tagged by folder, skipping those already here",
+                )
+                .clicked()
+            {
+                match self.import_browser_bookmarks(Path::new(import.trim())) {
+                    Ok((added, 0)) => {
+                        self.toasts.success(format!("Imported {added} bookmarks"));
+                        import.clear();
+                    }
+                    Ok((added, skipped)) => {
+                        self.toasts.success(format!(
+                            "Imported {added} bookmarks, {skipped} were already in the buffer"
+                        ));
+                        import.clear();
+                    }
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the bookmarks: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(title: &str, url: &str, folder: Option<&str>) -> Link {
+        Link {
+            title: title.to_string(),
+            url: url.to_string(),
+            folder: folder.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn links_are_in_their_innermost_folder() {
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><A HREF="https://top.example">Top</A>
+    <DT><H3 ADD_DATE="1">Reading</H3>
+    <DL><p>
+        <DT><A HREF="https://a.example" ADD_DATE="2">A</A>
+        <DT><H3>Rust &amp; more</H3>
+        <DL><p>
+            <DT><A HREF="https://b.example">B</A>
+        </DL><p>
+        <DT><A HREF="https://c.example">C</A>
+    </DL><p>
+    <DT><A HREF="https://d.example">D</A>
+</DL><p>"#;
+        assert_eq!(
+            parse(html),
+            [
+                link("Top", "https://top.example", None),
+                link("A", "https://a.example", Some("Reading")),
+                link("B", "https://b.example", Some("Rust & more")),
+                link("C", "https://c.example", Some("Reading")),
+                link("D", "https://d.example", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn bookmarklets_are_skipped_and_empty_titles_use_the_url() {
+        let html = r#"<DL>
+<DT><A HREF="javascript:alert(1)">Run</A>
+<DT><A HREF="place:sort=8">Recent</A>
+<DT><A HREF="https://e.example"></A>
+</DL>"#;
+        assert_eq!(
+            parse(html),
+            [link("https://e.example", "https://e.example", None)]
+        );
+    }
+
+    #[test]
+    fn text_decodes_entities_and_collapses_whitespace() {
+        assert_eq!(
+            text("<b>Fish</b> &amp;\n  chips &#233;&#x41; &bogus; & &lt;3"),
+            "Fish & chips éA &bogus; & <3"
+        );
+    }
+
+    #[test]
+    fn markdown_links_escape_brackets_and_odd_urls() {
+        assert_eq!(
+            markdown_link(&link("[draft] notes", "https://x.example/a", None)),
+            r"[\[draft\] notes](https://x.example/a)"
+        );
+        assert_eq!(
+            markdown_link(&link("Wiki", "https://x.example/a_(b)", None)),
+            "[Wiki](<https://x.example/a_(b)>)"
+        );
+    }
+}
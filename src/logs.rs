@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+use eframe::egui;
+use egui::{Color32, RichText};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Lines kept for the log viewer
+const CAPACITY: usize = 2000;
+
+/// Default filter if RUST_LOG is not set
+const DEFAULT_FILTER: &str = "info";
+
+/// Default filter of the command line queries if RUST_LOG is not set
+const CLI_FILTER: &str = "warn";
+
+pub struct LogLine {
+    pub time: chrono::NaiveDateTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// The most recent log lines, filled by the tracing layer below
+static BUFFER: LazyLock<Mutex<VecDeque<LogLine>>> = LazyLock::new(Default::default);
+
+pub fn log_dir() -> PathBuf {
+    PathBuf::from("logs")
+}
+
+/// Logs warnings to stderr only, for the command line queries. Status bars run them every
+/// few seconds, so they leave no log files behind. Logging goes back to what was set up
+/// before when the returned guard is dropped.
+pub fn init_cli() -> tracing::subscriber::DefaultGuard {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(CLI_FILTER));
+    tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_env_filter(filter)
+            .finish(),
+    )
+}
+
+/// Logs to stderr, to a daily rotated file in the log dir and to the in-app viewer.
+/// Records from the `log` crate (egui, eframe) are forwarded as well.
+/// The returned guard flushes the file on drop and must live as long as the app.
+pub fn init() -> Option<WorkerGuard> {
+    let filter =
+        || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("taskmonger")
+        .filename_suffix("log")
+        .max_log_files(7)
+        .build(log_dir());
+    let (file_layer, guard) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(filter());
+            (Some(layer), Some(guard))
+        }
+        Err(e) => {
+            eprintln!("Could not open log file in {}: {e}", log_dir().display());
+            (None, None)
+        }
+    };
+    let result = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(filter()),
+        )
+        .with(file_layer)
+        .with(ViewerLayer.with_filter(filter()))
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Could not set up logging: {e}");
+    }
+    guard
+}
+
+struct ViewerLayer;
+
+impl<S: Subscriber> Layer<S> for ViewerLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let Ok(mut buffer) = BUFFER.lock() else {
+            return;
+        };
+        if buffer.len() >= CAPACITY {
+            buffer.pop_front();
+        }
+        buffer.push_back(LogLine {
+            time: chrono::Local::now().naive_local(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.0,
+        });
+    }
+}
+
+/// Puts the message first, followed by the other fields as `key=value`
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let fields = std::mem::take(&mut self.0);
+                let _ = write!(self.0, "{value:?}{fields}");
+            }
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.0, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+fn level_color(level: Level, visuals: &egui::Visuals) -> Color32 {
+    match level {
+        Level::ERROR => visuals.error_fg_color,
+        Level::WARN => visuals.warn_fg_color,
+        Level::INFO => visuals.text_color(),
+        _ => visuals.weak_text_color(),
+    }
+}
+
+/// In-app log panel, handy to follow range bookkeeping without a terminal
+pub struct LogViewer {
+    pub open: bool,
+    level: Level,
+    query: String,
+}
+
+impl Default for LogViewer {
+    fn default() -> Self {
+        Self {
+            open: false,
+            level: Level::DEBUG,
+            query: String::new(),
+        }
+    }
+}
+
+impl LogViewer {
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Log")
+            .open(&mut open)
+            .default_size([600.0, 300.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("log_level")
+                        .selected_text(self.level.as_str())
+                        .show_ui(ui, |ui| {
+                            for level in [
+                                Level::ERROR,
+                                Level::WARN,
+                                Level::INFO,
+                                Level::DEBUG,
+                                Level::TRACE,
+                            ] {
+                                ui.selectable_value(&mut self.level, level, level.as_str());
+                            }
+                        });
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.query)
+                            .hint_text("Filter")
+                            .desired_width(160.0),
+                    );
+                    if ui.button("Clear").clicked() {
+                        if let Ok(mut buffer) = BUFFER.lock() {
+                            buffer.clear();
+                        }
+                    }
+                    if ui
+                        .button("Open log folder")
+                        .on_hover_text(log_dir().display().to_string())
+                        .clicked()
+                    {
+                        if let Ok(dir) = std::fs::canonicalize(log_dir()) {
+                            ctx.open_url(egui::OpenUrl::new_tab(format!(
+                                "file://{}",
+                                dir.display()
+                            )));
+                        }
+                    }
+                });
+                ui.separator();
+
+                // Format up front, the lock must not be held while egui logs something itself
+                let query = self.query.to_lowercase();
+                let lines: Vec<(Level, String)> = match BUFFER.lock() {
+                    Ok(buffer) => buffer
+                        .iter()
+                        .filter(|line| line.level <= self.level)
+                        .filter(|line| {
+                            query.is_empty()
+                                || line.message.to_lowercase().contains(&query)
+                                || line.target.to_lowercase().contains(&query)
+                        })
+                        .map(|line| {
+                            let text = format!(
+                                "{} {:5} {}: {}",
+                                line.time.format("%H:%M:%S%.3f"),
+                                line.level,
+                                line.target,
+                                line.message
+                            );
+                            (line.level, text)
+                        })
+                        .collect(),
+                    Err(_) => return,
+                };
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .auto_shrink(false)
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, lines.len(), |ui, rows| {
+                        for (level, text) in &lines[rows] {
+                            let color = level_color(*level, ui.visuals());
+                            ui.label(RichText::new(text).monospace().color(color));
+                        }
+                    });
+            });
+        // Pick up new lines while the viewer is open
+        if open {
+            ctx.request_repaint_after_secs(0.5);
+        }
+        self.open = open;
+    }
+}
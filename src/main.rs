@@ -1,18 +1,105 @@
-use crate::tools::{mix_colors, RangeExt, ReadableText};
-use crate::tools::{random_color, to_color32};
+use crate::tools::{
+    char_to_byte_range, expand_vars, fence_code_block, format_bytes, format_duration, line_col,
+    line_start, mix_colors, percent_encode, resolve_image_paths, saturate, split_blocks,
+    sync_task_checkboxes, syntax_extension, RangeExt, ReadableText,
+};
+use crate::tools::{to_color32, Palette};
+use archive::ArchivedRange;
+use base16::ThemeWatch;
+use bookmarks::{Bookmark, BOOKMARK_SPACE};
+use capture::CaptureApp;
+use collisions::TagCollision;
+use colormap::{Colormap, Marking};
+use commands::CommandRun;
 use eframe::egui;
 use egui::containers::menu::MenuConfig;
+use egui::text::{CCursor, CCursorRange};
 use egui::{color_picker, Button, Color32, Key, Layout, RichText};
 use egui_dnd::dnd;
 use egui_phosphor::regular::*;
-use log::{debug, info};
+use focus::default_focus_width;
+use geometry::Geometry;
+use history::RevisionCache;
+use idle::Idle;
+use indexmap::{IndexMap, IndexSet};
+use join::JoinRanges;
+use lines::{LineTagStyle, LINE_BAR_SPACE};
+use logs::LogViewer;
+use notifications::NotifyRule;
+use pdf::PdfSource;
+use print::PrintMarker;
+use recovery::Recovery;
+use retention::{RetentionSettings, TrashedRange};
+use rules::TagRule;
 use serde::{Deserialize, Serialize};
+use settings::{default_font_size, default_ui_scale, EditorFont, BOLD_MONO, BOLD_SANS};
+use split::SplitView;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use sync::{SyncSettings, SyncState};
+use taskwarrior::TaskwarriorSettings;
+use themes::Themes;
+use toasts::Toasts;
+use tracing::{debug, info, trace, warn};
+use words::WordRules;
+mod annotations;
+mod archive;
+mod base16;
+mod bookmarks;
+mod capture;
+mod cli;
+mod collisions;
+mod colormap;
+mod commands;
+mod encryption;
+mod export;
+mod focus;
+mod geometry;
+mod history;
+mod idle;
+mod join;
+mod lines;
+mod links;
+mod logs;
+mod notifications;
+mod opml;
+mod org;
+mod outline;
+mod pdf;
+mod print;
+mod profiles;
+mod recovery;
+mod retention;
+mod rules;
+mod settings;
+mod split;
+mod sync;
+mod taskwarrior;
+mod themes;
+mod toasts;
+mod todotxt;
 mod tools;
+#[cfg(feature = "tray")]
+mod tray;
+mod words;
+
+/// Lines with more chars than this are drawn without tag highlighting
+const LONG_LINE_CHARS: usize = 10_000;
+
+/// Buffers larger than this only get highlighting around the visible part
+const VIRTUALIZE_BYTES: usize = 200_000;
+
+/// Chars highlighted before and after the visible part, so scrolling does not show plain text
+const VIRTUAL_MARGIN_CHARS: usize = 20_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 struct TaggedRange {
@@ -22,6 +109,51 @@ struct TaggedRange {
     created: chrono::NaiveDateTime,
     #[serde(default)]
     modified: chrono::NaiveDateTime,
+    /// Pinned ranges are always shown first in the markdown view
+    #[serde(default)]
+    pinned: bool,
+    /// Shown verbatim instead of rendered as markdown
+    #[serde(default)]
+    plain_text: bool,
+    /// Code language, shown highlighted in the editor and the markdown view
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    due: Option<chrono::NaiveDate>,
+    /// Recurring ranges come due again after they are done
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    /// Finished time tracking sessions
+    #[serde(default)]
+    time_entries: Vec<TimeEntry>,
+    /// Start of the running time tracking session
+    #[serde(default)]
+    timer_started: Option<chrono::NaiveDateTime>,
+    /// Shell command that can be run from the range
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    last_run: Option<CommandRun>,
+    /// Last day a due reminder was shown
+    #[serde(default)]
+    notified: Option<chrono::NaiveDate>,
+    /// Free-form details kept out of the buffer
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    priority: Priority,
+    /// Lines covered, 0-based and end exclusive, for tags that work on whole lines
+    #[serde(default)]
+    lines: Option<Range<usize>>,
+}
+
+/// A stretch of time tracked on a range, in local time
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+struct TimeEntry {
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
 }
 
 impl TaggedRange {
@@ -31,133 +163,2655 @@ impl TaggedRange {
             range,
             created: chrono::Utc::now().naive_local(),
             modified: chrono::Utc::now().naive_local(),
+            pinned: false,
+            plain_text: false,
+            language: None,
+            done: false,
+            due: None,
+            recurrence: None,
+            time_entries: vec![],
+            timer_started: None,
+            command: None,
+            last_run: None,
+            notified: None,
+            note: String::new(),
+            priority: Priority::default(),
+            lines: None,
         }
     }
     fn mark(&mut self) {
         self.modified = chrono::Utc::now().naive_local();
     }
+
+    /// Grows to cover `other` as well, taking over its tracked time and note
+    fn absorb(&mut self, other: TaggedRange) {
+        self.range = self.range.union(&other.range);
+        self.created = self.created.min(other.created);
+        self.time_entries.extend(other.time_entries);
+        self.timer_started = self.timer_started.or(other.timer_started);
+        if !other.note.is_empty() && other.note != self.note {
+            if !self.note.is_empty() {
+                self.note.push_str("\n\n");
+            }
+            self.note.push_str(&other.note);
+        }
+        self.mark();
+    }
+
+    /// Rough size in memory, with the text it owns
+    fn estimated_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.tag_name.len()
+            + self.note.len()
+            + self.language.as_ref().map_or(0, String::len)
+            + self.command.as_ref().map_or(0, String::len)
+            + self.time_entries.len() * size_of::<TimeEntry>()
+    }
+
+    fn is_overdue(&self, today: chrono::NaiveDate) -> bool {
+        !self.done && self.due.is_some_and(|due| due < today)
+    }
+
+    /// Identifies a range while it is edited or moved around
+    fn key(&self) -> (String, chrono::NaiveDateTime) {
+        (self.tag_name.clone(), self.created)
+    }
+
+    fn start_timer(&mut self) {
+        if self.timer_started.is_none() {
+            self.timer_started = Some(chrono::Local::now().naive_local());
+        }
+    }
+
+    fn stop_timer(&mut self) {
+        if let Some(start) = self.timer_started.take() {
+            let end = chrono::Local::now().naive_local();
+            self.time_entries.push(TimeEntry { start, end });
+        }
+    }
+
+    /// Tracked sessions including the running one
+    fn sessions(&self) -> impl Iterator<Item = TimeEntry> + '_ {
+        let running = self.timer_started.map(|start| TimeEntry {
+            start,
+            end: chrono::Local::now().naive_local(),
+        });
+        self.time_entries.iter().cloned().chain(running)
+    }
+
+    fn tracked_time(&self) -> chrono::Duration {
+        self.sessions().map(|e| e.end - e.start).sum()
+    }
+
+    /// Resets a done recurring range once its next occurrence is reached.
+    /// Returns true if it was reset.
+    fn roll_over(&mut self, today: chrono::NaiveDate) -> bool {
+        let (true, Some(recurrence), Some(due)) = (self.done, self.recurrence, self.due) else {
+            return false;
+        };
+        // Skip occurrences that passed while the app was closed
+        let mut next = recurrence.next(due);
+        if next > today {
+            return false;
+        }
+        while recurrence.next(next) <= today {
+            next = recurrence.next(next);
+        }
+        self.due = Some(next);
+        self.done = false;
+        self.mark();
+        true
+    }
+
+    /// Due date picker, or a button to add one. Returns true if the due date changed.
+    fn due_ui(&mut self, ui: &mut egui::Ui, today: chrono::NaiveDate) -> bool {
+        let Some(mut due) = self.due else {
+            let add = ui.small_button(CALENDAR_PLUS).on_hover_text("Set due date");
+            if add.clicked() {
+                self.due = Some(today);
+            }
+            return add.clicked();
+        };
+        let picker = ui
+            .add(
+                egui_extras::DatePickerButton::new(&mut due)
+                    .id_salt(&format!("due_{}_{}", self.tag_name, self.range.start))
+                    .format("%b %d")
+                    .show_icon(false),
+            )
+            .on_hover_text("Due date, right click to clear");
+        let mut changed = picker.changed();
+        self.due = Some(due);
+        picker.context_menu(|ui| {
+            if ui.button("Clear due date").clicked() {
+                self.due = None;
+                self.recurrence = None;
+                changed = true;
+            }
+            ui.separator();
+            changed |= Recurrence::ui(ui, &mut self.recurrence);
+        });
+        if let Some(recurrence) = self.recurrence {
+            ui.label(ARROWS_CLOCKWISE)
+                .on_hover_text(format!("Repeats {}", recurrence.name()));
+        }
+        if self.is_overdue(today) {
+            ui.label(RichText::new(WARNING).color(ui.visuals().error_fg_color))
+                .on_hover_text("Overdue");
+        }
+        changed
+    }
 }
 
-#[derive(Serialize, Deserialize, Default)]
-struct Settings {
-    #[serde(default)]
-    dark_mode: bool,
-    #[serde(default)]
-    markdown_view_enabled: bool,
-    mark_as_background: bool,
+/// Schedule of a recurring range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Recurrence {
+    Daily,
+    /// Monday to Friday
+    Weekdays,
+    Weekly,
+    Monthly,
+    EveryDays(u32),
 }
 
-#[derive(Serialize, Deserialize)]
-struct Taskmonger {
-    buffer: String,
+impl Recurrence {
+    /// First occurrence after `date`
+    fn next(&self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+        use chrono::{Datelike, Days, Months, Weekday};
+        match self {
+            Recurrence::Daily => date + Days::new(1),
+            Recurrence::Weekdays => {
+                let mut next = date + Days::new(1);
+                while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+                    next = next + Days::new(1);
+                }
+                next
+            }
+            Recurrence::Weekly => date + Days::new(7),
+            Recurrence::Monthly => date + Months::new(1),
+            Recurrence::EveryDays(days) => date + Days::new((*days).max(1) as u64),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Recurrence::Daily => "daily".to_string(),
+            Recurrence::Weekdays => "on weekdays".to_string(),
+            Recurrence::Weekly => "weekly".to_string(),
+            Recurrence::Monthly => "monthly".to_string(),
+            Recurrence::EveryDays(days) => format!("every {days} days"),
+        }
+    }
+
+    /// Schedule picker. Returns true if it changed.
+    fn ui(ui: &mut egui::Ui, recurrence: &mut Option<Recurrence>) -> bool {
+        let before = *recurrence;
+        ui.label("Repeat");
+        ui.radio_value(recurrence, None, "Never");
+        for option in [
+            Recurrence::Daily,
+            Recurrence::Weekdays,
+            Recurrence::Weekly,
+            Recurrence::Monthly,
+        ] {
+            ui.radio_value(recurrence, Some(option), option.name());
+        }
+        ui.horizontal(|ui| {
+            let mut days = match recurrence {
+                Some(Recurrence::EveryDays(days)) => *days,
+                _ => 3,
+            };
+            let custom = matches!(recurrence, Some(Recurrence::EveryDays(_)));
+            if ui.radio(custom, "Every").clicked() {
+                *recurrence = Some(Recurrence::EveryDays(days));
+            }
+            if ui
+                .add(egui::DragValue::new(&mut days).range(1..=365))
+                .changed()
+            {
+                *recurrence = Some(Recurrence::EveryDays(days));
+            }
+            ui.label("days");
+        });
+        *recurrence != before
+    }
+}
+
+/// How pressing a range is, urgent ones are highlighted more strongly
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    const ALL: [Priority; 4] = [
+        Priority::Low,
+        Priority::Normal,
+        Priority::High,
+        Priority::Urgent,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+            Priority::Urgent => "Urgent",
+        }
+    }
+
+    fn icon(&self, ui: &egui::Ui) -> RichText {
+        match self {
+            Priority::Low => RichText::new(ARROW_DOWN).weak(),
+            Priority::Normal => RichText::new(MINUS).weak(),
+            Priority::High => RichText::new(ARROW_UP).color(ui.visuals().warn_fg_color),
+            Priority::Urgent => RichText::new(FIRE).color(ui.visuals().error_fg_color),
+        }
+    }
+
+    /// Menu button showing the priority icon. Returns true if it changed.
+    fn menu(ui: &mut egui::Ui, priority: &mut Priority) -> bool {
+        let before = *priority;
+        ui.menu_button(priority.icon(ui), |ui| {
+            for option in Priority::ALL {
+                if ui.radio_value(priority, option, option.name()).clicked() {
+                    ui.close();
+                }
+            }
+        })
+        .response
+        .on_hover_text(format!("Priority: {}", priority.name()));
+        *priority != before
+    }
+}
+
+/// Completion states the tagged ranges list can be narrowed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum StatusFilter {
+    #[default]
+    All,
+    Open,
+    Done,
+    Overdue,
+}
+
+impl StatusFilter {
+    const ALL: [StatusFilter; 4] = [
+        StatusFilter::All,
+        StatusFilter::Open,
+        StatusFilter::Done,
+        StatusFilter::Overdue,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            StatusFilter::All => "All",
+            StatusFilter::Open => "Open",
+            StatusFilter::Done => "Done",
+            StatusFilter::Overdue => "Overdue",
+        }
+    }
+}
+
+/// Order of the tagged ranges list and the markdown view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum RangeSort {
+    /// The order ranges were dragged into
+    #[default]
+    Manual,
+    /// Position in the buffer
+    Document,
+    /// Tag order, then position
+    Tag,
+    /// Newest first
+    Recent,
+    /// Most urgent first, then position
+    Priority,
+}
+
+impl RangeSort {
+    const ALL: [RangeSort; 5] = [
+        RangeSort::Manual,
+        RangeSort::Document,
+        RangeSort::Tag,
+        RangeSort::Recent,
+        RangeSort::Priority,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            RangeSort::Manual => "Manual order",
+            RangeSort::Document => "Document order",
+            RangeSort::Tag => "By tag",
+            RangeSort::Recent => "Recently added",
+            RangeSort::Priority => "By priority",
+        }
+    }
+
+    /// Sorts indices into `ranges`
+    fn sort(&self, indices: &mut [usize], ranges: &[TaggedRange], tags: &IndexMap<String, Tag>) {
+        match self {
+            RangeSort::Manual => {}
+            RangeSort::Document => indices.sort_by_key(|&i| ranges[i].range.start),
+            RangeSort::Tag => indices.sort_by_key(|&i| {
+                (
+                    tags.get_index_of(&ranges[i].tag_name),
+                    ranges[i].range.start,
+                )
+            }),
+            RangeSort::Recent => indices.sort_by_key(|&i| std::cmp::Reverse(ranges[i].created)),
+            RangeSort::Priority => indices
+                .sort_by_key(|&i| (std::cmp::Reverse(ranges[i].priority), ranges[i].range.start)),
+        }
+    }
+}
+
+/// Narrows down the tagged ranges list
+#[derive(Default)]
+struct RangeListFilter {
+    /// Matched case-insensitively against tag name and text
+    query: String,
+    status: StatusFilter,
+    /// Only ranges of exactly this tag
+    tag: Option<String>,
+}
+
+impl RangeListFilter {
+    fn matches(&self, tr: &TaggedRange, text: &str, today: chrono::NaiveDate) -> bool {
+        let status = match self.status {
+            StatusFilter::All => true,
+            StatusFilter::Open => !tr.done,
+            StatusFilter::Done => tr.done,
+            StatusFilter::Overdue => tr.is_overdue(today),
+        };
+        let query = self.query.trim().to_lowercase();
+        status
+            && self.tag.as_ref().is_none_or(|tag| *tag == tr.tag_name)
+            && (query.is_empty()
+                || tr.tag_name.to_lowercase().contains(&query)
+                || text.to_lowercase().contains(&query))
+    }
+}
+
+/// A tag definition: its color and how tagged text is drawn
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Tag {
+    color: [u8; 3],
     #[serde(default)]
-    tags: HashMap<String, [u8; 3]>,
+    bold: bool,
     #[serde(default)]
-    tagged_ranges: Vec<TaggedRange>,
-    settings: Settings,
-    #[serde(skip)]
-    selection: Range<usize>,
-    #[serde(skip)]
-    markdown_cache: HashMap<String, egui_commonmark::CommonMarkCache>,
+    italics: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    strikethrough: bool,
+    /// Always highlight the background, even when tags are shown as text color
+    #[serde(default)]
+    background_only: bool,
+    /// Hours of tracked time allowed over the last 7 days
+    #[serde(default)]
+    weekly_budget: Option<f32>,
+    /// Text typed right at the start or end of a range becomes part of it
+    #[serde(default = "default_true")]
+    grow_while_typing: bool,
+    /// When ranges of this tag remind about their due date
+    #[serde(default)]
+    notify: NotifyRule,
+    /// Ranges always cover whole lines and stay anchored to them
+    #[serde(default)]
+    whole_lines: bool,
+    /// Ranges take in the lines indented deeper below their first line, like sub-items
+    #[serde(default)]
+    inherit_indented: bool,
 }
 
-impl Default for Taskmonger {
-    fn default() -> Self {
+fn default_true() -> bool {
+    true
+}
+
+impl Tag {
+    fn new(color: [u8; 3]) -> Self {
         Self {
-            buffer: format!(
-                "Welcome to {}! \n\nJust start typing here and tag your things.",
-                env!("CARGO_PKG_NAME")
-            )
-            .to_string(),
-            tags: Default::default(),
-            tagged_ranges: Vec::new(),
-            settings: Default::default(),
-            selection: Default::default(),
-            markdown_cache: HashMap::new(),
+            color,
+            bold: false,
+            italics: false,
+            underline: false,
+            strikethrough: false,
+            background_only: false,
+            weekly_budget: None,
+            grow_while_typing: true,
+            notify: NotifyRule::default(),
+            whole_lines: false,
+            inherit_indented: false,
         }
     }
-}
 
-impl Taskmonger {
-    fn save_path() -> PathBuf {
-        // Save in the current directory for simplicity
-        // Could use dirs crate for a proper config directory
-        PathBuf::from("taskmonger_state.json")
+    fn budget_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let mut limited = self.weekly_budget.is_some();
+        if ui.checkbox(&mut limited, "Limit tracked time").changed() {
+            self.weekly_budget = limited.then_some(5.0);
+            changed = true;
+        }
+        if let Some(hours) = &mut self.weekly_budget {
+            changed |= ui
+                .add(
+                    egui::DragValue::new(hours)
+                        .range(0.5..=168.0)
+                        .speed(0.25)
+                        .suffix(" h per 7 days"),
+                )
+                .changed();
+        }
+        changed
     }
 
-    fn save_to_disk(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let json = serde_json::to_string_pretty(self)?;
-        fs::write("backup.txt", &self.buffer)?;
-        fs::write(Self::save_path(), json)?;
-        debug!("Saved state to {}", Self::save_path().display());
-        Ok(())
+    fn budget(&self) -> Option<chrono::Duration> {
+        self.weekly_budget
+            .map(|hours| chrono::Duration::seconds((hours * 3600.0) as i64))
     }
 
-    fn load_from_disk() -> Result<Self, Box<dyn std::error::Error>> {
-        let path = Self::save_path();
-        if path.exists() {
-            let json = fs::read_to_string(&path)?;
-            let mut app: Self = serde_json::from_str(&json)?;
-            debug!("Loaded state from {}", path.display());
-            // Clean up any invalid ranges that might have been saved
-            app.clean_invalid_ranges();
-            Ok(app)
+    fn style_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        changed |= ui.checkbox(&mut self.bold, "Bold").changed();
+        changed |= ui.checkbox(&mut self.italics, "Italics").changed();
+        changed |= ui.checkbox(&mut self.underline, "Underline").changed();
+        changed |= ui
+            .checkbox(&mut self.strikethrough, "Strikethrough")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.background_only, "Background only")
+            .changed();
+        changed
+    }
+}
+
+/// Group of a tag named like `work/reports`
+fn tag_group(name: &str) -> Option<&str> {
+    name.split_once('/').map(|(group, _)| group)
+}
+
+/// Tag name without its group prefix
+fn tag_leaf(name: &str) -> &str {
+    name.split_once('/').map_or(name, |(_, leaf)| leaf)
+}
+
+// Older state files stored tags as a bare color
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<IndexMap<String, Tag>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TagOrColor {
+        Color([u8; 3]),
+        Tag(Tag),
+    }
+
+    let tags: IndexMap<String, TagOrColor> = IndexMap::deserialize(deserializer)?;
+    Ok(tags
+        .into_iter()
+        .map(|(name, tag)| match tag {
+            TagOrColor::Color(color) => (name, Tag::new(color)),
+            TagOrColor::Tag(tag) => (name, tag),
+        })
+        .collect())
+}
+
+/// How a single character is marked by all the tags covering it
+#[derive(Clone, Default)]
+struct CharMarks {
+    text: Vec<Color32>,
+    background: Vec<Color32>,
+    bold: bool,
+    italics: bool,
+    underline: Vec<Color32>,
+    strikethrough: Vec<Color32>,
+}
+
+impl CharMarks {
+    fn add(&mut self, tag: &Tag, as_background: bool, urgent: bool) {
+        let color = if urgent {
+            saturate(to_color32(tag.color))
         } else {
-            Err("Save file does not exist".into())
+            to_color32(tag.color)
+        };
+        if as_background || tag.background_only {
+            self.background.push(color);
+        } else {
+            self.text.push(color);
+        }
+        self.bold |= tag.bold;
+        self.italics |= tag.italics;
+        if tag.underline {
+            self.underline.push(color);
+        }
+        if tag.strikethrough {
+            self.strikethrough.push(color);
         }
     }
 
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        // Try to load from disk, fallback to default
-        Self::load_from_disk().unwrap_or_else(|e| {
-            debug!("No saved state found ({}), starting fresh", e);
-            let mut def = Self::default();
-            if PathBuf::from("backup.txt").exists() {
-                let mut buf: String = Default::default();
-                if let Ok(mut f) = File::open(PathBuf::from("backup.txt")) {
-                    _ = f.read_to_string(&mut buf);
-                    if !buf.is_empty() {
-                        debug!("Recovered backup");
-                        def.buffer = buf;
+    /// Colorblind-safe patterns on top of the tag color: a shade, an underline and italics
+    fn add_pattern(&mut self, marker: PrintMarker, color: Color32, shade: Option<Color32>) {
+        if let Some(shade) = shade {
+            self.background.push(shade);
+        }
+        if marker.underline {
+            self.underline.push(color);
+        }
+        self.italics |= marker.italics;
+    }
+
+    /// Marks for the print preview, which does without colors
+    fn add_print(&mut self, marker: PrintMarker) {
+        if let Some(background) = marker.background {
+            self.background.push(background);
+        }
+        if marker.underline {
+            self.underline.push(Color32::BLACK);
+        }
+        self.italics |= marker.italics;
+    }
+}
+
+/// How characters covered by several tags are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum OverlapStyle {
+    /// Average all tag colors
+    Mix,
+    /// Alternate between the tag colors every few characters
+    #[default]
+    Stripes,
+}
+
+impl OverlapStyle {
+    const STRIPE_WIDTH: usize = 2;
+
+    /// Color for the char at `index` covered by tags with `colors`
+    fn pick(&self, colors: &[Color32], index: usize) -> Option<Color32> {
+        match self {
+            OverlapStyle::Mix => colors.iter().copied().reduce(mix_colors),
+            OverlapStyle::Stripes => {
+                (!colors.is_empty()).then(|| colors[(index / Self::STRIPE_WIDTH) % colors.len()])
+            }
+        }
+    }
+}
+
+/// When two tag names count as the same tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum TagNamePolicy {
+    /// Names only match if they are spelled exactly alike
+    CaseSensitive,
+    /// `Work` and `work` are the same tag
+    #[default]
+    IgnoreCase,
+}
+
+impl TagNamePolicy {
+    /// Trims the name and collapses whitespace, also around group separators
+    fn clean(name: &str) -> String {
+        name.split('/')
+            .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Key under which names are compared
+    fn key(&self, name: &str) -> String {
+        match self {
+            TagNamePolicy::CaseSensitive => Self::clean(name),
+            TagNamePolicy::IgnoreCase => Self::clean(name).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Settings {
+    #[serde(default)]
+    dark_mode: bool,
+    #[serde(default)]
+    markdown_view_enabled: bool,
+    /// Color the background of tagged text instead of the text itself
+    #[serde(default)]
+    mark_as_background: bool,
+    #[serde(default)]
+    show_line_numbers: bool,
+    /// Seconds between saves while typing, 0 saves on every change
+    #[serde(default)]
+    autosave_secs: u32,
+    #[serde(default)]
+    editor_font: EditorFont,
+    #[serde(default = "default_font_size")]
+    font_size: f32,
+    /// Zoom of the whole UI, independent of the system DPI
+    #[serde(default = "default_ui_scale")]
+    ui_scale: f32,
+    #[serde(default)]
+    markdown_style: MarkdownStyle,
+    /// Render the whole buffer in the markdown panel instead of the tagged ranges
+    #[serde(default)]
+    markdown_full_buffer: bool,
+    #[serde(default)]
+    palette: Palette,
+    #[serde(default)]
+    show_latency_overlay: bool,
+    #[serde(default)]
+    overlap_style: OverlapStyle,
+    /// Number of rendered markdown blocks kept in memory
+    #[serde(default = "default_markdown_cache_limit")]
+    markdown_cache_limit: usize,
+    /// Number of history revisions kept in memory for the history window
+    #[serde(default = "default_snapshot_cache_limit")]
+    snapshot_cache_limit: usize,
+    /// Pastes larger than this pause tag highlighting until resumed
+    #[serde(default = "default_large_paste_kb")]
+    large_paste_kb: usize,
+    /// Prefix text copied from the markdown view with its tag name
+    #[serde(default)]
+    copy_with_tag_header: bool,
+    #[serde(default)]
+    tag_name_policy: TagNamePolicy,
+    #[serde(default)]
+    range_sort: RangeSort,
+    #[serde(default)]
+    scratchpad_open: bool,
+    /// Second view of the buffer next to or below the editor
+    #[serde(default)]
+    split_view: Option<SplitView>,
+    #[serde(default)]
+    agenda_open: bool,
+    /// Sandbox: never run commands attached to ranges
+    #[serde(default)]
+    commands_disabled: bool,
+    /// Rewrite the calendar file on every save
+    #[serde(default)]
+    ics_auto_export: bool,
+    /// Commit the state to a git repository half a minute after a save and on close,
+    /// saves within five minutes of a commit's first one amend it
+    #[serde(default)]
+    git_history: bool,
+    #[serde(default)]
+    digest: DigestSettings,
+    #[serde(default)]
+    retention: RetentionSettings,
+    /// Name of the profile last switched to or saved
+    #[serde(default)]
+    profile: Option<String>,
+    /// Archiving done ranges cuts their text from the buffer
+    #[serde(default)]
+    archive_removes_text: bool,
+    /// base16 or terminal theme file tag colors follow, empty for none
+    #[serde(default)]
+    base16_theme: String,
+    /// Auto-tagging rules
+    #[serde(default)]
+    rules: Vec<TagRule>,
+    /// Check the edited line against the rules after every change
+    #[serde(default)]
+    rules_while_typing: bool,
+    /// Minutes without input after which timers pause, 0 never pauses them
+    #[serde(default = "default_idle_minutes")]
+    idle_minutes: u32,
+    #[serde(default)]
+    words: WordRules,
+    #[serde(default)]
+    line_tag_style: LineTagStyle,
+    /// Width of the text column in the distraction-free mode
+    #[serde(default = "default_focus_width")]
+    focus_width: f32,
+    /// Keep the cursor line centered in the distraction-free mode
+    #[serde(default)]
+    typewriter_scrolling: bool,
+    /// Colorblind-safe tag colors, with a pattern per tag on top
+    #[serde(default)]
+    colorblind: bool,
+    #[serde(default)]
+    geometry: Geometry,
+    /// Built-in or user theme by name, plain light or dark if none
+    #[serde(default)]
+    theme: Option<String>,
+    #[serde(default)]
+    pdf_source: PdfSource,
+    #[serde(default)]
+    sync: SyncSettings,
+    #[serde(default)]
+    taskwarrior: TaskwarriorSettings,
+    /// Closing the window hides it in the tray, with the `tray` feature
+    #[serde(default)]
+    minimize_to_tray: bool,
+}
+
+fn default_idle_minutes() -> u32 {
+    5
+}
+
+/// Where the weekly digest goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum DigestTarget {
+    #[default]
+    File,
+    /// Opens a pre-filled mail in the mail app
+    Email,
+}
+
+/// Weekly report of open and completed ranges per tag
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+struct DigestSettings {
+    enabled: bool,
+    target: DigestTarget,
+    /// File the digest is written to
+    path: String,
+    /// Recipient of the digest mail
+    email: String,
+    last_sent: Option<chrono::NaiveDate>,
+}
+
+impl DigestSettings {
+    const DEFAULT_PATH: &str = "taskmonger_digest.md";
+
+    fn due(&self, today: chrono::NaiveDate) -> bool {
+        self.enabled
+            && self
+                .last_sent
+                .is_none_or(|sent| today >= sent + chrono::Days::new(7))
+    }
+}
+
+fn default_large_paste_kb() -> usize {
+    100
+}
+
+fn default_markdown_cache_limit() -> usize {
+    200
+}
+
+fn default_snapshot_cache_limit() -> usize {
+    20
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            dark_mode: false,
+            markdown_view_enabled: false,
+            mark_as_background: false,
+            show_line_numbers: false,
+            autosave_secs: 0,
+            editor_font: Default::default(),
+            font_size: default_font_size(),
+            ui_scale: default_ui_scale(),
+            markdown_style: Default::default(),
+            markdown_full_buffer: false,
+            palette: Default::default(),
+            show_latency_overlay: false,
+            overlap_style: Default::default(),
+            markdown_cache_limit: default_markdown_cache_limit(),
+            snapshot_cache_limit: default_snapshot_cache_limit(),
+            large_paste_kb: default_large_paste_kb(),
+            copy_with_tag_header: false,
+            tag_name_policy: Default::default(),
+            range_sort: Default::default(),
+            scratchpad_open: false,
+            split_view: None,
+            agenda_open: false,
+            commands_disabled: false,
+            ics_auto_export: false,
+            git_history: false,
+            digest: Default::default(),
+            retention: Default::default(),
+            profile: None,
+            archive_removes_text: false,
+            base16_theme: String::new(),
+            rules: vec![],
+            rules_while_typing: false,
+            idle_minutes: default_idle_minutes(),
+            words: Default::default(),
+            line_tag_style: Default::default(),
+            focus_width: default_focus_width(),
+            typewriter_scrolling: false,
+            colorblind: false,
+            geometry: Default::default(),
+            theme: None,
+            pdf_source: Default::default(),
+            sync: Default::default(),
+            taskwarrior: Default::default(),
+            minimize_to_tray: false,
+        }
+    }
+}
+
+/// Appearance of the markdown preview panel
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+struct MarkdownStyle {
+    heading_size: f32,
+    code_background: Option<[u8; 3]>,
+    max_width: Option<f32>,
+    line_spacing: f32,
+}
+
+impl Default for MarkdownStyle {
+    fn default() -> Self {
+        Self {
+            heading_size: 22.0,
+            code_background: None,
+            max_width: None,
+            line_spacing: 3.0,
+        }
+    }
+}
+
+/// Durations of the expensive steps of a frame, shown in the latency overlay
+#[derive(Default)]
+struct Timings {
+    frame: Duration,
+    colormap: Duration,
+    layouter: Duration,
+    save: Cell<Duration>,
+}
+
+impl Timings {
+    fn overlay(&self, ctx: &egui::Context, memory: &[(&str, String)]) {
+        egui::Area::new("latency_overlay".into())
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("latency_grid").show(ui, |ui| {
+                        for (name, duration) in [
+                            ("Frame", self.frame),
+                            ("Colormap", self.colormap),
+                            ("Layouter", self.layouter),
+                            ("Last save", self.save.get()),
+                        ] {
+                            ui.label(name);
+                            ui.monospace(format!("{:>8.3} ms", duration.as_secs_f64() * 1000.0));
+                            ui.end_row();
+                        }
+                    });
+                    ui.separator();
+                    ui.strong("Memory");
+                    egui::Grid::new("memory_grid").show(ui, |ui| {
+                        for (name, value) in memory {
+                            ui.label(*name);
+                            ui.monospace(value);
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+    }
+}
+
+/// Markdown viewer caches, evicting the least recently used ones beyond a limit
+#[derive(Default)]
+struct MarkdownCaches {
+    /// Cache, the frame it was last used in and its estimated size, by content hash
+    entries: HashMap<u64, (egui_commonmark::CommonMarkCache, u64, usize)>,
+    frame: u64,
+}
+
+impl MarkdownCaches {
+    const FULL_BUFFER: u64 = 0;
+
+    /// Caches unused for this many frames are dropped, e.g. those of edited text
+    const STALE_FRAMES: u64 = 600;
+
+    fn key(tag_name: &str, text: &str) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        (tag_name, text).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The cache for rendering `text`
+    fn get(&mut self, key: u64, text: &str) -> &mut egui_commonmark::CommonMarkCache {
+        let entry = self.entries.entry(key).or_default();
+        entry.1 = self.frame;
+        // The rendered blocks are not visible from outside, their source gives an idea
+        entry.2 = size_of::<egui_commonmark::CommonMarkCache>() + text.len();
+        &mut entry.0
+    }
+
+    /// Called once per frame: drops stale caches and the least recently used ones beyond `limit`
+    fn evict(&mut self, limit: usize) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.entries
+            .retain(|_, (_, used, _)| *used + Self::STALE_FRAMES >= frame);
+        if self.entries.len() <= limit {
+            return;
+        }
+        let mut by_age: Vec<(u64, u64)> = self
+            .entries
+            .iter()
+            .map(|(key, (_, used, _))| (*used, *key))
+            .collect();
+        by_age.sort();
+        for (_, key) in by_age.into_iter().take(self.entries.len() - limit) {
+            self.entries.remove(&key);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn bytes(&self) -> usize {
+        self.entries.values().map(|(_, _, bytes)| bytes).sum()
+    }
+}
+
+/// Keeps the editor and the full buffer preview at the same relative scroll position
+#[derive(Default)]
+struct ScrollSync {
+    editor: ScrollPos,
+    preview: ScrollPos,
+}
+
+#[derive(Default)]
+struct ScrollPos {
+    fraction: f32,
+    max_offset: f32,
+    target: Option<f32>,
+}
+
+impl ScrollPos {
+    /// Offset to jump to because the other view was scrolled
+    fn take_target(&mut self) -> Option<f32> {
+        self.target.take().map(|f| f * self.max_offset)
+    }
+
+    /// Records where the view ended up and returns the new fraction if the user scrolled it
+    fn update<R>(
+        &mut self,
+        output: &egui::scroll_area::ScrollAreaOutput<R>,
+        jumped: bool,
+    ) -> Option<f32> {
+        let max_offset = (output.content_size.y - output.inner_rect.height()).max(0.0);
+        let fraction = if max_offset > 0.0 {
+            output.state.offset.y / max_offset
+        } else {
+            0.0
+        };
+        let scrolled = !jumped && (fraction - self.fraction).abs() > 0.001;
+        self.fraction = fraction;
+        self.max_offset = max_offset;
+        scrolled.then_some(fraction)
+    }
+}
+
+impl MarkdownStyle {
+    fn apply(&self, ui: &mut egui::Ui) {
+        if let Some(font) = ui
+            .style_mut()
+            .text_styles
+            .get_mut(&egui::TextStyle::Heading)
+        {
+            font.size = self.heading_size;
+        }
+        if let Some(c) = self.code_background {
+            ui.visuals_mut().extreme_bg_color = to_color32(c);
+            ui.visuals_mut().code_bg_color = to_color32(c);
+        }
+        ui.spacing_mut().item_spacing.y = self.line_spacing;
+        if let Some(width) = self.max_width {
+            ui.set_max_width(width);
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.clone();
+        ui.add(egui::Slider::new(&mut self.heading_size, 14.0..=40.0).text("Heading size"));
+        ui.add(egui::Slider::new(&mut self.line_spacing, 0.0..=16.0).text("Line spacing"));
+
+        let mut limit_width = self.max_width.is_some();
+        if ui.checkbox(&mut limit_width, "Limit width").changed() {
+            self.max_width = limit_width.then_some(400.0);
+        }
+        if let Some(width) = &mut self.max_width {
+            ui.add(egui::Slider::new(width, 150.0..=1200.0).text("Max width"));
+        }
+
+        let mut custom_code_bg = self.code_background.is_some();
+        if ui
+            .checkbox(&mut custom_code_bg, "Custom code background")
+            .changed()
+        {
+            self.code_background = custom_code_bg.then_some([40, 40, 40]);
+        }
+        if let Some(c) = &mut self.code_background {
+            ui.color_edit_button_srgb(c);
+        }
+
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+        *self != before
+    }
+}
+
+/// Version of the state file layout, raised when a change needs older files to be converted
+const STATE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Taskmonger {
+    /// [`STATE_VERSION`] of the app that wrote the file, 0 from before it was recorded
+    #[serde(default)]
+    version: u32,
+    /// A plain String rather than a rope: the egui TextEdit edits a TextBuffer, which hands
+    /// the layouter one contiguous `&str`, so a rope would be flattened every frame
+    buffer: String,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    /// Tag definitions, in the order the user arranged them
+    tags: IndexMap<String, Tag>,
+    /// Colors new tags of a group start with
+    #[serde(default)]
+    group_colors: IndexMap<String, [u8; 3]>,
+    #[serde(default)]
+    tagged_ranges: Vec<TaggedRange>,
+    /// Transient notes kept apart from the main buffer
+    #[serde(default)]
+    scratchpad: String,
+    /// Deleted ranges, until the retention period runs out
+    #[serde(default)]
+    trash: Vec<TrashedRange>,
+    /// Done ranges moved out of the active list
+    #[serde(default)]
+    archive: Vec<ArchivedRange>,
+    /// Named positions, sorted by position
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+    settings: Settings,
+    #[serde(skip)]
+    selection: Range<usize>,
+    #[serde(skip)]
+    cursor: usize,
+    #[serde(skip)]
+    markdown_cache: MarkdownCaches,
+    /// History revisions loaded for the history window
+    #[serde(skip)]
+    revisions: RevisionCache,
+    #[serde(skip)]
+    scroll_sync: ScrollSync,
+    #[serde(skip)]
+    timings: Timings,
+    /// Char index and screen position of the tagged text popup
+    #[serde(skip)]
+    tag_hover: Option<(usize, egui::Pos2)>,
+    /// Range to scroll to in the side panel list
+    #[serde(skip)]
+    reveal_in_list: Option<TaggedRange>,
+    /// Range to scroll to in the markdown view
+    #[serde(skip)]
+    reveal_in_preview: Option<TaggedRange>,
+    /// Range to select and scroll to in the editor
+    #[serde(skip)]
+    jump_to: Option<Range<usize>>,
+    /// Selection to set in the editor, without scrolling
+    #[serde(skip)]
+    select: Option<Range<usize>>,
+    /// Lines the editor draws without highlighting because they are too long
+    #[serde(skip)]
+    long_lines: usize,
+    #[serde(skip)]
+    colormap: Colormap,
+    /// Chars the editor showed last frame
+    #[serde(skip)]
+    visible_chars: Range<usize>,
+    /// Chars of a large paste the auto-tagging rules wait to be confirmed for
+    #[serde(skip)]
+    pasted: Option<Range<usize>>,
+    /// Only list ranges whose tag belongs to this group
+    #[serde(skip)]
+    group_filter: Option<String>,
+    #[serde(skip)]
+    range_filter: RangeListFilter,
+    /// Tag the scratchpad content gets when promoted
+    #[serde(skip)]
+    scratchpad_tag: Option<String>,
+    #[serde(skip)]
+    time_summary_open: bool,
+    /// Commands still running, by range key
+    #[serde(skip)]
+    running_commands: HashMap<(String, chrono::NaiveDateTime), Receiver<CommandRun>>,
+    #[serde(skip)]
+    toasts: Toasts,
+    #[serde(skip)]
+    maintenance_open: bool,
+    #[serde(skip)]
+    archive_open: bool,
+    #[serde(skip)]
+    theme_watch: ThemeWatch,
+    #[serde(skip)]
+    themes: Themes,
+    /// White background, black text and gray patterns instead of tag colors
+    #[serde(skip)]
+    print_preview: bool,
+    /// The buffer is read-only, selecting and tagging still work
+    #[serde(skip)]
+    locked: bool,
+    /// Full screen with only the editor, toggled with F11
+    #[serde(skip)]
+    focus_mode: bool,
+    #[serde(skip)]
+    quick_add_text: String,
+    #[serde(skip)]
+    rules_open: bool,
+    #[serde(skip)]
+    bookmarks_open: bool,
+    #[serde(skip)]
+    outline_open: bool,
+    #[serde(skip)]
+    sync_open: bool,
+    #[serde(skip)]
+    history_open: bool,
+    /// When the window or panel geometry last changed, it is saved once it settles
+    #[serde(skip)]
+    geometry_changed: Option<Instant>,
+    /// Start of the lines whose sub-items are folded away in the editor
+    #[serde(skip)]
+    folds: Vec<usize>,
+    #[serde(skip)]
+    idle: Idle,
+    #[serde(skip)]
+    inbox_checked: Option<Instant>,
+    #[serde(skip)]
+    log_viewer: LogViewer,
+    #[serde(skip)]
+    settings_open: bool,
+    /// Started with `--safe-mode`: commands and automatic jobs are off, the look is the default one.
+    /// The stored settings are left untouched.
+    #[serde(skip)]
+    safe_mode: bool,
+    #[serde(skip)]
+    join_ranges: Option<JoinRanges>,
+    /// Incoming tags that look different from ours, waiting for a decision
+    #[serde(skip)]
+    tag_collisions: Vec<TagCollision>,
+    /// Tag every paragraph typed into is wrapped in, until disarmed
+    #[serde(skip)]
+    armed_tag: Option<String>,
+    /// Text of the open "Go to line" dialog
+    #[serde(skip)]
+    goto_line: Option<String>,
+    /// Set when the state file could only be read in part
+    #[serde(skip)]
+    recovery: Option<Recovery>,
+    /// When the first edit not yet written to disk happened
+    #[serde(skip)]
+    unsaved_since: Cell<Option<Instant>>,
+    /// When the first save not yet committed to the history happened
+    #[serde(skip)]
+    history_since: Cell<Option<Instant>>,
+    /// Loaded on first use, while a sync folder is set
+    #[serde(skip)]
+    sync_state: RefCell<Option<SyncState>>,
+    #[serde(skip)]
+    sync_checked: Option<Instant>,
+    #[cfg(feature = "tray")]
+    #[serde(skip)]
+    tray: Option<tray::Tray>,
+}
+
+impl Default for Taskmonger {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            buffer: format!(
+                "Welcome to {}! \n\nJust start typing here and tag your things.",
+                env!("CARGO_PKG_NAME")
+            )
+            .to_string(),
+            tags: Default::default(),
+            group_colors: Default::default(),
+            tagged_ranges: Vec::new(),
+            scratchpad: String::new(),
+            trash: vec![],
+            archive: vec![],
+            bookmarks: vec![],
+            settings: Default::default(),
+            selection: Default::default(),
+            cursor: 0,
+            markdown_cache: Default::default(),
+            revisions: Default::default(),
+            scroll_sync: Default::default(),
+            timings: Default::default(),
+            tag_hover: None,
+            reveal_in_list: None,
+            reveal_in_preview: None,
+            jump_to: None,
+            select: None,
+            long_lines: 0,
+            visible_chars: 0..0,
+            colormap: Default::default(),
+            pasted: None,
+            group_filter: None,
+            range_filter: Default::default(),
+            scratchpad_tag: None,
+            time_summary_open: false,
+            running_commands: Default::default(),
+            toasts: Default::default(),
+            maintenance_open: false,
+            archive_open: false,
+            theme_watch: Default::default(),
+            themes: Default::default(),
+            print_preview: false,
+            locked: false,
+            focus_mode: false,
+            quick_add_text: String::new(),
+            rules_open: false,
+            bookmarks_open: false,
+            outline_open: false,
+            sync_open: false,
+            history_open: false,
+            geometry_changed: None,
+            folds: vec![],
+            idle: Default::default(),
+            inbox_checked: None,
+            log_viewer: Default::default(),
+            settings_open: false,
+            safe_mode: false,
+            join_ranges: None,
+            tag_collisions: vec![],
+            armed_tag: None,
+            goto_line: None,
+            recovery: None,
+            unsaved_since: Default::default(),
+            history_since: Default::default(),
+            sync_state: Default::default(),
+            sync_checked: None,
+            #[cfg(feature = "tray")]
+            tray: None,
+        }
+    }
+}
+
+impl Taskmonger {
+    /// Writes the tag co-occurrence graph next to the state file
+    fn export_tag_graph(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger_tags.dot");
+        fs::write(
+            &path,
+            export::tag_graph_dot(&self.buffer, &self.tagged_ranges, &self.tags),
+        )?;
+        Ok(path)
+    }
+
+    /// Weekly digest options. Returns true if they changed.
+    fn digest_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.settings.digest.clone();
+        let digest = &mut self.settings.digest;
+        ui.checkbox(&mut digest.enabled, "Weekly digest")
+            .on_hover_text("Once a week, report open and completed ranges per tag");
+        ui.add_enabled_ui(digest.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut digest.target, DigestTarget::File, "File");
+                ui.radio_value(&mut digest.target, DigestTarget::Email, "Email");
+            });
+            match digest.target {
+                DigestTarget::File => ui.add(
+                    egui::TextEdit::singleline(&mut digest.path)
+                        .hint_text(DigestSettings::DEFAULT_PATH),
+                ),
+                DigestTarget::Email => ui.add(
+                    egui::TextEdit::singleline(&mut digest.email).hint_text("you@example.com"),
+                ),
+            };
+            if let Some(sent) = digest.last_sent {
+                ui.label(RichText::new(format!("Last sent {}", sent.format("%b %d"))).weak());
+            }
+        });
+        let changed = self.settings.digest != before;
+        if ui.button("Send digest now").clicked() {
+            self.send_digest(ui.ctx());
+            ui.close();
+        }
+        changed
+    }
+
+    /// Writes the weekly digest to its file or opens it as a mail
+    fn send_digest(&mut self, ctx: &egui::Context) {
+        let today = chrono::Local::now().date_naive();
+        let report = export::digest(&self.tagged_ranges, &self.tags, today);
+        let digest = &self.settings.digest;
+        match digest.target {
+            DigestTarget::File => {
+                let path = if digest.path.trim().is_empty() {
+                    DigestSettings::DEFAULT_PATH
+                } else {
+                    digest.path.trim()
+                };
+                let path = PathBuf::from(expand_vars(path, &Self::document_dir()).as_ref());
+                if let Err(e) = fs::write(&path, report) {
+                    self.toasts.error(format!("Writing the digest failed: {e}"));
+                    return;
+                }
+                self.toasts
+                    .success(format!("Weekly digest written to {}", path.display()));
+            }
+            DigestTarget::Email => {
+                let subject = format!("{} weekly digest", env!("CARGO_PKG_NAME"));
+                ctx.open_url(egui::OpenUrl::new_tab(format!(
+                    "mailto:{}?subject={}&body={}",
+                    digest.email.trim(),
+                    percent_encode(&subject),
+                    percent_encode(&report)
+                )));
+                self.toasts.success("Weekly digest opened in your mail app");
+            }
+        }
+        info!("Sent weekly digest");
+        self.settings.digest.last_sent = Some(today);
+        let _ = self.save_to_disk();
+    }
+
+    fn ics_path() -> PathBuf {
+        PathBuf::from("taskmonger.ics")
+    }
+
+    fn stats_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&export::stats(&self.tagged_ranges, &self.tags))
+    }
+
+    /// Writes the buffer with its tags highlighted as a web page
+    fn export_html(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger.html");
+        fs::write(
+            &path,
+            export::html(
+                env!("CARGO_PKG_NAME"),
+                &self.buffer,
+                &self.tagged_ranges,
+                &self.tags,
+            ),
+        )?;
+        Ok(path)
+    }
+
+    /// Writes tag statistics next to the state file
+    fn export_stats(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger_stats.json");
+        fs::write(&path, self.stats_json()?)?;
+        Ok(path)
+    }
+
+    /// Writes all dated ranges to the calendar file
+    fn export_ics(&self) -> std::io::Result<()> {
+        fs::write(
+            Self::ics_path(),
+            export::ics(&self.buffer, &self.tagged_ranges),
+        )?;
+        debug!("Exported calendar to {}", Self::ics_path().display());
+        Ok(())
+    }
+
+    /// Directory of the state file, used to resolve relative paths in the buffer
+    fn document_dir() -> PathBuf {
+        fs::canonicalize(Self::save_path())
+            .ok()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the state, failures are reported as a toast
+    fn save_to_disk(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.write_state();
+        if let Err(e) = &result {
+            warn!("Saving failed: {e}");
+            self.toasts.error(format!("Saving failed: {e}"));
+        }
+        result
+    }
+
+    fn write_state(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write("backup.txt", &self.buffer)?;
+        fs::write(Self::save_path(), &json)?;
+        self.write_snapshot(&json)?;
+        if self.settings.ics_auto_export && !self.safe_mode {
+            self.export_ics()?;
+        }
+        if self.settings.git_history && !self.safe_mode && self.history_since.get().is_none() {
+            self.history_since.set(Some(Instant::now()));
+        }
+        self.timings.save.set(start.elapsed());
+        self.unsaved_since.set(None);
+        debug!("Saved state to {}", Self::save_path().display());
+        Ok(())
+    }
+
+    /// Repairs what older versions or hand edits could leave behind
+    fn after_load(&mut self) {
+        if self.version > STATE_VERSION {
+            warn!(
+                "The state file was written by a newer version ({} > {STATE_VERSION})",
+                self.version
+            );
+        }
+        self.version = STATE_VERSION;
+        self.restore_line_ranges();
+        // Clean up any invalid ranges that might have been saved
+        self.clean_invalid_ranges();
+        // Older versions could leave overlapping duplicates behind
+        let tag_names: Vec<String> = self.tags.keys().cloned().collect();
+        for tag_name in tag_names {
+            self.normalize_tag_ranges(&tag_name);
+        }
+    }
+
+    fn load_from_disk() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::save_path();
+        if path.exists() {
+            let json = fs::read_to_string(&path)?;
+            let mut app: Self = serde_json::from_str(&json)?;
+            debug!("Loaded state from {}", path.display());
+            app.after_load();
+            Ok(app)
+        } else {
+            Err("Save file does not exist".into())
+        }
+    }
+
+    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+        // Try to load from disk, fallback to default
+        Self::load_from_disk().unwrap_or_else(|e| {
+            debug!("No saved state found ({}), starting fresh", e);
+            let path = Self::save_path();
+            if path.exists() {
+                warn!("Could not load {}: {e}", path.display());
+                if let Some(app) = Self::recover(&path, &e.to_string()) {
+                    return app;
+                }
+            }
+            let mut def = Self::default();
+            if path.exists() {
+                def.toasts.error(format!(
+                    "Could not load {}: {e}. Started with an empty state, the file was kept aside.",
+                    path.display()
+                ));
+            }
+            if PathBuf::from("backup.txt").exists() {
+                let mut buf: String = Default::default();
+                if let Ok(mut f) = File::open(PathBuf::from("backup.txt")) {
+                    _ = f.read_to_string(&mut buf);
+                    if !buf.is_empty() {
+                        debug!("Recovered backup");
+                        def.buffer = buf;
+                    }
+                }
+            }
+            def
+        })
+    }
+
+    /// Adds a tag and returns its name. A tag of exactly that name is kept as it is.
+    fn add_tag(&mut self, name: String) -> String {
+        let name = self.insert_tag(name);
+        let _ = self.save_to_disk();
+        name
+    }
+
+    /// Like [`Self::add_tag`], without saving
+    fn insert_tag(&mut self, name: String) -> String {
+        let name = TagNamePolicy::clean(&name);
+        if self.tags.contains_key(&name) {
+            return name;
+        }
+        let color = tag_group(&name)
+            .and_then(|group| self.group_colors.get(group).copied())
+            .unwrap_or_else(|| self.next_tag_color());
+        self.tags.insert(name.clone(), Tag::new(color));
+        name
+    }
+
+    /// Tag for a name an import brings: one matching under the tag name policy, or a new one.
+    /// Imports save once they are done.
+    fn import_tag(&mut self, name: String) -> String {
+        match self.find_tag(&name) {
+            Some(existing) => existing,
+            None => self.insert_tag(name),
+        }
+    }
+
+    /// Existing tag that counts as the same as `name` under the tag name policy
+    fn find_tag(&self, name: &str) -> Option<String> {
+        let key = self.settings.tag_name_policy.key(name);
+        self.tags
+            .keys()
+            .find(|existing| self.settings.tag_name_policy.key(existing) == key)
+            .cloned()
+    }
+
+    /// Tags that match an earlier tag under the tag name policy, each with the tag it matches
+    fn duplicate_tags(&self) -> Vec<(String, String)> {
+        self.tags
+            .keys()
+            .filter_map(|name| {
+                let first = self.find_tag(name)?;
+                (first != *name).then(|| (name.clone(), first))
+            })
+            .collect()
+    }
+
+    /// Folds tags that match under the tag name policy into the first of them.
+    /// Returns the number of tags merged away.
+    fn merge_duplicate_tags(&mut self) -> usize {
+        let duplicates = self.duplicate_tags();
+        for (name, first) in &duplicates {
+            info!("Merging duplicate tag '{name}' into '{first}'");
+            self.merge_tag(name, first);
+        }
+        duplicates.len()
+    }
+
+    /// First palette color not used by any tag yet
+    fn next_tag_color(&self) -> [u8; 3] {
+        // A theme file takes over from the palette
+        let accents = &self.theme_watch.accents;
+        if !accents.is_empty() {
+            return accents[self.tags.len() % accents.len()];
+        }
+        let palette = self.tag_palette();
+        (0..=self.tags.len())
+            .map(|i| palette.color(i))
+            .find(|c| !self.tags.values().any(|t| &t.color == c))
+            .unwrap_or_else(|| palette.color(self.tags.len()))
+    }
+
+    /// The chosen palette, or the colorblind-safe one in the colorblind mode
+    fn tag_palette(&self) -> Palette {
+        if self.settings.colorblind {
+            Palette::ColorblindSafe
+        } else {
+            self.settings.palette
+        }
+    }
+
+    /// Reassigns all tag colors from the current palette, in tag order
+    fn recolor_tags(&mut self) {
+        let palette = self.tag_palette();
+        for (i, tag) in self.tags.values_mut().enumerate() {
+            tag.color = palette.color(i);
+        }
+        let _ = self.save_to_disk();
+    }
+
+    fn apply_tag_to_selection(&mut self, tag_name: &str) {
+        let selection = self.snap_to_words(self.selection.clone());
+
+        self.tagged_ranges
+            .push(TaggedRange::new(tag_name.to_string(), selection));
+        // The new range may bridge several existing ones, fold them together
+        self.normalize_tag_ranges(tag_name);
+
+        let _ = self.save_to_disk();
+    }
+
+    /// The paragraph, or line, the cursor is in, without surrounding whitespace
+    fn block_at_cursor(&self, per_line: bool) -> Option<Range<usize>> {
+        split_blocks(&self.buffer, per_line)
+            .into_iter()
+            .find(|block| block.start <= self.cursor && self.cursor <= block.end)
+    }
+
+    /// Tags the line or paragraph at the cursor, no selection needed
+    fn tag_block_at_cursor(&mut self, tag_name: &str, per_line: bool) {
+        let Some(block) = self.block_at_cursor(per_line) else {
+            self.toasts.info("The cursor is on an empty line");
+            return;
+        };
+        debug!(tag = tag_name, ?block, per_line, "Tagging block at cursor");
+        self.tagged_ranges
+            .push(TaggedRange::new(tag_name.to_string(), block));
+        self.normalize_tag_ranges(tag_name);
+        let _ = self.save_to_disk();
+    }
+
+    /// Wraps the paragraph at the cursor in a range of the armed tag, unless it already is
+    fn tag_typed_paragraph(&mut self) {
+        let Some(tag_name) = self.armed_tag.clone() else {
+            return;
+        };
+        if !self.tags.contains_key(&tag_name) {
+            self.armed_tag = None;
+            return;
+        }
+        let Some(paragraph) = self.block_at_cursor(false) else {
+            return;
+        };
+        let covered = self.tagged_ranges.iter().any(|tr| {
+            tr.tag_name == tag_name
+                && tr.range.start <= paragraph.start
+                && paragraph.end <= tr.range.end
+        });
+        if covered {
+            return;
+        }
+        trace!(tag = tag_name, ?paragraph, "Tagging typed paragraph");
+        self.tagged_ranges
+            .push(TaggedRange::new(tag_name.clone(), paragraph));
+        self.normalize_tag_ranges(&tag_name);
+    }
+
+    /// Moves the scratchpad content to the end of the buffer as a range tagged `tag_name`
+    fn promote_scratchpad(&mut self, tag_name: &str) {
+        let text = self.scratchpad.trim();
+        if text.is_empty() {
+            return;
+        }
+        let separator = match self.buffer.as_str() {
+            "" => "",
+            b if b.ends_with("\n\n") => "",
+            b if b.ends_with('\n') => "\n",
+            _ => "\n\n",
+        };
+        self.buffer.push_str(separator);
+        let start = self.buffer.chars().count();
+        self.buffer.push_str(text);
+        let end = self.buffer.chars().count();
+        self.buffer.push('\n');
+        self.tagged_ranges
+            .push(TaggedRange::new(tag_name.to_string(), start..end));
+        self.normalize_tag_ranges(tag_name);
+        self.scratchpad.clear();
+        let _ = self.save_to_disk();
+    }
+
+    /// Merges all intersecting or adjacent ranges of a tag into single ranges.
+    /// The merged range keeps the list position and creation date of the earliest one.
+    fn normalize_tag_ranges(&mut self, tag_name: &str) {
+        if self
+            .tags
+            .get(tag_name)
+            .is_some_and(|t| t.whole_lines || t.inherit_indented)
+        {
+            self.snap_line_ranges();
+            self.inherit_indented_lines();
+        }
+        let mut i = 0;
+        while i < self.tagged_ranges.len() {
+            if self.tagged_ranges[i].tag_name != tag_name {
+                i += 1;
+                continue;
+            }
+            let mut merged = false;
+            let mut j = i + 1;
+            while j < self.tagged_ranges.len() {
+                let other = &self.tagged_ranges[j];
+                if other.tag_name == tag_name && other.range.touches(&self.tagged_ranges[i].range) {
+                    let other = self.tagged_ranges.remove(j);
+                    self.tagged_ranges[i].absorb(other);
+                    merged = true;
+                } else {
+                    j += 1;
+                }
+            }
+            // A grown range may now reach ranges we already passed, so start over
+            if merged {
+                i = 0;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Replaces a range with one range per paragraph or line of its text.
+    /// The pieces keep the range's settings, tracked time stays with the first one.
+    fn split_range(&mut self, target: &TaggedRange, per_line: bool) {
+        let Some(index) = self.tagged_ranges.iter().position(|tr| tr == target) else {
+            return;
+        };
+        let text = &self.buffer[char_to_byte_range(&self.buffer, &target.range)];
+        let blocks = split_blocks(text, per_line);
+        if blocks.len() < 2 {
+            self.toasts
+                .info("Nothing to split, the range is a single block");
+            return;
+        }
+        let now = chrono::Local::now().naive_local();
+        let pieces: Vec<TaggedRange> = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let mut piece = target.clone();
+                piece.range = target.range.start + block.start..target.range.start + block.end;
+                if i > 0 {
+                    // Distinct creation times keep the pieces apart as keys
+                    piece.created = now + chrono::Duration::microseconds(i as i64);
+                    piece.time_entries.clear();
+                    piece.timer_started = None;
+                    piece.last_run = None;
+                    piece.note.clear();
+                }
+                piece.mark();
+                piece
+            })
+            .collect();
+        info!(
+            "Split a {} range into {} pieces",
+            target.tag_name,
+            pieces.len()
+        );
+        self.tagged_ranges.splice(index..=index, pieces);
+        let _ = self.save_to_disk();
+    }
+
+    fn remove_tag_from_selection(&mut self, tag_name: &str) {
+        let selection = self.selection.clone();
+        if selection.is_empty() {
+            return;
+        }
+        self.cut_tag(tag_name, &selection);
+        let _ = self.save_to_disk();
+    }
+
+    /// Takes `range` out of the ranges of a tag, splitting those that reach past it
+    fn cut_tag(&mut self, tag_name: &str, selection: &Range<usize>) {
+        let mut remaining = Vec::with_capacity(self.tagged_ranges.len());
+        for tr in self.tagged_ranges.drain(..) {
+            if tr.tag_name != tag_name || !tr.range.intersects(selection) {
+                remaining.push(tr);
+                continue;
+            }
+            // Keep whatever is left before and after the selection
+            for piece in tr.range.subtract(selection) {
+                let mut split = tr.clone();
+                split.range = piece;
+                split.mark();
+                remaining.push(split);
+            }
+        }
+        self.tagged_ranges = remaining;
+    }
+
+    fn untag_selection(&mut self) {
+        let tag_names: Vec<String> = self.tags.keys().cloned().collect();
+        for tag_name in tag_names {
+            self.remove_tag_from_selection(&tag_name);
+        }
+    }
+
+    /// Indices of the ranges in markdown view order: pinned first, then by the sort setting
+    fn markdown_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.tagged_ranges.len()).collect();
+        self.settings
+            .range_sort
+            .sort(&mut order, &self.tagged_ranges, &self.tags);
+        order.sort_by_key(|&i| !self.tagged_ranges[i].pinned);
+        order
+    }
+
+    /// Text of a range as copied from the markdown view
+    fn range_copy_text(&self, tr: &TaggedRange) -> String {
+        let text = &self.buffer[char_to_byte_range(&self.buffer, &tr.range)];
+        if self.settings.copy_with_tag_header {
+            // The copy is markdown then, so plain ranges must not get rendered
+            let text = if let Some(language) = &tr.language {
+                Cow::Owned(fence_code_block(text, language))
+            } else if tr.plain_text {
+                Cow::Owned(fence_code_block(text, ""))
+            } else {
+                Cow::Borrowed(text)
+            };
+            format!("## {}\n\n{text}", tr.tag_name)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn delete_tagged_range(&mut self, range: &TaggedRange) {
+        if let Some(i) = self.tagged_ranges.iter().position(|t| t == range) {
+            let removed = self.tagged_ranges.remove(i);
+            self.trash_range(removed);
+        }
+        let _ = self.save_to_disk();
+    }
+
+    fn delete_tag(&mut self, tag_name: &str) {
+        self.tags.shift_remove(tag_name);
+        let (removed, kept) = std::mem::take(&mut self.tagged_ranges)
+            .into_iter()
+            .partition(|tr| tr.tag_name == tag_name);
+        self.tagged_ranges = kept;
+        for tr in removed {
+            self.trash_range(tr);
+        }
+        let _ = self.save_to_disk();
+    }
+
+    /// First line of the text in `range`, shortened
+    fn preview(&self, range: &Range<usize>) -> String {
+        self.buffer
+            .chars()
+            .skip(range.start)
+            .take(range.len())
+            .take_while(|c| c != &'\n')
+            .take(30)
+            .collect()
+    }
+
+    /// Moves all ranges of `source` over to `target` and deletes `source`
+    fn merge_tag(&mut self, source: &str, target: &str) {
+        if source == target || !self.tags.contains_key(target) {
+            return;
+        }
+        for tr in &mut self.tagged_ranges {
+            if tr.tag_name == source {
+                tr.tag_name = target.to_string();
+                tr.mark();
+            }
+        }
+        self.normalize_tag_ranges(target);
+        self.tags.shift_remove(source);
+        let _ = self.save_to_disk();
+    }
+
+    /// Char index under the pointer in the editor, if it hovers the text
+    pub fn hovered_char(output: &egui::text_edit::TextEditOutput) -> Option<usize> {
+        let pos = output.response.hover_pos()? - output.galley_pos;
+        let cursor = output.galley.cursor_from_pos(pos);
+        // The cursor sits on the nearest char boundary, step back if that is right of the pointer
+        if pos.x < output.galley.pos_from_cursor(cursor).min.x {
+            cursor.index.checked_sub(1)
+        } else {
+            Some(cursor.index)
+        }
+    }
+
+    /// Popup listing the tags of the text under the pointer, with quick actions
+    fn tagged_text_popup(&mut self, ctx: &egui::Context, output: &egui::text_edit::TextEditOutput) {
+        let hovered = Self::hovered_char(output)
+            .filter(|i| self.tagged_ranges.iter().any(|tr| tr.range.contains(i)));
+
+        if let Some(index) = hovered {
+            // Only pop up once the pointer rests, so it does not get in the way while selecting
+            let delay = ctx.style().interaction.tooltip_delay as f64;
+            let resting = ctx.input(|i| {
+                !i.pointer.any_down() && i.pointer.time_since_last_movement() as f64 >= delay
+            });
+            if resting && self.tag_hover.map(|(i, _)| i) != Some(index) {
+                let rect = output.galley.pos_from_cursor(CCursor::new(index));
+                self.tag_hover = Some((index, output.galley_pos + rect.left_bottom().to_vec2()));
+            } else if !resting && self.tag_hover.is_none() {
+                ctx.request_repaint_after_secs(delay as f32);
+            }
+        }
+
+        let Some((index, pos)) = self.tag_hover else {
+            return;
+        };
+        let ranges: Vec<TaggedRange> = self
+            .tagged_ranges
+            .iter()
+            .filter(|tr| tr.range.contains(&index))
+            .cloned()
+            .collect();
+
+        let mut remove = None;
+        let mut split = None;
+        let area = egui::Area::new("tagged_text_popup".into())
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for tr in &ranges {
+                        ui.horizontal(|ui| {
+                            let color = self
+                                .tags
+                                .get(&tr.tag_name)
+                                .map_or(ui.visuals().widgets.inactive.bg_fill, |t| {
+                                    to_color32(t.color)
+                                });
+                            egui::Frame::new()
+                                .fill(color)
+                                .corner_radius(4.0)
+                                .inner_margin(egui::Margin::symmetric(6, 2))
+                                .show(ui, |ui| {
+                                    ui.label(
+                                        RichText::new(&tr.tag_name)
+                                            .color(color.readable_text_color()),
+                                    );
+                                });
+                            if ui
+                                .small_button(LIST_BULLETS)
+                                .on_hover_text("Show in tagged ranges")
+                                .clicked()
+                            {
+                                self.reveal_in_list = Some(tr.clone());
+                            }
+                            if self.settings.markdown_view_enabled
+                                && !self.settings.markdown_full_buffer
+                                && ui
+                                    .small_button(FILE_MD)
+                                    .on_hover_text("Show in markdown view")
+                                    .clicked()
+                            {
+                                self.reveal_in_preview = Some(tr.clone());
+                            }
+                            // Plain buttons, a menu would close the popup when the pointer leaves it
+                            if ui
+                                .small_button(SCISSORS)
+                                .on_hover_text("Split into one range per paragraph")
+                                .clicked()
+                            {
+                                split = Some((tr.clone(), false));
+                            }
+                            if ui
+                                .small_button(ROWS)
+                                .on_hover_text("Split into one range per line")
+                                .clicked()
+                            {
+                                split = Some((tr.clone(), true));
+                            }
+                            if ui.small_button(X).on_hover_text("Remove tag").clicked() {
+                                remove = Some(tr.clone());
+                            }
+                        });
+                        if !tr.note.is_empty() {
+                            ui.label(RichText::new(&tr.note).weak());
+                        }
+                    }
+                });
+            });
+
+        if let Some(tr) = remove {
+            self.delete_tagged_range(&tr);
+        }
+        if let Some((tr, per_line)) = split {
+            self.split_range(&tr, per_line);
+            self.tag_hover = None;
+        }
+        if ranges.is_empty() || (hovered.is_none() && !area.response.contains_pointer()) {
+            self.tag_hover = None;
+        }
+    }
+
+    fn command_action(&mut self, ctx: &egui::Context, target: &TaggedRange, action: CommandAction) {
+        let key = target.key();
+        let commands_disabled = self.commands_disabled();
+        let Some(tr) = self.tagged_ranges.iter_mut().find(|tr| tr.key() == key) else {
+            return;
+        };
+        match action {
+            CommandAction::Set(command) => {
+                tr.command = command;
+                tr.mark();
+                let _ = self.save_to_disk();
+            }
+            CommandAction::Run => {
+                if commands_disabled {
+                    warn!("Not running '{:?}', commands are disabled", tr.command);
+                    self.toasts
+                        .warning("Running commands is disabled in the settings or safe mode");
+                    return;
+                }
+                if let Some(command) = &tr.command {
+                    let dir = Self::document_dir();
+                    let command = expand_vars(command, &dir);
+                    let receiver = commands::spawn(&command, &dir, ctx.clone());
+                    self.running_commands.insert(key, receiver);
+                }
+            }
+        }
+    }
+
+    /// Stores the results of commands that finished
+    fn poll_commands(&mut self) {
+        let mut finished = vec![];
+        for (key, receiver) in &self.running_commands {
+            if let Ok(run) = receiver.try_recv() {
+                finished.push((key.clone(), run));
+            }
+        }
+        if finished.is_empty() {
+            return;
+        }
+        for (key, run) in finished {
+            self.running_commands.remove(&key);
+            if let Some(tr) = self.tagged_ranges.iter_mut().find(|tr| tr.key() == key) {
+                let command = tr.command.clone().unwrap_or_default();
+                if run.success() {
+                    self.toasts.success(format!("`{command}` finished"));
+                } else {
+                    self.toasts.error(match run.exit_code {
+                        Some(code) => format!("`{command}` failed with exit code {code}"),
+                        None => format!("`{command}` could not run"),
+                    });
+                }
+                tr.last_run = Some(run);
+            }
+        }
+        let _ = self.save_to_disk();
+    }
+
+    /// Window listing the open ranges that are overdue or due within the week
+    fn agenda_window(&mut self, ctx: &egui::Context) {
+        let today = chrono::Local::now().date_naive();
+        let week_end = today + chrono::Days::new(7);
+        let mut due: Vec<usize> = (0..self.tagged_ranges.len())
+            .filter(|&i| {
+                let tr = &self.tagged_ranges[i];
+                !tr.done && tr.due.is_some_and(|due| due < week_end)
+            })
+            .collect();
+        // Pinned ranges first like in the markdown view, then within a day the most pressing
+        due.sort_by_key(|&i| {
+            let tr = &self.tagged_ranges[i];
+            (!tr.pinned, tr.due, std::cmp::Reverse(tr.priority))
+        });
+
+        // Sections by how the due date compares to today
+        let sections = [
+            ("Overdue", Ordering::Less),
+            ("Today", Ordering::Equal),
+            ("This week", Ordering::Greater),
+        ];
+
+        let mut open = self.settings.agenda_open;
+        let mut changed = false;
+        egui::Window::new("Agenda")
+            .open(&mut open)
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                if due.is_empty() {
+                    ui.label("Nothing due this week.");
+                    return;
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (title, section) in sections {
+                        let entries: Vec<usize> = due
+                            .iter()
+                            .copied()
+                            .filter(|&i| {
+                                self.tagged_ranges[i]
+                                    .due
+                                    .is_some_and(|due| due.cmp(&today) == section)
+                            })
+                            .collect();
+                        if entries.is_empty() {
+                            continue;
+                        }
+                        let heading =
+                            RichText::new(format!("{title} ({})", entries.len())).strong();
+                        ui.label(if section == Ordering::Less {
+                            heading.color(ui.visuals().error_fg_color)
+                        } else {
+                            heading
+                        });
+                        for i in entries {
+                            let tr = &mut self.tagged_ranges[i];
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .checkbox(&mut tr.done, "")
+                                    .on_hover_text("Done")
+                                    .changed()
+                                {
+                                    tr.mark();
+                                    changed = true;
+                                }
+                                if let Some(due) = tr.due {
+                                    ui.label(
+                                        RichText::new(due.format("%a %b %d").to_string()).weak(),
+                                    );
+                                }
+                                if tr.priority != Priority::Normal {
+                                    ui.label(tr.priority.icon(ui))
+                                        .on_hover_text(tr.priority.name());
+                                }
+                                let preview: String = self
+                                    .buffer
+                                    .chars()
+                                    .skip(tr.range.start)
+                                    .take(tr.range.len())
+                                    .take_while(|c| c != &'\n')
+                                    .take(40)
+                                    .collect();
+                                let color = self
+                                    .tags
+                                    .get(&tr.tag_name)
+                                    .map_or(ui.visuals().text_color(), |t| to_color32(t.color));
+                                if ui
+                                    .link(RichText::new(preview).color(color))
+                                    .on_hover_text(format!("{}: show in editor", tr.tag_name))
+                                    .clicked()
+                                {
+                                    self.jump_to = Some(tr.range.clone());
+                                }
+                            });
+                        }
+                        ui.add_space(6.0);
+                    }
+                });
+            });
+        if open != self.settings.agenda_open || changed {
+            self.settings.agenda_open = open;
+            let _ = self.save_to_disk();
+        }
+    }
+
+    /// "Go to line" dialog, opened with Ctrl+G
+    fn goto_line_window(&mut self, ctx: &egui::Context) {
+        let Some(text) = &mut self.goto_line else {
+            return;
+        };
+        let line_count = self.buffer.matches('\n').count() + 1;
+        let mut open = true;
+        let mut go = None;
+        egui::Window::new("Go to line")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(text)
+                        .hint_text(format!("1 - {line_count}"))
+                        .desired_width(120.0),
+                );
+                response.request_focus();
+                let line = text.trim().parse::<usize>().ok();
+                let start = line.and_then(|line| line_start(&self.buffer, line));
+                if !text.trim().is_empty() && start.is_none() {
+                    ui.colored_label(ui.visuals().warn_fg_color, "No such line");
+                }
+                if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                    go = start;
+                }
+            });
+        if let Some(start) = go {
+            self.jump_to = Some(start..start);
+            open = false;
+        }
+        if !open || ctx.input(|i| i.key_pressed(Key::Escape)) {
+            self.goto_line = None;
+        }
+    }
+
+    /// Chars the editor highlights, None for all of them.
+    /// Large buffers only get the part around what was visible last frame.
+    fn layout_window(&self) -> Option<Range<usize>> {
+        // Snapped to steps, so the cached colormap survives small scrolls
+        let step = VIRTUAL_MARGIN_CHARS / 2;
+        (self.buffer.len() > VIRTUALIZE_BYTES).then(|| {
+            let start = self
+                .visible_chars
+                .start
+                .saturating_sub(VIRTUAL_MARGIN_CHARS);
+            let end = self.visible_chars.end + VIRTUAL_MARGIN_CHARS;
+            start / step * step..end.div_ceil(step) * step
+        })
+    }
+
+    /// Tags that tracked more time over the last 7 days than their budget, with the excess
+    fn budget_overruns(&self) -> Vec<(&str, chrono::Duration)> {
+        // Same window as the time summary: today and the 6 days before
+        let first = chrono::Local::now().date_naive() - chrono::Days::new(6);
+        self.tags
+            .iter()
+            .filter_map(|(name, tag)| {
+                let budget = tag.budget()?;
+                let tracked: chrono::Duration = self
+                    .tagged_ranges
+                    .iter()
+                    .filter(|tr| &tr.tag_name == name)
+                    .flat_map(|tr| tr.sessions())
+                    .filter(|entry| entry.start.date() >= first)
+                    .map(|entry| entry.end - entry.start)
+                    .sum();
+                (tracked > budget).then(|| (name.as_str(), tracked - budget))
+            })
+            .collect()
+    }
+
+    /// Window with the tracked time per tag for the last week
+    fn time_summary_window(&mut self, ctx: &egui::Context) {
+        const DAYS: u64 = 7;
+        let today = chrono::Local::now().date_naive();
+        let first = today - chrono::Days::new(DAYS - 1);
+        let mut per_day: HashMap<(&str, chrono::NaiveDate), chrono::Duration> = HashMap::new();
+        for tr in &self.tagged_ranges {
+            for entry in tr.sessions() {
+                // Sessions count for the day they started on
+                *per_day
+                    .entry((tr.tag_name.as_str(), entry.start.date()))
+                    .or_default() += entry.end - entry.start;
+            }
+        }
+
+        egui::Window::new("Time tracked")
+            .open(&mut self.time_summary_open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if per_day.is_empty() {
+                    ui.label(format!("Start a timer with {PLAY} on a tagged range."));
+                    return;
+                }
+                egui::Grid::new("time_summary")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        for day in first.iter_days().take(DAYS as usize) {
+                            let label = if day == today {
+                                "Today".to_string()
+                            } else {
+                                day.format("%a").to_string()
+                            };
+                            ui.label(RichText::new(label).strong());
+                        }
+                        ui.label(RichText::new("Week").strong());
+                        ui.label(RichText::new("Budget").strong());
+                        ui.end_row();
+
+                        for (name, tag) in &self.tags {
+                            let days: Vec<chrono::Duration> = first
+                                .iter_days()
+                                .take(DAYS as usize)
+                                .map(|day| {
+                                    per_day
+                                        .get(&(name.as_str(), day))
+                                        .copied()
+                                        .unwrap_or_default()
+                                })
+                                .collect();
+                            let week: chrono::Duration = days.iter().copied().sum();
+                            if week.is_zero() {
+                                continue;
+                            }
+                            ui.label(RichText::new(name).color(to_color32(tag.color)));
+                            for time in days {
+                                if time.is_zero() {
+                                    ui.label("-");
+                                } else {
+                                    ui.label(format_duration(time));
+                                }
+                            }
+                            ui.label(RichText::new(format_duration(week)).strong());
+                            match tag.budget() {
+                                Some(budget) if week > budget => {
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "{} over",
+                                            format_duration(week - budget)
+                                        ))
+                                        .color(ui.visuals().warn_fg_color),
+                                    )
+                                    .on_hover_text(format!(
+                                        "Budget is {}",
+                                        format_duration(budget)
+                                    ));
+                                }
+                                Some(budget) => {
+                                    ui.label(format!("{} left", format_duration(budget - week)));
+                                }
+                                None => {
+                                    ui.label("-");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    /// Removable chips for the filters narrowing the tagged ranges list
+    fn filter_chips_ui(&mut self, ui: &mut egui::Ui) {
+        type Reset = fn(&mut Taskmonger);
+        let neutral = ui.visuals().widgets.inactive.bg_fill;
+        let mut chips: Vec<(String, Color32, Reset)> = vec![];
+        if let Some(tag) = &self.range_filter.tag {
+            let color = self.tags.get(tag).map_or(neutral, |t| to_color32(t.color));
+            chips.push((format!("{TAG} {tag}"), color, |s| s.range_filter.tag = None));
+        }
+        if let Some(group) = &self.group_filter {
+            chips.push((format!("{FOLDER} {group}"), neutral, |s| {
+                s.group_filter = None
+            }));
+        }
+        if self.range_filter.status != StatusFilter::All {
+            chips.push((
+                format!("{CHECK_SQUARE} {}", self.range_filter.status.name()),
+                neutral,
+                |s| s.range_filter.status = StatusFilter::All,
+            ));
+        }
+        if !self.range_filter.query.trim().is_empty() {
+            chips.push((
+                format!("{MAGNIFYING_GLASS} {}", self.range_filter.query.trim()),
+                neutral,
+                |s| s.range_filter.query.clear(),
+            ));
+        }
+        if chips.is_empty() {
+            return;
+        }
+
+        let mut clear: Vec<Reset> = vec![];
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Filters:");
+            for (label, color, reset) in &chips {
+                egui::Frame::new()
+                    .fill(*color)
+                    .corner_radius(4.0)
+                    .inner_margin(egui::Margin::symmetric(6, 2))
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let text_color = color.readable_text_color();
+                            ui.label(RichText::new(label).color(text_color));
+                            if ui
+                                .add(Button::new(RichText::new(X).color(text_color)).frame(false))
+                                .on_hover_text("Remove filter")
+                                .clicked()
+                            {
+                                clear.push(*reset);
+                            }
+                        });
+                    });
+            }
+            if chips.len() > 1 && ui.small_button("Clear all").clicked() {
+                clear.extend(chips.iter().map(|(_, _, reset)| *reset));
+            }
+        });
+        for reset in clear {
+            reset(self);
+        }
+        ui.separator();
+    }
+
+    /// Context menu of a tag group header
+    fn tag_group_menu(&mut self, ui: &mut egui::Ui, group: &str) {
+        let members = |name: &String| tag_group(name) == Some(group);
+        let mut color = self
+            .group_colors
+            .get(group)
+            .copied()
+            .or_else(|| {
+                self.tags
+                    .iter()
+                    .find(|(n, _)| members(n))
+                    .map(|(_, t)| t.color)
+            })
+            .unwrap_or_else(|| self.next_tag_color());
+
+        ui.horizontal(|ui| {
+            ui.label("Default color");
+            if ui.color_edit_button_srgb(&mut color).changed() {
+                self.group_colors.insert(group.to_string(), color);
+                let _ = self.save_to_disk();
+            }
+        });
+        if ui.button("Apply to all tags in group").clicked() {
+            for (_, tag) in self.tags.iter_mut().filter(|(n, _)| members(n)) {
+                tag.color = color;
+            }
+            let _ = self.save_to_disk();
+        }
+        if ui.button("Show only this group").clicked() {
+            self.group_filter = Some(group.to_string());
+        }
+        if !self.settings.sync.folder.trim().is_empty() {
+            let mut local = self.settings.sync.local_tags.iter().any(|t| t == group);
+            if ui
+                .checkbox(&mut local, "Keep on this device")
+                .on_hover_text("Tags of the group are left out of the sync, with their lines")
+                .changed()
+            {
+                self.settings.sync.set_local(group, local);
+                let _ = self.save_to_disk();
+            }
+        }
+    }
+
+    /// Draggable tag chips with their edit popups, for a subset of the tags
+    fn tag_chips_ui(&mut self, ui: &mut egui::Ui, names: Vec<String>, id: &str) {
+        let response = dnd(ui, id).show(names.iter().cloned(), |ui, tag, handle, _state| {
+            let Some(t) = self.tags.get(&tag).cloned() else {
+                return;
+            };
+            let color = to_color32(t.color);
+            let syncing = !self.settings.sync.folder.trim().is_empty();
+            let name = if syncing && self.settings.sync.is_local(&tag) {
+                format!("{CLOUD_SLASH} {}", tag_leaf(&tag))
+            } else {
+                tag_leaf(&tag).to_string()
+            };
+            let mut label = egui::RichText::new(name).color(color.readable_text_color());
+            // The same pattern as in the editor tells tags of similar colors apart
+            if self.settings.colorblind {
+                let index = self.tags.get_index_of(&tag).unwrap_or_default();
+                label = PrintMarker::for_tag(index).pattern(label);
+            }
+            let mut button = None;
+            handle.ui(ui, |ui| {
+                button = Some(ui.add(egui::Button::new(label).fill(color)));
+            });
+            let Some(button) = button else {
+                return;
+            };
+
+            let p = egui::Popup::from_toggle_button_response(&button);
+            p.show(|ui| {
+                let mut srgba = color;
+
+                if !self.selection.is_empty() {
+                    if ui
+                        .add(
+                            egui::Button::new(
+                                RichText::new("Assign to selection")
+                                    .color(srgba.readable_text_color()),
+                            )
+                            .fill(srgba),
+                        )
+                        .clicked()
+                    {
+                        self.apply_tag_to_selection(&tag);
+                    }
+                    if ui.button("Remove from selection").clicked() {
+                        self.remove_tag_from_selection(&tag);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Tag current line")
+                        .on_hover_text("The line the cursor is on")
+                        .clicked()
+                    {
+                        self.tag_block_at_cursor(&tag, true);
+                    }
+                    if ui
+                        .button("Tag current paragraph")
+                        .on_hover_text("The paragraph the cursor is in")
+                        .clicked()
+                    {
+                        self.tag_block_at_cursor(&tag, false);
+                    }
+                });
+                if ui.button("Show only this tag").clicked() {
+                    self.range_filter.tag = Some(tag.clone());
+                }
+                let button =
+                    Button::new(format!("Color {ARROW_RIGHT}")).fill(srgba.gamma_multiply(0.3));
+                use egui::containers::menu::SubMenuButton;
+                SubMenuButton::from_button(button)
+                    .config(
+                        MenuConfig::new()
+                            .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside),
+                    )
+                    .ui(ui, |ui| {
+                        ui.spacing_mut().slider_width = 200.0;
+                        if color_picker::color_picker_color32(
+                            ui,
+                            &mut srgba,
+                            color_picker::Alpha::Opaque,
+                        ) {
+                            if let Some(t) = self.tags.get_mut(&tag) {
+                                t.color = [srgba.r(), srgba.g(), srgba.b()];
+                            }
+                        }
+                    });
+                if ui.button("Rand col").clicked() {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        t.color = self
+                            .settings
+                            .palette
+                            .color(rand::random_range(0..40) as usize);
+                    }
+                }
+
+                let style_button = Button::new(format!("Style {ARROW_RIGHT}"));
+                SubMenuButton::from_button(style_button).ui(ui, |ui| {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        if t.style_ui(ui) {
+                            let _ = self.save_to_disk();
+                        }
+                    }
+                });
+
+                let budget_button = Button::new(format!("Time budget {ARROW_RIGHT}"));
+                SubMenuButton::from_button(budget_button).ui(ui, |ui| {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        if t.budget_ui(ui) {
+                            let _ = self.save_to_disk();
+                        }
+                    }
+                });
+
+                let notify_button = Button::new(format!("Notify {ARROW_RIGHT}"));
+                SubMenuButton::from_button(notify_button).ui(ui, |ui| {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        if NotifyRule::ui(ui, &mut t.notify) {
+                            let _ = self.save_to_disk();
+                        }
+                    }
+                });
+
+                if let Some(t) = self.tags.get_mut(&tag) {
+                    if ui
+                        .checkbox(&mut t.grow_while_typing, "Grow while typing at the edges")
+                        .on_hover_text(
+                            "Text typed right at the start or end of a range becomes part of it",
+                        )
+                        .changed()
+                    {
+                        let _ = self.save_to_disk();
+                    }
+                }
+                let mut whole_lines = self.tags.get(&tag).is_some_and(|t| t.whole_lines);
+                if ui
+                    .checkbox(&mut whole_lines, "Whole lines")
+                    .on_hover_text(
+                        "Ranges cover whole lines and stick to them, suits list-style tasks",
+                    )
+                    .changed()
+                {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        t.whole_lines = whole_lines;
+                    }
+                    self.snap_line_ranges();
+                    self.normalize_tag_ranges(&tag);
+                    let _ = self.save_to_disk();
+                }
+                let mut inherit = self.tags.get(&tag).is_some_and(|t| t.inherit_indented);
+                if ui
+                    .checkbox(&mut inherit, "Include indented sub-items")
+                    .on_hover_text(
+                        "Lines indented deeper below a tagged line belong to it, as in an outliner",
+                    )
+                    .changed()
+                {
+                    if let Some(t) = self.tags.get_mut(&tag) {
+                        t.inherit_indented = inherit;
                     }
+                    self.normalize_tag_ranges(&tag);
+                    let _ = self.save_to_disk();
+                }
+                if ui
+                    .button("Join nearby ranges")
+                    .on_hover_text("Join ranges of this tag that lie close together")
+                    .clicked()
+                {
+                    self.join_ranges = Some(JoinRanges::new(tag.clone()));
+                }
+                if syncing {
+                    let mut local = self.settings.sync.local_tags.contains(&tag);
+                    let response = ui
+                        .add_enabled(
+                            local || !self.settings.sync.is_local(&tag),
+                            egui::Checkbox::new(&mut local, "Keep on this device"),
+                        )
+                        .on_hover_text(
+                            "Its ranges and the lines they are on are left out of the sync, and \
+                             taken out on the other devices",
+                        )
+                        .on_disabled_hover_text("Its whole group is kept on this device");
+                    if response.changed() {
+                        self.settings.sync.set_local(&tag, local);
+                        let _ = self.save_to_disk();
+                    }
+                }
+                let mut armed = self.armed_tag.as_ref() == Some(&tag);
+                if ui
+                    .checkbox(&mut armed, "Tag everything I type")
+                    .on_hover_text(
+                        "Every paragraph typed into is added to this tag until turned off",
+                    )
+                    .changed()
+                {
+                    self.armed_tag = armed.then(|| tag.clone());
                 }
-            }
-            def
-        })
-    }
-
-    fn add_tag(&mut self, name: String) {
-        let name = name.trim().to_string();
-        self.tags.insert(name, random_color(self.tags.len()));
-        let _ = self.save_to_disk();
-    }
 
-    fn apply_tag_to_selection(&mut self, tag_name: &str) {
-        let selection = self.selection.clone();
+                let merge_button = Button::new(format!("Merge into {ARROW_RIGHT}"));
+                SubMenuButton::from_button(merge_button).ui(ui, |ui| {
+                    let targets: Vec<String> =
+                        self.tags.keys().filter(|t| **t != tag).cloned().collect();
+                    if targets.is_empty() {
+                        ui.label("No other tags");
+                    }
+                    for target in targets {
+                        if ui
+                            .button(&target)
+                            .on_hover_text(format!("Retag all '{tag}' ranges as '{target}'"))
+                            .clicked()
+                        {
+                            self.merge_tag(&tag, &target);
+                        }
+                    }
+                });
 
-        for tr in self.tagged_ranges.iter_mut() {
-            if tr.tag_name == tag_name && tr.range.intersects(&selection) {
-                tr.range = tr.range.union(&selection);
+                if ui.button(TRASH).clicked() {
+                    self.delete_tag(&tag);
+                }
+            });
+        });
+        if let Some(update) = response.final_update() {
+            // Indices are relative to `names`, translate them into the full tag order
+            let Some(from) = self.tags.get_index_of(&names[update.from]) else {
                 return;
-            }
+            };
+            let slot = match names.get(update.to) {
+                Some(name) => self.tags.get_index_of(name),
+                None => names
+                    .last()
+                    .and_then(|name| self.tags.get_index_of(name))
+                    .map(|i| i + 1),
+            };
+            let Some(slot) = slot else {
+                return;
+            };
+            // egui_dnd targets the slot before which the item is dropped
+            let to = if slot > from { slot - 1 } else { slot };
+            self.tags.move_index(from, to);
+            let _ = self.save_to_disk();
         }
-
-        // Just add the range
-        self.tagged_ranges
-            .push(TaggedRange::new(tag_name.to_string(), selection));
-
-        let _ = self.save_to_disk();
-    }
-
-    fn delete_tagged_range(&mut self, range: &TaggedRange) {
-        self.tagged_ranges.retain(|t| t != range);
-        let _ = self.save_to_disk();
-    }
-
-    fn delete_tag(&mut self, tag_name: &str) {
-        self.tags.remove(tag_name);
-        self.tagged_ranges.retain(|tr| tr.tag_name != tag_name);
-        let _ = self.save_to_disk();
     }
 
     fn clean_invalid_ranges(&mut self) {
@@ -182,16 +2836,81 @@ impl Taskmonger {
 
 impl eframe::App for Taskmonger {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Apply the theme
-        if self.settings.dark_mode {
-            ctx.set_visuals(egui::Visuals::dark());
-        } else {
-            ctx.set_visuals(egui::Visuals::light());
+        let frame_start = Instant::now();
+        self.handle_idle(ctx);
+        self.poll_commands();
+        self.flush_autosave(ctx);
+        self.flush_history(ctx);
+        #[cfg(feature = "tray")]
+        self.handle_tray(ctx);
+        self.handle_ui_scale(ctx);
+        self.handle_zoom(ctx);
+        self.handle_focus_mode(ctx);
+        self.sync_theme_colors(ctx);
+        self.poll_inbox(ctx);
+        self.poll_sync(ctx);
+        // Nothing runs on its own in safe mode
+        if !self.safe_mode {
+            if self.settings.digest.due(chrono::Local::now().date_naive()) {
+                self.send_digest(ctx);
+            }
+            if self.settings.retention.last_run != Some(chrono::Local::now().date_naive()) {
+                self.run_maintenance();
+            }
+        }
+        self.check_notifications();
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::G)) {
+            self.goto_line = Some(String::new());
+        }
+        if ctx.input_mut(|i| {
+            i.consume_key(
+                egui::Modifiers::ALT | egui::Modifiers::SHIFT,
+                Key::ArrowRight,
+            )
+        }) {
+            self.expand_selection();
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::CloseBracket)) {
+            self.indent_subtree(false);
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::OpenBracket)) {
+            self.indent_subtree(true);
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::Period)) {
+            self.toggle_fold_at_cursor();
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::B)) {
+            self.add_bookmark();
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, Key::F2)) {
+            self.jump_to_bookmark(true);
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::SHIFT, Key::F2)) {
+            self.jump_to_bookmark(false);
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, Key::S))
+            && self.save_to_disk().is_ok()
+        {
+            self.toasts.success("Saved");
         }
 
+        let today = chrono::Local::now().date_naive();
+        let mut rolled_over = false;
+        for tr in &mut self.tagged_ranges {
+            rolled_over |= tr.roll_over(today);
+        }
+        if rolled_over {
+            info!("Recurring ranges came due again");
+            let _ = self.save_to_disk();
+        }
+
+        self.apply_theme(ctx);
+
         egui::SidePanel::right("tags_panel")
             .min_width(250.0)
-            .show(ctx, |ui| {
+            .default_width(self.settings.geometry.panel("tags_panel", 250.0))
+            .show_animated(ctx, !self.focus_mode, |ui| {
                 ui.horizontal(|ui| {
                     ui.heading("Tags");
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -202,6 +2921,7 @@ impl eframe::App for Taskmonger {
                             .clicked()
                         {
                             self.settings.dark_mode = !self.settings.dark_mode;
+                            self.settings.theme = None;
                             let _ = self.save_to_disk();
                         }
 
@@ -214,14 +2934,241 @@ impl eframe::App for Taskmonger {
                                 !self.settings.markdown_view_enabled;
                             let _ = self.save_to_disk();
                         }
+
+                        ui.menu_button(EXPORT, |ui| {
+                            if ui.button("Calendar (.ics)").clicked() {
+                                match self.export_ics() {
+                                    Ok(()) => self.toasts.success(format!(
+                                        "Exported calendar to {}",
+                                        Self::ics_path().display()
+                                    )),
+                                    Err(e) => {
+                                        self.toasts.error(format!("Calendar export failed: {e}"))
+                                    }
+                                }
+                                ui.close();
+                            }
+                            if ui
+                                .button("Tag graph (.dot)")
+                                .on_hover_text("Which tags overlap or share lines, for graphviz")
+                                .clicked()
+                            {
+                                match self.export_tag_graph() {
+                                    Ok(path) => self.toasts.success(format!(
+                                        "Exported tag graph to {}",
+                                        path.display()
+                                    )),
+                                    Err(e) => {
+                                        self.toasts.error(format!("Tag graph export failed: {e}"))
+                                    }
+                                }
+                                ui.close();
+                            }
+                            if ui
+                                .button("Statistics (.json)")
+                                .on_hover_text("Counts, completion rates and tracked time per tag")
+                                .clicked()
+                            {
+                                match self.export_stats() {
+                                    Ok(path) => self.toasts.success(format!(
+                                        "Exported statistics to {}",
+                                        path.display()
+                                    )),
+                                    Err(e) => {
+                                        self.toasts.error(format!("Statistics export failed: {e}"))
+                                    }
+                                }
+                                ui.close();
+                            }
+                            if ui
+                                .button("Web page (.html)")
+                                .on_hover_text("The buffer with its tags highlighted, for sharing")
+                                .clicked()
+                            {
+                                match self.export_html() {
+                                    Ok(path) => self.toasts.success(format!(
+                                        "Exported web page to {}",
+                                        path.display()
+                                    )),
+                                    Err(e) => {
+                                        self.toasts.error(format!("Web page export failed: {e}"))
+                                    }
+                                }
+                                ui.close();
+                            }
+                            self.pdf_ui(ui);
+                            self.pdf_highlights_ui(ui);
+                            self.browser_bookmarks_ui(ui);
+                            self.opml_ui(ui);
+                            self.todo_txt_ui(ui);
+                            self.org_ui(ui);
+                            if ui
+                                .checkbox(
+                                    &mut self.settings.ics_auto_export,
+                                    "Keep calendar updated",
+                                )
+                                .on_hover_text(format!(
+                                    "Rewrite {} on every change, for calendar apps to subscribe to",
+                                    Self::ics_path().display()
+                                ))
+                                .changed()
+                            {
+                                let _ = self.save_to_disk();
+                            }
+                            ui.separator();
+                            if self.digest_ui(ui) {
+                                let _ = self.save_to_disk();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Export");
+
+                        if ui.button(CALENDAR_CHECK).on_hover_text("Agenda").clicked() {
+                            self.settings.agenda_open = !self.settings.agenda_open;
+                            let _ = self.save_to_disk();
+                        }
+
+                        if ui
+                            .button(BROOM)
+                            .on_hover_text("Data retention and trash")
+                            .clicked()
+                        {
+                            self.maintenance_open = !self.maintenance_open;
+                        }
+
+                        if ui.button(ARCHIVE).on_hover_text("Archive").clicked() {
+                            self.archive_open = !self.archive_open;
+                        }
+
+                        let lock_icon = if self.locked { LOCK } else { LOCK_OPEN };
+                        ui.toggle_value(&mut self.locked, lock_icon)
+                            .on_hover_text("Lock the buffer against edits, tagging still works");
+
+                        ui.toggle_value(&mut self.print_preview, PRINTER)
+                            .on_hover_text("Print preview: white page with gray tag patterns");
+
+                        if ui
+                            .button(MAGIC_WAND)
+                            .on_hover_text("Auto-tagging rules")
+                            .clicked()
+                        {
+                            self.rules_open = !self.rules_open;
+                        }
+
+                        if ui.button(TREE_STRUCTURE).on_hover_text("Outline").clicked() {
+                            self.outline_open = !self.outline_open;
+                        }
+
+                        if ui.button(BOOKMARKS).on_hover_text("Bookmarks").clicked() {
+                            self.bookmarks_open = !self.bookmarks_open;
+                        }
+
+                        if ui.button(TIMER).on_hover_text("Time tracked").clicked() {
+                            self.time_summary_open = !self.time_summary_open;
+                        }
+
+                        if self.settings.git_history
+                            && ui
+                                .button(CLOCK_COUNTER_CLOCKWISE)
+                                .on_hover_text("History")
+                                .clicked()
+                        {
+                            self.history_open = !self.history_open;
+                        }
+
+                        if !self.settings.sync.folder.trim().is_empty() {
+                            let conflicts = self.sync_conflicts();
+                            let (icon, hover) = if conflicts > 0 {
+                                (CLOUD_WARNING, format!("Sync, {conflicts} conflicts"))
+                            } else {
+                                (CLOUD_CHECK, "Sync".to_string())
+                            };
+                            if ui.button(icon).on_hover_text(hover).clicked() {
+                                self.sync_open = !self.sync_open;
+                            }
+                        }
+
+                        if SplitView::menu(ui, &mut self.settings.split_view) {
+                            let _ = self.save_to_disk();
+                        }
+
+                        if ui
+                            .button(NOTE_PENCIL)
+                            .on_hover_text("Toggle scratchpad")
+                            .clicked()
+                        {
+                            self.settings.scratchpad_open = !self.settings.scratchpad_open;
+                            let _ = self.save_to_disk();
+                        }
+
+                        if ui.button(GEAR).on_hover_text("Settings").clicked() {
+                            self.settings_open = !self.settings_open;
+                        }
+
+                        ui.menu_button(BUG, |ui| {
+                            if ui
+                                .checkbox(
+                                    &mut self.settings.show_latency_overlay,
+                                    "Latency overlay",
+                                )
+                                .changed()
+                            {
+                                let _ = self.save_to_disk();
+                            }
+                            ui.checkbox(&mut self.log_viewer.open, "Log viewer");
+                        })
+                        .response
+                        .on_hover_text("Debug");
+
+                        ui.menu_button(PALETTE, |ui| {
+                            ui.label("New tags take their colors from");
+                            for palette in Palette::ALL {
+                                if ui
+                                    .radio_value(
+                                        &mut self.settings.palette,
+                                        palette,
+                                        palette.name(),
+                                    )
+                                    .changed()
+                                {
+                                    let _ = self.save_to_disk();
+                                }
+                            }
+                            ui.separator();
+                            if ui.button("Recolor all tags").clicked() {
+                                self.recolor_tags();
+                            }
+                            ui.separator();
+                            ui.label("Overlapping tags");
+                            let style = &mut self.settings.overlap_style;
+                            if ui
+                                .radio_value(style, OverlapStyle::Stripes, "Stripes")
+                                .changed()
+                                | ui.radio_value(style, OverlapStyle::Mix, "Mix colors")
+                                    .changed()
+                            {
+                                let _ = self.save_to_disk();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Tag colors");
                     });
                 });
                 ui.separator();
 
                 // Tag adding
-                if ui.button("Add tag").clicked() {
-                    ctx.memory_mut(|w| w.data.insert_temp("tag".into(), "".to_string()));
-                }
+                ui.horizontal(|ui| {
+                    if ui.button("Add tag").clicked() {
+                        ctx.memory_mut(|w| w.data.insert_temp("tag".into(), "".to_string()));
+                    }
+                    if ui
+                        .add_enabled(!self.selection.is_empty(), Button::new("Untag selection"))
+                        .on_hover_text("Remove all tags from the selected text")
+                        .clicked()
+                    {
+                        self.untag_selection();
+                    }
+                });
 
                 let tag = ctx.memory(|r| r.data.get_temp::<String>("tag".into()));
 
@@ -237,19 +3184,43 @@ impl eframe::App for Taskmonger {
                         }
                         ui.memory_mut(|w| w.request_focus(text_edit.id));
 
+                        // A matching tag is suggested, adding the name as typed stays possible
+                        let existing = self.find_tag(&tag_name);
+                        let same = existing
+                            .as_ref()
+                            .is_some_and(|existing| *existing == TagNamePolicy::clean(&tag_name));
+                        if let Some(existing) = &existing {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Tag '{existing}' already exists"));
+                                if ui.button("Use it and assign").clicked() {
+                                    self.apply_tag_to_selection(existing);
+                                    ctx.memory_mut(|w| w.data.remove_temp::<String>("tag".into()));
+                                }
+                            });
+                        }
+
                         ui.horizontal(|ui| {
                             if ui.button("Cancel").clicked() {
                                 ctx.memory_mut(|w| w.data.remove_temp::<String>("tag".into()));
                             }
 
-                            if ui.button("Add").clicked() {
+                            let valid = !same && !TagNamePolicy::clean(&tag_name).is_empty();
+                            let verb = if existing.is_some() {
+                                "Add anyway"
+                            } else {
+                                "Add"
+                            };
+                            if ui.add_enabled(valid, Button::new(verb)).clicked() {
                                 self.add_tag(tag_name.clone());
                                 ctx.memory_mut(|w| w.data.remove_temp::<String>("tag".into()));
                             }
 
-                            if ui.button("Add and assign").clicked() {
-                                self.apply_tag_to_selection(&tag);
-                                self.add_tag(tag_name);
+                            if ui
+                                .add_enabled(valid, Button::new("Add and assign"))
+                                .clicked()
+                            {
+                                let name = self.add_tag(tag_name);
+                                self.apply_tag_to_selection(&name);
                                 ctx.memory_mut(|w| w.data.remove_temp::<String>("tag".into()));
                             }
                         });
@@ -261,89 +3232,148 @@ impl eframe::App for Taskmonger {
                     .max_height(150.0)
                     .min_scrolled_width(222.)
                     .show(ui, |ui| {
-                        ui.horizontal_wrapped(|ui| {
-                            for (tag, c) in self.tags.clone() {
-                                let color = to_color32(c);
-                                let button = ui.add(
-                                    egui::Button::new(
-                                        egui::RichText::new(tag.to_string())
-                                            .color(color.readable_text_color()),
-                                    )
-                                    .fill(color),
-                                );
+                        // Ungrouped tags and then one collapsible section per group
+                        let mut groups: IndexMap<Option<String>, Vec<String>> = IndexMap::new();
+                        for name in self.tags.keys() {
+                            groups
+                                .entry(tag_group(name).map(str::to_string))
+                                .or_default()
+                                .push(name.clone());
+                        }
+                        groups.sort_by_key(|group, _| group.is_some());
 
-                                let p = egui::Popup::from_toggle_button_response(&button);
-                                p.show(|ui| {
-                                    let mut srgba = Color32::from_rgb(c[0], c[1], c[2]);
-
-                                    if !self.selection.is_empty() {
-                                        if ui
-                                            .add(
-                                                egui::Button::new(
-                                                    RichText::new("Assign to selection")
-                                                        .color(srgba.readable_text_color()),
-                                                )
-                                                .fill(srgba),
-                                            )
-                                            .clicked()
-                                        {
-                                            self.apply_tag_to_selection(&tag);
-                                        }
-                                    } else {
-                                        ui.label("Select something to assign this tag.");
-                                    }
-                                    let button = Button::new(format!("Color {ARROW_RIGHT}"))
-                                        .fill(srgba.gamma_multiply(0.3));
-                                    use egui::containers::menu::SubMenuButton;
-                                    SubMenuButton::from_button(button)
-                                        .config(MenuConfig::new().close_behavior(
-                                            egui::PopupCloseBehavior::CloseOnClickOutside,
-                                        ))
-                                        .ui(ui, |ui| {
-                                            ui.spacing_mut().slider_width = 200.0;
-                                            if color_picker::color_picker_color32(
+                        for (group, names) in groups {
+                            let Some(group) = group else {
+                                ui.horizontal_wrapped(|ui| {
+                                    self.tag_chips_ui(ui, names, "tag_chips");
+                                });
+                                continue;
+                            };
+                            let header =
+                                egui::CollapsingHeader::new(RichText::new(&group).strong())
+                                    .id_salt(("tag_group", &group))
+                                    .default_open(true)
+                                    .show(ui, |ui| {
+                                        ui.horizontal_wrapped(|ui| {
+                                            self.tag_chips_ui(
                                                 ui,
-                                                &mut srgba,
-                                                color_picker::Alpha::Opaque,
-                                            ) {
-                                                if let Some(t) = self.tags.get_mut(&tag) {
-                                                    t[0] = srgba.r();
-                                                    t[1] = srgba.g();
-                                                    t[2] = srgba.b();
-                                                }
-                                            }
+                                                names,
+                                                &format!("tag_chips_{group}"),
+                                            );
                                         });
-                                    if ui.button("Rand col").clicked() {
-                                        if let Some(t) = self.tags.get_mut(&tag) {
-                                            *t = random_color(rand::random_range(0..40) as usize);
-                                        }
-                                    }
-
-                                    if ui.button(TRASH).clicked() {
-                                        self.delete_tag(&tag);
-                                    }
-                                });
-                            }
-                        });
+                                    });
+                            header
+                                .header_response
+                                .on_hover_text("Right click for group options")
+                                .context_menu(|ui| self.tag_group_menu(ui, &group));
+                        }
                     });
 
                 ui.separator();
-                ui.label("Tagged ranges:");
+                ui.horizontal(|ui| {
+                    ui.label("Tagged ranges:");
+                    let groups: IndexSet<&str> =
+                        self.tags.keys().filter_map(|t| tag_group(t)).collect();
+                    if !groups.is_empty() || self.group_filter.is_some() {
+                        egui::ComboBox::from_id_salt("group_filter")
+                            .selected_text(self.group_filter.as_deref().unwrap_or("All groups"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.group_filter, None, "All groups");
+                                for group in groups {
+                                    ui.selectable_value(
+                                        &mut self.group_filter,
+                                        Some(group.to_string()),
+                                        group,
+                                    );
+                                }
+                            });
+                    }
+                });
+
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.range_filter.query)
+                        .hint_text(format!("{MAGNIFYING_GLASS} Filter by tag or text"))
+                        .desired_width(f32::INFINITY),
+                );
+                ui.horizontal(|ui| {
+                    let filter = &mut self.range_filter;
+                    egui::ComboBox::from_id_salt("status_filter")
+                        .selected_text(filter.status.name())
+                        .show_ui(ui, |ui| {
+                            for status in StatusFilter::ALL {
+                                ui.selectable_value(&mut filter.status, status, status.name());
+                            }
+                        });
+                    let sort = self.settings.range_sort;
+                    egui::ComboBox::from_id_salt("range_sort")
+                        .selected_text(sort.name())
+                        .show_ui(ui, |ui| {
+                            for mode in RangeSort::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.range_sort,
+                                    mode,
+                                    mode.name(),
+                                );
+                            }
+                        });
+                    if self.settings.range_sort != sort {
+                        let _ = self.save_to_disk();
+                    }
+                });
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     let mut delete_tr: Option<TaggedRange> = None;
+                    let mut archive_tr: Option<usize> = None;
+                    let mut ranges_changed = false;
+                    let mut timer_started = None;
+                    let mut command_edit: Option<(TaggedRange, CommandAction)> = None;
 
-                    dnd(ui, "drag_drop").show_vec(
-                        &mut self.tagged_ranges,
-                        |ui, item, handle, state| {
-                            ui.horizontal(|ui| {
-                                handle.ui(ui, |ui| {
-                                    if state.dragged {
-                                        ui.label("-");
-                                    } else {
-                                        ui.label(DOTS_SIX_VERTICAL);
-                                    }
-                                });
+                    let today = chrono::Local::now().date_naive();
+                    let mut visible: Vec<usize> = (0..self.tagged_ranges.len())
+                        .filter(|&i| {
+                            let tr = &self.tagged_ranges[i];
+                            let in_group = self.group_filter.as_ref().is_none_or(|group| {
+                                tag_group(&tr.tag_name) == Some(group.as_str())
+                            });
+                            let text = &self.buffer[char_to_byte_range(&self.buffer, &tr.range)];
+                            in_group && self.range_filter.matches(tr, text, today)
+                        })
+                        .collect();
+                    self.settings
+                        .range_sort
+                        .sort(&mut visible, &self.tagged_ranges, &self.tags);
+                    // Dragging only makes sense when the list shows the stored order
+                    let draggable = self.settings.range_sort == RangeSort::Manual;
+                    let commands_disabled = self.commands_disabled();
+
+                    let response = dnd(ui, "drag_drop").show(
+                        visible.iter().copied(),
+                        |ui, index, handle, state| {
+                            let item = &mut self.tagged_ranges[index];
+                            let mut note =
+                                egui::collapsing_header::CollapsingState::load_with_default_open(
+                                    ui.ctx(),
+                                    egui::Id::new(("range_note", item.key())),
+                                    false,
+                                );
+                            let row = ui.horizontal(|ui| {
+                                if draggable {
+                                    handle.ui(ui, |ui| {
+                                        if state.dragged {
+                                            ui.label("-");
+                                        } else {
+                                            ui.label(DOTS_SIX_VERTICAL);
+                                        }
+                                    });
+                                }
+                                if ui
+                                    .checkbox(&mut item.done, "")
+                                    .on_hover_text("Done")
+                                    .changed()
+                                {
+                                    item.mark();
+                                    ranges_changed = true;
+                                }
 
                                 let preview: String = self
                                     .buffer
@@ -354,213 +3384,922 @@ impl eframe::App for Taskmonger {
                                     .take(30)
                                     .collect();
 
-                                if let Some(col) = &self.tags.get(&item.tag_name) {
-                                    let color = to_color32(**col);
-                                    ui.label(
-                                        egui::RichText::new(format!(
-                                            "{}: {}",
-                                            item.tag_name, preview
-                                        ))
-                                        .color(color),
-                                    );
-                                } else {
-                                    ui.label(format!("{}: {}", item.tag_name, preview));
+                                let mut label =
+                                    egui::RichText::new(format!("{}: {}", item.tag_name, preview));
+                                if let Some(tag) = self.tags.get(&item.tag_name) {
+                                    label = label.color(to_color32(tag.color));
                                 }
+                                if item.done {
+                                    label = label.strikethrough();
+                                }
+                                ui.label(label);
                                 ui.horizontal(|ui| {
                                     ui.with_layout(
                                         Layout::right_to_left(egui::Align::Center),
                                         |ui| {
-                                            // TODO: add button to scroll to this range
                                             if ui.small_button(TRASH).clicked() {
                                                 delete_tr = Some(item.clone());
                                             }
+                                            if item.done
+                                                && ui
+                                                    .small_button(ARCHIVE)
+                                                    .on_hover_text("Archive")
+                                                    .clicked()
+                                            {
+                                                archive_tr = Some(index);
+                                            }
+                                            let note_icon = if item.note.is_empty() {
+                                                RichText::new(NOTE_BLANK)
+                                            } else {
+                                                RichText::new(NOTE).strong()
+                                            };
+                                            let note_button = ui.small_button(note_icon);
+                                            let note_button = if item.note.is_empty() {
+                                                note_button.on_hover_text("Add a note")
+                                            } else {
+                                                note_button.on_hover_text(&item.note)
+                                            };
+                                            if note_button.clicked() {
+                                                note.toggle(ui);
+                                            }
+                                            let (pin_icon, pin_hover) = if item.pinned {
+                                                (PUSH_PIN_SLASH, "Unpin")
+                                            } else {
+                                                (PUSH_PIN, "Pin to top of markdown view")
+                                            };
+                                            if ui
+                                                .small_button(pin_icon)
+                                                .on_hover_text(pin_hover)
+                                                .clicked()
+                                            {
+                                                item.pinned = !item.pinned;
+                                                ranges_changed = true;
+                                            }
+                                            if ui
+                                                .small_button(CROSSHAIR)
+                                                .on_hover_text("Show in editor")
+                                                .clicked()
+                                            {
+                                                self.jump_to = Some(item.range.clone());
+                                            }
+                                            if item.due_ui(ui, today) {
+                                                item.mark();
+                                                ranges_changed = true;
+                                            }
+                                            if Priority::menu(ui, &mut item.priority) {
+                                                item.mark();
+                                                ranges_changed = true;
+                                            }
+                                            if language_menu(ui, &mut item.language) {
+                                                item.mark();
+                                                ranges_changed = true;
+                                            }
+                                            let running =
+                                                self.running_commands.contains_key(&item.key());
+                                            if let Some(action) =
+                                                command_menu(ui, item, running, commands_disabled)
+                                            {
+                                                command_edit = Some((item.clone(), action));
+                                            }
+                                            let tracked = item.tracked_time();
+                                            let (timer_icon, timer_hover) =
+                                                if item.timer_started.is_some() {
+                                                    (STOP, "Stop timer")
+                                                } else {
+                                                    (PLAY, "Start timer")
+                                                };
+                                            let timer_button = if tracked.is_zero() {
+                                                Button::new(timer_icon)
+                                            } else {
+                                                Button::new(format!(
+                                                    "{timer_icon} {}",
+                                                    format_duration(tracked)
+                                                ))
+                                            };
+                                            if ui
+                                                .add(timer_button.small())
+                                                .on_hover_text(timer_hover)
+                                                .clicked()
+                                            {
+                                                if item.timer_started.is_some() {
+                                                    item.stop_timer();
+                                                } else {
+                                                    item.start_timer();
+                                                    timer_started = Some(index);
+                                                }
+                                                ranges_changed = true;
+                                            }
                                         },
                                     );
                                 });
                             });
+                            note.show_body_unindented(ui, |ui| {
+                                if ui
+                                    .add(
+                                        egui::TextEdit::multiline(&mut item.note)
+                                            .hint_text("Note")
+                                            .desired_rows(2)
+                                            .desired_width(f32::INFINITY),
+                                    )
+                                    .changed()
+                                {
+                                    item.mark();
+                                    ranges_changed = true;
+                                }
+                            });
+                            if self.reveal_in_list.as_ref() == Some(&*item) {
+                                row.response.scroll_to_me(Some(egui::Align::Center));
+                                row.response.highlight();
+                                self.reveal_in_list = None;
+                            }
                         },
                     );
+                    if let Some(update) = response.final_update() {
+                        // Map the positions in the visible list back to the full list
+                        let from = visible[update.from];
+                        let to = visible
+                            .get(update.to)
+                            .copied()
+                            .unwrap_or_else(|| visible.last().map_or(0, |last| last + 1));
+                        egui_dnd::utils::shift_vec(from, to, &mut self.tagged_ranges);
+                        ranges_changed = true;
+                    }
+                    if let Some((target, action)) = command_edit {
+                        self.command_action(ui.ctx(), &target, action);
+                    }
+                    if let Some(started) = timer_started {
+                        // Only one timer runs at a time
+                        for (i, tr) in self.tagged_ranges.iter_mut().enumerate() {
+                            if i != started {
+                                tr.stop_timer();
+                            }
+                        }
+                    }
+                    if let Some(i) = archive_tr {
+                        self.archive_ranges(vec![i], self.settings.archive_removes_text);
+                    }
                     if let Some(r) = delete_tr {
                         self.delete_tagged_range(&r);
                     };
+                    if ranges_changed {
+                        let _ = self.save_to_disk();
+                    }
+                });
+            });
+
+        // Markdown view panel (conditional, on the right side of text edit)
+        if self.settings.markdown_view_enabled && !self.focus_mode {
+            egui::SidePanel::right("markdown_view_panel")
+                .resizable(true)
+                .default_width(self.settings.geometry.panel("markdown_view_panel", 300.0))
+                .min_width(200.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Markdown");
+                        ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.menu_button(PAINT_BRUSH, |ui| {
+                                if self.settings.markdown_style.ui(ui) {
+                                    let _ = self.save_to_disk();
+                                }
+                            })
+                            .response
+                            .on_hover_text("Preview style");
+                            ui.menu_button(COPY, |ui| {
+                                if ui
+                                    .checkbox(
+                                        &mut self.settings.copy_with_tag_header,
+                                        "Include tag name",
+                                    )
+                                    .changed()
+                                {
+                                    let _ = self.save_to_disk();
+                                }
+                                if ui.button("Copy all").clicked() {
+                                    let text = if self.settings.markdown_full_buffer {
+                                        self.buffer.clone()
+                                    } else {
+                                        self.markdown_order()
+                                            .into_iter()
+                                            .map(|i| self.range_copy_text(&self.tagged_ranges[i]))
+                                            .collect::<Vec<_>>()
+                                            .join("\n\n")
+                                    };
+                                    ui.ctx().copy_text(text);
+                                    self.toasts.info("Copied to clipboard");
+                                    ui.close();
+                                }
+                            })
+                            .response
+                            .on_hover_text("Copy");
+                            if ui
+                                .toggle_value(
+                                    &mut self.settings.markdown_full_buffer,
+                                    "Whole buffer",
+                                )
+                                .on_hover_text("Render the entire buffer instead of tagged ranges")
+                                .changed()
+                            {
+                                let _ = self.save_to_disk();
+                            }
+                        });
+                    });
+                    ui.separator();
+
+                    if !self.safe_mode {
+                        self.settings.markdown_style.apply(ui);
+                    }
+                    ui.style_mut().interaction.selectable_labels = true;
+                    let document_dir = Self::document_dir();
+                    if self.settings.markdown_full_buffer {
+                        let mut area = egui::ScrollArea::vertical().id_salt("markdown_full_buffer");
+                        let target = self.scroll_sync.preview.take_target();
+                        if let Some(offset) = target {
+                            area = area.vertical_scroll_offset(offset);
+                        }
+                        let output = area.show(ui, |ui| {
+                            let cache = self.markdown_cache
+                                .get(MarkdownCaches::FULL_BUFFER, &self.buffer);
+                            show_markdown(ui, cache, &self.buffer, &document_dir)
+                        });
+                        if let (Some(updated), false) = (&output.inner, self.locked) {
+                            self.buffer = updated.clone();
+                            let _ = self.save_to_disk();
+                        }
+                        if let Some(fraction) =
+                            self.scroll_sync.preview.update(&output, target.is_some())
+                        {
+                            self.scroll_sync.editor.target = Some(fraction);
+                        }
+                        return;
+                    }
+
+                    let mut checkbox_edits: Vec<(Range<usize>, String)> = vec![];
+                    let mut jump = None;
+                    let mut reveal_done = false;
+                    let mut plain_toggle: Option<TaggedRange> = None;
+                    let mut language_edit: Option<(TaggedRange, Option<String>)> = None;
+                    let mut command_edit: Option<(TaggedRange, CommandAction)> = None;
+                    let commands_disabled = self.commands_disabled();
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for i in self.markdown_order() {
+                            let tr = &self.tagged_ranges[i];
+                            if tr.range.end <= self.buffer.len() {
+                                let byte_range = char_to_byte_range(&self.buffer, &tr.range);
+                                let text = &self.buffer[byte_range.clone()];
+                                let copy_text = self.range_copy_text(tr);
+
+                                let card = ui.scope_builder(
+                                    egui::UiBuilder::new().sense(egui::Sense::click()),
+                                    |ui| {
+                                        ui.group(|ui| {
+                                            // Show tag name header with color
+                                            let header = if tr.pinned {
+                                                format!("{PUSH_PIN} {}", tr.tag_name)
+                                            } else {
+                                                tr.tag_name.clone()
+                                            };
+                                            ui.horizontal(|ui| {
+                                                if let Some(tag) = self.tags.get(&tr.tag_name) {
+                                                    let color = to_color32(tag.color);
+                                                    ui.label(
+                                                        egui::RichText::new(header)
+                                                            .color(color)
+                                                            .strong(),
+                                                    );
+                                                } else {
+                                                    ui.label(egui::RichText::new(header).strong());
+                                                }
+                                                ui.with_layout(
+                                                    Layout::right_to_left(egui::Align::Center),
+                                                    |ui| {
+                                                        if ui
+                                                            .small_button(COPY)
+                                                            .on_hover_text("Copy")
+                                                            .clicked()
+                                                        {
+                                                            ui.ctx().copy_text(copy_text);
+                                                            self.toasts.info("Copied to clipboard");
+                                                        }
+                                                        if ui
+                                                            .selectable_label(tr.plain_text, TEXT_T)
+                                                            .on_hover_text("Render as plain text")
+                                                            .clicked()
+                                                        {
+                                                            plain_toggle = Some(tr.clone());
+                                                        }
+                                                        let mut language = tr.language.clone();
+                                                        if language_menu(ui, &mut language) {
+                                                            language_edit = Some((tr.clone(), language));
+                                                        }
+                                                        let running = self.running_commands.contains_key(&tr.key());
+                                                        if let Some(action) =
+                                                            command_menu(ui, tr, running, commands_disabled)
+                                                        {
+                                                            command_edit = Some((tr.clone(), action));
+                                                        }
+                                                    },
+                                                );
+                                            });
+
+                                            ui.separator();
+
+                                            // Keyed by content, so ranges moving around keep their cache
+                                            let cache_key = MarkdownCaches::key(&tr.tag_name, text);
+
+                                            if let Some(language) = &tr.language {
+                                                let theme = egui_extras::syntax_highlighting::CodeTheme::from_memory(
+                                                    ui.ctx(),
+                                                    ui.style(),
+                                                );
+                                                egui_extras::syntax_highlighting::code_view_ui(
+                                                    ui,
+                                                    &theme,
+                                                    text,
+                                                    syntax_extension(language),
+                                                );
+                                            } else if tr.plain_text {
+                                                ui.label(RichText::new(text).monospace());
+                                            } else if let Some(updated) = show_markdown(
+                                                ui,
+                                                self.markdown_cache.get(cache_key, text),
+                                                text,
+                                                &document_dir,
+                                            ) {
+                                                checkbox_edits.push((byte_range, updated));
+                                            }
+                                            command_output_ui(ui, tr, self.running_commands.contains_key(&tr.key()));
+                                        });
+                                    },
+                                );
+                                if card.response.double_clicked() {
+                                    jump = Some(tr.range.clone());
+                                }
+                                if self.reveal_in_preview.as_ref() == Some(tr) {
+                                    card.response.scroll_to_me(Some(egui::Align::Center));
+                                    card.response.highlight();
+                                    reveal_done = true;
+                                }
+                                ui.add_space(10.0);
+                            }
+                        }
+                    });
+                    if jump.is_some() {
+                        self.jump_to = jump;
+                    }
+                    if reveal_done {
+                        self.reveal_in_preview = None;
+                    }
+                    if let Some((target, action)) = command_edit {
+                        self.command_action(ui.ctx(), &target, action);
+                    }
+                    if let Some((edited, language)) = language_edit {
+                        if let Some(tr) = self.tagged_ranges.iter_mut().find(|tr| **tr == edited) {
+                            tr.language = language;
+                            tr.mark();
+                        }
+                        let _ = self.save_to_disk();
+                    }
+                    if let Some(toggled) = plain_toggle {
+                        if let Some(tr) = self.tagged_ranges.iter_mut().find(|tr| **tr == toggled) {
+                            tr.plain_text = !tr.plain_text;
+                            tr.mark();
+                        }
+                        let _ = self.save_to_disk();
+                    }
+                    // Toggling a checkbox keeps the text length, so no range needs shifting
+                    if !checkbox_edits.is_empty() && !self.locked {
+                        for (byte_range, updated) in checkbox_edits {
+                            self.buffer.replace_range(byte_range, &updated);
+                        }
+                        let _ = self.save_to_disk();
+                    }
                 });
+        }
+
+        egui::TopBottomPanel::bottom("status_bar").show_animated(ctx, !self.focus_mode, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                if self.safe_mode {
+                    ui.label(
+                        RichText::new(format!("{SHIELD} Safe mode"))
+                            .color(ui.visuals().warn_fg_color),
+                    )
+                    .on_hover_text(
+                        "Commands, the digest, cleanup and calendar export are off and the default look is used. Restart without --safe-mode to leave.",
+                    );
+                    ui.separator();
+                }
+                if let Some(tag) = self.armed_tag.clone() {
+                    let color = self
+                        .tags
+                        .get(&tag)
+                        .map(|t| to_color32(t.color))
+                        .unwrap_or(ui.visuals().text_color());
+                    ui.label(RichText::new(format!("{RECORD} Tagging as {tag}")).color(color));
+                    if ui
+                        .small_button("Stop")
+                        .on_hover_text("Stop tagging typed paragraphs")
+                        .clicked()
+                    {
+                        self.armed_tag = None;
+                    }
+                    ui.separator();
+                }
+                let (line, col) = line_col(&self.buffer, self.cursor);
+                ui.label(format!("Ln {line}, Col {col}"));
+                if self.settings.font_size != default_font_size() {
+                    ui.separator();
+                    let zoom = self.settings.font_size / default_font_size() * 100.0;
+                    if ui
+                        .small_button(format!("{MAGNIFYING_GLASS} {zoom:.0}%"))
+                        .on_hover_text("Reset zoom (Ctrl+0)")
+                        .clicked()
+                    {
+                        self.settings.font_size = default_font_size();
+                        let _ = self.save_to_disk();
+                    }
+                }
+                ui.separator();
+                ui.label(format!(
+                    "{} words, {} chars, {} lines",
+                    self.buffer.split_whitespace().count(),
+                    self.buffer.chars().count(),
+                    self.buffer.lines().count()
+                ));
+                if !self.selection.is_empty() {
+                    ui.separator();
+                    ui.label(format!("{} selected", self.selection.len()));
+                }
+                if self.locked {
+                    ui.separator();
+                    ui.label(format!("{LOCK} Read-only"));
+                }
+                if let Some(progress) = self.colormap.progress() {
+                    ui.separator();
+                    ui.label("Highlighting the paste");
+                    ui.add(
+                        egui::ProgressBar::new(progress)
+                            .desired_width(100.0)
+                            .show_percentage(),
+                    );
+                }
+                if let Some(pasted) = self.pasted.clone() {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!("{WARNING} Auto-tag the pasted text?"))
+                            .color(ui.visuals().warn_fg_color),
+                    );
+                    if ui.small_button("Apply rules").clicked() {
+                        self.pasted = None;
+                        let added = self.apply_rules_to(&pasted);
+                        self.toasts
+                            .info(format!("Rules tagged {added} range(s) in the paste"));
+                    }
+                    if ui.small_button("Skip").clicked() {
+                        self.pasted = None;
+                    }
+                }
+                if self.long_lines > 0 {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!(
+                            "{WARNING} {} very long line(s) shown without highlighting",
+                            self.long_lines
+                        ))
+                        .color(ui.visuals().warn_fg_color),
+                    )
+                    .on_hover_text(format!(
+                        "Lines over {LONG_LINE_CHARS} characters are drawn as plain text to keep the editor responsive"
+                    ));
+                }
+                for (tag, over) in self.budget_overruns() {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!(
+                            "{WARNING} {tag} is {} over its time budget",
+                            format_duration(over)
+                        ))
+                        .color(ui.visuals().warn_fg_color),
+                    );
+                }
+                if !self.tags.is_empty() {
+                    ui.separator();
+                }
+                for (tag, t) in &self.tags {
+                    let count = self
+                        .tagged_ranges
+                        .iter()
+                        .filter(|tr| &tr.tag_name == tag)
+                        .count();
+                    ui.label(RichText::new(format!("{tag}: {count}")).color(to_color32(t.color)));
+                }
             });
+        });
 
-        // Markdown view panel (conditional, on the right side of text edit)
-        if self.settings.markdown_view_enabled {
-            egui::SidePanel::right("markdown_view_panel")
+        if self.settings.scratchpad_open && !self.focus_mode {
+            egui::TopBottomPanel::bottom("scratchpad")
                 .resizable(true)
-                .default_width(300.0)
-                .min_width(200.0)
+                .default_height(self.settings.geometry.panel("scratchpad", 120.0))
                 .show(ctx, |ui| {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        // Sort tagged ranges by their position in the buffer
-
-                        for tr in &self.tagged_ranges {
-                            if tr.range.end <= self.buffer.len() {
-                                let text = &self.buffer[tr.range.clone()];
-
-                                ui.group(|ui| {
-                                    // Show tag name header with color
-                                    if let Some(col) = self.tags.get(&tr.tag_name) {
-                                        let color = to_color32(*col);
-                                        ui.label(
-                                            egui::RichText::new(&tr.tag_name).color(color).strong(),
+                    ui.horizontal(|ui| {
+                        ui.label("Scratchpad");
+                        ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                            let tag = self
+                                .scratchpad_tag
+                                .clone()
+                                .filter(|t| self.tags.contains_key(t));
+                            let promote = ui
+                                .add_enabled(
+                                    tag.is_some() && !self.scratchpad.trim().is_empty(),
+                                    Button::new(format!("{ARROW_LINE_UP} Promote")),
+                                )
+                                .on_hover_text("Move the notes into the buffer as a tagged range");
+                            if promote.clicked() {
+                                if let Some(tag) = &tag {
+                                    self.promote_scratchpad(tag);
+                                }
+                            }
+                            egui::ComboBox::from_id_salt("scratchpad_tag")
+                                .selected_text(tag.as_deref().unwrap_or("Pick a tag"))
+                                .show_ui(ui, |ui| {
+                                    for name in self.tags.keys() {
+                                        ui.selectable_value(
+                                            &mut self.scratchpad_tag,
+                                            Some(name.clone()),
+                                            name,
                                         );
-                                    } else {
-                                        ui.label(egui::RichText::new(&tr.tag_name).strong());
                                     }
-
-                                    ui.separator();
-
-                                    // Get or create cache for this tagged range
-                                    let cache_key = format!(
-                                        "{}:{}-{}",
-                                        tr.tag_name, tr.range.start, tr.range.end
-                                    );
-                                    let cache = self.markdown_cache.entry(cache_key).or_default();
-
-                                    // Render markdown
-                                    egui_commonmark::CommonMarkViewer::new().show(ui, cache, text);
                                 });
-                                ui.add_space(10.0);
-                            }
+                        });
+                    });
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let edit = ui.add(
+                            egui::TextEdit::multiline(&mut self.scratchpad)
+                                .hint_text("Quick notes that stay out of the buffer")
+                                .desired_width(f32::INFINITY),
+                        );
+                        if edit.changed() {
+                            let _ = self.save_to_disk();
                         }
                     });
                 });
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            let mut tagged_ranges = self.tagged_ranges.clone();
-            let tags = self.tags.clone();
-
-            //  make a default colormap for all chars
-            let mut colormap: HashMap<usize, Color32> = Default::default();
-            // go though all ranges. If color exists, mix it.
-            for tr in &mut tagged_ranges {
-                if let Some(col) = tags.get(&tr.tag_name) {
-                    for i in &mut tr.range {
-                        let x = to_color32(*col);
-                        colormap
-                            .entry(i)
-                            .and_modify(|c| {
-                                *c = mix_colors(*c, x);
-                            })
-                            .or_insert(x);
+            if self.print_preview {
+                self.print_legend_ui(ui);
+            } else if !self.focus_mode {
+                self.quick_add_ui(ui);
+            }
+            if !self.focus_mode {
+                self.filter_chips_ui(ui);
+            }
+            // Chars of a large paste
+            let large_paste = ctx.input(|i| {
+                i.events.iter().find_map(|e| match e {
+                    egui::Event::Paste(text) if text.len() > self.settings.large_paste_kb * 1024 => {
+                        Some(text.chars().count())
                     }
-                }
+                    _ => None,
+                })
+            });
+            if large_paste.is_some() {
+                debug!("Large paste, highlighting in steps");
+                self.colormap.in_steps = true;
             }
 
+            let marking = if self.print_preview {
+                Marking::Print
+            } else if self.settings.mark_as_background {
+                Marking::Background
+            } else {
+                Marking::Text
+            };
+            let overlap = self.settings.overlap_style;
+            let (editor_font, font_size) = if self.safe_mode {
+                (EditorFont::default(), default_font_size())
+            } else {
+                (self.settings.editor_font, self.settings.font_size)
+            };
+            let colormap_start = Instant::now();
+            // The second view may show any part of the buffer, so everything is highlighted
+            let split_view = self.settings.split_view;
+            let split_size = split_view
+                .and_then(|split| self.settings.geometry.panels.get(split.key()).copied());
+            let window = self.layout_window().filter(|_| split_view.is_none());
+            let line_tag_style = self.settings.line_tag_style;
+            let line_tags = self.line_tag_colors(line_tag_style);
+            let (folded, fold_markers) = self.folded();
+            self.colormap.skip_line_tags =
+                line_tag_style != LineTagStyle::Text && marking != Marking::Print;
+            self.colormap.patterns = self.settings.colorblind && !self.safe_mode;
+
+            if self.colormap.update(
+                ui,
+                &self.buffer,
+                &self.tagged_ranges,
+                &self.tags,
+                marking,
+                window.as_ref(),
+            ) {
+                self.timings.colormap = colormap_start.elapsed();
+            }
+            // Plain text until a rebuild in steps is done
+            let highlighting_paused = self.colormap.busy();
+            if highlighting_paused {
+                ctx.request_repaint();
+            }
+            let colormap = &self.colormap;
+
+            let mut layouter_time = Duration::ZERO;
+            let mut long_lines = 0;
+
             let mut layouter = |ui: &egui::Ui, text: &dyn egui::TextBuffer, wrap_width: f32| {
+                let layouter_start = Instant::now();
                 let text = text.as_str();
                 let mut layout_job = egui::text::LayoutJob::default();
                 layout_job.wrap.max_width = wrap_width;
 
                 let default_color = ui.style().visuals.text_color();
-                let font_id = egui::FontId::monospace(14.0);
-
-                // TODO: if it is faster, collapse ranges so we need fewer layoutjobs
-                // TODO: expose this as setting later
-                let background = self.settings.mark_as_background;
+                let font_id = editor_font.font_id(font_size);
+                let bold_font_id = editor_font.bold_font_id(font_size);
 
-                for (i, c) in text.chars().enumerate() {
-                    let selected = self.selection.contains(&i);
-                    let selected_color = ui.visuals().selection.bg_fill;
+                if highlighting_paused {
+                    layout_job.append(
+                        text,
+                        0.0,
+                        egui::TextFormat {
+                            font_id,
+                            color: default_color,
+                            ..Default::default()
+                        },
+                    );
+                    let galley = ui.fonts_mut(|f| f.layout_job(layout_job));
+                    layouter_time += layouter_start.elapsed();
+                    return galley;
+                }
 
-                    if let Some(col) = colormap.get(&i) {
+                // TODO: if it is faster, collapse ranges so we need fewer layoutjobs
+                let mut i = 0;
+                long_lines = 0;
+                let mut marks_at = colormap.lookup();
+                for line in text.split_inclusive('\n') {
+                    let line_chars = line.chars().count();
+                    if folded.iter().any(|range| range.contains(&i)) {
+                        layout_job.append(line, 0.0, outline::folded_format());
+                        i += line_chars;
+                        continue;
+                    }
+                    let offscreen = window
+                        .as_ref()
+                        .is_some_and(|w| i + line_chars < w.start || i > w.end);
+                    if line_chars > LONG_LINE_CHARS || offscreen {
+                        // Formatting every char of pasted logs or JSON freezes the UI, keep it plain
                         layout_job.append(
-                            &c.to_string(),
+                            line,
                             0.0,
                             egui::TextFormat {
                                 font_id: font_id.clone(),
-                                color: if background {
-                                    if selected {
-                                        ui.visuals().selection.stroke.color
-                                    } else {
-                                        default_color
-                                    }
-                                } else if selected {
-                                    ui.visuals().selection.stroke.color
-                                } else {
-                                    *col
-                                },
-                                background: if selected {
-                                    selected_color
-                                } else if background {
-                                    *col
-                                } else {
-                                    Color32::from_white_alpha(0)
-                                },
+                                color: default_color,
                                 ..Default::default()
                             },
                         );
-                    } else {
-                        // default text
-                        layout_job.append(
-                            &c.to_string(),
-                            0.0,
-                            egui::TextFormat {
-                                font_id: font_id.clone(),
-                                color: if selected {
-                                    ui.visuals().selection.stroke.color
-                                } else {
-                                    default_color
+                        if !offscreen {
+                            long_lines += 1;
+                        }
+                        i += line_chars;
+                        continue;
+                    }
+
+                    for c in line.chars() {
+                        let selected = self.selection.contains(&i);
+                        let selected_color = ui.visuals().selection.bg_fill;
+
+                        if let Some(marks) = marks_at.get(i) {
+                            let stroke = |colors: &[Color32]| {
+                                overlap
+                                    .pick(colors, i)
+                                    .map_or(egui::Stroke::NONE, |c| egui::Stroke::new(1.0, c))
+                            };
+                            layout_job.append(
+                                &c.to_string(),
+                                0.0,
+                                egui::TextFormat {
+                                    font_id: if marks.bold {
+                                        bold_font_id.clone()
+                                    } else {
+                                        font_id.clone()
+                                    },
+                                    color: if selected {
+                                        ui.visuals().selection.stroke.color
+                                    } else {
+                                        overlap
+                                            .pick(&marks.text, i)
+                                            .or(colormap.syntax(i))
+                                            .unwrap_or(default_color)
+                                    },
+                                    background: if selected {
+                                        selected_color
+                                    } else {
+                                        overlap
+                                            .pick(&marks.background, i)
+                                            .unwrap_or(Color32::from_white_alpha(0))
+                                    },
+                                    italics: marks.italics,
+                                    underline: stroke(&marks.underline),
+                                    strikethrough: stroke(&marks.strikethrough),
+                                    ..Default::default()
                                 },
-                                background: if selected {
-                                    selected_color
-                                } else {
-                                    Color32::from_white_alpha(0)
+                            );
+                        } else {
+                            // default text
+                            layout_job.append(
+                                &c.to_string(),
+                                0.0,
+                                egui::TextFormat {
+                                    font_id: font_id.clone(),
+                                    color: if selected {
+                                        ui.visuals().selection.stroke.color
+                                    } else {
+                                        default_color
+                                    },
+                                    background: if selected {
+                                        selected_color
+                                    } else {
+                                        Color32::from_white_alpha(0)
+                                    },
+                                    ..Default::default()
                                 },
-                                ..Default::default()
-                            },
-                        );
+                            );
+                        }
+                        i += 1;
                     }
                 }
 
-                ui.fonts_mut(|f| f.layout_job(layout_job))
+                let galley = ui.fonts_mut(|f| f.layout_job(layout_job));
+                layouter_time += layouter_start.elapsed();
+                galley
             };
 
-            let output = egui::ScrollArea::vertical()
-                .show(ui, |ui| {
-                    egui::TextEdit::multiline(&mut self.buffer)
-                        .desired_width(f32::INFINITY)
-                        .lock_focus(true)
-                        .frame(false)
-                        .font(egui::TextStyle::Monospace)
-                        .layouter(&mut layouter)
-                        .show(ui)
-                })
-                .inner;
+            if let Some(split) = split_view {
+                split.ui(ui, &self.buffer, split_size, &mut layouter);
+            }
+
+            let mut area = egui::ScrollArea::vertical();
+            let target = self.scroll_sync.editor.take_target();
+            if let Some(offset) = target {
+                area = area.vertical_scroll_offset(offset);
+            }
+            let jump = self.jump_to.take();
+            let select = self.select.take();
+            let line_count = self.buffer.matches('\n').count() + 1;
+            let (cursor_line, _) = line_col(&self.buffer, self.cursor);
+            let gutter = self
+                .settings
+                .show_line_numbers
+                .then(|| egui::FontId::monospace(font_size * 0.85));
+            let mut visible = 0..0;
+            let column = self.focus_mode.then_some(self.settings.focus_width);
+            let typewriter = self.focus_mode && self.settings.typewriter_scrolling;
+            let previous_cursor = self.cursor;
+            let scroll_output = area.show(ui, |ui| {
+                let gutter_width = gutter.as_ref().map_or(0.0, |font_id| {
+                    let digit = ui.fonts_mut(|f| f.glyph_width(font_id, '0'));
+                    digit * line_count.to_string().len() as f32 + 12.0
+                });
+                let mut output = ui
+                    .horizontal_top(|ui| {
+                        if let Some(column) = column {
+                            ui.add_space(focus::focus_margin(ui.available_width(), column));
+                        }
+                        // Drawn below the text, once the rows are known
+                        let background = ui.painter().add(egui::Shape::Noop);
+                        let bar_left = ui.cursor().left();
+                        if line_tag_style == LineTagStyle::Bar {
+                            ui.add_space(LINE_BAR_SPACE);
+                        }
+                        let bookmark_left = ui.cursor().left();
+                        if !self.bookmarks.is_empty() {
+                            ui.add_space(BOOKMARK_SPACE);
+                        }
+                        ui.add_space(gutter_width);
+                        let mut editor = |text: &mut dyn egui::TextBuffer| {
+                            egui::TextEdit::multiline(text)
+                                .desired_width(column.unwrap_or(f32::INFINITY))
+                                .lock_focus(true)
+                                .frame(false)
+                                .font(egui::TextStyle::Monospace)
+                                .layouter(&mut layouter)
+                                .show(ui)
+                        };
+                        // A `&str` can still be selected, but not edited
+                        let output = if self.locked {
+                            editor(&mut self.buffer.as_str())
+                        } else {
+                            editor(&mut self.buffer)
+                        };
+                        if !line_tags.is_empty() {
+                            let shapes = lines::line_tag_shapes(
+                                ui,
+                                &output,
+                                &line_tags,
+                                line_tag_style,
+                                bar_left,
+                            );
+                            ui.painter().set(background, egui::Shape::Vec(shapes));
+                        }
+                        let markers = bookmarks::bookmark_shapes(
+                            ui,
+                            &output,
+                            &self.bookmarks,
+                            bookmark_left,
+                        );
+                        ui.painter().extend(markers);
+                        outline::fold_markers(ui, &output, &fold_markers);
+                        if typewriter {
+                            let moved = output
+                                .cursor_range
+                                .is_some_and(|range| range.primary.index != previous_cursor);
+                            focus::typewriter_scroll(ui, &output, moved);
+                        }
+                        output
+                    })
+                    .inner;
+                if let Some(font_id) = gutter {
+                    line_numbers_ui(ui, &output, font_id, gutter_width, cursor_line);
+                }
+                visible = visible_chars(ui.clip_rect(), &output);
+                if let Some(range) = select {
+                    output.state.cursor.set_char_range(Some(CCursorRange::two(
+                        CCursor::new(range.start),
+                        CCursor::new(range.end),
+                    )));
+                    output.state.clone().store(ui.ctx(), output.response.id);
+                    ui.ctx().request_repaint();
+                }
+                if let Some(range) = jump {
+                    // Select the range and bring its start into view
+                    let (start, end) = (CCursor::new(range.start), CCursor::new(range.end));
+                    output
+                        .state
+                        .cursor
+                        .set_char_range(Some(CCursorRange::two(start, end)));
+                    output.state.clone().store(ui.ctx(), output.response.id);
+                    output.response.request_focus();
+                    let rect = output
+                        .galley
+                        .pos_from_cursor(start)
+                        .translate(output.galley_pos.to_vec2());
+                    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                }
+                output
+            });
+            self.timings.layouter = layouter_time;
+            self.long_lines = long_lines;
+            if visible != self.visible_chars {
+                // Scrolled, highlight the new part right away
+                let outside = window
+                    .as_ref()
+                    .is_some_and(|w| visible.start < w.start || visible.end > w.end);
+                self.visible_chars = visible;
+                if outside {
+                    ctx.request_repaint();
+                }
+            }
+            self.tagged_text_popup(ctx, &scroll_output.inner);
+            if let Some(fraction) = self
+                .scroll_sync
+                .editor
+                .update(&scroll_output, target.is_some())
+            {
+                if self.settings.markdown_view_enabled && self.settings.markdown_full_buffer {
+                    self.scroll_sync.preview.target = Some(fraction);
+                }
+            }
+            let output = scroll_output.inner;
 
             let selection_len = self.selection.len() as i32;
 
             if let Some(cursor_range) = output.state.cursor.char_range() {
                 self.selection = cursor_range.as_sorted_char_range();
+                self.cursor = cursor_range.primary.index;
             }
+            self.select_word_on_double_click(ctx, &output);
             if output.response.changed() {
-                debug!("len {selection_len}");
                 let mut shift: i32 = 0;
 
                 if let Some(range) = output.cursor_range {
-                    debug!("Cursor range {:?}", range);
-
                     let keys_down = ctx.input(|i| i.keys_down.clone());
                     let delete = keys_down.iter().nth(0) == Some(&Key::Backspace);
 
                     if !keys_down.is_empty() {
-                        debug!("key down {:?}", keys_down);
-
                         // No selection
                         if selection_len == 0 {
-                            debug!("Single range Cursor");
                             if delete {
                                 shift -= 1;
                             } else {
                                 shift += 1;
                             }
                         } else {
-                            // let selection_len = range.as_sorted_char_range().len() as i32;
-                            debug!("Cursor range {:?}, len {selection_len}", range);
                             if delete {
                                 shift -= selection_len;
                             } else {
@@ -568,15 +4307,28 @@ impl eframe::App for Taskmonger {
                             }
                         }
 
-                        debug!("shift {:?}", shift);
+                        debug!(
+                            cursor = range.primary.index,
+                            selection_len,
+                            shift,
+                            keys = ?keys_down,
+                            "Shifting ranges after edit"
+                        );
 
+                        // Where inserted text begins
+                        let inserted_at = range.primary.index as i32 - shift;
+                        self.shift_bookmarks(inserted_at, shift);
+                        self.shift_folds(inserted_at, shift);
                         for tr in &mut self.tagged_ranges {
-                            debug!(
-                                "Tagged range: {:?}, shift: {shift}, cursor: {}",
-                                tr, range.primary.index
-                            );
+                            let before = tr.range.clone();
                             let mut modified = false;
-                            if tr.range.start > range.primary.index {
+                            let grow = self
+                                .tags
+                                .get(&tr.tag_name)
+                                .is_none_or(|t| t.grow_while_typing);
+                            let typed_at_start =
+                                !grow && shift > 0 && tr.range.start as i32 == inserted_at;
+                            if tr.range.start > range.primary.index || typed_at_start {
                                 tr.range.start =
                                     (tr.range.start as i32 + shift).unsigned_abs() as usize;
                                 modified = true;
@@ -588,7 +4340,7 @@ impl eframe::App for Taskmonger {
                                 modified = true;
                             }
                             // when at the end of a range, extend it. This is convenient when extending to an existing paragraph
-                            if tr.range.end == range.primary.index - 1 && shift > 0 {
+                            if grow && tr.range.end == range.primary.index - 1 && shift > 0 {
                                 let last = self
                                     .buffer
                                     .chars()
@@ -599,7 +4351,7 @@ impl eframe::App for Taskmonger {
                                     .chars()
                                     .nth(range.primary.index.saturating_sub(2));
 
-                                info!("Shift 1, {:?} {:?}", before_last, last);
+                                trace!(?before_last, ?last, "Edit at the end of a range");
                                 if !(last == Some('\n') && before_last == Some('\n')) {
                                     tr.range.end =
                                         (tr.range.end as i32 + shift).unsigned_abs() as usize;
@@ -608,6 +4360,7 @@ impl eframe::App for Taskmonger {
                                 // TODO: if last two chars before cursor are newlines, donot do the next shift
                             }
                             if modified {
+                                trace!(tag = tr.tag_name, ?before, after = ?tr.range, "Range shifted");
                                 tr.mark();
                             }
                         }
@@ -615,15 +4368,383 @@ impl eframe::App for Taskmonger {
                 }
 
                 // Clean up invalid ranges and auto-save on text changes
+                self.snap_line_ranges();
+                self.inherit_indented_lines();
                 self.clean_invalid_ranges();
-                let _ = self.save_to_disk();
+                self.tag_typed_paragraph();
+                match large_paste {
+                    // Rules over a whole paste wait for a yes
+                    Some(chars) if self.has_rules() => {
+                        self.pasted = Some(self.cursor.saturating_sub(chars)..self.cursor);
+                    }
+                    Some(_) => {}
+                    None => self.apply_rules_at_cursor(),
+                }
+                self.autosave();
+            }
+        });
+
+        self.markdown_cache
+            .evict(self.settings.markdown_cache_limit);
+
+        self.timings.frame = frame_start.elapsed();
+        self.toasts.show(ctx);
+        if self.time_summary_open {
+            self.time_summary_window(ctx);
+        }
+        if self.archive_open {
+            self.archive_window(ctx);
+        }
+        if self.rules_open {
+            self.rules_window(ctx);
+        }
+        if self.bookmarks_open {
+            self.bookmarks_window(ctx);
+        }
+        if self.outline_open {
+            self.outline_window(ctx);
+        }
+        if self.sync_open {
+            self.sync_window(ctx);
+        }
+        if self.history_open {
+            self.history_window(ctx);
+        }
+        if self.maintenance_open {
+            self.maintenance_window(ctx);
+        }
+        if self.settings.agenda_open {
+            self.agenda_window(ctx);
+        }
+        if self.log_viewer.open {
+            self.log_viewer.show(ctx);
+        }
+        if self.settings_open {
+            self.settings_window(ctx);
+        }
+        self.recovery_window(ctx);
+        self.goto_line_window(ctx);
+        self.join_window(ctx);
+        self.tag_collision_window(ctx);
+        // Keep running timers ticking
+        if self
+            .tagged_ranges
+            .iter()
+            .any(|tr| tr.timer_started.is_some())
+        {
+            ctx.request_repaint_after_secs(1.0);
+        }
+
+        if self.settings.show_latency_overlay {
+            let ranges: usize = self
+                .tagged_ranges
+                .iter()
+                .map(TaggedRange::estimated_bytes)
+                .sum();
+            let memory = [
+                ("Buffer", format_bytes(self.buffer.len())),
+                ("Tagged ranges", format_bytes(ranges)),
+                (
+                    "Markdown caches",
+                    format!(
+                        "{} ({} / {})",
+                        format_bytes(self.markdown_cache.bytes()),
+                        self.markdown_cache.len(),
+                        self.settings.markdown_cache_limit
+                    ),
+                ),
+                (
+                    "Snapshots",
+                    format!(
+                        "{} ({} / {})",
+                        format_bytes(self.revisions.bytes()),
+                        self.revisions.len(),
+                        self.settings.snapshot_cache_limit
+                    ),
+                ),
+            ];
+            self.timings.overlay(ctx, &memory);
+        }
+        self.remember_geometry(ctx);
+    }
+}
+
+/// Menu button to set the code language of a range. Returns true if it changed.
+fn language_menu(ui: &mut egui::Ui, language: &mut Option<String>) -> bool {
+    const SUGGESTIONS: [&str; 8] = ["rust", "python", "sql", "json", "toml", "yaml", "sh", "log"];
+    let before = language.clone();
+    let icon = if language.is_some() {
+        RichText::new(CODE).strong()
+    } else {
+        RichText::new(CODE)
+    };
+    ui.menu_button(icon, |ui| {
+        let mut name = language.clone().unwrap_or_default();
+        if ui
+            .add(egui::TextEdit::singleline(&mut name).hint_text("Language"))
+            .changed()
+        {
+            *language = Some(name.trim().to_lowercase()).filter(|n| !n.is_empty());
+        }
+        ui.horizontal_wrapped(|ui| {
+            for suggestion in SUGGESTIONS {
+                if ui
+                    .selectable_label(language.as_deref() == Some(suggestion), suggestion)
+                    .clicked()
+                {
+                    *language = Some(suggestion.to_string());
+                    ui.close();
+                }
+            }
+        });
+        if language.is_some() && ui.button("No language").clicked() {
+            *language = None;
+            ui.close();
+        }
+    })
+    .response
+    .on_hover_text(match language {
+        Some(name) => format!("Code language: {name}"),
+        None => "Set code language".to_string(),
+    });
+    *language != before
+}
+
+/// What the command menu of a range asks for
+enum CommandAction {
+    Set(Option<String>),
+    Run,
+}
+
+/// Menu button to edit and run the command of a range
+fn command_menu(
+    ui: &mut egui::Ui,
+    tr: &TaggedRange,
+    running: bool,
+    disabled: bool,
+) -> Option<CommandAction> {
+    let mut action = None;
+    let icon = match &tr.last_run {
+        _ if tr.command.is_none() => RichText::new(TERMINAL_WINDOW),
+        Some(run) if !run.success() => {
+            RichText::new(TERMINAL_WINDOW).color(ui.visuals().error_fg_color)
+        }
+        _ => RichText::new(TERMINAL_WINDOW).strong(),
+    };
+    ui.menu_button(icon, |ui| {
+        let mut command = tr.command.clone().unwrap_or_default();
+        if ui
+            .add(egui::TextEdit::singleline(&mut command).hint_text("Command, e.g. cargo test"))
+            .changed()
+        {
+            action = Some(CommandAction::Set(
+                Some(command.clone()).filter(|c| !c.trim().is_empty()),
+            ));
+        }
+        ui.horizontal(|ui| {
+            let can_run = !disabled && !running && !command.trim().is_empty();
+            if ui
+                .add_enabled(can_run, Button::new(format!("{PLAY} Run")))
+                .on_disabled_hover_text(if disabled {
+                    "Running commands is disabled in the settings"
+                } else {
+                    "Nothing to run"
+                })
+                .clicked()
+            {
+                action = Some(CommandAction::Run);
+                ui.close();
+            }
+            if running {
+                ui.spinner();
+            } else if let Some(run) = &tr.last_run {
+                ui.label(run_status(ui, run));
             }
         });
+    })
+    .response
+    .on_hover_text(match &tr.command {
+        Some(command) => format!("Command: {command}"),
+        None => "Attach a command".to_string(),
+    });
+    action
+}
+
+fn run_status(ui: &egui::Ui, run: &CommandRun) -> RichText {
+    let exit = run
+        .exit_code
+        .map_or("failed to run".to_string(), |code| format!("exit {code}"));
+    let text = RichText::new(format!("{exit}, {}", run.finished.format("%b %d %H:%M")));
+    if run.success() {
+        text
+    } else {
+        text.color(ui.visuals().error_fg_color)
+    }
+}
+
+/// Collapsible output of the last command run beneath a range
+fn command_output_ui(ui: &mut egui::Ui, tr: &TaggedRange, running: bool) {
+    if running {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(tr.command.as_deref().unwrap_or_default());
+        });
+        return;
+    }
+    let Some(run) = &tr.last_run else {
+        return;
+    };
+    egui::CollapsingHeader::new(run_status(ui, run))
+        .id_salt(("command_output", tr.key()))
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    ui.label(RichText::new(&run.output).monospace());
+                });
+        });
+}
+
+/// Chars of the rows of the editor galley that intersect `clip`
+fn visible_chars(clip: egui::Rect, output: &egui::text_edit::TextEditOutput) -> Range<usize> {
+    let mut index = 0;
+    let mut visible: Option<Range<usize>> = None;
+    for row in &output.galley.rows {
+        let rect = row.rect().translate(output.galley_pos.to_vec2());
+        let chars = row.char_count_including_newline();
+        if rect.top() > clip.bottom() {
+            break;
+        }
+        if rect.bottom() >= clip.top() {
+            let start = visible.as_ref().map_or(index, |v| v.start);
+            visible = Some(start..index + chars);
+        }
+        index += chars;
+    }
+    visible.unwrap_or(index..index)
+}
+
+/// Paints the number of each buffer line left of the editor, next to the row it starts on
+fn line_numbers_ui(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    font_id: egui::FontId,
+    gutter_width: f32,
+    cursor_line: usize,
+) {
+    let right = output.galley_pos.x - 6.0;
+    let clip = ui.clip_rect();
+    let painter = ui.painter();
+    let mut line = 1;
+    let mut line_starts = true;
+    for row in &output.galley.rows {
+        let rect = row.rect().translate(output.galley_pos.to_vec2());
+        if line_starts && rect.bottom() >= clip.top() && rect.top() <= clip.bottom() {
+            let color = if line == cursor_line {
+                ui.visuals().text_color()
+            } else {
+                ui.visuals().weak_text_color()
+            };
+            painter.text(
+                egui::pos2(right, rect.top()),
+                egui::Align2::RIGHT_TOP,
+                line.to_string(),
+                font_id.clone(),
+                color,
+            );
+        }
+        if rect.top() > clip.bottom() {
+            break;
+        }
+        line_starts = row.ends_with_newline;
+        if line_starts {
+            line += 1;
+        }
+    }
+    let separator = output.galley_pos.x - 3.0;
+    let top = output.response.rect.top();
+    let bottom = output.response.rect.bottom().max(clip.bottom());
+    if gutter_width > 0.0 {
+        painter.vline(
+            separator,
+            top..=bottom,
+            ui.visuals().widgets.noninteractive.bg_stroke,
+        );
+    }
+}
+
+/// Renders markdown with clickable task list checkboxes.
+/// Returns the updated source text when a checkbox was toggled.
+fn show_markdown(
+    ui: &mut egui::Ui,
+    cache: &mut egui_commonmark::CommonMarkCache,
+    source: &str,
+    document_dir: &Path,
+) -> Option<String> {
+    let mut rendered = resolve_image_paths(source, document_dir).into_owned();
+    let response = egui_commonmark::CommonMarkViewer::new().show_mut(ui, cache, &mut rendered);
+    if !response.response.changed() {
+        return None;
     }
+    let mut updated = source.to_string();
+    sync_task_checkboxes(&mut updated, &rendered).then_some(updated)
 }
 
 fn main() -> eframe::Result<()> {
-    env_logger::init();
+    // Status bars run this every few seconds, so it comes before setting up anything
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "status") {
+        std::process::exit(cli::status(&args[2..]));
+    }
+
+    let cli_log = logs::init_cli();
+
+    // Add a line from scripts and terminals, through the inbox the app polls
+    if args.get(1).is_some_and(|arg| arg == "add") {
+        match capture::add_from_cli(&args[2..]) {
+            Ok(line) => {
+                println!("Added: {line}");
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(cli::exit::USAGE);
+            }
+        }
+    }
+
+    // List ranges for scripts and status bars, the exit code tells if any are overdue
+    if args.get(1).is_some_and(|arg| arg == "list") {
+        std::process::exit(cli::list(&args[2..]));
+    }
+    // Validate the state file, e.g. in a git hook before committing it
+    if args.get(1).is_some_and(|arg| arg == "check") {
+        std::process::exit(cli::check(&args[2..]));
+    }
+
+    // Print statistics of the saved state for scripts and dashboards
+    if std::env::args().any(|arg| arg == "--stats") {
+        let json = Taskmonger::load_from_disk().and_then(|app| Ok(app.stats_json()?));
+        match json {
+            Ok(json) => {
+                println!("{json}");
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("Could not read {}: {e}", Taskmonger::save_path().display());
+                std::process::exit(cli::exit::NO_STATE);
+            }
+        }
+    }
+
+    // Only the app logs to a file, the guard flushes it when main returns
+    drop(cli_log);
+    let _log_guard = logs::init();
+
+    let safe_mode = std::env::args().any(|arg| arg == "--safe-mode");
+    if safe_mode {
+        info!("Starting in safe mode");
+    }
 
     let icon_rgba = image::load_from_memory(include_bytes!("../icon.png"))
         .expect("Failed to load icon")
@@ -635,14 +4756,67 @@ fn main() -> eframe::Result<()> {
         height,
     };
 
+    // Small window for a global shortcut, its lines go to the running app through the inbox
+    if std::env::args().any(|arg| arg == "--capture") {
+        let options = eframe::NativeOptions {
+            viewport: egui::ViewportBuilder::default()
+                .with_inner_size([420.0, 48.0])
+                .with_title("Taskmonger quick add")
+                .with_always_on_top()
+                .with_icon(icon_data),
+            ..Default::default()
+        };
+        return eframe::run_native(
+            "Taskmonger quick add",
+            options,
+            Box::new(|cc| {
+                cc.egui_ctx.set_fonts(fonts());
+                Ok(Box::<CaptureApp>::default())
+            }),
+        );
+    }
+
+    // Safe mode starts with the default look, that includes the window
+    let geometry = if safe_mode {
+        Geometry::default()
+    } else {
+        Geometry::load()
+    };
     let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1000.0, 700.0])
-            .with_title("Taskmonger")
-            .with_icon(icon_data),
+        viewport: geometry.apply(
+            egui::ViewportBuilder::default()
+                .with_inner_size([1000.0, 700.0])
+                .with_title("Taskmonger")
+                .with_icon(icon_data.clone()),
+        ),
         ..Default::default()
     };
 
+    eframe::run_native(
+        "Taskmonger",
+        native_options,
+        Box::new(|cc| {
+            cc.egui_ctx.set_fonts(fonts());
+            // The zoom shortcuts scale the editor text, not the whole UI
+            cc.egui_ctx.options_mut(|o| o.zoom_with_keyboard = false);
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+
+            let mut app = Taskmonger::new(cc);
+            app.safe_mode = safe_mode;
+            if !safe_mode {
+                app.restore_windows();
+            }
+            #[cfg(feature = "tray")]
+            {
+                app.tray = tray::Tray::start(&cc.egui_ctx, icon_data);
+            }
+            Ok(Box::new(app))
+        }),
+    )
+}
+
+/// IBM Plex, DejaVu for bold tags and the phosphor icons
+fn fonts() -> egui::FontDefinitions {
     let mut fonts = egui::FontDefinitions::default();
 
     fonts.font_data.insert(
@@ -665,15 +4839,153 @@ fn main() -> eframe::Result<()> {
         .unwrap()
         .insert(0, "IBMPlexMono".to_owned());
 
+    // IBM Plex comes without bold here. Chars bold lacks come from the regular family.
+    for (bold, file, family) in [
+        (
+            BOLD_SANS,
+            &include_bytes!("../fonts/DejaVuSans-Bold.ttf")[..],
+            egui::FontFamily::Proportional,
+        ),
+        (
+            BOLD_MONO,
+            &include_bytes!("../fonts/DejaVuSansMono-Bold.ttf")[..],
+            egui::FontFamily::Monospace,
+        ),
+    ] {
+        fonts
+            .font_data
+            .insert(bold.to_owned(), egui::FontData::from_static(file).into());
+        let mut names = vec![bold.to_owned()];
+        names.extend(fonts.families[&family].iter().cloned());
+        fonts
+            .families
+            .insert(egui::FontFamily::Name(bold.into()), names);
+    }
+
     egui_phosphor::add_to_fonts(&mut fonts, egui_phosphor::Variant::Regular);
+    fonts
+}
 
-    eframe::run_native(
-        "Taskmonger",
-        native_options,
-        Box::new(|cc| {
-            cc.egui_ctx.set_fonts(fonts);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            Ok(Box::new(Taskmonger::new(cc)))
-        }),
-    )
+    fn app(buffer: &str, tags: &[&str]) -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: buffer.to_string(),
+            ..Default::default()
+        };
+        app.tags.clear();
+        app.tagged_ranges.clear();
+        for (i, tag) in tags.iter().enumerate() {
+            app.tags.insert(tag.to_string(), Tag::new([i as u8, 0, 0]));
+        }
+        app
+    }
+
+    fn ranges(app: &Taskmonger, tag: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<_> = app
+            .tagged_ranges
+            .iter()
+            .filter(|tr| tr.tag_name == tag)
+            .map(|tr| tr.range.clone())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        ranges
+    }
+
+    #[test]
+    fn untagging_a_selection_over_several_ranges() {
+        let mut app = app("aaaa bbbb cccc dddd", &["x", "y"]);
+        for range in [0..4, 5..9, 10..14] {
+            app.tagged_ranges
+                .push(TaggedRange::new("x".to_string(), range));
+        }
+        app.tagged_ranges
+            .push(TaggedRange::new("y".to_string(), 0..19));
+        app.cut_tag("x", &(2..12));
+        assert_eq!(ranges(&app, "x"), vec![0..2, 12..14]);
+        assert_eq!(ranges(&app, "y"), vec![0..19]);
+    }
+
+    #[test]
+    fn untagging_the_middle_of_a_range_splits_it() {
+        let mut app = app("aaaa bbbb cccc", &["x"]);
+        app.tagged_ranges
+            .push(TaggedRange::new("x".to_string(), 0..14));
+        app.cut_tag("x", &(5..9));
+        assert_eq!(ranges(&app, "x"), vec![0..5, 9..14]);
+    }
+
+    #[test]
+    fn normalizing_merges_overlapping_and_touching_ranges() {
+        let mut app = app("aaaa bbbb cccc dddd", &["x"]);
+        for range in [0..6, 4..9, 9..12, 15..19] {
+            app.tagged_ranges
+                .push(TaggedRange::new("x".to_string(), range));
+        }
+        app.normalize_tag_ranges("x");
+        assert_eq!(ranges(&app, "x"), vec![0..12, 15..19]);
+    }
+
+    #[test]
+    fn normalizing_leaves_other_tags_alone() {
+        let mut app = app("aaaa bbbb cccc", &["x", "y"]);
+        app.tagged_ranges
+            .push(TaggedRange::new("x".to_string(), 0..6));
+        app.tagged_ranges
+            .push(TaggedRange::new("y".to_string(), 4..9));
+        app.tagged_ranges
+            .push(TaggedRange::new("y".to_string(), 9..12));
+        app.normalize_tag_ranges("x");
+        assert_eq!(ranges(&app, "x"), vec![0..6]);
+        assert_eq!(ranges(&app, "y"), vec![4..9, 9..12]);
+    }
+
+    #[test]
+    fn merged_ranges_keep_the_earliest_creation_and_are_modified() {
+        let day = |d| {
+            chrono::NaiveDate::from_ymd_opt(2024, 5, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        };
+        let mut app = app("aaaa bbbb", &["x"]);
+        let mut later = TaggedRange::new("x".to_string(), 0..4);
+        later.created = day(3);
+        later.modified = day(3);
+        let mut earlier = TaggedRange::new("x".to_string(), 4..9);
+        earlier.created = day(1);
+        earlier.modified = day(1);
+        app.tagged_ranges = vec![later, earlier];
+        app.normalize_tag_ranges("x");
+        assert_eq!(app.tagged_ranges.len(), 1);
+        let merged = &app.tagged_ranges[0];
+        assert_eq!(merged.range, 0..9);
+        assert_eq!(merged.created, day(1));
+        assert!(merged.modified > day(3));
+    }
+
+    #[test]
+    fn recurrences_skip_to_the_next_occurrence() {
+        let day = |m, d| chrono::NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        // May 3rd 2024 is a Friday
+        assert_eq!(Recurrence::Weekdays.next(day(5, 3)), day(5, 6));
+        assert_eq!(Recurrence::Weekdays.next(day(5, 6)), day(5, 7));
+        assert_eq!(Recurrence::Daily.next(day(2, 28)), day(2, 29));
+        assert_eq!(Recurrence::Weekly.next(day(5, 3)), day(5, 10));
+        assert_eq!(Recurrence::Monthly.next(day(1, 31)), day(2, 29));
+        assert_eq!(Recurrence::EveryDays(0).next(day(5, 3)), day(5, 4));
+        assert_eq!(Recurrence::EveryDays(10).next(day(5, 3)), day(5, 13));
+    }
+
+    #[test]
+    fn duplicate_tags_depend_on_the_name_policy() {
+        let mut app = app("", &["Work", "home", "work", "WORK"]);
+        app.settings.tag_name_policy = TagNamePolicy::CaseSensitive;
+        assert!(app.duplicate_tags().is_empty());
+        app.settings.tag_name_policy = TagNamePolicy::IgnoreCase;
+        let into_work = |name: &str| (name.to_string(), "Work".to_string());
+        assert_eq!(app.duplicate_tags(), [into_work("work"), into_work("WORK")]);
+    }
 }
@@ -0,0 +1,113 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::Taskmonger;
+
+/// When the ranges of a tag remind about their due date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NotifyRule {
+    /// Once, when the due date is reached
+    #[default]
+    OnDue,
+    /// Every day from the due date until the range is done
+    DailyUntilDone,
+    Never,
+}
+
+impl NotifyRule {
+    pub const ALL: [NotifyRule; 3] = [
+        NotifyRule::OnDue,
+        NotifyRule::DailyUntilDone,
+        NotifyRule::Never,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotifyRule::OnDue => "When due",
+            NotifyRule::DailyUntilDone => "Daily until done",
+            NotifyRule::Never => "Never",
+        }
+    }
+
+    /// Whether a range due on `due` and last notified on `notified` needs a reminder today
+    fn applies(
+        &self,
+        due: chrono::NaiveDate,
+        notified: Option<chrono::NaiveDate>,
+        today: chrono::NaiveDate,
+    ) -> bool {
+        if due > today {
+            return false;
+        }
+        match self {
+            // A recurring range has a new due date for every occurrence
+            NotifyRule::OnDue => notified.is_none_or(|n| n < due),
+            NotifyRule::DailyUntilDone => notified != Some(today),
+            NotifyRule::Never => false,
+        }
+    }
+
+    /// Radio buttons for the rule. Returns true if it changed.
+    pub fn ui(ui: &mut egui::Ui, rule: &mut NotifyRule) -> bool {
+        let before = *rule;
+        for option in NotifyRule::ALL {
+            ui.radio_value(rule, option, option.name());
+        }
+        *rule != before
+    }
+}
+
+/// More reminders than this are shown as a single one
+const MAX_TOASTS: usize = 3;
+
+impl Taskmonger {
+    /// Reminds about open ranges that came due, following the rule of their tag
+    pub fn check_notifications(&mut self) {
+        let today = chrono::Local::now().date_naive();
+        let mut due = vec![];
+        for (i, tr) in self.tagged_ranges.iter().enumerate() {
+            let (false, Some(date)) = (tr.done, tr.due) else {
+                continue;
+            };
+            let rule = self
+                .tags
+                .get(&tr.tag_name)
+                .map(|t| t.notify)
+                .unwrap_or_default();
+            if rule.applies(date, tr.notified, today) {
+                due.push(i);
+            }
+        }
+        if due.is_empty() {
+            return;
+        }
+
+        // A pile of reminders at startup would cover the window
+        if due.len() > MAX_TOASTS {
+            info!(count = due.len(), "Due reminders");
+            self.toasts.warning(format!(
+                "{} ranges are due or overdue, see the agenda",
+                due.len()
+            ));
+        } else {
+            for &i in &due {
+                let tr = &self.tagged_ranges[i];
+                let text = self.preview(&tr.range);
+                let date = tr.due.unwrap_or(today);
+                let when = if date == today {
+                    "is due today".to_string()
+                } else {
+                    format!("is overdue since {}", date.format("%b %d"))
+                };
+                info!(tag = tr.tag_name, %date, "Due reminder");
+                self.toasts
+                    .warning(format!("{}: \"{text}\" {when}", tr.tag_name));
+            }
+        }
+        for i in due {
+            self.tagged_ranges[i].notified = Some(today);
+        }
+        let _ = self.save_to_disk();
+    }
+}
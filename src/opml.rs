@@ -0,0 +1,395 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use tracing::info;
+
+use crate::lines::line_of;
+use crate::tools::line_starts;
+use crate::{TaggedRange, Taskmonger};
+
+/// Added per level to imported items, like the outliner does
+const INDENT: &str = "  ";
+
+/// A heading or list item, with what goes into its `<outline>` element
+struct Node {
+    depth: usize,
+    text: String,
+    categories: Vec<String>,
+    complete: bool,
+    collapsed: bool,
+}
+
+/// An `<outline>` element read back, `depth` counted from the body
+#[derive(Default)]
+struct Imported {
+    depth: usize,
+    text: String,
+    note: String,
+    categories: Vec<String>,
+    complete: bool,
+}
+
+/// Line text without its heading or list marker, and whether its checkbox is ticked
+fn item_text(line: &str) -> (&str, bool) {
+    let mut text = line.trim();
+    let hashes = text.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) && text[hashes..].starts_with(' ') {
+        text = text[hashes..].trim_start();
+    }
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = text.strip_prefix(marker) {
+            text = rest.trim_start();
+            break;
+        }
+    }
+    for (checkbox, ticked) in [("[ ] ", false), ("[x] ", true), ("[X] ", true)] {
+        if let Some(rest) = text.strip_prefix(checkbox) {
+            return (rest.trim_start(), ticked);
+        }
+    }
+    (text, false)
+}
+
+/// Headings nest by level and everything below them, list items by indentation
+fn rank(line: &str) -> usize {
+    let trimmed = line.trim_start();
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        hashes
+    } else {
+        7 + crate::tools::indent(line)
+    }
+}
+
+/// The buffer's non-blank lines as an outline tree
+fn nodes(buffer: &str, ranges: &[TaggedRange], folded_lines: &[usize]) -> Vec<Node> {
+    let starts = line_starts(buffer);
+    let mut ranks: Vec<usize> = vec![];
+    let mut nodes = vec![];
+    for (i, line) in buffer.split('\n').enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let rank = rank(line);
+        while ranks.last().is_some_and(|&parent| parent >= rank) {
+            ranks.pop();
+        }
+        let depth = ranks.len();
+        ranks.push(rank);
+
+        let start = starts[i];
+        let end = start + line.chars().count();
+        let covering = ranges
+            .iter()
+            .filter(|tr| tr.range.start < end && tr.range.end > start);
+        let mut categories = vec![];
+        let mut done = false;
+        for tr in covering {
+            if !categories.contains(&tr.tag_name) {
+                categories.push(tr.tag_name.clone());
+            }
+            done |= tr.done;
+        }
+        let (text, ticked) = item_text(line);
+        nodes.push(Node {
+            depth,
+            text: text.to_string(),
+            categories,
+            complete: ticked || done,
+            collapsed: folded_lines.contains(&i),
+        });
+    }
+    nodes
+}
+
+/// OPML 2.0 document of the buffer's headings and list items. Tags become categories,
+/// done items `_complete` and folded items are left out of the `expansionState`.
+pub fn opml(title: &str, buffer: &str, ranges: &[TaggedRange], folded_lines: &[usize]) -> String {
+    let nodes = nodes(buffer, ranges, folded_lines);
+    let has_children = |i: usize| {
+        nodes
+            .get(i + 1)
+            .is_some_and(|next| next.depth > nodes[i].depth)
+    };
+    // Numbered from 1 in document order, as outliners count their lines
+    let expanded: Vec<String> = (0..nodes.len())
+        .filter(|&i| has_children(i) && !nodes[i].collapsed)
+        .map(|i| (i + 1).to_string())
+        .collect();
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<opml version=\"2.0\">\n  <head>\n");
+    out.push_str(&format!("    <title>{}</title>\n", escape(title)));
+    if !expanded.is_empty() {
+        out.push_str(&format!(
+            "    <expansionState>{}</expansionState>\n",
+            expanded.join(",")
+        ));
+    }
+    out.push_str("  </head>\n  <body>\n");
+    for (i, node) in nodes.iter().enumerate() {
+        let pad = "  ".repeat(node.depth + 2);
+        out.push_str(&format!("{pad}<outline text=\"{}\"", escape(&node.text)));
+        if !node.categories.is_empty() {
+            let categories: Vec<String> = node
+                .categories
+                .iter()
+                .map(|tag| format!("/{tag}"))
+                .collect();
+            out.push_str(&format!(" category=\"{}\"", escape(categories.join(","))));
+        }
+        if node.complete {
+            out.push_str(" _complete=\"true\"");
+        }
+        if has_children(i) {
+            out.push_str(">\n");
+            continue;
+        }
+        out.push_str("/>\n");
+        let next_depth = nodes.get(i + 1).map_or(0, |next| next.depth);
+        for depth in (next_depth..node.depth).rev() {
+            out.push_str(&format!("{}</outline>\n", "  ".repeat(depth + 2)));
+        }
+    }
+    out.push_str("  </body>\n</opml>\n");
+    out
+}
+
+fn imported(element: &BytesStart, depth: usize) -> Result<Imported, quick_xml::Error> {
+    let mut item = Imported {
+        depth,
+        ..Default::default()
+    };
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let value = attribute.normalized_value(XmlVersion::Implicit1_0)?;
+        match attribute.key.as_ref() {
+            b"text" => item.text = value.split_whitespace().collect::<Vec<_>>().join(" "),
+            b"_note" => item.note = value.trim().to_string(),
+            b"category" => {
+                item.categories = value
+                    .split(',')
+                    .map(|category| category.trim().trim_start_matches('/').to_string())
+                    .filter(|category| !category.is_empty())
+                    .collect();
+            }
+            b"_complete" => item.complete = value.as_ref() == "true",
+            _ => {}
+        }
+    }
+    Ok(item)
+}
+
+/// The `<outline>` elements of an OPML document in order, and the items to show expanded
+/// if the document says
+fn parse(xml: &str) -> Result<(Vec<Imported>, Option<Vec<usize>>), quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut items = vec![];
+    let mut expansion = None;
+    let mut depth = 0;
+    let mut in_expansion = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"outline" => {
+                items.push(imported(&e, depth)?);
+                depth += 1;
+            }
+            Event::Empty(e) if e.name().as_ref() == b"outline" => {
+                items.push(imported(&e, depth)?);
+            }
+            Event::End(e) if e.name().as_ref() == b"outline" => depth = depth.saturating_sub(1),
+            Event::Start(e) if e.name().as_ref() == b"expansionState" => in_expansion = true,
+            Event::End(e) if e.name().as_ref() == b"expansionState" => in_expansion = false,
+            Event::Text(text) if in_expansion => {
+                let text = text.decode()?;
+                expansion = Some(
+                    text.split(',')
+                        .filter_map(|n| n.trim().parse().ok())
+                        .collect(),
+                );
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((items, expansion))
+}
+
+impl Taskmonger {
+    /// Writes the outline of the buffer, see [`opml`]
+    pub fn export_opml(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger.opml");
+        let starts = line_starts(&self.buffer);
+        let folded_lines: Vec<usize> = self
+            .folds
+            .iter()
+            .map(|&fold| line_of(&starts, fold))
+            .collect();
+        fs::write(
+            &path,
+            opml(
+                env!("CARGO_PKG_NAME"),
+                &self.buffer,
+                &self.tagged_ranges,
+                &folded_lines,
+            ),
+        )?;
+        Ok(path)
+    }
+
+    /// Appends the outline of an OPML file as an indented list. Categories tag their items,
+    /// completed items get ticked and collapsed ones folded. Returns the number of items.
+    pub fn import_opml(&mut self, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let (items, expansion) = parse(&fs::read_to_string(path)?)?;
+        if items.is_empty() {
+            return Err("no outline items".into());
+        }
+        if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        for (i, item) in items.iter().enumerate() {
+            let line_start = self.buffer.chars().count();
+            let pad = INDENT.repeat(item.depth);
+            let checkbox = if item.complete { "[x] " } else { "" };
+            self.buffer.push_str(&format!("{pad}- {checkbox}"));
+            let start = self.buffer.chars().count();
+            self.buffer.push_str(&item.text);
+            let end = self.buffer.chars().count();
+            self.buffer.push('\n');
+            for line in item.note.lines() {
+                self.buffer
+                    .push_str(&format!("{pad}{INDENT}{}\n", line.trim()));
+            }
+            for category in &item.categories {
+                let tag = self.import_tag(category.clone());
+                self.tagged_ranges
+                    .push(TaggedRange::new(tag.clone(), start..end));
+                self.normalize_tag_ranges(&tag);
+            }
+            let has_children = items.get(i + 1).is_some_and(|next| next.depth > item.depth);
+            let collapsed = expansion
+                .as_ref()
+                .is_some_and(|expanded| !expanded.contains(&(i + 1)));
+            if has_children && collapsed {
+                self.folds.push(line_start);
+            }
+        }
+        info!(items = items.len(), "Imported {}", path.display());
+        let _ = self.save_to_disk();
+        Ok(items.len())
+    }
+
+    /// Export menu rows for OPML, the export button and a path to import from
+    pub fn opml_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Outline (.opml)")
+            .on_hover_text("Headings and list items with their tags and folds, for outliners")
+            .clicked()
+        {
+            match self.export_opml() {
+                Ok(path) => self
+                    .toasts
+                    .success(format!("Exported outline to {}", path.display())),
+                Err(e) => self.toasts.error(format!("Outline export failed: {e}")),
+            }
+            ui.close();
+        }
+        let import_id = egui::Id::new("opml_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of an OPML file")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(!import.trim().is_empty(), egui::Button::new("Import"))
+                .clicked()
+            {
+                match self.import_opml(Path::new(import.trim())) {
+                    Ok(count) => {
+                        self.toasts
+                            .success(format!("Imported {count} outline items"));
+                        import.clear();
+                    }
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the outline: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_text_strips_markers_and_checkboxes() {
+        assert_eq!(item_text("## Heading"), ("Heading", false));
+        assert_eq!(item_text("  - [x] done thing"), ("done thing", true));
+        assert_eq!(item_text("* [ ] open thing"), ("open thing", false));
+        assert_eq!(item_text("#hashtag"), ("#hashtag", false));
+    }
+
+    #[test]
+    fn headings_rank_above_list_items() {
+        assert!(rank("# Top") < rank("## Below"));
+        assert!(rank("###### Deepest") < rank("- item"));
+        assert!(rank("- item") < rank("    - nested"));
+    }
+
+    #[test]
+    fn parses_nested_outlines() {
+        let xml = r#"<?xml version="1.0"?>
+<opml version="2.0">
+  <head><expansionState>0, 2</expansionState></head>
+  <body>
+    <outline text="Home &amp; garden" category="/chores,/@home">
+      <outline text="water   plants" _complete="true" _note=" daily "/>
+    </outline>
+    <outline text="Work"/>
+  </body>
+</opml>"#;
+        let (items, expansion) = parse(xml).unwrap();
+        assert_eq!(expansion, Some(vec![0, 2]));
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            (items[0].depth, items[0].text.as_str()),
+            (0, "Home & garden")
+        );
+        assert_eq!(items[0].categories, ["chores", "@home"]);
+        assert_eq!(
+            (items[1].depth, items[1].text.as_str()),
+            (1, "water plants")
+        );
+        assert!(items[1].complete);
+        assert_eq!(items[1].note, "daily");
+        assert_eq!(items[2].depth, 0);
+    }
+
+    #[test]
+    fn exports_what_it_imports() {
+        let buffer = "# Home\n- water plants\n  - the ferns";
+        let tr = TaggedRange::new("chores".to_string(), 9..21);
+        let (items, _) = parse(&opml("test", buffer, &[tr], &[])).unwrap();
+        let read: Vec<(usize, &str)> = items
+            .iter()
+            .map(|item| (item.depth, item.text.as_str()))
+            .collect();
+        assert_eq!(read, [(0, "Home"), (1, "water plants"), (2, "the ferns")]);
+        assert_eq!(items[1].categories, ["chores"]);
+    }
+}
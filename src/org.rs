@@ -0,0 +1,637 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::NaiveDate;
+use eframe::egui;
+use regex::{Captures, Regex};
+use tracing::info;
+
+use crate::todotxt::{letter_priority, priority_letter, UNFILED_TAG};
+use crate::tools::{indent, line_starts};
+use crate::{Priority, TaggedRange, Taskmonger};
+
+/// Added per level to tasks nested below a heading, like the outliner does
+const INDENT: &str = "  ";
+
+/// Column org-mode aligns the end of headline tags to
+const TAGS_COLUMN: usize = 77;
+
+/// `[text](url)` and `![alt](image)`
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(!?)\[([^\]\n]*)\]\(<?([^)\s>]+)>?\)").expect("valid regex"));
+/// `[[url][text]]` and `[[url]]`
+static ORG_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[([^\]\n]+)\](?:\[([^\]\n]+)\])?\]").expect("valid regex"));
+/// `DEADLINE: <2024-05-03 Fri>` and the other words of a headline's planning line
+static PLANNING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(DEADLINE|SCHEDULED|CLOSED):\s*[<\[](\d{4}-\d{2}-\d{2})[^>\]]*[>\]]")
+        .expect("valid regex")
+});
+
+fn org_date(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d %a").to_string()
+}
+
+/// Tag name as an org tag, which only holds letters, digits and `_@#%`
+fn org_tag(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "_@#%".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn org_links(text: &str) -> String {
+    MARKDOWN_LINK
+        .replace_all(text, |link: &Captures| match (&link[1], &link[2]) {
+            // Org shows links to images without a description inline
+            ("!", _) | (_, "") => format!("[[{}]]", &link[3]),
+            (_, title) => format!("[[{}][{title}]]", &link[3]),
+        })
+        .to_string()
+}
+
+fn markdown_links(text: &str) -> String {
+    ORG_LINK
+        .replace_all(text, |link: &Captures| match link.get(2) {
+            Some(title) => format!("[{}]({})", title.as_str(), &link[1]),
+            None => format!("<{}>", &link[1]),
+        })
+        .to_string()
+}
+
+/// Level and text of a markdown heading
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    ((1..=6).contains(&hashes) && line[hashes..].starts_with(' '))
+        .then(|| (hashes, line[hashes..].trim()))
+}
+
+/// Text of a list item after its marker, and whether its checkbox is ticked if it has one
+fn list_item(line: &str) -> Option<(&str, Option<bool>)> {
+    let trimmed = line.trim_start();
+    let rest = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))?
+        .trim_start();
+    for (checkbox, ticked) in [("[ ] ", false), ("[x] ", true), ("[X] ", true)] {
+        if let Some(text) = rest.strip_prefix(checkbox) {
+            return Some((text.trim_start(), Some(ticked)));
+        }
+    }
+    Some((rest, None))
+}
+
+/// An org headline for a heading or task, with the tags and state of the ranges starting
+/// on its line and a planning line for its closed and due dates
+fn headline(level: usize, text: &str, checkbox: Option<bool>, ranges: &[&TaggedRange]) -> String {
+    let done = checkbox == Some(true) || ranges.iter().any(|tr| tr.done);
+    let priority = ranges
+        .iter()
+        .map(|tr| tr.priority)
+        .max()
+        .unwrap_or_default();
+    let due = ranges.iter().find_map(|tr| tr.due);
+    let task = checkbox.is_some()
+        || due.is_some()
+        || priority != Priority::Normal
+        || ranges.iter().any(|tr| tr.tag_name == UNFILED_TAG);
+
+    let mut words = vec!["*".repeat(level.max(1))];
+    if done {
+        words.push("DONE".to_string());
+    } else if task {
+        words.push("TODO".to_string());
+    }
+    if let Some(letter) = priority_letter(priority) {
+        words.push(format!("[#{letter}]"));
+    }
+    if !text.is_empty() {
+        words.push(org_links(text));
+    }
+    let mut out = words.join(" ");
+    let mut tags: Vec<String> = vec![];
+    for tr in ranges {
+        let tag = org_tag(&tr.tag_name);
+        if tr.tag_name != UNFILED_TAG && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if !tags.is_empty() {
+        let tags = format!(":{}:", tags.join(":"));
+        let width = out.chars().count() + tags.chars().count();
+        out.push_str(&" ".repeat(TAGS_COLUMN.saturating_sub(width).max(1)));
+        out.push_str(&tags);
+    }
+    out.push('\n');
+
+    let mut planning = vec![];
+    if done {
+        if let Some(closed) = ranges.iter().map(|tr| tr.modified.date()).max() {
+            planning.push(format!("CLOSED: [{}]", org_date(closed)));
+        }
+    }
+    if let Some(due) = due {
+        planning.push(format!("DEADLINE: <{}>", org_date(due)));
+    }
+    if !planning.is_empty() {
+        out.push_str(&planning.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// The buffer as an org document. Headings become headlines, and so do the lines tagged
+/// ranges start on, nested below the heading they are under. Their tags go to the headline,
+/// done and open tasks get `DONE` and `TODO`, and priorities and due dates their cookie and
+/// deadline. Untagged checkbox items stay checkbox list items.
+fn org(buffer: &str, ranges: &[TaggedRange]) -> String {
+    let starts = line_starts(buffer);
+    let mut out = String::new();
+    let mut heading_level = 0;
+    // Indentation of the items the current one is nested in
+    let mut items: Vec<usize> = vec![];
+    let mut in_fence = false;
+    for (i, line) in buffer.split('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(language) = trimmed.strip_prefix("```") {
+            if in_fence {
+                out.push_str("#+END_SRC\n");
+            } else {
+                out.push_str(format!("#+BEGIN_SRC {}", language.trim()).trim_end());
+                out.push('\n');
+            }
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let start = starts[i];
+        let end = start + line.chars().count();
+        let starting: Vec<&TaggedRange> = ranges
+            .iter()
+            .filter(|tr| !tr.range.is_empty() && (start..end).contains(&tr.range.start))
+            .collect();
+        if let Some((level, text)) = heading(line) {
+            heading_level = level;
+            items.clear();
+            out.push_str(&headline(level, text, None, &starting));
+            continue;
+        }
+        if starting.is_empty() {
+            // A `*` list item at the start of a line would be a headline
+            let pad = &line[..line.len() - trimmed.len()];
+            let mut text = match trimmed.strip_prefix("* ") {
+                Some(item) => format!("- {item}"),
+                None => trimmed.to_string(),
+            };
+            for marker in ["- [x] ", "+ [x] "] {
+                if let Some(rest) = text.strip_prefix(marker) {
+                    text = format!("{}[X] {rest}", &marker[..2]);
+                }
+            }
+            out.push_str(&format!("{pad}{}\n", org_links(&text)));
+            continue;
+        }
+        let (text, checkbox) = list_item(line).unwrap_or((trimmed, None));
+        let depth = indent(line);
+        while items.last().is_some_and(|&parent| parent >= depth) {
+            items.pop();
+        }
+        items.push(depth);
+        out.push_str(&headline(
+            heading_level + items.len(),
+            text,
+            checkbox,
+            &starting,
+        ));
+    }
+    format!("{}\n", out.trim_end())
+}
+
+/// The TODO keywords of an org file, from its `#+TODO:` lines or org's default
+struct Keywords {
+    open: Vec<String>,
+    done: Vec<String>,
+}
+
+impl Keywords {
+    fn parse(org: &str) -> Self {
+        let mut keywords = Keywords {
+            open: vec![],
+            done: vec![],
+        };
+        for line in org.lines() {
+            let line = line.trim();
+            let upper = line.to_ascii_uppercase();
+            let Some(prefix) = ["#+TODO:", "#+SEQ_TODO:", "#+TYP_TODO:"]
+                .into_iter()
+                .find(|prefix| upper.starts_with(prefix))
+            else {
+                continue;
+            };
+            // `TODO(t) NEXT | DONE(d)`, without the bar the last one is the done one
+            let words: Vec<&str> = line[prefix.len()..]
+                .split_whitespace()
+                .map(|word| word.split('(').next().unwrap_or(word))
+                .collect();
+            let (open, done) = match words.iter().position(|word| *word == "|") {
+                Some(bar) => (&words[..bar], &words[bar + 1..]),
+                None if words.len() > 1 => words.split_at(words.len() - 1),
+                None => (&words[..], &[][..]),
+            };
+            keywords
+                .open
+                .extend(open.iter().map(|word| word.to_string()));
+            keywords
+                .done
+                .extend(done.iter().map(|word| word.to_string()));
+        }
+        if keywords.open.is_empty() && keywords.done.is_empty() {
+            keywords.open.push("TODO".to_string());
+            keywords.done.push("DONE".to_string());
+        }
+        keywords
+    }
+
+    /// Whether `word` is a done keyword, `None` if it is no keyword
+    fn state(&self, word: &str) -> Option<bool> {
+        if self.done.iter().any(|done| done == word) {
+            Some(true)
+        } else if self.open.iter().any(|open| open == word) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// A headline of an org file
+#[derive(Debug, PartialEq)]
+struct Headline {
+    level: usize,
+    /// Whether its keyword is a done one, `None` for headlines that are no task
+    done: Option<bool>,
+    priority: Priority,
+    title: String,
+    tags: Vec<String>,
+}
+
+/// Reads `** TODO [#A] title   :tag:other:`
+fn parse_headline(line: &str, keywords: &Keywords) -> Option<Headline> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    if level == 0 || !line[level..].starts_with(' ') {
+        return None;
+    }
+    let mut rest = line[level..].trim();
+    let mut tags = vec![];
+    if let Some(last) = rest.split_whitespace().last() {
+        let is_tags = last.len() > 2
+            && last.starts_with(':')
+            && last.ends_with(':')
+            && last[1..last.len() - 1]
+                .split(':')
+                .all(|tag| !tag.is_empty());
+        if is_tags {
+            tags = last[1..last.len() - 1]
+                .split(':')
+                .map(str::to_string)
+                .collect();
+            rest = rest[..rest.len() - last.len()].trim_end();
+        }
+    }
+    let mut done = None;
+    let (word, title) = rest.split_once(' ').unwrap_or((rest, ""));
+    if let Some(state) = keywords.state(word) {
+        done = Some(state);
+        rest = title.trim_start();
+    }
+    let mut priority = Priority::Normal;
+    let cookie = rest
+        .strip_prefix("[#")
+        .and_then(|cookie| cookie.chars().next())
+        .filter(|_| rest.get(3..4) == Some("]"));
+    if let Some(letter) = cookie {
+        priority = letter_priority(letter);
+        rest = rest[4..].trim_start();
+    }
+    Some(Headline {
+        level,
+        done,
+        priority,
+        title: rest.to_string(),
+        tags,
+    })
+}
+
+/// `:PROPERTIES:`, `:LOGBOOK:` and the other drawers, skipped up to their `:END:`
+fn is_drawer(line: &str) -> bool {
+    line.len() > 2
+        && line.starts_with(':')
+        && line.ends_with(':')
+        && line[1..line.len() - 1]
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+impl Taskmonger {
+    /// Writes the buffer as an org document, see [`org`]
+    pub fn export_org(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger.org");
+        fs::write(&path, org(&self.buffer, &self.tagged_ranges))?;
+        Ok(path)
+    }
+
+    /// Appends an org file as markdown. Headlines with a TODO keyword become checkbox items
+    /// nested below the headline above them, with their tags, priority, deadline and done
+    /// state on their ranges, the other headlines become headings tagged with their tags.
+    /// Returns the number of headlines.
+    pub fn import_org(&mut self, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let org = fs::read_to_string(path)?;
+        if org.trim().is_empty() {
+            return Err("the file is empty".into());
+        }
+        let keywords = Keywords::parse(&org);
+        if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        let mut headlines = 0;
+        // Level of the last headline, and of the last one that became a heading
+        let mut level = 0;
+        let mut heading_level = 0;
+        // Where text below a task goes, to stay part of it
+        let mut body_pad = String::new();
+        // Closing line of the block being copied
+        let mut block: Option<String> = None;
+        let mut drawer = false;
+        // Ranges of the last headline, for the planning line below it
+        let mut planned: Vec<usize> = vec![];
+        let mut tags = HashSet::new();
+        for line in org.lines() {
+            let trimmed = line.trim();
+            let upper = trimmed.to_ascii_uppercase();
+            if let Some(end) = &block {
+                if upper.starts_with(end.as_str()) {
+                    self.buffer.push_str(&format!("{body_pad}```\n"));
+                    block = None;
+                } else {
+                    self.buffer.push_str(&format!("{body_pad}{line}\n"));
+                }
+                continue;
+            }
+            if drawer {
+                drawer = upper != ":END:";
+                continue;
+            }
+            if let Some(headline) = parse_headline(line, &keywords) {
+                headlines += 1;
+                level = headline.level;
+                planned.clear();
+                let done = headline.done.unwrap_or(false);
+                let start = match headline.done {
+                    Some(done) => {
+                        let pad = INDENT.repeat(level.saturating_sub(heading_level + 1));
+                        let checkbox = if done { "[x]" } else { "[ ]" };
+                        self.buffer.push_str(&format!("{pad}- {checkbox} "));
+                        body_pad = format!("{pad}{INDENT}");
+                        self.buffer.chars().count()
+                    }
+                    None => {
+                        heading_level = level;
+                        body_pad.clear();
+                        self.buffer
+                            .push_str(&format!("{} ", "#".repeat(level.min(6))));
+                        self.buffer.chars().count()
+                    }
+                };
+                self.buffer.push_str(&markdown_links(&headline.title));
+                let end = self.buffer.chars().count();
+                self.buffer.push('\n');
+                if start == end {
+                    continue;
+                }
+                let mut names = headline.tags.clone();
+                if names.is_empty() && headline.done.is_some() {
+                    names.push(UNFILED_TAG.to_string());
+                }
+                for name in names {
+                    let tag = self.import_tag(name);
+                    let mut tr = TaggedRange::new(tag.clone(), start..end);
+                    tr.done = done;
+                    tr.priority = headline.priority;
+                    planned.push(self.tagged_ranges.len());
+                    self.tagged_ranges.push(tr);
+                    tags.insert(tag);
+                }
+                continue;
+            }
+            if !planned.is_empty() && PLANNING.is_match(trimmed) {
+                let mut due = None;
+                let mut scheduled = None;
+                let mut closed = None;
+                for word in PLANNING.captures_iter(trimmed) {
+                    let day = NaiveDate::parse_from_str(&word[2], "%Y-%m-%d").ok();
+                    match &word[1] {
+                        "DEADLINE" => due = day,
+                        "SCHEDULED" => scheduled = day,
+                        _ => closed = day,
+                    }
+                }
+                for &i in &planned {
+                    let tr = &mut self.tagged_ranges[i];
+                    tr.due = due.or(scheduled);
+                    if let Some(closed) = closed {
+                        tr.modified = closed.and_time(tr.modified.time());
+                    }
+                }
+                planned.clear();
+                continue;
+            }
+            planned.clear();
+            if is_drawer(trimmed) {
+                drawer = true;
+                continue;
+            }
+            if let Some(kind) = upper.strip_prefix("#+BEGIN_") {
+                let kind = kind.split_whitespace().next().unwrap_or_default();
+                let language = match kind {
+                    "SRC" => trimmed.split_whitespace().nth(1).unwrap_or_default(),
+                    _ => "",
+                };
+                self.buffer.push_str(&format!("{body_pad}```{language}\n"));
+                block = Some(format!("#+END_{kind}"));
+                continue;
+            }
+            // Keywords, settings and comments
+            if upper.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+                continue;
+            }
+            if trimmed.is_empty() {
+                self.buffer.push('\n');
+                continue;
+            }
+            // Bodies may be indented up to their headline's text
+            let strip = line
+                .chars()
+                .take(level + 1)
+                .take_while(|c| *c == ' ')
+                .count();
+            let mut text = markdown_links(&line[strip..]);
+            for marker in ["- [X] ", "+ [X] "] {
+                if let Some(rest) = text.trim_start().strip_prefix(marker) {
+                    let pad = &text[..text.len() - text.trim_start().len()];
+                    text = format!("{pad}{}[x] {rest}", &marker[..2]);
+                }
+            }
+            self.buffer.push_str(&format!("{body_pad}{text}\n"));
+        }
+        for tag in &tags {
+            self.normalize_tag_ranges(tag);
+        }
+        info!(headlines, "Imported {}", path.display());
+        let _ = self.save_to_disk();
+        Ok(headlines)
+    }
+
+    /// Export menu rows for org-mode, the export button and a path to import from
+    pub fn org_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Org (.org)")
+            .on_hover_text("Headings and tagged items as org headlines with TODO states and tags")
+            .clicked()
+        {
+            match self.export_org() {
+                Ok(path) => self
+                    .toasts
+                    .success(format!("Exported to {}", path.display())),
+                Err(e) => self.toasts.error(format!("Org export failed: {e}")),
+            }
+            ui.close();
+        }
+        let import_id = egui::Id::new("org_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of an org file")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(!import.trim().is_empty(), egui::Button::new("Import"))
+                .clicked()
+            {
+                match self.import_org(Path::new(import.trim())) {
+                    Ok(count) => {
+                        self.toasts
+                            .success(format!("Imported {count} org headlines"));
+                        import.clear();
+                    }
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the org file: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keywords_are_todo_and_done() {
+        let keywords = Keywords::parse("* TODO something");
+        assert_eq!(keywords.state("TODO"), Some(false));
+        assert_eq!(keywords.state("DONE"), Some(true));
+        assert_eq!(keywords.state("NEXT"), None);
+    }
+
+    #[test]
+    fn keywords_come_from_todo_lines() {
+        let keywords =
+            Keywords::parse("#+TODO: TODO(t) NEXT | DONE(d) CANCELED\n#+seq_todo: WAIT OK");
+        assert_eq!(keywords.open, ["TODO", "NEXT", "WAIT"]);
+        assert_eq!(keywords.done, ["DONE", "CANCELED", "OK"]);
+    }
+
+    #[test]
+    fn parses_a_headline_with_keyword_priority_and_tags() {
+        let keywords = Keywords::parse("");
+        let headline =
+            parse_headline("** TODO [#A] call mom        :family:phone:", &keywords).unwrap();
+        assert_eq!(
+            headline,
+            Headline {
+                level: 2,
+                done: Some(false),
+                priority: Priority::Urgent,
+                title: "call mom".to_string(),
+                tags: vec!["family".to_string(), "phone".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_plain_headlines_and_rejects_other_lines() {
+        let keywords = Keywords::parse("");
+        let headline = parse_headline("* Notes on :colons: here", &keywords).unwrap();
+        assert_eq!(headline.done, None);
+        assert_eq!(headline.title, "Notes on :colons: here");
+        assert!(headline.tags.is_empty());
+        assert_eq!(parse_headline("*bold* text", &keywords), None);
+        assert_eq!(parse_headline("no headline", &keywords), None);
+    }
+
+    #[test]
+    fn drawers_are_recognized() {
+        assert!(is_drawer(":PROPERTIES:"));
+        assert!(is_drawer(":END:"));
+        assert!(!is_drawer(":tag:other:"));
+        assert!(!is_drawer("::"));
+    }
+
+    #[test]
+    fn links_convert_both_ways() {
+        assert_eq!(
+            org_links("see [the docs](https://example.com) and ![](a.png)"),
+            "see [[https://example.com][the docs]] and [[a.png]]"
+        );
+        assert_eq!(
+            markdown_links("see [[https://example.com][the docs]] and [[b.png]]"),
+            "see [the docs](https://example.com) and <b.png>"
+        );
+    }
+
+    #[test]
+    fn exports_tagged_lines_as_headlines_below_headings() {
+        let buffer = "# Home\n- [ ] water plants\n- [ ] untagged\n```sh\nls\n```";
+        let mut tr = TaggedRange::new("chores".to_string(), 13..25);
+        tr.due = Some(NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+        let org = org(buffer, &[tr]);
+        let lines: Vec<&str> = org.lines().collect();
+        assert_eq!(lines[0], "* Home");
+        assert!(lines[1].starts_with("** TODO water plants "));
+        assert!(lines[1].ends_with(":chores:"));
+        assert_eq!(lines[1].chars().count(), TAGS_COLUMN);
+        assert_eq!(lines[2], "DEADLINE: <2024-06-03 Mon>");
+        assert_eq!(lines[3], "- [ ] untagged");
+        assert_eq!(&lines[4..], ["#+BEGIN_SRC sh", "ls", "#+END_SRC"]);
+    }
+}
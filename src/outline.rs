@@ -0,0 +1,470 @@
+use std::ops::Range;
+
+use eframe::egui;
+use egui::text::CCursor;
+use egui_dnd::dnd;
+use egui_phosphor::regular::{CARET_DOWN, CARET_RIGHT, TEXT_INDENT, TEXT_OUTDENT};
+use tracing::{debug, info};
+
+use crate::lines::{line_chars, line_of};
+use crate::tools::{indent, line_starts, subtree};
+use crate::Taskmonger;
+
+/// Added per level, enough to nest markdown list items
+const INDENT: &str = "  ";
+
+/// Font size of folded lines, small enough to take no visible space
+const FOLDED_FONT_SIZE: f32 = 0.5;
+
+/// A non-blank line in the outline panel
+#[derive(Hash)]
+struct OutlineItem {
+    line: usize,
+    depth: usize,
+    text: String,
+    has_children: bool,
+    folded: bool,
+}
+
+/// Where `p` ends up after the chars in `range` are replaced by `len` chars
+fn map_position(p: usize, range: &Range<usize>, len: usize) -> usize {
+    if p < range.start {
+        p
+    } else if p >= range.end {
+        p + len - range.len()
+    } else {
+        range.start + (p - range.start).min(len)
+    }
+}
+
+/// Edits that indent, or outdent, the item at line `head` together with its sub-items.
+/// Blank lines are left alone, and an item that is not indented can't be outdented.
+fn indent_edits(buffer: &str, head: usize, outdent: bool) -> Vec<(Range<usize>, String)> {
+    let starts = line_starts(buffer);
+    let lines: Vec<&str> = buffer.split('\n').collect();
+    if outdent && indent(lines[head]) == 0 {
+        return vec![];
+    }
+    subtree(&lines, head)
+        .filter(|&i| !lines[i].trim().is_empty())
+        .map(|i| {
+            let start = starts[i];
+            if outdent {
+                let removed = if lines[i].starts_with('\t') {
+                    1
+                } else {
+                    lines[i]
+                        .chars()
+                        .take(INDENT.len())
+                        .take_while(|c| *c == ' ')
+                        .count()
+                };
+                (start..start + removed, String::new())
+            } else {
+                (start..start, INDENT.to_string())
+            }
+        })
+        .collect()
+}
+
+/// "⋯ n lines" after the last char of each folded head line
+pub fn fold_markers(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    markers: &[(usize, usize)],
+) {
+    let clip = ui.clip_rect();
+    let font_id = egui::TextStyle::Small.resolve(ui.style());
+    let color = ui.visuals().weak_text_color();
+    for &(line_end, hidden) in markers {
+        let rect = output
+            .galley
+            .pos_from_cursor(CCursor::new(line_end))
+            .translate(output.galley_pos.to_vec2());
+        if clip.contains(rect.center()) {
+            ui.painter().text(
+                egui::pos2(rect.right() + 8.0, rect.center().y),
+                egui::Align2::LEFT_CENTER,
+                format!("⋯ {hidden} lines"),
+                font_id.clone(),
+                color,
+            );
+        }
+    }
+}
+
+/// Format of folded lines, tiny and transparent
+pub fn folded_format() -> egui::TextFormat {
+    egui::TextFormat {
+        font_id: egui::FontId::monospace(FOLDED_FONT_SIZE),
+        color: egui::Color32::TRANSPARENT,
+        ..Default::default()
+    }
+}
+
+impl Taskmonger {
+    /// [`Self::replace_text`], then snaps and saves like every outline edit
+    pub fn edit_buffer(&mut self, edits: Vec<(Range<usize>, String)>) {
+        self.replace_text(edits);
+        self.after_outline_edit();
+    }
+
+    /// Replaces char ranges of the buffer, moving tagged ranges, bookmarks, folds and the
+    /// cursor along. `edits` must not overlap.
+    pub fn replace_text(&mut self, mut edits: Vec<(Range<usize>, String)>) {
+        // Back to front, so the earlier ranges stay valid
+        edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, text) in edits {
+            let bytes = crate::tools::char_to_byte_range(&self.buffer, &range);
+            self.buffer.replace_range(bytes, &text);
+            let len = text.chars().count();
+            for tr in &mut self.tagged_ranges {
+                let moved = map_position(tr.range.start, &range, len)
+                    ..map_position(tr.range.end, &range, len);
+                if moved != tr.range {
+                    tr.range = moved;
+                    tr.mark();
+                }
+            }
+            for bookmark in &mut self.bookmarks {
+                bookmark.at = map_position(bookmark.at, &range, len);
+            }
+            for fold in &mut self.folds {
+                *fold = map_position(*fold, &range, len);
+            }
+            self.cursor = map_position(self.cursor, &range, len);
+        }
+    }
+
+    fn after_outline_edit(&mut self) {
+        self.snap_line_ranges();
+        self.inherit_indented_lines();
+        self.clean_invalid_ranges();
+        self.select = Some(self.cursor..self.cursor);
+        let _ = self.save_to_disk();
+    }
+
+    /// Indents, or outdents, the item at the cursor together with its sub-items
+    pub fn indent_subtree(&mut self, outdent: bool) {
+        if self.locked {
+            return;
+        }
+        let head = line_of(&line_starts(&self.buffer), self.cursor);
+        let edits = indent_edits(&self.buffer, head, outdent);
+        if edits.is_empty() {
+            return;
+        }
+        debug!(head, outdent, "Indenting subtree");
+        self.edit_buffer(edits);
+    }
+
+    /// Moves the item at line `from` with its sub-items in front of line `to`,
+    /// or to the end if `to` is past the last line
+    fn move_subtree(&mut self, from: usize, to: usize) {
+        if self.locked {
+            self.toasts.info("The buffer is locked");
+            return;
+        }
+        if self.reorder_lines(from, to) {
+            self.after_outline_edit();
+        }
+    }
+
+    /// [`Self::move_subtree`] without the checks and saving, false if nothing moved
+    fn reorder_lines(&mut self, from: usize, to: usize) -> bool {
+        let starts = line_starts(&self.buffer);
+        let total = self.buffer.chars().count();
+        let lines: Vec<&str> = self.buffer.split('\n').collect();
+        let block = subtree(&lines, from);
+        if block.contains(&to) || to == block.end {
+            return false;
+        }
+        let mut order: Vec<usize> = (0..lines.len()).filter(|i| !block.contains(i)).collect();
+        let at = order.iter().position(|&i| i >= to).unwrap_or(order.len());
+        order.splice(at..at, block.clone());
+
+        // Where every old line starts in the new text
+        let mut new_starts = vec![0; lines.len()];
+        let mut next = 0;
+        for &i in &order {
+            new_starts[i] = next;
+            next += lines[i].chars().count() + 1;
+        }
+        let map = |p: usize| {
+            let line = line_of(&starts, p);
+            new_starts[line] + (p - starts[line])
+        };
+        let buffer = order
+            .iter()
+            .map(|&i| lines[i])
+            .collect::<Vec<_>>()
+            .join("\n");
+        for tr in &mut self.tagged_ranges {
+            if tr.range.is_empty() || tr.range.end > total {
+                continue;
+            }
+            // Ranges spanning the moved block keep their length and go with their start
+            let start = map(tr.range.start);
+            let end = map(tr.range.end - 1) + 1;
+            let moved = if end > start {
+                start..end
+            } else {
+                start..start + tr.range.len()
+            };
+            if moved != tr.range {
+                tr.range = moved;
+                tr.mark();
+            }
+        }
+        for bookmark in &mut self.bookmarks {
+            bookmark.at = map(bookmark.at.min(total));
+        }
+        for fold in &mut self.folds {
+            *fold = map((*fold).min(total));
+        }
+        self.cursor = map(self.cursor.min(total));
+        self.buffer = buffer;
+        info!(from, to, lines = block.len(), "Moved subtree");
+        true
+    }
+
+    /// Moves folds behind an edit at `inserted_at` by `shift` chars
+    pub fn shift_folds(&mut self, inserted_at: i32, shift: i32) {
+        for fold in &mut self.folds {
+            if *fold as i32 >= inserted_at {
+                *fold = (*fold as i32 + shift).max(0) as usize;
+            }
+        }
+    }
+
+    /// Folds or unfolds the sub-items of the item at `line`
+    fn toggle_fold(&mut self, line: usize) {
+        let starts = line_starts(&self.buffer);
+        let Some(&start) = starts.get(line) else {
+            return;
+        };
+        let before = self.folds.len();
+        self.folds.retain(|&fold| line_of(&starts, fold) != line);
+        if self.folds.len() == before {
+            self.folds.push(start);
+        }
+    }
+
+    /// Folds or unfolds the item at the cursor
+    pub fn toggle_fold_at_cursor(&mut self) {
+        let line = line_of(&line_starts(&self.buffer), self.cursor);
+        self.toggle_fold(line);
+    }
+
+    /// Chars the editor hides because their item is folded, and for each fold the end of
+    /// its head line with the number of hidden lines
+    pub fn folded(&self) -> (Vec<Range<usize>>, Vec<(usize, usize)>) {
+        if self.folds.is_empty() {
+            return (vec![], vec![]);
+        }
+        let starts = line_starts(&self.buffer);
+        let total = self.buffer.chars().count();
+        let lines: Vec<&str> = self.buffer.split('\n').collect();
+        let mut hidden = vec![];
+        let mut markers = vec![];
+        for &fold in &self.folds {
+            let head = line_of(&starts, fold.min(total));
+            let block = subtree(&lines, head);
+            if block.len() < 2 {
+                continue;
+            }
+            let head_end = line_chars(&starts, total, &(head..head + 1)).end;
+            // From the head's line break, so the sub-items' lines collapse entirely
+            let end = line_chars(&starts, total, &(block.end - 1..block.end)).end;
+            hidden.push(head_end + 1..(end + 1).min(total));
+            markers.push((head_end, block.len() - 1));
+        }
+        (hidden, markers)
+    }
+
+    /// Tree of the buffer's lines by indentation, to fold items and drag them around with
+    /// their sub-items
+    pub fn outline_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.outline_open;
+        let starts = line_starts(&self.buffer);
+        let lines: Vec<&str> = self.buffer.split('\n').collect();
+        let folded_lines: Vec<usize> = self
+            .folds
+            .iter()
+            .map(|&fold| line_of(&starts, fold))
+            .collect();
+        let mut items = vec![];
+        let mut skip_until = 0;
+        for (line, text) in lines.iter().enumerate() {
+            if line < skip_until || text.trim().is_empty() {
+                continue;
+            }
+            let block = subtree(&lines, line);
+            let folded = folded_lines.contains(&line);
+            if folded {
+                skip_until = block.end;
+            }
+            items.push(OutlineItem {
+                line,
+                depth: indent(text),
+                text: text.trim().chars().take(60).collect(),
+                has_children: block.len() > 1,
+                folded,
+            });
+        }
+        let mut jump = None;
+        let mut fold = None;
+        let mut moved = None;
+        let mut indent_at = None;
+        egui::Window::new("Outline")
+            .open(&mut open)
+            .default_width(320.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(TEXT_INDENT)
+                        .on_hover_text("Indent the item at the cursor with its sub-items (Ctrl+])")
+                        .clicked()
+                    {
+                        indent_at = Some(false);
+                    }
+                    if ui
+                        .button(TEXT_OUTDENT)
+                        .on_hover_text("Outdent the item at the cursor with its sub-items (Ctrl+[)")
+                        .clicked()
+                    {
+                        indent_at = Some(true);
+                    }
+                });
+                ui.label(
+                    egui::RichText::new("Drag items to move them with their sub-items")
+                        .small()
+                        .weak(),
+                );
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let response = dnd(ui, "outline").show(items.iter(), |ui, item, handle, _| {
+                        ui.horizontal(|ui| {
+                            ui.add_space(item.depth as f32 * 6.0);
+                            if item.has_children {
+                                let icon = if item.folded { CARET_RIGHT } else { CARET_DOWN };
+                                if ui.small_button(icon).clicked() {
+                                    fold = Some(item.line);
+                                }
+                            } else {
+                                ui.add_space(ui.spacing().interact_size.y);
+                            }
+                            handle.ui(ui, |ui| {
+                                if ui
+                                    .add(egui::Label::new(&item.text).sense(egui::Sense::click()))
+                                    .clicked()
+                                {
+                                    jump = Some(starts[item.line]);
+                                }
+                            });
+                        });
+                    });
+                    if let Some(update) = response.final_update() {
+                        let from = items[update.from].line;
+                        let to = items.get(update.to).map_or(lines.len(), |item| item.line);
+                        moved = Some((from, to));
+                    }
+                });
+            });
+        self.outline_open = open;
+        if let Some(line) = fold {
+            self.toggle_fold(line);
+        }
+        if let Some(at) = jump {
+            self.jump_to = Some(at..at);
+        }
+        if let Some(outdent) = indent_at {
+            self.indent_subtree(outdent);
+        }
+        if let Some((from, to)) = moved {
+            self.move_subtree(from, to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaggedRange;
+
+    const OUTLINE: &str = "a\n  b\n    c\n\n  d\ne";
+
+    fn app(buffer: &str) -> Taskmonger {
+        Taskmonger {
+            buffer: buffer.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn positions_follow_a_replacement() {
+        assert_eq!(map_position(1, &(2..4), 5), 1);
+        assert_eq!(map_position(6, &(2..4), 5), 9);
+        assert_eq!(map_position(3, &(2..4), 0), 2);
+        assert_eq!(map_position(3, &(2..4), 5), 3);
+    }
+
+    #[test]
+    fn indenting_takes_the_sub_items_along() {
+        let mut app = app(OUTLINE);
+        app.replace_text(indent_edits(&app.buffer, 1, false));
+        assert_eq!(app.buffer, "a\n    b\n      c\n\n  d\ne");
+
+        let mut app = self::app(OUTLINE);
+        app.replace_text(indent_edits(&app.buffer, 1, true));
+        assert_eq!(app.buffer, "a\nb\n  c\n\n  d\ne");
+    }
+
+    #[test]
+    fn items_that_are_not_indented_cannot_be_outdented() {
+        assert!(indent_edits(OUTLINE, 0, true).is_empty());
+        assert!(indent_edits("\ta\n\t\tb", 0, true)
+            .iter()
+            .all(|(range, _)| range.len() == 1));
+    }
+
+    #[test]
+    fn tagged_ranges_move_with_their_subtree() {
+        let mut app = app("x\na\n  b\ny");
+        app.tagged_ranges = vec![
+            TaggedRange::new("b".to_string(), 6..7),
+            TaggedRange::new("y".to_string(), 8..9),
+        ];
+        app.cursor = 2;
+        assert!(app.reorder_lines(1, 0));
+        assert_eq!(app.buffer, "a\n  b\nx\ny");
+        assert_eq!(app.tagged_ranges[0].range, 4..5);
+        assert_eq!(app.tagged_ranges[1].range, 8..9);
+        assert_eq!(app.cursor, 0);
+    }
+
+    #[test]
+    fn a_subtree_is_not_moved_into_itself() {
+        let mut app = app(OUTLINE);
+        assert!(!app.reorder_lines(1, 2));
+        assert!(!app.reorder_lines(1, 3));
+        assert_eq!(app.buffer, OUTLINE);
+    }
+
+    #[test]
+    fn folding_hides_the_sub_items() {
+        let mut app = app(OUTLINE);
+        app.toggle_fold(0);
+        // From the end of "a" to the end of "d", blank lines in between included
+        let (hidden, markers) = app.folded();
+        assert_eq!(hidden.len(), 1);
+        assert_eq!(hidden[0], 2..17);
+        assert_eq!(markers, [(1, 4)]);
+        app.toggle_fold(0);
+        assert_eq!(app.folded(), (vec![], vec![]));
+        // Items without sub-items have nothing to hide
+        app.toggle_fold(5);
+        assert_eq!(app.folded(), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,984 @@
+use std::fs;
+use std::path::PathBuf;
+
+use eframe::egui;
+use indexmap::IndexMap;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag as Markdown, TagEnd};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::print::PrintMarker;
+use crate::tools::char_to_byte_range;
+use crate::{Tag, TaggedRange, Taskmonger};
+
+/// A4 in points
+const PAGE: [f32; 2] = [595.0, 842.0];
+const MARGIN: f32 = 50.0;
+const BODY_SIZE: f32 = 10.0;
+/// Line height as a multiple of the font size
+const LEADING: f32 = 1.4;
+/// Added per level of list items and quotes in the preview
+const INDENT: f32 = 16.0;
+/// How much of the tag color shows in highlights, as in the HTML export
+const HIGHLIGHT_ALPHA: f32 = 0.35;
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRAY: [u8; 3] = [110, 110, 110];
+
+/// Helvetica's widths of the printable ASCII chars, in thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// What the PDF shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PdfSource {
+    /// The raw buffer in monospace, highlighted like in the editor
+    #[default]
+    Buffer,
+    /// The markdown panel, the whole buffer or the tagged ranges as it is set up
+    Preview,
+}
+
+/// The standard fonts every PDF viewer has built in, so none need embedding
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Font {
+    Sans,
+    SansBold,
+    SansItalic,
+    Mono,
+    MonoBold,
+    MonoItalic,
+}
+
+impl Font {
+    const ALL: [Font; 6] = [
+        Font::Sans,
+        Font::SansBold,
+        Font::SansItalic,
+        Font::Mono,
+        Font::MonoBold,
+        Font::MonoItalic,
+    ];
+
+    fn base_font(self) -> Name<'static> {
+        Name(match self {
+            Font::Sans => b"Helvetica",
+            Font::SansBold => b"Helvetica-Bold",
+            Font::SansItalic => b"Helvetica-Oblique",
+            Font::Mono => b"Courier",
+            Font::MonoBold => b"Courier-Bold",
+            Font::MonoItalic => b"Courier-Oblique",
+        })
+    }
+
+    /// Name of the font in the page resources
+    fn resource(self) -> Name<'static> {
+        Name(match self {
+            Font::Sans => b"F1",
+            Font::SansBold => b"F2",
+            Font::SansItalic => b"F3",
+            Font::Mono => b"F4",
+            Font::MonoBold => b"F5",
+            Font::MonoItalic => b"F6",
+        })
+    }
+
+    fn is_mono(self) -> bool {
+        matches!(self, Font::Mono | Font::MonoBold | Font::MonoItalic)
+    }
+
+    fn bold(self) -> Self {
+        if self.is_mono() {
+            Font::MonoBold
+        } else {
+            Font::SansBold
+        }
+    }
+
+    fn italic(self) -> Self {
+        if self.is_mono() {
+            Font::MonoItalic
+        } else {
+            Font::SansItalic
+        }
+    }
+
+    /// Width of a WinAnsi encoded char at size 1
+    fn width(self, c: u8) -> f32 {
+        if self.is_mono() {
+            return 0.6;
+        }
+        let width = c
+            .checked_sub(b' ')
+            .and_then(|i| HELVETICA_WIDTHS.get(i as usize))
+            .copied()
+            .unwrap_or(556) as f32
+            / 1000.0;
+        // Bold glyphs are a little wider, erring on the wide side keeps lines in the margins
+        if self == Font::SansBold {
+            width * 1.1
+        } else {
+            width
+        }
+    }
+}
+
+/// `c` in the WinAnsi encoding of the standard fonts, `?` if it has no glyph there
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        '„' => 0x84,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '™' => 0x99,
+        _ => b'?',
+    }
+}
+
+/// How the tags covering a char mark it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Mark {
+    background: Option<[u8; 3]>,
+    underline: Option<[u8; 3]>,
+    bold: bool,
+    italics: bool,
+    strikethrough: bool,
+}
+
+/// The mark of every char of the buffer. Tag colors are mixed and lightened like the HTML export
+/// does, `print` uses the gray patterns of the print preview instead.
+fn marks(
+    len: usize,
+    ranges: &[TaggedRange],
+    tags: &IndexMap<String, Tag>,
+    print: bool,
+) -> Vec<Mark> {
+    let mut marks = vec![Mark::default(); len];
+    let mut sums = vec![([0u32; 3], 0u32); len];
+    for tr in ranges.iter().filter(|tr| tr.range.end <= len) {
+        let Some((index, _, tag)) = tags.get_full(&tr.tag_name) else {
+            continue;
+        };
+        let marker = PrintMarker::for_tag(index);
+        for i in tr.range.clone() {
+            let mark = &mut marks[i];
+            if print {
+                if let Some(gray) = marker.background {
+                    mark.background = Some([gray.r(), gray.g(), gray.b()]);
+                }
+                if marker.underline {
+                    mark.underline = Some(BLACK);
+                }
+                mark.italics |= marker.italics;
+                continue;
+            }
+            let (sum, count) = &mut sums[i];
+            for (s, c) in sum.iter_mut().zip(tag.color) {
+                *s += c as u32;
+            }
+            *count += 1;
+            if tag.underline || !tag.background_only {
+                mark.underline = Some(tag.color);
+            }
+            mark.bold |= tag.bold;
+            mark.italics |= tag.italics;
+            mark.strikethrough |= tag.strikethrough;
+        }
+    }
+    for (mark, (sum, count)) in marks.iter_mut().zip(sums) {
+        if count > 0 {
+            let light = |s: u32| {
+                let c = s as f32 / count as f32;
+                (c * HIGHLIGHT_ALPHA + 255.0 * (1.0 - HIGHLIGHT_ALPHA)).round() as u8
+            };
+            mark.background = Some(sum.map(light));
+        }
+    }
+    marks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Style {
+    font: Font,
+    size: f32,
+    color: [u8; 3],
+    background: Option<[u8; 3]>,
+    underline: Option<[u8; 3]>,
+    strikethrough: bool,
+}
+
+impl Style {
+    fn new(font: Font, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: BLACK,
+            background: None,
+            underline: None,
+            strikethrough: false,
+        }
+    }
+
+    fn color(self, color: [u8; 3]) -> Self {
+        Self { color, ..self }
+    }
+
+    fn marked(self, mark: Mark) -> Self {
+        Self {
+            // There is no bold italic font, bold wins like in markdown
+            font: if mark.bold {
+                self.font.bold()
+            } else if mark.italics {
+                self.font.italic()
+            } else {
+                self.font
+            },
+            background: mark.background,
+            underline: mark.underline,
+            strikethrough: self.strikethrough || mark.strikethrough,
+            ..self
+        }
+    }
+}
+
+/// Text on the current line, waiting for the line to be full
+struct Fragment {
+    x: f32,
+    width: f32,
+    text: Vec<u8>,
+    style: Style,
+}
+
+/// Lays out text into lines and pages
+struct Writer {
+    pages: Vec<Content>,
+    /// Top of the next line
+    y: f32,
+    /// Where the next fragment goes
+    x: f32,
+    /// Where wrapped lines start
+    wrap_indent: f32,
+    line: Vec<Fragment>,
+}
+
+fn set_fill(content: &mut Content, [r, g, b]: [u8; 3]) {
+    content.set_fill_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+}
+
+fn set_stroke(content: &mut Content, [r, g, b]: [u8; 3]) {
+    content.set_stroke_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self {
+            pages: vec![Content::new()],
+            y: PAGE[1] - MARGIN,
+            x: MARGIN,
+            wrap_indent: MARGIN,
+            line: vec![],
+        }
+    }
+
+    fn right(&self) -> f32 {
+        PAGE[0] - MARGIN
+    }
+
+    fn page(&mut self) -> &mut Content {
+        self.pages
+            .last_mut()
+            .expect("the writer starts with a page")
+    }
+
+    /// Starts lines `indent` points into the text column, unless a line is under way
+    fn set_indent(&mut self, indent: f32) {
+        self.wrap_indent = MARGIN + indent;
+        if self.line.is_empty() {
+            self.x = self.wrap_indent;
+        }
+    }
+
+    /// Makes sure `height` fits on the page
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.pages.push(Content::new());
+            self.y = PAGE[1] - MARGIN;
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn push(&mut self, text: Vec<u8>, width: f32, style: Style) {
+        match self.line.last_mut() {
+            Some(last) if last.style == style => {
+                last.text.extend(text);
+                last.width += width;
+            }
+            _ => self.line.push(Fragment {
+                x: self.x,
+                width,
+                text,
+                style,
+            }),
+        }
+        self.x += width;
+    }
+
+    /// Adds text to the current line, wrapping it at spaces, or anywhere in words too long
+    /// for a line
+    fn text(&mut self, text: &str, style: Style) {
+        let text = text.replace('\t', "    ");
+        let mut words = vec![];
+        let mut word = String::new();
+        for c in text.chars() {
+            if !word.is_empty() && (c == ' ') != word.ends_with(' ') {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push(c);
+        }
+        words.push(word);
+
+        for word in words.iter().filter(|word| !word.is_empty()) {
+            let bytes: Vec<u8> = word.chars().map(win_ansi).collect();
+            let width: f32 = bytes
+                .iter()
+                .map(|&c| style.font.width(c) * style.size)
+                .sum();
+            let is_space = word.starts_with(' ');
+            if self.x + width > self.right() && !self.line.is_empty() {
+                self.end_line();
+                if is_space {
+                    continue;
+                }
+            }
+            if self.x + width <= self.right() {
+                self.push(bytes, width, style);
+                continue;
+            }
+            for c in bytes {
+                let width = style.font.width(c) * style.size;
+                if self.x + width > self.right() && !self.line.is_empty() {
+                    self.end_line();
+                }
+                self.push(vec![c], width, style);
+            }
+        }
+    }
+
+    /// Draws the current line and moves to the next, empty lines take the body's height
+    fn end_line(&mut self) {
+        let size = self
+            .line
+            .iter()
+            .map(|f| f.style.size)
+            .fold(0.0, f32::max)
+            .max(if self.line.is_empty() { BODY_SIZE } else { 0.0 });
+        let height = size * LEADING;
+        self.reserve(height);
+        let bottom = self.y - height;
+        let baseline = bottom + (height - size) / 2.0 + size * 0.22;
+        let line = std::mem::take(&mut self.line);
+        let content = self.page();
+        for fragment in &line {
+            if let Some(background) = fragment.style.background {
+                set_fill(content, background);
+                content
+                    .rect(fragment.x, bottom, fragment.width, height)
+                    .fill_nonzero();
+            }
+        }
+        for fragment in &line {
+            let style = fragment.style;
+            set_fill(content, style.color);
+            content
+                .begin_text()
+                .set_font(style.font.resource(), style.size)
+                .next_line(fragment.x, baseline)
+                .show(Str(&fragment.text))
+                .end_text();
+            let mut rule = |color: [u8; 3], y: f32| {
+                set_stroke(content, color);
+                content
+                    .set_line_width(style.size / 14.0)
+                    .move_to(fragment.x, y)
+                    .line_to(fragment.x + fragment.width, y)
+                    .stroke();
+            };
+            if let Some(color) = style.underline {
+                rule(color, baseline - style.size * 0.15);
+            }
+            if style.strikethrough {
+                rule(style.color, baseline + style.size * 0.3);
+            }
+        }
+        self.y = bottom;
+        self.x = self.wrap_indent;
+    }
+
+    /// Ends a line only if something is on it
+    fn finish_line(&mut self) {
+        if !self.line.is_empty() {
+            self.end_line();
+        }
+    }
+
+    /// Horizontal line across the text column
+    fn rule(&mut self, color: [u8; 3]) {
+        self.finish_line();
+        self.reserve(BODY_SIZE);
+        let y = self.y - BODY_SIZE / 2.0;
+        let left = self.wrap_indent;
+        let right = self.right();
+        let content = self.page();
+        set_stroke(content, color);
+        content
+            .set_line_width(0.8)
+            .move_to(left, y)
+            .line_to(right, y)
+            .stroke();
+        self.y -= BODY_SIZE;
+    }
+
+    /// The tag names marked like their text, above everything else
+    fn legend(&mut self, tags: &IndexMap<String, Tag>, marks: &[Mark]) {
+        let body = Style::new(Font::Sans, BODY_SIZE);
+        for (name, mark) in tags.keys().zip(marks) {
+            self.text(&format!(" {name} "), body.marked(*mark));
+            self.text("  ", body);
+        }
+        self.finish_line();
+        self.rule(GRAY);
+    }
+
+    /// The PDF document with the title and page numbers in the footers
+    fn finish(mut self, title: &str) -> Vec<u8> {
+        self.finish_line();
+        let count = self.pages.len();
+        let footer = Style::new(Font::Sans, 8.0);
+        for (i, content) in self.pages.iter_mut().enumerate() {
+            let text: Vec<u8> = format!("{title}  ·  {} / {count}", i + 1)
+                .chars()
+                .map(win_ansi)
+                .collect();
+            let width: f32 = text
+                .iter()
+                .map(|&c| footer.font.width(c) * footer.size)
+                .sum();
+            set_fill(content, GRAY);
+            content
+                .begin_text()
+                .set_font(footer.font.resource(), footer.size)
+                .next_line(PAGE[0] - MARGIN - width, MARGIN / 2.0)
+                .show(Str(&text))
+                .end_text();
+        }
+
+        let mut pdf = Pdf::new();
+        let catalog = Ref::new(1);
+        let tree = Ref::new(2);
+        let document_info = Ref::new(3);
+        let font_ref = |i: usize| Ref::new(4 + i as i32);
+        let first_page = 4 + Font::ALL.len() as i32;
+        let page_refs: Vec<(Ref, Ref)> = (0..count as i32)
+            .map(|i| {
+                (
+                    Ref::new(first_page + 2 * i),
+                    Ref::new(first_page + 2 * i + 1),
+                )
+            })
+            .collect();
+        pdf.catalog(catalog).pages(tree);
+        pdf.pages(tree)
+            .kids(page_refs.iter().map(|(page, _)| *page))
+            .count(count as i32);
+        for ((page_ref, content_ref), content) in page_refs.into_iter().zip(self.pages) {
+            let mut page = pdf.page(page_ref);
+            page.media_box(Rect::new(0.0, 0.0, PAGE[0], PAGE[1]))
+                .parent(tree)
+                .contents(content_ref);
+            let mut resources = page.resources();
+            let mut fonts = resources.fonts();
+            for (i, font) in Font::ALL.iter().enumerate() {
+                fonts.pair(font.resource(), font_ref(i));
+            }
+            fonts.finish();
+            resources.finish();
+            page.finish();
+            pdf.stream(content_ref, &content.finish());
+        }
+        for (i, font) in Font::ALL.iter().enumerate() {
+            pdf.type1_font(font_ref(i))
+                .base_font(font.base_font())
+                .encoding_predefined(Name(b"WinAnsiEncoding"));
+        }
+        pdf.document_info(document_info)
+            .title(TextStr(title))
+            .creator(TextStr(env!("CARGO_PKG_NAME")));
+        pdf.finish()
+    }
+}
+
+/// The mark of every tag, as `marks` marks chars of only that tag
+fn tag_marks(tags: &IndexMap<String, Tag>, print: bool) -> Vec<Mark> {
+    tags.keys()
+        .map(|name| {
+            let range = TaggedRange::new(name.clone(), 0..1);
+            marks(1, &[range], tags, print)[0]
+        })
+        .collect()
+}
+
+/// The raw buffer in monospace, tagged text highlighted and a legend of the tags on top
+pub fn buffer_pdf(
+    title: &str,
+    buffer: &str,
+    ranges: &[TaggedRange],
+    tags: &IndexMap<String, Tag>,
+    print: bool,
+) -> Vec<u8> {
+    let marks = marks(buffer.chars().count(), ranges, tags, print);
+    let mut writer = Writer::new();
+    writer.legend(tags, &tag_marks(tags, print));
+    let body = Style::new(Font::Mono, BODY_SIZE);
+    let mut at = 0;
+    for line in buffer.split('\n') {
+        let mut run = String::new();
+        let mut run_mark = Mark::default();
+        for (c, mark) in line.chars().zip(&marks[at..]) {
+            if *mark != run_mark && !run.is_empty() {
+                writer.text(&run, body.marked(run_mark));
+                run.clear();
+            }
+            run_mark = *mark;
+            run.push(c);
+        }
+        writer.text(&run, body.marked(run_mark));
+        writer.end_line();
+        at += line.chars().count() + 1;
+    }
+    writer.finish(title)
+}
+
+/// Part of the markdown panel
+pub struct Section<'a> {
+    /// Tag name, color and due date or done state of a range's card
+    pub header: Option<(String, [u8; 3], String)>,
+    /// Char index of the text in the buffer
+    pub start: usize,
+    pub text: &'a str,
+    /// Plain text and code ranges are not rendered as markdown
+    pub monospace: bool,
+}
+
+fn heading_size(level: HeadingLevel) -> f32 {
+    match level {
+        HeadingLevel::H1 => 20.0,
+        HeadingLevel::H2 => 16.0,
+        HeadingLevel::H3 => 13.0,
+        _ => 11.0,
+    }
+}
+
+/// Renders the markdown of `section`, with the marks of the buffer's chars if any
+fn markdown(writer: &mut Writer, section: &Section, marks: Option<&[Mark]>) {
+    let text = section.text;
+    // Char index in the buffer of every byte of the text
+    let mut chars = vec![0; text.len() + 1];
+    for (i, (byte, c)) in text.char_indices().enumerate() {
+        chars[byte..byte + c.len_utf8()].fill(section.start + i);
+    }
+    chars[text.len()] = section.start + text.chars().count();
+
+    let options =
+        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut bold = 0;
+    let mut italic = 0;
+    let mut strikethrough = 0;
+    let mut code_block = false;
+    let mut heading = None;
+    let mut lists: Vec<Option<u64>> = vec![];
+    let mut quotes = 0;
+    let mut cells = 0;
+    let indent = |lists: &Vec<Option<u64>>, quotes: usize| {
+        (lists.len().saturating_sub(1) + quotes) as f32 * INDENT
+    };
+    writer.set_indent(0.0);
+    for (event, range) in Parser::new_ext(text, options).into_offset_iter() {
+        let inline_code = matches!(event, Event::Code(_));
+        let font = match (code_block, heading.is_some() || bold > 0, italic > 0) {
+            (true, _, _) => Font::Mono,
+            (false, true, _) => Font::SansBold,
+            (false, false, true) => Font::SansItalic,
+            (false, false, false) => Font::Sans,
+        };
+        let mut style = Style::new(font, heading.unwrap_or(BODY_SIZE));
+        style.strikethrough = strikethrough > 0;
+        match event {
+            Event::Start(Markdown::Heading { level, .. }) => {
+                writer.finish_line();
+                writer.gap(6.0);
+                heading = Some(heading_size(level));
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                writer.finish_line();
+                writer.gap(3.0);
+                heading = None;
+            }
+            Event::End(TagEnd::Paragraph) => {
+                writer.finish_line();
+                if lists.is_empty() {
+                    writer.gap(5.0);
+                }
+            }
+            Event::Start(Markdown::List(first)) => {
+                writer.finish_line();
+                lists.push(first);
+            }
+            Event::End(TagEnd::List(_)) => {
+                writer.finish_line();
+                lists.pop();
+                writer.set_indent(indent(&lists, quotes));
+                if lists.is_empty() {
+                    writer.gap(5.0);
+                }
+            }
+            Event::Start(Markdown::Item) => {
+                writer.finish_line();
+                writer.set_indent(indent(&lists, quotes));
+                let bullet = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                writer.text(&bullet, style);
+                writer.wrap_indent = writer.x;
+            }
+            Event::End(TagEnd::Item) => writer.finish_line(),
+            Event::Start(Markdown::BlockQuote(_)) => {
+                writer.finish_line();
+                quotes += 1;
+                writer.set_indent(indent(&lists, quotes));
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                writer.finish_line();
+                quotes -= 1;
+                writer.set_indent(indent(&lists, quotes));
+            }
+            Event::Start(Markdown::CodeBlock(_)) => {
+                writer.finish_line();
+                code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                code_block = false;
+                writer.finish_line();
+                writer.gap(5.0);
+            }
+            Event::Start(Markdown::Strong) => bold += 1,
+            Event::End(TagEnd::Strong) => bold -= 1,
+            Event::Start(Markdown::Emphasis) => italic += 1,
+            Event::End(TagEnd::Emphasis) => italic -= 1,
+            Event::Start(Markdown::Strikethrough) => strikethrough += 1,
+            Event::End(TagEnd::Strikethrough) => strikethrough -= 1,
+            Event::Text(t) if code_block => {
+                let mut lines = t.split('\n').peekable();
+                while let Some(line) = lines.next() {
+                    writer.text(line, style);
+                    if lines.peek().is_some() {
+                        writer.end_line();
+                    }
+                }
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if inline_code {
+                    style.font = Font::Mono;
+                }
+                let Some(marks) = marks else {
+                    writer.text(&t, style);
+                    continue;
+                };
+                // Text that differs from its source, like entities, takes the mark of its start
+                let exact = t.len() == range.len() && !inline_code;
+                let mut run = String::new();
+                let mut run_mark = Mark::default();
+                for (i, c) in t.chars().enumerate() {
+                    let at = if exact {
+                        chars[range.start] + i
+                    } else {
+                        chars[range.start]
+                    };
+                    let mark = marks.get(at).copied().unwrap_or_default();
+                    if mark != run_mark && !run.is_empty() {
+                        writer.text(&run, style.marked(run_mark));
+                        run.clear();
+                    }
+                    run_mark = mark;
+                    run.push(c);
+                }
+                writer.text(&run, style.marked(run_mark));
+            }
+            Event::SoftBreak => writer.text(" ", style),
+            Event::HardBreak => writer.end_line(),
+            Event::TaskListMarker(done) => {
+                writer.text(if done { "[x] " } else { "[ ] " }, style);
+                writer.wrap_indent = writer.x;
+            }
+            Event::Rule => writer.rule(GRAY),
+            Event::Start(Markdown::TableCell) => {
+                if cells > 0 {
+                    writer.text("  |  ", style.color(GRAY));
+                }
+                cells += 1;
+            }
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => {
+                writer.finish_line();
+                cells = 0;
+            }
+            Event::End(TagEnd::Table) => writer.gap(5.0),
+            _ => {}
+        }
+    }
+    writer.finish_line();
+}
+
+/// The markdown panel's sections rendered, highlighted like the buffer if `highlight`
+pub fn preview_pdf(
+    title: &str,
+    sections: &[Section],
+    ranges: &[TaggedRange],
+    tags: &IndexMap<String, Tag>,
+    highlight: Option<(usize, bool)>,
+) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let marks = highlight.map(|(len, print)| {
+        writer.legend(tags, &tag_marks(tags, print));
+        marks(len, ranges, tags, print)
+    });
+    for section in sections {
+        if let Some((name, color, details)) = &section.header {
+            writer.set_indent(0.0);
+            writer.reserve(4.0 * BODY_SIZE * LEADING);
+            writer.gap(BODY_SIZE);
+            writer.text(name, Style::new(Font::SansBold, 12.0).color(*color));
+            writer.text(
+                &format!("  {details}"),
+                Style::new(Font::Sans, 9.0).color(GRAY),
+            );
+            writer.rule(*color);
+        }
+        if section.monospace {
+            writer.set_indent(0.0);
+            for line in section.text.trim_end().split('\n') {
+                writer.text(line, Style::new(Font::Mono, BODY_SIZE));
+                writer.end_line();
+            }
+        } else {
+            markdown(&mut writer, section, marks.as_deref());
+        }
+    }
+    writer.finish(title)
+}
+
+impl Taskmonger {
+    /// Writes the buffer or the markdown preview as a PDF, see [`PdfSource`]. The print
+    /// preview's gray patterns replace tag colors while it is on.
+    pub fn export_pdf(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("taskmonger.pdf");
+        let title = env!("CARGO_PKG_NAME");
+        let print = self.print_preview;
+        let len = self.buffer.chars().count();
+        let pdf = match self.settings.pdf_source {
+            PdfSource::Buffer => {
+                buffer_pdf(title, &self.buffer, &self.tagged_ranges, &self.tags, print)
+            }
+            PdfSource::Preview if self.settings.markdown_full_buffer => {
+                let section = Section {
+                    header: None,
+                    start: 0,
+                    text: &self.buffer,
+                    monospace: false,
+                };
+                preview_pdf(
+                    title,
+                    &[section],
+                    &self.tagged_ranges,
+                    &self.tags,
+                    Some((len, print)),
+                )
+            }
+            PdfSource::Preview => {
+                let today = chrono::Local::now().date_naive();
+                let sections: Vec<Section> = self
+                    .markdown_order()
+                    .into_iter()
+                    .map(|i| &self.tagged_ranges[i])
+                    .filter(|tr| tr.range.end <= len)
+                    .map(|tr| {
+                        let color = match self.tags.get(&tr.tag_name) {
+                            Some(tag) if !print => tag.color,
+                            _ => BLACK,
+                        };
+                        let mut details = vec![];
+                        if tr.done {
+                            details.push("done".to_string());
+                        }
+                        match tr.due {
+                            Some(due) if tr.is_overdue(today) => {
+                                details.push(format!("overdue {due}"))
+                            }
+                            Some(due) => details.push(format!("due {due}")),
+                            None => {}
+                        }
+                        Section {
+                            header: Some((tr.tag_name.clone(), color, details.join(", "))),
+                            start: tr.range.start,
+                            text: &self.buffer[char_to_byte_range(&self.buffer, &tr.range)],
+                            monospace: tr.plain_text || tr.language.is_some(),
+                        }
+                    })
+                    .collect();
+                preview_pdf(title, &sections, &self.tagged_ranges, &self.tags, None)
+            }
+        };
+        fs::write(&path, pdf)?;
+        info!(source = ?self.settings.pdf_source, "Exported {}", path.display());
+        Ok(path)
+    }
+
+    /// Export menu row to write the PDF and pick what goes into it
+    pub fn pdf_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Print / Export PDF")
+                .on_hover_text(
+                    "A PDF to print or hand off, in the print preview's grays while it is on",
+                )
+                .clicked()
+            {
+                match self.export_pdf() {
+                    Ok(path) => self
+                        .toasts
+                        .success(format!("Exported PDF to {}", path.display())),
+                    Err(e) => self.toasts.error(format!("PDF export failed: {e}")),
+                }
+                ui.close();
+            }
+            let mut changed = ui
+                .selectable_value(&mut self.settings.pdf_source, PdfSource::Buffer, "Buffer")
+                .on_hover_text("The raw buffer with its tags highlighted")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut self.settings.pdf_source, PdfSource::Preview, "Preview")
+                .on_hover_text("The markdown view, whole buffer or tagged ranges as it shows them")
+                .changed();
+            if changed {
+                let _ = self.save_to_disk();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines ended on the current page
+    fn line_count(writer: &Writer) -> usize {
+        ((PAGE[1] - MARGIN - writer.y) / (BODY_SIZE * LEADING)).round() as usize
+    }
+
+    #[test]
+    fn chars_are_encoded_for_the_standard_fonts() {
+        assert_eq!(win_ansi('a'), b'a');
+        assert_eq!(win_ansi('é'), 0xe9);
+        assert_eq!(win_ansi('€'), 0x80);
+        assert_eq!(win_ansi('“'), 0x93);
+        assert_eq!(win_ansi('日'), b'?');
+        assert_eq!(win_ansi('\n'), b'?');
+    }
+
+    #[test]
+    fn text_wraps_at_spaces_within_the_margins() {
+        let mut writer = Writer::new();
+        let body = Style::new(Font::Mono, BODY_SIZE);
+        // 6 points per char, 82 chars fit between the margins
+        let word = "abcdefghi ";
+        writer.text(&word.repeat(20), body);
+        writer.end_line();
+        assert_eq!(line_count(&writer), 3);
+
+        let mut writer = Writer::new();
+        writer.text(&word.repeat(8), body);
+        assert_eq!(line_count(&writer), 0);
+        assert!(writer.line.iter().all(|f| f.x + f.width <= writer.right()));
+        // A word that does not fit starts the next line at the margin
+        writer.text("x".repeat(9).as_str(), body);
+        assert_eq!(line_count(&writer), 1);
+        assert_eq!(writer.line[0].text, b"xxxxxxxxx");
+        assert_eq!(writer.line[0].x, MARGIN);
+    }
+
+    #[test]
+    fn words_longer_than_a_line_are_broken_anywhere() {
+        let mut writer = Writer::new();
+        writer.text(&"x".repeat(100), Style::new(Font::Mono, BODY_SIZE));
+        assert_eq!(line_count(&writer), 1);
+        let rest: usize = writer.line.iter().map(|f| f.text.len()).sum();
+        assert_eq!(rest, 100 - 82);
+    }
+
+    #[test]
+    fn full_pages_start_a_new_one() {
+        let lines_per_page = ((PAGE[1] - 2.0 * MARGIN) / (BODY_SIZE * LEADING)) as usize;
+        let mut writer = Writer::new();
+        for _ in 0..lines_per_page {
+            writer.text("line", Style::new(Font::Sans, BODY_SIZE));
+            writer.end_line();
+        }
+        assert_eq!(writer.pages.len(), 1);
+        writer.text("line", Style::new(Font::Sans, BODY_SIZE));
+        writer.end_line();
+        assert_eq!(writer.pages.len(), 2);
+        assert_eq!(line_count(&writer), 1);
+    }
+
+    #[test]
+    fn parentheses_and_backslashes_are_escaped() {
+        let pdf = buffer_pdf("t", r"smile :) \ f(x)", &[], &IndexMap::new(), false);
+        let pdf = String::from_utf8_lossy(&pdf);
+        let shown: Vec<&str> = pdf.lines().filter(|l| l.ends_with(" Tj")).collect();
+        assert_eq!(shown[0], r"(smile :\) \\ f\(x\)) Tj");
+    }
+
+    #[test]
+    fn overlapping_tags_mix_their_colors() {
+        let mut tags = IndexMap::new();
+        tags.insert("a".to_string(), Tag::new([255, 0, 0]));
+        tags.insert("b".to_string(), Tag::new([0, 0, 255]));
+        let ranges = [
+            TaggedRange::new("a".to_string(), 0..2),
+            TaggedRange::new("b".to_string(), 1..3),
+        ];
+        let marks = marks(4, &ranges, &tags, false);
+        assert_eq!(marks[0].background, Some([255, 166, 166]));
+        assert_eq!(marks[1].background, Some([210, 166, 210]));
+        assert_eq!(marks[2].background, Some([166, 166, 255]));
+        assert_eq!(marks[3], Mark::default());
+    }
+}
@@ -0,0 +1,85 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+
+use crate::Taskmonger;
+
+/// Gray backgrounds that stay readable on paper
+const SHADES: [Option<Color32>; 3] = [
+    None,
+    Some(Color32::from_gray(232)),
+    Some(Color32::from_gray(205)),
+];
+
+/// How a tag is marked when colors can't be relied on: a gray shade, an underline and italics.
+/// Every tag index below 11 gets a distinct combination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintMarker {
+    pub background: Option<Color32>,
+    pub underline: bool,
+    pub italics: bool,
+}
+
+impl PrintMarker {
+    pub fn for_tag(index: usize) -> Self {
+        // Skip the combination that marks nothing
+        let n = index % 11 + 1;
+        Self {
+            background: SHADES[n % 3],
+            underline: (n / 3) % 2 == 1,
+            italics: (n / 6) % 2 == 1,
+        }
+    }
+
+    /// The tag name drawn with its marker, for legends
+    pub fn label(&self, name: &str) -> RichText {
+        let mut text = RichText::new(name).color(Color32::BLACK);
+        if let Some(background) = self.background {
+            text = text.background_color(background);
+        }
+        self.pattern(text)
+    }
+
+    /// `text` with the underline and italics of the marker, for colored tag names
+    pub fn pattern(&self, mut text: RichText) -> RichText {
+        if self.underline {
+            text = text.underline();
+        }
+        if self.italics {
+            text = text.italics();
+        }
+        text
+    }
+
+    /// The gray shade as a see-through overlay on top of tag colors
+    pub fn overlay(&self, dark_mode: bool) -> Option<Color32> {
+        let alpha = 255 - self.background?.r();
+        Some(if dark_mode {
+            Color32::from_white_alpha(alpha)
+        } else {
+            Color32::from_black_alpha(alpha)
+        })
+    }
+}
+
+/// White background and black text, whatever the theme
+pub fn print_visuals() -> egui::Visuals {
+    let mut visuals = egui::Visuals::light();
+    visuals.override_text_color = Some(Color32::BLACK);
+    visuals.panel_fill = Color32::WHITE;
+    visuals.window_fill = Color32::WHITE;
+    visuals.extreme_bg_color = Color32::WHITE;
+    visuals
+}
+
+impl Taskmonger {
+    /// Which marker stands for which tag
+    pub fn print_legend_ui(&self, ui: &mut egui::Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Print preview:");
+            for (i, name) in self.tags.keys().enumerate() {
+                ui.label(PrintMarker::for_tag(i).label(name));
+            }
+        });
+        ui.separator();
+    }
+}
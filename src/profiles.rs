@@ -0,0 +1,208 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{Settings, Tag, Taskmonger};
+
+/// Preferences that can be moved to another machine: the settings and how tags look and behave.
+/// Ranges and the buffer are not part of it.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Profile {
+    settings: Settings,
+    /// Applied to tags of the same name, missing ones are created
+    tags: IndexMap<String, Tag>,
+    group_colors: IndexMap<String, [u8; 3]>,
+}
+
+impl Profile {
+    fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+fn profile_dir() -> PathBuf {
+    PathBuf::from("profiles")
+}
+
+fn profile_path(name: &str) -> PathBuf {
+    profile_dir().join(format!("{name}.json"))
+}
+
+/// Names of the stored profiles, sorted
+fn profile_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(profile_dir()) else {
+        return vec![];
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter_map(|p| Some(p.file_stem()?.to_string_lossy().to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+impl Taskmonger {
+    fn profile(&self) -> Profile {
+        Profile {
+            settings: self.settings.clone(),
+            tags: self.tags.clone(),
+            group_colors: self.group_colors.clone(),
+        }
+    }
+
+    /// Writes the current preferences as profile `name`
+    fn save_profile(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(profile_dir())?;
+        self.settings.profile = Some(name.to_string());
+        let json = serde_json::to_string_pretty(&self.profile())?;
+        fs::write(profile_path(name), json)?;
+        info!("Saved profile {name}");
+        let _ = self.save_to_disk();
+        Ok(())
+    }
+
+    /// Switches to a profile. Bookkeeping of this machine, like when the digest was sent, stays.
+    fn apply_profile(&mut self, name: &str, profile: Profile) {
+        let last_sent = self.settings.digest.last_sent;
+        let last_run = self.settings.retention.last_run;
+        self.settings = profile.settings;
+        self.settings.digest.last_sent = last_sent;
+        self.settings.retention.last_run = last_run;
+        self.settings.profile = Some(name.to_string());
+        self.tags.extend(profile.tags);
+        self.group_colors.extend(profile.group_colors);
+        info!("Switched to profile {name}");
+        self.toasts.success(format!("Using profile {name}"));
+        let _ = self.save_to_disk();
+    }
+
+    fn switch_profile(&mut self, name: &str) {
+        match Profile::load(&profile_path(name)) {
+            Ok(profile) => self.apply_profile(name, profile),
+            Err(e) => self
+                .toasts
+                .error(format!("Could not read profile {name}: {e}")),
+        }
+    }
+
+    /// Copies a profile file from elsewhere into the profiles and switches to it
+    fn import_profile(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let profile = Profile::load(path)?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .ok_or("not a file")?;
+        fs::create_dir_all(profile_dir())?;
+        if fs::canonicalize(path).ok() != fs::canonicalize(profile_path(&name)).ok() {
+            fs::copy(path, profile_path(&name))?;
+        }
+        let source = format!("profile {name}");
+        let collisions: Vec<_> = profile
+            .tags
+            .iter()
+            .filter_map(|(tag, theirs)| self.tag_collision(tag, theirs, &source))
+            .collect();
+        self.apply_profile(&name, profile);
+        self.tag_collisions.extend(collisions);
+        Ok(())
+    }
+
+    /// Profile section of the settings window
+    pub fn profiles_ui(&mut self, ui: &mut egui::Ui) {
+        let names = profile_names();
+        let mut switch_to = None;
+        ui.horizontal(|ui| {
+            ui.label("Profile");
+            egui::ComboBox::from_id_salt("settings_profile")
+                .selected_text(self.settings.profile.as_deref().unwrap_or("None"))
+                .show_ui(ui, |ui| {
+                    if names.is_empty() {
+                        ui.label("No profiles saved yet");
+                    }
+                    for name in &names {
+                        let selected = self.settings.profile.as_ref() == Some(name);
+                        if ui.selectable_label(selected, name).clicked() {
+                            switch_to = Some(name.clone());
+                        }
+                    }
+                });
+        });
+        if let Some(name) = switch_to {
+            self.switch_profile(&name);
+        }
+
+        let name_id = egui::Id::new("profile_name_edit");
+        let mut name = ui
+            .data(|d| d.get_temp::<String>(name_id))
+            .or_else(|| self.settings.profile.clone())
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut name)
+                    .hint_text("Profile name")
+                    .desired_width(140.0),
+            );
+            let valid = !name.trim().is_empty()
+                && !name.contains(|c: char| std::path::is_separator(c) || c == '.');
+            if ui
+                .add_enabled(valid, egui::Button::new("Save as profile"))
+                .on_hover_text("Store the settings and tag looks and rules under this name")
+                .clicked()
+            {
+                match self.save_profile(name.trim()) {
+                    Ok(()) => self
+                        .toasts
+                        .success(format!("Saved {}", profile_path(name.trim()).display())),
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not save the profile: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(name_id, name));
+
+        let import_id = egui::Id::new("profile_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of a profile file")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(!import.trim().is_empty(), egui::Button::new("Import"))
+                .clicked()
+            {
+                match self.import_profile(Path::new(import.trim())) {
+                    Ok(()) => import.clear(),
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the profile: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+
+        if ui
+            .button("Open profile folder")
+            .on_hover_text("Copy a profile file from here to use it on another machine")
+            .clicked()
+        {
+            if let Ok(dir) =
+                fs::create_dir_all(profile_dir()).and_then(|_| fs::canonicalize(profile_dir()))
+            {
+                ui.ctx()
+                    .open_url(egui::OpenUrl::new_tab(format!("file://{}", dir.display())));
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use egui::RichText;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::{Settings, TaggedRange, Taskmonger};
+
+/// What was lost when the state file could only be read in part
+pub struct Recovery {
+    /// Untouched copy of the unreadable file
+    pub broken_copy: Option<PathBuf>,
+    /// Field path and why it could not be read
+    pub fields: Vec<(String, String)>,
+}
+
+/// Takes over the readable fields of `loaded` into the defaults of `T`, one at a time.
+/// Returns the merged value and the fields that had to stay at their defaults.
+fn salvage<T: Serialize + DeserializeOwned + Default>(
+    loaded: &Map<String, Value>,
+    prefix: &str,
+    skip: &[&str],
+) -> (T, Vec<(String, String)>) {
+    let Ok(Value::Object(mut merged)) = serde_json::to_value(T::default()) else {
+        return (T::default(), vec![]);
+    };
+    let mut unreadable = vec![];
+    for (key, value) in loaded {
+        if skip.contains(&key.as_str()) {
+            continue;
+        }
+        let previous = merged.insert(key.clone(), value.clone());
+        if let Err(e) = serde_json::from_value::<T>(Value::Object(merged.clone())) {
+            unreadable.push((format!("{prefix}{key}"), e.to_string()));
+            match previous {
+                Some(previous) => merged.insert(key.clone(), previous),
+                None => merged.remove(key),
+            };
+        }
+    }
+    let value = serde_json::from_value(Value::Object(merged)).unwrap_or_default();
+    (value, unreadable)
+}
+
+impl Taskmonger {
+    /// Copies an unreadable state file aside, so saving does not overwrite it
+    fn keep_broken(path: &Path) -> Option<PathBuf> {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let copy = path.with_extension(format!("broken-{stamp}.json"));
+        match fs::copy(path, &copy) {
+            Ok(_) => Some(copy),
+            Err(e) => {
+                warn!("Could not keep a copy of {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Loads what can still be read from a state file that failed to load.
+    /// Returns None if it is not even valid JSON.
+    pub fn recover(path: &Path, error: &str) -> Option<Self> {
+        let broken_copy = Self::keep_broken(path);
+        let json = fs::read_to_string(path).ok()?;
+        let mut loaded = match serde_json::from_str::<Value>(&json) {
+            Ok(Value::Object(loaded)) => loaded,
+            _ => {
+                warn!("{} is not valid JSON: {error}", path.display());
+                return None;
+            }
+        };
+        // A single broken range should not take all the others with it
+        let mut fields = vec![];
+        if let Some(Value::Array(ranges)) = loaded.get_mut("tagged_ranges") {
+            let mut index = 0;
+            ranges.retain(|range| {
+                let result = serde_json::from_value::<TaggedRange>(range.clone());
+                if let Err(e) = &result {
+                    fields.push((format!("tagged_ranges[{index}]"), e.to_string()));
+                }
+                index += 1;
+                result.is_ok()
+            });
+        }
+        let (mut app, unreadable) = salvage::<Taskmonger>(&loaded, "", &["settings"]);
+        fields.extend(unreadable);
+        match loaded.get("settings") {
+            Some(Value::Object(settings)) => {
+                let (settings, unreadable) = salvage::<Settings>(settings, "settings.", &[]);
+                app.settings = settings;
+                fields.extend(unreadable);
+            }
+            Some(_) => fields.push(("settings".into(), "not an object".into())),
+            None => {}
+        }
+        warn!(
+            "Recovered {} with {} unreadable field(s)",
+            path.display(),
+            fields.len()
+        );
+        app.after_load();
+        app.recovery = Some(Recovery {
+            broken_copy,
+            fields,
+        });
+        Some(app)
+    }
+
+    /// Lists the fields that fell back to defaults after a partial load
+    pub fn recovery_window(&mut self, ctx: &egui::Context) {
+        let Some(recovery) = &self.recovery else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("State recovered")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} could not be read completely. These fields were reset to their defaults:",
+                    Self::save_path().display()
+                ));
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("recovered_fields")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (field, error) in &recovery.fields {
+                                    ui.monospace(field);
+                                    ui.label(RichText::new(error).weak());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                if recovery.fields.is_empty() {
+                    ui.label("None, only the file layout was off.");
+                }
+                match &recovery.broken_copy {
+                    Some(copy) => {
+                        ui.label(format!("The original file is kept as {}.", copy.display()))
+                    }
+                    None => ui.label("The original file could not be copied."),
+                };
+                ui.label(format!(
+                    "Daily snapshots in {} hold older versions.",
+                    Self::snapshot_dir().display()
+                ));
+                if ui.button("OK").clicked() {
+                    close = true;
+                }
+            });
+        if close {
+            self.recovery = None;
+        }
+    }
+}
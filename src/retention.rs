@@ -0,0 +1,389 @@
+use std::fs;
+use std::path::PathBuf;
+
+use eframe::egui;
+use egui::RichText;
+use egui_phosphor::regular::ARROW_COUNTER_CLOCKWISE;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::sync::find_text;
+use crate::tools::char_to_byte_range;
+use crate::{TaggedRange, Taskmonger};
+
+/// How long old data is kept. Zero keeps it forever.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub snapshot_days: u32,
+    /// Done ranges untouched for this long move to the archive
+    pub archive_done_days: u32,
+    pub trash_days: u32,
+    pub last_run: Option<chrono::NaiveDate>,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            snapshot_days: 90,
+            archive_done_days: 30,
+            trash_days: 14,
+            last_run: None,
+        }
+    }
+}
+
+/// A deleted range, kept around for a while so it can be restored
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrashedRange {
+    pub range: TaggedRange,
+    /// The range's text when it was deleted, its indices may be stale by now
+    pub text: String,
+    pub deleted: chrono::NaiveDateTime,
+}
+
+impl TrashedRange {
+    /// Start of the first line of the text, for lists
+    fn preview(&self) -> String {
+        self.text
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(30)
+            .collect()
+    }
+}
+
+/// What a maintenance run is going to do
+#[derive(Default)]
+pub struct MaintenancePlan {
+    pub snapshots: Vec<PathBuf>,
+    /// Indices into the tagged ranges
+    pub archive: Vec<usize>,
+    /// Indices into the trash
+    pub purge: Vec<usize>,
+}
+
+impl MaintenancePlan {
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty() && self.archive.is_empty() && self.purge.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} snapshot(s) to delete, {} range(s) to archive, {} trashed range(s) to purge",
+            self.snapshots.len(),
+            self.archive.len(),
+            self.purge.len()
+        )
+    }
+}
+
+const SNAPSHOT_PREFIX: &str = "taskmonger_state-";
+
+impl Taskmonger {
+    pub fn snapshot_dir() -> PathBuf {
+        PathBuf::from("snapshots")
+    }
+
+    /// Keeps a copy of the state per day
+    pub fn write_snapshot(&self, json: &str) -> std::io::Result<()> {
+        let today = chrono::Local::now().date_naive();
+        let path = Self::snapshot_dir().join(format!(
+            "{SNAPSHOT_PREFIX}{}.json",
+            today.format("%Y-%m-%d")
+        ));
+        if !path.exists() {
+            fs::create_dir_all(Self::snapshot_dir())?;
+            fs::write(&path, json)?;
+            debug!("Wrote snapshot {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Moves a deleted range into the trash
+    pub fn trash_range(&mut self, range: TaggedRange) {
+        let len = self.buffer.chars().count();
+        let text = if range.range.end <= len {
+            self.buffer[char_to_byte_range(&self.buffer, &range.range)].to_string()
+        } else {
+            String::new()
+        };
+        self.trash.push(TrashedRange {
+            range,
+            text,
+            deleted: chrono::Local::now().naive_local(),
+        });
+    }
+
+    pub fn restore_from_trash(&mut self, index: usize) {
+        if !self.untrash(self.trash[index].clone()) {
+            self.toasts
+                .warning("The text is gone and the buffer is locked, so it stays in the trash");
+            return;
+        }
+        self.trash.remove(index);
+        let _ = self.save_to_disk();
+    }
+
+    /// Puts a trashed range back on its text, wherever that is now. Text no longer in the
+    /// buffer is added at its end, unless the buffer is locked. Returns false if it was not
+    /// restored.
+    fn untrash(&mut self, mut trashed: TrashedRange) -> bool {
+        if trashed.text.is_empty() {
+            info!("Nothing to restore, the trashed range had no text");
+            return true;
+        }
+        let near = trashed.range.range.start;
+        trashed.range.range = match find_text(&self.buffer, &trashed.text, near) {
+            Some(found) => found,
+            None if self.locked => return false,
+            None => {
+                debug!("The trashed text is gone, adding it at the end");
+                if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+                    self.buffer.push('\n');
+                }
+                let start = self.buffer.chars().count();
+                self.buffer.push_str(&trashed.text);
+                let end = self.buffer.chars().count();
+                self.buffer.push('\n');
+                start..end
+            }
+        };
+        trashed.range.mark();
+        let tag_name = trashed.range.tag_name.clone();
+        if !self.tags.contains_key(&tag_name) {
+            self.add_tag(tag_name.clone());
+        }
+        self.tagged_ranges.push(trashed.range);
+        self.clean_invalid_ranges();
+        self.normalize_tag_ranges(&tag_name);
+        true
+    }
+
+    /// Dry run: collects what the retention settings would remove or move
+    pub fn plan_maintenance(&self) -> MaintenancePlan {
+        let retention = &self.settings.retention;
+        let now = chrono::Local::now().naive_local();
+        let older_than = |days: u32| now - chrono::Days::new(days as u64);
+        let mut plan = MaintenancePlan::default();
+
+        if retention.snapshot_days > 0 {
+            let cutoff = older_than(retention.snapshot_days).date();
+            if let Ok(entries) = fs::read_dir(Self::snapshot_dir()) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let date = path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .and_then(|s| s.strip_prefix(SNAPSHOT_PREFIX))
+                        .and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+                    if date.is_some_and(|date| date < cutoff) {
+                        plan.snapshots.push(path);
+                    }
+                }
+            }
+            plan.snapshots.sort();
+        }
+        if retention.archive_done_days > 0 {
+            let cutoff = older_than(retention.archive_done_days);
+            plan.archive = (0..self.tagged_ranges.len())
+                .filter(|&i| {
+                    let tr = &self.tagged_ranges[i];
+                    tr.done && tr.recurrence.is_none() && tr.modified < cutoff
+                })
+                .collect();
+        }
+        if retention.trash_days > 0 {
+            let cutoff = older_than(retention.trash_days);
+            plan.purge = (0..self.trash.len())
+                .filter(|&i| self.trash[i].deleted < cutoff)
+                .collect();
+        }
+        plan
+    }
+
+    pub fn run_maintenance(&mut self) {
+        let plan = self.plan_maintenance();
+        self.settings.retention.last_run = Some(chrono::Local::now().date_naive());
+        if plan.is_empty() {
+            let _ = self.save_to_disk();
+            return;
+        }
+        info!("Maintenance: {}", plan.summary());
+        let mut failed = 0;
+        for path in &plan.snapshots {
+            if fs::remove_file(path).is_err() {
+                failed += 1;
+            }
+        }
+        self.archive_ranges(plan.archive.clone(), false);
+        for &i in plan.purge.iter().rev() {
+            self.trash.remove(i);
+        }
+        if failed > 0 {
+            self.toasts
+                .warning(format!("{failed} old snapshot(s) could not be deleted"));
+        }
+        self.toasts.info(format!("Maintenance: {}", plan.summary()));
+        let _ = self.save_to_disk();
+    }
+
+    /// Retention settings, a preview of the next run and the trash
+    pub fn maintenance_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.maintenance_open;
+        egui::Window::new("Data retention")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                let before = self.settings.retention.clone();
+                let retention = &mut self.settings.retention;
+                egui::Grid::new("retention").show(ui, |ui| {
+                    let days = |ui: &mut egui::Ui, label: &str, value: &mut u32, zero: &str| {
+                        ui.label(label);
+                        ui.add(
+                            egui::DragValue::new(value)
+                                .range(0..=3650)
+                                .custom_formatter(|n, _| {
+                                    if n == 0.0 {
+                                        zero.to_string()
+                                    } else {
+                                        format!("{n} days")
+                                    }
+                                }),
+                        );
+                        ui.end_row();
+                    };
+                    days(
+                        ui,
+                        "Keep daily snapshots",
+                        &mut retention.snapshot_days,
+                        "forever",
+                    );
+                    days(
+                        ui,
+                        "Archive done ranges after",
+                        &mut retention.archive_done_days,
+                        "never",
+                    );
+                    days(ui, "Keep trash", &mut retention.trash_days, "forever");
+                });
+                if self.settings.retention != before {
+                    let _ = self.save_to_disk();
+                }
+
+                ui.separator();
+                let plan = self.plan_maintenance();
+                ui.label(RichText::new("Next run").strong());
+                if plan.is_empty() {
+                    ui.label("Nothing to clean up.");
+                } else {
+                    for path in &plan.snapshots {
+                        ui.label(format!("Delete {}", path.display()));
+                    }
+                    for &i in &plan.archive {
+                        let tr = &self.tagged_ranges[i];
+                        ui.label(format!(
+                            "Archive {}: {}",
+                            tr.tag_name,
+                            self.preview(&tr.range)
+                        ));
+                    }
+                    for &i in &plan.purge {
+                        let trashed = &self.trash[i];
+                        ui.label(format!(
+                            "Purge {}: {}",
+                            trashed.range.tag_name,
+                            trashed.preview()
+                        ));
+                    }
+                }
+                if ui
+                    .add_enabled(!plan.is_empty(), egui::Button::new("Run now"))
+                    .clicked()
+                {
+                    self.run_maintenance();
+                }
+
+                ui.separator();
+                ui.label(RichText::new(format!("Trash ({})", self.trash.len())).strong());
+                let mut restore = None;
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for (i, trashed) in self.trash.iter().enumerate().rev() {
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .small_button(ARROW_COUNTER_CLOCKWISE)
+                                    .on_hover_text("Restore")
+                                    .clicked()
+                                {
+                                    restore = Some(i);
+                                }
+                                ui.label(format!(
+                                    "{} {}: {}",
+                                    trashed.deleted.format("%b %d"),
+                                    trashed.range.tag_name,
+                                    trashed.preview()
+                                ));
+                            });
+                        }
+                    });
+                if let Some(i) = restore {
+                    self.restore_from_trash(i);
+                }
+            });
+        self.maintenance_open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tag;
+
+    fn app(buffer: &str) -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: buffer.to_string(),
+            ..Default::default()
+        };
+        app.tags.clear();
+        app.tags.insert("todo".to_string(), Tag::new([1, 2, 3]));
+        app.tagged_ranges.clear();
+        app
+    }
+
+    fn trashed(range: std::ops::Range<usize>, text: &str) -> TrashedRange {
+        TrashedRange {
+            range: TaggedRange::new("todo".to_string(), range),
+            text: text.to_string(),
+            deleted: chrono::Local::now().naive_local(),
+        }
+    }
+
+    #[test]
+    fn restored_ranges_follow_their_text() {
+        let mut app = app("new line\nfix bug\nfix bug again\n");
+        assert!(app.untrash(trashed(0..7, "fix bug")));
+        assert_eq!(app.tagged_ranges[0].range, 9..16);
+        assert_eq!(app.buffer, "new line\nfix bug\nfix bug again\n");
+    }
+
+    #[test]
+    fn text_that_is_gone_is_added_at_the_end() {
+        let mut app = app("other text");
+        assert!(app.untrash(trashed(0..3, "äbc")));
+        assert_eq!(app.buffer, "other text\näbc\n");
+        assert_eq!(app.tagged_ranges[0].range, 11..14);
+    }
+
+    #[test]
+    fn a_locked_buffer_keeps_gone_text_in_the_trash() {
+        let mut app = app("other text");
+        app.locked = true;
+        assert!(!app.untrash(trashed(0..3, "abc")));
+        assert_eq!(app.buffer, "other text");
+        assert!(app.tagged_ranges.is_empty());
+    }
+}
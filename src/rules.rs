@@ -0,0 +1,331 @@
+use std::ops::Range;
+
+use eframe::egui;
+use egui::RichText;
+use egui_phosphor::regular::{PLUS, TRASH};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::tools::char_to_byte_range;
+use crate::{TaggedRange, Taskmonger};
+
+/// Tags text matching a keyword or regex
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagRule {
+    pub enabled: bool,
+    pub pattern: String,
+    /// Treat the pattern as a regex instead of a keyword, which ignores ASCII case
+    pub regex: bool,
+    /// Tag the whole line the match is on, not just the match
+    pub whole_line: bool,
+    pub tag: String,
+}
+
+impl Default for TagRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pattern: String::new(),
+            regex: false,
+            whole_line: true,
+            tag: String::new(),
+        }
+    }
+}
+
+enum Matcher {
+    Keyword(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Byte range of the first match in `line`
+    fn find(&self, line: &str) -> Option<Range<usize>> {
+        match self {
+            // ASCII lowercasing keeps byte offsets intact
+            Matcher::Keyword(keyword) => {
+                let start = line.to_ascii_lowercase().find(keyword.as_str())?;
+                Some(start..start + keyword.len())
+            }
+            Matcher::Regex(regex) => regex.find(line).map(|m| m.range()),
+        }
+    }
+}
+
+impl TagRule {
+    fn matcher(&self) -> Result<Option<Matcher>, regex::Error> {
+        if !self.enabled || self.pattern.is_empty() || self.tag.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(if self.regex {
+            Matcher::Regex(Regex::new(&self.pattern)?)
+        } else {
+            Matcher::Keyword(self.pattern.to_ascii_lowercase())
+        }))
+    }
+}
+
+/// Char ranges in `text` the rules match, with the tag to give them.
+/// `offset` is the char index `text` starts at in the buffer.
+fn rule_matches(
+    rules: &[(String, Matcher, bool)],
+    text: &str,
+    offset: usize,
+) -> Vec<(String, Range<usize>)> {
+    let mut found = vec![];
+    let mut line_start = offset;
+    for line in text.split('\n') {
+        for (tag, matcher, whole_line) in rules {
+            let Some(bytes) = matcher.find(line) else {
+                continue;
+            };
+            let range = if *whole_line {
+                let leading = line.chars().take_while(|c| c.is_whitespace()).count();
+                let trailing = line.chars().rev().take_while(|c| c.is_whitespace()).count();
+                leading..line.chars().count() - trailing
+            } else {
+                line[..bytes.start].chars().count()..line[..bytes.end].chars().count()
+            };
+            if !range.is_empty() {
+                found.push((
+                    tag.clone(),
+                    line_start + range.start..line_start + range.end,
+                ));
+            }
+        }
+        line_start += line.chars().count() + 1;
+    }
+    found
+}
+
+impl Taskmonger {
+    /// Rules that compile, with their tag. Broken regexes are skipped.
+    fn active_rules(&self) -> Vec<(String, Matcher, bool)> {
+        self.settings
+            .rules
+            .iter()
+            .filter(|rule| self.tags.contains_key(&rule.tag))
+            .filter_map(|rule| Some((rule.tag.clone(), rule.matcher().ok()??, rule.whole_line)))
+            .collect()
+    }
+
+    /// Adds ranges for the matches that their tag does not cover yet. Returns how many.
+    fn tag_matches(&mut self, found: Vec<(String, Range<usize>)>) -> usize {
+        let mut added = 0;
+        for (tag, range) in found {
+            let covered = self.tagged_ranges.iter().any(|tr| {
+                tr.tag_name == tag && tr.range.start <= range.start && range.end <= tr.range.end
+            });
+            if covered {
+                continue;
+            }
+            debug!(tag, ?range, "Rule matched");
+            self.tagged_ranges
+                .push(TaggedRange::new(tag.clone(), range));
+            self.normalize_tag_ranges(&tag);
+            added += 1;
+        }
+        added
+    }
+
+    /// Runs the rules over the whole buffer
+    pub fn apply_rules(&mut self) -> usize {
+        let rules = self.active_rules();
+        let found = rule_matches(&rules, &self.buffer, 0);
+        let added = self.tag_matches(found);
+        info!("Rules tagged {added} range(s)");
+        let _ = self.save_to_disk();
+        added
+    }
+
+    /// Whether rules run on their own while typing
+    pub fn has_rules(&self) -> bool {
+        self.settings.rules_while_typing && !self.safe_mode && !self.settings.rules.is_empty()
+    }
+
+    /// Runs the rules over the lines of `range`, a paste
+    pub fn apply_rules_to(&mut self, range: &Range<usize>) -> usize {
+        let len = self.buffer.chars().count();
+        let range = range.start.min(len)..range.end.min(len);
+        let bytes = char_to_byte_range(&self.buffer, &range);
+        let start = self.buffer[..bytes.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.buffer[bytes.end..]
+            .find('\n')
+            .map_or(self.buffer.len(), |i| bytes.end + i);
+        let offset = self.buffer[..start].chars().count();
+        let found = rule_matches(&self.active_rules(), &self.buffer[start..end], offset);
+        let added = self.tag_matches(found);
+        info!("Rules tagged {added} range(s) in a paste");
+        let _ = self.save_to_disk();
+        added
+    }
+
+    /// Runs the rules over the line at the cursor, after typing
+    pub fn apply_rules_at_cursor(&mut self) {
+        if !self.has_rules() {
+            return;
+        }
+        let rules = self.active_rules();
+        if rules.is_empty() {
+            return;
+        }
+        let cursor = char_to_byte_range(&self.buffer, &(self.cursor..self.cursor)).start;
+        let start = self.buffer[..cursor].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.buffer[cursor..]
+            .find('\n')
+            .map_or(self.buffer.len(), |i| cursor + i);
+        let offset = self.buffer[..start].chars().count();
+        let found = rule_matches(&rules, &self.buffer[start..end], offset);
+        self.tag_matches(found);
+    }
+
+    /// Panel to edit, add and run the auto-tagging rules
+    pub fn rules_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.rules_open;
+        let mut apply = false;
+        let tags: Vec<String> = self.tags.keys().cloned().collect();
+        egui::Window::new("Auto-tagging rules")
+            .open(&mut open)
+            .default_width(460.0)
+            .show(ctx, |ui| {
+                let before = (
+                    self.settings.rules.clone(),
+                    self.settings.rules_while_typing,
+                );
+                let mut remove = None;
+                egui::Grid::new("rules")
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (i, rule) in self.settings.rules.iter_mut().enumerate() {
+                            ui.checkbox(&mut rule.enabled, "").on_hover_text("Enabled");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut rule.pattern)
+                                    .hint_text(if rule.regex { "^TODO" } else { "keyword" })
+                                    .desired_width(140.0),
+                            );
+                            ui.checkbox(&mut rule.regex, "Regex");
+                            ui.checkbox(&mut rule.whole_line, "Line")
+                                .on_hover_text("Tag the whole line, not just the match");
+                            egui::ComboBox::from_id_salt(("rule_tag", i))
+                                .selected_text(if rule.tag.is_empty() {
+                                    "Tag"
+                                } else {
+                                    &rule.tag
+                                })
+                                .show_ui(ui, |ui| {
+                                    for tag in &tags {
+                                        ui.selectable_value(&mut rule.tag, tag.clone(), tag);
+                                    }
+                                });
+                            if ui
+                                .small_button(TRASH)
+                                .on_hover_text("Delete rule")
+                                .clicked()
+                            {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
+                            if let Err(e) = rule.matcher() {
+                                ui.label("");
+                                ui.label(
+                                    RichText::new(e.to_string())
+                                        .small()
+                                        .color(ui.visuals().error_fg_color),
+                                );
+                                ui.end_row();
+                            }
+                        }
+                    });
+                if let Some(i) = remove {
+                    self.settings.rules.remove(i);
+                }
+                if ui.button(format!("{PLUS} Add rule")).clicked() {
+                    self.settings.rules.push(TagRule::default());
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.settings.rules_while_typing, "Apply while typing")
+                        .on_hover_text("Check the line being edited after every change");
+                    if ui.button("Apply rules").clicked() {
+                        apply = true;
+                    }
+                });
+                if (&self.settings.rules, self.settings.rules_while_typing) != (&before.0, before.1)
+                {
+                    let _ = self.save_to_disk();
+                }
+            });
+        if apply {
+            let added = self.apply_rules();
+            self.toasts
+                .info(format!("Rules tagged {added} new range(s)"));
+        }
+        self.rules_open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, regex: bool, whole_line: bool) -> (String, Matcher, bool) {
+        let rule = TagRule {
+            pattern: pattern.to_string(),
+            regex,
+            whole_line,
+            tag: "t".to_string(),
+            ..Default::default()
+        };
+        (
+            "t".to_string(),
+            rule.matcher().unwrap().unwrap(),
+            whole_line,
+        )
+    }
+
+    fn ranges(found: Vec<(String, Range<usize>)>) -> Vec<Range<usize>> {
+        found.into_iter().map(|(_, range)| range).collect()
+    }
+
+    #[test]
+    fn keywords_tag_the_trimmed_line_ignoring_case() {
+        let text = "milk\n  Buy EGGS today  \nbread";
+        let found = rule_matches(&[rule("eggs", false, true)], text, 10);
+        assert_eq!(found, [("t".to_string(), 17..31)]);
+    }
+
+    #[test]
+    fn matches_alone_are_counted_in_chars() {
+        let text = "äöü TODO\nnone";
+        let found = rule_matches(&[rule("todo", false, false)], text, 0);
+        assert_eq!(found, [("t".to_string(), 4..8)]);
+    }
+
+    #[test]
+    fn regexes_match_per_line() {
+        let text = "call 555-1234\nno number\n555-9999";
+        let found = rule_matches(&[rule(r"\d{3}-\d{4}", true, false)], text, 0);
+        assert_eq!(ranges(found), [5..13, 24..32]);
+    }
+
+    #[test]
+    fn disabled_and_broken_rules_do_not_match() {
+        let disabled = TagRule {
+            enabled: false,
+            pattern: "x".to_string(),
+            tag: "t".to_string(),
+            ..Default::default()
+        };
+        assert!(disabled.matcher().unwrap().is_none());
+        let broken = TagRule {
+            pattern: "(".to_string(),
+            regex: true,
+            tag: "t".to_string(),
+            ..Default::default()
+        };
+        assert!(broken.matcher().is_err());
+    }
+}
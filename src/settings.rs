@@ -0,0 +1,525 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::lines::LineTagStyle;
+use crate::tools::Palette;
+use crate::{OverlapStyle, TagNamePolicy, Taskmonger};
+
+const DEFAULT_SAVE_PATH: &str = "taskmonger_state.json";
+
+/// Remembers a custom state file location. It can't live in the state file itself.
+const LOCATION_FILE: &str = "taskmonger_location.txt";
+
+static SAVE_PATH: LazyLock<Mutex<PathBuf>> = LazyLock::new(|| {
+    let custom = fs::read_to_string(LOCATION_FILE).unwrap_or_default();
+    let custom = custom.trim();
+    Mutex::new(PathBuf::from(if custom.is_empty() {
+        DEFAULT_SAVE_PATH
+    } else {
+        custom
+    }))
+});
+
+/// Font the editor is set in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EditorFont {
+    #[default]
+    Monospace,
+    Proportional,
+}
+
+impl EditorFont {
+    pub const ALL: [EditorFont; 2] = [EditorFont::Monospace, EditorFont::Proportional];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditorFont::Monospace => "IBM Plex Mono",
+            EditorFont::Proportional => "IBM Plex Sans",
+        }
+    }
+
+    pub fn font_id(&self, size: f32) -> egui::FontId {
+        match self {
+            EditorFont::Monospace => egui::FontId::monospace(size),
+            EditorFont::Proportional => egui::FontId::proportional(size),
+        }
+    }
+
+    /// The font for text of bold tags
+    pub fn bold_font_id(&self, size: f32) -> egui::FontId {
+        let family = match self {
+            EditorFont::Monospace => BOLD_MONO,
+            EditorFont::Proportional => BOLD_SANS,
+        };
+        egui::FontId::new(size, egui::FontFamily::Name(family.into()))
+    }
+}
+
+/// Font families for bold text
+pub const BOLD_SANS: &str = "DejaVuSansBold";
+pub const BOLD_MONO: &str = "DejaVuSansMonoBold";
+
+pub fn default_font_size() -> f32 {
+    14.0
+}
+
+const FONT_SIZES: std::ops::RangeInclusive<f32> = 8.0..=48.0;
+
+pub fn default_ui_scale() -> f32 {
+    1.0
+}
+
+const UI_SCALES: std::ops::RangeInclusive<f32> = 0.75..=2.0;
+
+impl Taskmonger {
+    pub fn save_path() -> PathBuf {
+        SAVE_PATH
+            .lock()
+            .map(|path| path.clone())
+            .unwrap_or_else(|_| DEFAULT_SAVE_PATH.into())
+    }
+
+    /// Writes the state to a new location and uses it from now on.
+    /// The old file is left alone.
+    fn move_save_path(&mut self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+        let old = Self::save_path();
+        if let Ok(mut current) = SAVE_PATH.lock() {
+            *current = path.clone();
+        }
+        let result = self.write_state().and_then(|_| {
+            if path == Path::new(DEFAULT_SAVE_PATH) {
+                if PathBuf::from(LOCATION_FILE).exists() {
+                    fs::remove_file(LOCATION_FILE)?;
+                }
+            } else {
+                fs::write(LOCATION_FILE, path.to_string_lossy().as_bytes())?;
+            }
+            Ok(())
+        });
+        match &result {
+            Ok(()) => {
+                info!("Moved state from {} to {}", old.display(), path.display());
+                if let Err(e) = self.move_sync_state(&old) {
+                    warn!("Could not move the sync state: {e}");
+                }
+            }
+            Err(_) => {
+                if let Ok(mut current) = SAVE_PATH.lock() {
+                    *current = old;
+                }
+            }
+        }
+        result
+    }
+
+    /// Commands never run in safe mode or with the sandbox setting
+    pub fn commands_disabled(&self) -> bool {
+        self.safe_mode || self.settings.commands_disabled
+    }
+
+    /// Zooms the editor text with Ctrl+= / Ctrl+- / Ctrl+0 and Ctrl+scroll
+    pub fn handle_zoom(&mut self, ctx: &egui::Context) {
+        let modifiers = egui::Modifiers::COMMAND;
+        let (zoom_in, zoom_out, reset, factor) = ctx.input_mut(|i| {
+            (
+                i.consume_key(modifiers, egui::Key::Equals)
+                    || i.consume_key(modifiers, egui::Key::Plus),
+                i.consume_key(modifiers, egui::Key::Minus),
+                i.consume_key(modifiers, egui::Key::Num0),
+                i.zoom_delta(),
+            )
+        });
+        let before = self.settings.font_size;
+        let size = &mut self.settings.font_size;
+        if zoom_in {
+            *size += 1.0;
+        }
+        if zoom_out {
+            *size -= 1.0;
+        }
+        if reset {
+            *size = default_font_size();
+        }
+        *size = (*size * factor).clamp(*FONT_SIZES.start(), *FONT_SIZES.end());
+        if *size != before {
+            self.autosave();
+        }
+    }
+
+    /// Scales the whole UI with Ctrl+Alt+= / Ctrl+Alt+- / Ctrl+Alt+0, on top of the system DPI.
+    /// Runs before the editor zoom, which would take the keys otherwise.
+    pub fn handle_ui_scale(&mut self, ctx: &egui::Context) {
+        let modifiers = egui::Modifiers::COMMAND | egui::Modifiers::ALT;
+        let (bigger, smaller, reset) = ctx.input_mut(|i| {
+            (
+                i.consume_key(modifiers, egui::Key::Equals)
+                    || i.consume_key(modifiers, egui::Key::Plus),
+                i.consume_key(modifiers, egui::Key::Minus),
+                i.consume_key(modifiers, egui::Key::Num0),
+            )
+        });
+        let before = self.settings.ui_scale;
+        let scale = &mut self.settings.ui_scale;
+        if bigger {
+            *scale += 0.1;
+        }
+        if smaller {
+            *scale -= 0.1;
+        }
+        if reset {
+            *scale = default_ui_scale();
+        }
+        // Steps of 5% keep repeated changes from drifting
+        *scale = ((*scale * 20.0).round() / 20.0).clamp(*UI_SCALES.start(), *UI_SCALES.end());
+        if *scale != before {
+            let _ = self.save_to_disk();
+        }
+
+        let scale = if self.safe_mode {
+            default_ui_scale()
+        } else {
+            self.settings.ui_scale
+        };
+        if ctx.zoom_factor() != scale {
+            ctx.set_zoom_factor(scale);
+        }
+    }
+
+    /// Saves text edits right away, or once the autosave interval has passed
+    pub fn autosave(&mut self) {
+        if self.settings.autosave_secs == 0 {
+            let _ = self.save_to_disk();
+        } else if self.unsaved_since.get().is_none() {
+            self.unsaved_since.set(Some(Instant::now()));
+        }
+    }
+
+    /// Writes pending edits when the interval is up or the window closes
+    pub fn flush_autosave(&mut self, ctx: &egui::Context) {
+        let Some(since) = self.unsaved_since.get() else {
+            return;
+        };
+        let interval = Duration::from_secs(self.settings.autosave_secs as u64);
+        if since.elapsed() >= interval || ctx.input(|i| i.viewport().close_requested()) {
+            let _ = self.save_to_disk();
+        } else {
+            ctx.request_repaint_after(interval - since.elapsed());
+        }
+    }
+
+    pub fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.settings_open;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .default_width(360.0)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let before = self.settings.clone();
+                let settings = &mut self.settings;
+
+                ui.label(RichText::new("Appearance").strong());
+                egui::Grid::new("settings_appearance")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Theme");
+                        self.themes
+                            .ui(ui, &mut settings.theme, &mut settings.dark_mode);
+                        ui.end_row();
+
+                        ui.label("Editor font");
+                        egui::ComboBox::from_id_salt("editor_font")
+                            .selected_text(settings.editor_font.name())
+                            .show_ui(ui, |ui| {
+                                for font in EditorFont::ALL {
+                                    ui.selectable_value(
+                                        &mut settings.editor_font,
+                                        font,
+                                        font.name(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Font size");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.font_size)
+                                .range(FONT_SIZES)
+                                .speed(0.5),
+                        );
+                        ui.end_row();
+
+                        ui.label("UI scale");
+                        ui.add(
+                            egui::Slider::new(&mut settings.ui_scale, UI_SCALES)
+                                .step_by(0.05)
+                                .custom_formatter(|n, _| format!("{:.0}%", n * 100.0))
+                                .custom_parser(|s| {
+                                    s.trim_end_matches('%')
+                                        .trim()
+                                        .parse::<f64>()
+                                        .ok()
+                                        .map(|n| n / 100.0)
+                                }),
+                        )
+                        .on_hover_text("Ctrl+Alt+= / Ctrl+Alt+- / Ctrl+Alt+0");
+                        ui.end_row();
+
+                        ui.label("Tag palette");
+                        // The colorblind-safe mode brings its own palette
+                        ui.add_enabled_ui(!settings.colorblind, |ui| {
+                            egui::ComboBox::from_id_salt("settings_palette")
+                                .selected_text(settings.palette.name())
+                                .show_ui(ui, |ui| {
+                                    for palette in Palette::ALL {
+                                        ui.selectable_value(
+                                            &mut settings.palette,
+                                            palette,
+                                            palette.name(),
+                                        );
+                                    }
+                                });
+                        });
+                        ui.end_row();
+
+                        ui.label("Overlapping tags");
+                        ui.horizontal(|ui| {
+                            let style = &mut settings.overlap_style;
+                            ui.radio_value(style, OverlapStyle::Stripes, "Stripes");
+                            ui.radio_value(style, OverlapStyle::Mix, "Mix colors");
+                        });
+                        ui.end_row();
+
+                        ui.label("Whole-line tags");
+                        ui.horizontal(|ui| {
+                            let style = &mut settings.line_tag_style;
+                            ui.radio_value(style, LineTagStyle::Stripe, "Stripe");
+                            ui.radio_value(style, LineTagStyle::Bar, "Bar");
+                            ui.radio_value(style, LineTagStyle::Text, "Like other tags");
+                        });
+                        ui.end_row();
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Tag colors from theme");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut settings.base16_theme)
+                            .hint_text("base16 or terminal theme file"),
+                    )
+                    .on_hover_text(
+                        "Tags take their colors from this file and follow it when it changes",
+                    );
+                });
+                if let (false, Some(error)) = (
+                    settings.base16_theme.trim().is_empty(),
+                    &self.theme_watch.error,
+                ) {
+                    ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Distraction-free column");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.focus_width)
+                            .range(300.0..=2000.0)
+                            .speed(10.0)
+                            .suffix(" px"),
+                    )
+                    .on_hover_text("F11 hides the panels and centers the text in this width");
+                });
+                ui.checkbox(&mut settings.typewriter_scrolling, "Typewriter scrolling")
+                    .on_hover_text("Keep the cursor line centered in the distraction-free mode");
+                ui.checkbox(&mut settings.show_line_numbers, "Line numbers");
+                ui.checkbox(&mut settings.mark_as_background, "Mark tags as background")
+                    .on_hover_text("Color the background of tagged text instead of the text");
+                ui.checkbox(&mut settings.colorblind, "Colorblind-safe mode")
+                    .on_hover_text(
+                        "Recolor the tags from the colorblind-safe palette and give every tag \
+                         a shade, underline or italics on top of its color",
+                    );
+
+                ui.separator();
+                ui.label(RichText::new("Words").strong());
+                settings.words.ui(ui);
+
+                ui.separator();
+                ui.label(RichText::new("Saving").strong());
+                ui.horizontal(|ui| {
+                    ui.label("Autosave");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.autosave_secs)
+                            .range(0..=3600)
+                            .custom_formatter(|n, _| {
+                                if n == 0.0 {
+                                    "on every change".to_string()
+                                } else {
+                                    format!("every {n} s")
+                                }
+                            }),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pause timers after");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.idle_minutes)
+                            .range(0..=240)
+                            .custom_formatter(|n, _| {
+                                if n == 0.0 {
+                                    "never".to_string()
+                                } else {
+                                    format!("{n} min")
+                                }
+                            }),
+                    )
+                    .on_hover_text(
+                        "Without input to this window. Background checks slow down too.",
+                    );
+                });
+                #[cfg(feature = "tray")]
+                ui.checkbox(&mut settings.minimize_to_tray, "Close to the tray")
+                    .on_hover_text("Closing the window hides it, quit from the tray menu");
+                ui.checkbox(&mut settings.git_history, "Keep a history in git")
+                    .on_hover_text(
+                        "Commits the buffer and state to a git repository next to the state file \
+                         half a minute after saving and on close, to look back at and restore \
+                         from the history window. Saves within five minutes go into one commit.",
+                    );
+                let id = egui::Id::new("save_path_edit");
+                let mut path = ui
+                    .data(|d| d.get_temp::<String>(id))
+                    .unwrap_or_else(|| Self::save_path().display().to_string());
+                let mut move_to = None;
+                ui.horizontal(|ui| {
+                    ui.label("State file");
+                    ui.text_edit_singleline(&mut path);
+                    let changed = Path::new(&path) != Self::save_path();
+                    if ui
+                        .add_enabled(changed && !path.is_empty(), egui::Button::new("Move"))
+                        .clicked()
+                    {
+                        move_to = Some(PathBuf::from(&path));
+                    }
+                    if ui
+                        .add_enabled(
+                            Self::save_path() != Path::new(DEFAULT_SAVE_PATH),
+                            egui::Button::new("Reset"),
+                        )
+                        .clicked()
+                    {
+                        move_to = Some(PathBuf::from(DEFAULT_SAVE_PATH));
+                    }
+                });
+                ui.data_mut(|d| d.insert_temp(id, path));
+
+                ui.separator();
+                ui.label(RichText::new("Markdown view").strong());
+                ui.checkbox(
+                    &mut settings.markdown_full_buffer,
+                    "Render the whole buffer",
+                );
+                ui.checkbox(
+                    &mut settings.copy_with_tag_header,
+                    "Include tag name when copying",
+                );
+                ui.collapsing("Style", |ui| {
+                    settings.markdown_style.ui(ui);
+                });
+
+                ui.separator();
+                ui.label(RichText::new("Advanced").strong());
+                ui.checkbox(&mut settings.commands_disabled, "Disable running commands")
+                    .on_hover_text("Sandbox: commands attached to ranges never run");
+                egui::Grid::new("settings_advanced")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Large paste threshold");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.large_paste_kb)
+                                .range(1..=100_000)
+                                .suffix(" KB"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Markdown cache limit");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.markdown_cache_limit)
+                                .range(1..=10_000),
+                        );
+                        ui.end_row();
+
+                        ui.label("Snapshots in memory");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.snapshot_cache_limit)
+                                .range(1..=1000),
+                        )
+                        .on_hover_text("History revisions kept loaded for the history window");
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.label(RichText::new("Tag names").strong());
+                self.tag_names_ui(ui);
+
+                ui.separator();
+                ui.label(RichText::new("Sync").strong());
+                self.sync_ui(ui);
+                self.taskwarrior_ui(ui);
+
+                ui.separator();
+                ui.label(RichText::new("Profiles").strong());
+                self.profiles_ui(ui);
+
+                if self.settings.colorblind && !before.colorblind {
+                    self.recolor_tags();
+                } else if self.settings != before {
+                    let _ = self.save_to_disk();
+                }
+                if let Some(path) = move_to {
+                    match self.move_save_path(path) {
+                        Ok(()) => {
+                            ui.data_mut(|d| d.remove::<String>(id));
+                            self.toasts
+                                .success(format!("Saving to {}", Self::save_path().display()));
+                        }
+                        Err(e) => self.toasts.error(format!("Could not move the state: {e}")),
+                    }
+                }
+            });
+        self.settings_open = open;
+    }
+
+    /// The tag name policy, and merging the tags that match under it
+    fn tag_names_ui(&mut self, ui: &mut egui::Ui) {
+        let mut ignore_case = self.settings.tag_name_policy == TagNamePolicy::IgnoreCase;
+        ui.checkbox(&mut ignore_case, "Ignore case")
+            .on_hover_text("Adding or importing Work reuses an existing work tag");
+        self.settings.tag_name_policy = if ignore_case {
+            TagNamePolicy::IgnoreCase
+        } else {
+            TagNamePolicy::CaseSensitive
+        };
+        let duplicates = self.duplicate_tags();
+        let button = ui
+            .add_enabled(
+                !duplicates.is_empty(),
+                egui::Button::new(format!("Merge duplicate tags ({})", duplicates.len())),
+            )
+            .on_disabled_hover_text("No tags match each other");
+        let clicked = button
+            .on_hover_ui(|ui| {
+                for (name, first) in &duplicates {
+                    ui.label(format!("{name} into {first}"));
+                }
+            })
+            .clicked();
+        if clicked {
+            let merged = self.merge_duplicate_tags();
+            self.toasts
+                .success(format!("Merged {merged} duplicate tag(s)"));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use eframe::egui;
+use egui_phosphor::regular::{SQUARE_SPLIT_HORIZONTAL, SQUARE_SPLIT_VERTICAL};
+use serde::{Deserialize, Serialize};
+
+/// Where the second view of the buffer goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitView {
+    SideBySide,
+    Stacked,
+}
+
+impl SplitView {
+    /// Name its panel size is saved under
+    pub fn key(self) -> &'static str {
+        match self {
+            SplitView::SideBySide => "split_view",
+            SplitView::Stacked => "split_view_stacked",
+        }
+    }
+
+    /// Menu to split the editor or close the split. Returns true if it changed.
+    pub fn menu(ui: &mut egui::Ui, split: &mut Option<SplitView>) -> bool {
+        let before = *split;
+        let icon = match split {
+            Some(SplitView::Stacked) => SQUARE_SPLIT_VERTICAL,
+            _ => SQUARE_SPLIT_HORIZONTAL,
+        };
+        ui.menu_button(icon, |ui| {
+            ui.radio_value(split, None, "Single view");
+            ui.radio_value(split, Some(SplitView::SideBySide), "Side by side");
+            ui.radio_value(split, Some(SplitView::Stacked), "Top and bottom");
+        })
+        .response
+        .on_hover_text("Split the editor");
+        *split != before
+    }
+
+    /// Second, independently scrolled view of `buffer`, with the editor's highlighting.
+    /// It is for reference, edits happen in the main view. `size` is the panel's saved size,
+    /// half of the space by default.
+    pub fn ui(
+        self,
+        ui: &mut egui::Ui,
+        buffer: &str,
+        size: Option<f32>,
+        layouter: &mut dyn FnMut(&egui::Ui, &dyn egui::TextBuffer, f32) -> Arc<egui::Galley>,
+    ) {
+        let contents = |ui: &mut egui::Ui| {
+            egui::ScrollArea::vertical()
+                .id_salt("split_view_scroll")
+                .show(ui, |ui| {
+                    let mut text = buffer;
+                    egui::TextEdit::multiline(&mut text)
+                        .id_salt("split_view_text")
+                        .desired_width(f32::INFINITY)
+                        .frame(false)
+                        .font(egui::TextStyle::Monospace)
+                        .layouter(layouter)
+                        .show(ui);
+                });
+        };
+        match self {
+            SplitView::SideBySide => {
+                let width = size.unwrap_or(ui.available_width() / 2.0);
+                egui::SidePanel::right("split_view")
+                    .resizable(true)
+                    .default_width(width)
+                    .show_inside(ui, contents);
+            }
+            SplitView::Stacked => {
+                let height = size.unwrap_or(ui.available_height() / 2.0);
+                egui::TopBottomPanel::bottom("split_view")
+                    .resizable(true)
+                    .default_height(height)
+                    .show_inside(ui, contents);
+            }
+        }
+    }
+}
@@ -0,0 +1,1519 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use eframe::egui;
+use egui::RichText;
+use egui_phosphor::regular::{ARROW_RIGHT, CLOUD_SLASH, COPY, LOCK, X};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::collisions::TagCollision;
+use crate::encryption::{is_sealed, is_sealed_file, SyncKey};
+use crate::tools::char_to_byte_range;
+use crate::{tag_group, Tag, TaggedRange, Taskmonger};
+
+/// This device's side of the sync, kept next to the state file and never synced itself
+const SYNC_FILE: &str = "taskmonger_sync.json";
+
+/// The state the first device brought into the sync folder, everyone's operations apply on it
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// End of the name of every device's operation log in the sync folder
+const LOG_SUFFIX: &str = ".ops.json";
+
+const SYNC_POLL: Duration = Duration::from_secs(60);
+
+/// Size of the table for diffing lines, past it the changed lines go as one block
+const DIFF_LIMIT: usize = 4_000_000;
+
+/// Next to the state file, it goes wherever the state is moved
+fn sync_path() -> PathBuf {
+    Taskmonger::save_path().with_file_name(SYNC_FILE)
+}
+
+/// Contents of the sync folder's file `name`, decrypted with `key` if it is encrypted
+fn open_payload(name: &str, data: Vec<u8>, key: Option<&SyncKey>) -> Result<String, String> {
+    let plain = match (is_sealed(&data), key) {
+        (true, Some(key)) => key.open(name, &data)?,
+        (true, None) => {
+            return Err(
+                "the sync folder is encrypted, enter its recovery phrase in the settings".into(),
+            )
+        }
+        (false, key) => {
+            if key.is_some() {
+                warn!("{name} in the sync folder is not encrypted");
+            }
+            data
+        }
+    };
+    String::from_utf8(plain).map_err(|e| format!("{name}: {e}"))
+}
+
+/// Writes a file of the sync folder, encrypted if there is a key. It is written aside
+/// first, so other devices never read half of it.
+fn write_payload(
+    folder: &Path,
+    name: &str,
+    contents: &str,
+    key: Option<&SyncKey>,
+) -> std::io::Result<()> {
+    let data = match key {
+        Some(key) => key.seal(name, contents.as_bytes()),
+        None => contents.as_bytes().to_vec(),
+    };
+    let path = folder.join(name);
+    let partial = path.with_extension("partial");
+    fs::write(&partial, data)?;
+    fs::rename(&partial, &path)
+}
+
+/// Where the devices meet
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct SyncSettings {
+    /// Folder shared between the devices, by a file sync tool or a network mount.
+    /// Empty turns syncing off.
+    pub folder: String,
+    /// How the other devices list this one
+    pub device_name: String,
+    /// Tags, or tag groups, whose ranges and the lines they are on stay on this device
+    pub local_tags: Vec<String>,
+}
+
+impl SyncSettings {
+    pub fn set_local(&mut self, tag: &str, local: bool) {
+        self.local_tags.retain(|name| name != tag);
+        if local {
+            self.local_tags.push(tag.to_string());
+        }
+    }
+
+    /// Whether a tag is kept out of the sync, by its name or its group's
+    pub fn is_local(&self, tag: &str) -> bool {
+        self.local_tags
+            .iter()
+            .any(|local| local == tag || tag_group(tag) == Some(local.as_str()))
+    }
+}
+
+/// A change to the buffer, a tag or a range
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Change {
+    /// The lines from `at` that read `old` became `new`. `before` is the line above them, to
+    /// find the spot again after other devices moved the lines.
+    Lines {
+        at: usize,
+        before: Option<String>,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    /// A tag was defined or changed, or removed with `None`
+    Tag { name: String, tag: Option<Tag> },
+    /// A range was added or changed, or removed with `None`. `text` is what it covered, to
+    /// place it where the lines differ.
+    Range {
+        tag_name: String,
+        created: NaiveDateTime,
+        range: Option<Box<TaggedRange>>,
+        text: String,
+    },
+}
+
+impl Change {
+    /// What the change is to. The newer change to a tag or range wins, lines merge instead.
+    fn subject(&self) -> Option<String> {
+        match self {
+            Change::Lines { .. } => None,
+            Change::Tag { name, .. } => Some(format!("tag:{name}")),
+            Change::Range {
+                tag_name, created, ..
+            } => Some(format!("range:{tag_name}@{created}")),
+        }
+    }
+
+    fn tag_name(&self) -> Option<&str> {
+        match self {
+            Change::Lines { .. } => None,
+            Change::Tag { name, .. } => Some(name),
+            Change::Range { tag_name, .. } => Some(tag_name),
+        }
+    }
+}
+
+/// A change made on one device
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Op {
+    device: String,
+    /// Counts the device's operations from 1
+    seq: u64,
+    /// Lamport clock, above that of every operation the device had seen when making this one
+    clock: u64,
+    time: NaiveDateTime,
+    #[serde(flatten)]
+    change: Change,
+}
+
+impl Op {
+    /// The order all devices apply operations in
+    fn order(&self) -> (u64, &str) {
+        (self.clock, &self.device)
+    }
+}
+
+/// What to do with a conflict in the sync window
+enum Resolution {
+    GoTo,
+    KeepBoth,
+    KeepMine,
+}
+
+/// A device's file in the sync folder
+#[derive(Serialize, Deserialize)]
+struct DeviceLog {
+    device: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    updated: Option<NaiveDateTime>,
+    #[serde(default)]
+    ops: Vec<Op>,
+}
+
+/// The synced part of the state
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct Snapshot {
+    buffer: String,
+    tags: IndexMap<String, Tag>,
+    tagged_ranges: Vec<TaggedRange>,
+}
+
+/// Lines another device changed that were changed here as well. Both versions are kept.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Conflict {
+    device: String,
+    time: NaiveDateTime,
+    /// Where the other device's lines were put, from 0
+    line: usize,
+    /// The lines the other device replaced
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Another device as last seen in the sync folder
+#[derive(Serialize, Deserialize, Clone)]
+struct DeviceInfo {
+    device: String,
+    name: String,
+    /// When it last wrote its log
+    updated: Option<NaiveDateTime>,
+    ops: usize,
+}
+
+impl DeviceInfo {
+    fn label(&self) -> &str {
+        if self.name.is_empty() {
+            &self.device
+        } else {
+            &self.name
+        }
+    }
+}
+
+/// This device's operations and what it has seen of the others
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SyncState {
+    /// Random id of this device
+    device: String,
+    /// Encrypts everything this device puts into the sync folder
+    key: Option<SyncKey>,
+    /// The sync folder this state belongs to
+    folder: String,
+    clock: u64,
+    /// All operations of this device, in order
+    ops: Vec<Op>,
+    /// Last operation applied of every other device
+    applied: HashMap<String, u64>,
+    /// Clock and device of the last change to every tag and range
+    versions: HashMap<String, (u64, String)>,
+    /// What the recorded operations lead to, `None` before the first sync
+    base: Option<Snapshot>,
+    last_sync: Option<NaiveDateTime>,
+    conflicts: Vec<Conflict>,
+    /// Operations of this device already in its log in the folder
+    pushed: usize,
+    /// The other devices found on the last sync
+    devices: Vec<DeviceInfo>,
+}
+
+impl SyncState {
+    fn load() -> Self {
+        let mut state: SyncState = match fs::read_to_string(sync_path()) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!(
+                    "Could not read {}, starting over: {e}",
+                    sync_path().display()
+                );
+                Default::default()
+            }),
+            Err(_) => Default::default(),
+        };
+        if state.device.is_empty() {
+            state.device = format!("{:016x}", rand::random::<u64>());
+        }
+        state
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        fs::write(sync_path(), serde_json::to_string(self)?)
+    }
+
+    /// Adds a change made on this device
+    fn push(&mut self, change: Change) {
+        self.clock += 1;
+        if let Some(subject) = change.subject() {
+            self.versions
+                .insert(subject, (self.clock, self.device.clone()));
+        }
+        self.ops.push(Op {
+            device: self.device.clone(),
+            seq: self.ops.len() as u64 + 1,
+            clock: self.clock,
+            time: chrono::Local::now().naive_local(),
+            change,
+        });
+    }
+
+    /// Records the changes from the base to `now`
+    fn record(&mut self, now: Snapshot) -> bool {
+        let Some(base) = &self.base else {
+            return false;
+        };
+        let changes = changes(base, &now);
+        if changes.is_empty() {
+            return false;
+        }
+        for change in changes {
+            self.push(change);
+        }
+        self.base = Some(now);
+        true
+    }
+
+    /// Whether `op` is newer than the last change to the same tag or range
+    fn wins(&self, op: &Op) -> bool {
+        let Some(subject) = op.change.subject() else {
+            return true;
+        };
+        self.versions
+            .get(&subject)
+            .is_none_or(|(clock, device)| op.order() > (*clock, device.as_str()))
+    }
+}
+
+/// Text of `range`, empty if it is outside the buffer of `len` chars
+fn text_of(buffer: &str, len: usize, range: &Range<usize>) -> String {
+    if range.start < range.end && range.end <= len {
+        buffer[char_to_byte_range(buffer, range)].to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// A range as compared between snapshots, without what edits around it change
+fn comparable(tr: &TaggedRange) -> TaggedRange {
+    let mut tr = tr.clone();
+    tr.range = 0..0;
+    tr.modified = tr.created;
+    tr
+}
+
+/// The blocks of lines that differ between `old` and `new`, top to bottom, as the lines
+/// of `old` and the lines of `new` that replace them
+pub fn line_hunks(old: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(old.len().min(new.len()) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if a.is_empty() && b.is_empty() {
+        return vec![];
+    }
+    if (a.len() + 1) * (b.len() + 1) > DIFF_LIMIT {
+        return vec![(prefix..prefix + a.len(), prefix..prefix + b.len())];
+    }
+    // Longest common subsequence of the lines after each position
+    let width = b.len() + 1;
+    let mut common = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i * width + j] = if a[i] == b[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+    let mut hunks = vec![];
+    let mut hunk: Option<(Range<usize>, Range<usize>)> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            hunks.extend(hunk.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (removed, added) = hunk.get_or_insert((prefix + i..prefix + i, prefix + j..prefix + j));
+        if j == b.len() || (i < a.len() && common[(i + 1) * width + j] >= common[i * width + j + 1])
+        {
+            i += 1;
+            removed.end += 1;
+        } else {
+            j += 1;
+            added.end += 1;
+        }
+    }
+    hunks.extend(hunk);
+    hunks
+}
+
+/// The changes from `base` to `now`, lines first so ranges find their text
+fn changes(base: &Snapshot, now: &Snapshot) -> Vec<Change> {
+    let mut changes = vec![];
+    if base.buffer != now.buffer {
+        let old: Vec<&str> = base.buffer.split('\n').collect();
+        let new: Vec<&str> = now.buffer.split('\n').collect();
+        let owned = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect();
+        // Bottom up, so the lines above each block are still the old ones
+        for (removed, added) in line_hunks(&old, &new).into_iter().rev() {
+            changes.push(Change::Lines {
+                at: removed.start,
+                before: removed.start.checked_sub(1).map(|i| old[i].to_string()),
+                old: owned(&old[removed]),
+                new: owned(&new[added]),
+            });
+        }
+    }
+
+    for (name, tag) in &now.tags {
+        if base.tags.get(name) != Some(tag) {
+            changes.push(Change::Tag {
+                name: name.clone(),
+                tag: Some(tag.clone()),
+            });
+        }
+    }
+    for name in base.tags.keys() {
+        if !now.tags.contains_key(name) {
+            changes.push(Change::Tag {
+                name: name.clone(),
+                tag: None,
+            });
+        }
+    }
+
+    let base_len = base.buffer.chars().count();
+    let now_len = now.buffer.chars().count();
+    let before: HashMap<_, _> = base
+        .tagged_ranges
+        .iter()
+        .map(|tr| {
+            let text = text_of(&base.buffer, base_len, &tr.range);
+            (tr.key(), (comparable(tr), text))
+        })
+        .collect();
+    let mut keys = HashSet::new();
+    for tr in &now.tagged_ranges {
+        keys.insert(tr.key());
+        let text = text_of(&now.buffer, now_len, &tr.range);
+        if before.get(&tr.key()) != Some(&(comparable(tr), text.clone())) {
+            changes.push(Change::Range {
+                tag_name: tr.tag_name.clone(),
+                created: tr.created,
+                range: Some(Box::new(tr.clone())),
+                text,
+            });
+        }
+    }
+    for (tag_name, created) in before.into_keys() {
+        if !keys.contains(&(tag_name.clone(), created)) {
+            changes.push(Change::Range {
+                tag_name,
+                created,
+                range: None,
+                text: String::new(),
+            });
+        }
+    }
+    changes
+}
+
+/// Line where `old` still is, below `before`, closest to `at`. Without the line above, any
+/// place `old` is found at will do.
+fn find_lines(lines: &[&str], at: usize, before: Option<&str>, old: &[String]) -> Option<usize> {
+    let fits = |p: usize, anchored: bool| {
+        p + old.len() <= lines.len()
+            && lines[p..p + old.len()].iter().zip(old).all(|(a, b)| a == b)
+            && (!anchored
+                || match before {
+                    None => p == 0,
+                    Some(before) => p > 0 && lines[p - 1] == before,
+                })
+    };
+    let closest = |anchored: bool| {
+        (0..=lines.len())
+            .filter(|&p| fits(p, anchored))
+            .min_by_key(|&p| p.abs_diff(at))
+    };
+    closest(true).or_else(|| if old.is_empty() { None } else { closest(false) })
+}
+
+/// The char range where `text` is found closest to `near`
+pub fn find_text(buffer: &str, text: &str, near: usize) -> Option<Range<usize>> {
+    if text.is_empty() {
+        return None;
+    }
+    let len = text.chars().count();
+    let mut chars = 0;
+    let mut last = 0;
+    buffer
+        .match_indices(text)
+        .map(|(at, _)| {
+            chars += buffer[last..at].chars().count();
+            last = at;
+            chars..chars + len
+        })
+        .min_by_key(|range| range.start.abs_diff(near))
+}
+
+/// The one edit that turns `old` into `new`, as a char range of `old` and its replacement
+fn text_edit(old: &str, new: &str) -> Option<(Range<usize>, String)> {
+    if old == new {
+        return None;
+    }
+    let prefix = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_len = old.chars().count();
+    let new_len = new.chars().count();
+    let suffix = old
+        .chars()
+        .rev()
+        .zip(new.chars().rev())
+        .take(old_len.min(new_len) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let text = new
+        .chars()
+        .skip(prefix)
+        .take(new_len - prefix - suffix)
+        .collect();
+    Some((prefix..old_len - suffix, text))
+}
+
+/// This device and the others that synced into the folder
+fn devices_ui(ui: &mut egui::Ui, sync: &SyncState, name: &str) {
+    ui.label(RichText::new("Devices").strong());
+    egui::Grid::new("sync_devices")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label(if name.is_empty() { &sync.device } else { name });
+            ui.label(RichText::new("This device").weak());
+            ui.label(format!("{} changes", sync.ops.len()));
+            ui.end_row();
+            for device in &sync.devices {
+                ui.label(device.label()).on_hover_text(&device.device);
+                let seen = match device.updated {
+                    Some(updated) => format!("Seen {}", updated.format("%b %d %H:%M")),
+                    None => "Never synced".to_string(),
+                };
+                ui.label(RichText::new(seen).weak());
+                ui.label(format!("{} changes", device.ops));
+                ui.end_row();
+            }
+        });
+    if sync.devices.is_empty() {
+        ui.label(RichText::new("No other device synced into the folder yet").weak());
+    }
+}
+
+/// The conflicts with both versions of their lines. Returns the one to resolve and how.
+fn conflicts_ui(ui: &mut egui::Ui, sync: &SyncState, locked: bool) -> Option<(usize, Resolution)> {
+    let mut resolve = None;
+    ui.label(RichText::new(format!("Conflicts ({})", sync.conflicts.len())).strong())
+        .on_hover_text("Lines edited here and on another device, both versions were kept");
+    egui::ScrollArea::vertical()
+        .max_height(300.0)
+        .show(ui, |ui| {
+            for (i, conflict) in sync.conflicts.iter().enumerate() {
+                let device = sync
+                    .devices
+                    .iter()
+                    .find(|d| d.device == conflict.device)
+                    .map_or(conflict.device.as_str(), DeviceInfo::label);
+                ui.horizontal(|ui| {
+                    ui.label(format!("Line {}", conflict.line + 1));
+                    let time = conflict.time.format("%b %d %H:%M");
+                    ui.label(RichText::new(format!("{device}, {time}")).weak());
+                });
+                let removed = ui.visuals().error_fg_color;
+                for line in &conflict.old {
+                    ui.label(
+                        RichText::new(format!("- {line}"))
+                            .monospace()
+                            .color(removed),
+                    );
+                }
+                let added = ui.visuals().warn_fg_color;
+                for line in &conflict.new {
+                    ui.label(RichText::new(format!("+ {line}")).monospace().color(added));
+                }
+                ui.horizontal(|ui| {
+                    if ui.small_button(format!("{ARROW_RIGHT} Go to")).clicked() {
+                        resolve = Some((i, Resolution::GoTo));
+                    }
+                    if ui
+                        .small_button("Keep both")
+                        .on_hover_text("Leave the text as it is")
+                        .clicked()
+                    {
+                        resolve = Some((i, Resolution::KeepBoth));
+                    }
+                    if ui
+                        .add_enabled(!locked, egui::Button::new("Keep mine").small())
+                        .on_hover_text("Take out the lines the other device added")
+                        .clicked()
+                    {
+                        resolve = Some((i, Resolution::KeepMine));
+                    }
+                });
+                ui.separator();
+            }
+        });
+    resolve
+}
+
+impl Taskmonger {
+    /// What of the state goes into the sync: all but the tags kept on this device, their
+    /// ranges and the lines those ranges are on. Other ranges on those lines stay here too.
+    fn sync_snapshot(&self) -> Snapshot {
+        let sync = &self.settings.sync;
+        let local: Vec<&Range<usize>> = self
+            .tagged_ranges
+            .iter()
+            .filter(|tr| sync.is_local(&tr.tag_name))
+            .map(|tr| &tr.range)
+            .collect();
+        let tags: IndexMap<String, Tag> = self
+            .tags
+            .iter()
+            .filter(|(name, _)| !sync.is_local(name))
+            .map(|(name, tag)| (name.clone(), tag.clone()))
+            .collect();
+        if local.is_empty() {
+            return Snapshot {
+                buffer: self.buffer.clone(),
+                tags,
+                tagged_ranges: self.tagged_ranges.clone(),
+            };
+        }
+
+        let mut buffer = String::new();
+        // Char ranges of the lines left out, with their line breaks
+        let mut removed: Vec<Range<usize>> = vec![];
+        let mut start = 0;
+        for line in self.buffer.split_inclusive('\n') {
+            let len = line.chars().count();
+            let end = start + line.trim_end_matches('\n').chars().count();
+            if local
+                .iter()
+                .any(|range| range.start < end.max(start + 1) && range.end > start)
+            {
+                removed.push(start..start + len);
+            } else {
+                buffer.push_str(line);
+            }
+            start += len;
+        }
+        let moved = |at: usize| {
+            at - removed
+                .iter()
+                .map(|gap| gap.end.min(at).saturating_sub(gap.start))
+                .sum::<usize>()
+        };
+        let tagged_ranges = self
+            .tagged_ranges
+            .iter()
+            .filter(|tr| {
+                !removed
+                    .iter()
+                    .any(|gap| tr.range.start < gap.end && tr.range.end > gap.start)
+            })
+            .map(|tr| {
+                let mut tr = tr.clone();
+                tr.range = moved(tr.range.start)..moved(tr.range.end);
+                tr
+            })
+            .collect();
+        Snapshot {
+            buffer,
+            tags,
+            tagged_ranges,
+        }
+    }
+
+    /// Applies an operation of another device. Returns the conflict if its lines were
+    /// changed here as well.
+    fn apply_op(&mut self, op: &Op) -> Option<Conflict> {
+        match &op.change {
+            Change::Lines {
+                at,
+                before,
+                old,
+                new,
+            } => {
+                let lines: Vec<&str> = self.buffer.split('\n').collect();
+                let found = find_lines(&lines, *at, before.as_deref(), old);
+                let at = found.unwrap_or((*at).min(lines.len()));
+                let replaced = if found.is_some() { old.len() } else { 0 };
+                let mut spliced = lines[..at].to_vec();
+                spliced.extend(new.iter().map(String::as_str));
+                spliced.extend(&lines[at + replaced..]);
+                if let Some(edit) = text_edit(&self.buffer, &spliced.join("\n")) {
+                    self.replace_text(vec![edit]);
+                }
+                (found.is_none() && !old.is_empty()).then(|| Conflict {
+                    device: op.device.clone(),
+                    time: op.time,
+                    line: at,
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            }
+            Change::Tag {
+                name,
+                tag: Some(tag),
+            } => {
+                self.tags.insert(name.clone(), tag.clone());
+                None
+            }
+            Change::Tag { name, tag: None } => {
+                self.tags.shift_remove(name);
+                self.tagged_ranges.retain(|tr| &tr.tag_name != name);
+                None
+            }
+            Change::Range {
+                tag_name,
+                created,
+                range,
+                text,
+            } => {
+                let key = (tag_name.clone(), *created);
+                let local = self.tagged_ranges.iter().position(|tr| tr.key() == key);
+                let Some(remote) = range else {
+                    if let Some(i) = local {
+                        self.tagged_ranges.remove(i);
+                    }
+                    return None;
+                };
+                let len = self.buffer.chars().count();
+                let unchanged = local
+                    .map(|i| &self.tagged_ranges[i].range)
+                    .filter(|range| text_of(&self.buffer, len, range) == *text)
+                    .cloned();
+                // Where the text moved to, or where it is here if it was edited on both sides
+                let place = unchanged
+                    .or_else(|| find_text(&self.buffer, text, remote.range.start))
+                    .or_else(|| local.map(|i| self.tagged_ranges[i].range.clone()));
+                let Some(place) = place else {
+                    debug!(tag = tag_name, "The text of a synced range is not here");
+                    return None;
+                };
+                let mut tr = TaggedRange::clone(remote);
+                tr.range = place;
+                match local {
+                    Some(i) => self.tagged_ranges[i] = tr,
+                    None => self.tagged_ranges.push(tr),
+                }
+                if !self.tags.contains_key(tag_name) {
+                    let color = self.next_tag_color();
+                    self.tags.insert(tag_name.clone(), Tag::new(color));
+                }
+                None
+            }
+        }
+    }
+
+    /// First sync of this device. Takes the folder's snapshot and keeps what was here below
+    /// it, or brings this device's state as the snapshot into an empty folder.
+    fn join_sync(
+        &mut self,
+        folder: &Path,
+        sync: &mut SyncState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = folder.join(SNAPSHOT_FILE);
+        if !path.exists() {
+            let snapshot = self.sync_snapshot();
+            let json = serde_json::to_string(&snapshot)?;
+            write_payload(folder, SNAPSHOT_FILE, &json, sync.key.as_ref())?;
+            info!("Started syncing in {}", folder.display());
+            sync.base = Some(snapshot);
+            return Ok(());
+        }
+        let json = open_payload(SNAPSHOT_FILE, fs::read(&path)?, sync.key.as_ref())?;
+        let snapshot: Snapshot = serde_json::from_str(&json)?;
+        // All of it, with what is kept on this device
+        let local = Snapshot {
+            buffer: self.buffer.clone(),
+            tags: self.tags.clone(),
+            tagged_ranges: self.tagged_ranges.clone(),
+        };
+        self.buffer = snapshot.buffer.clone();
+        self.tags = snapshot.tags.clone();
+        self.tagged_ranges = snapshot.tagged_ranges.clone();
+        if local.buffer != snapshot.buffer && !local.buffer.trim().is_empty() {
+            if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+                self.buffer.push('\n');
+            }
+            let offset = self.buffer.chars().count();
+            self.buffer.push_str(&local.buffer);
+            for (name, tag) in local.tags {
+                // The folder's tag is used, ours is offered in the collision dialog
+                if let Some(theirs) = self.tags.get(&name) {
+                    if theirs.color != tag.color {
+                        self.tag_collisions.push(TagCollision {
+                            name,
+                            mine: tag,
+                            theirs: theirs.clone(),
+                            source: "the sync folder".to_string(),
+                        });
+                    }
+                    continue;
+                }
+                self.tags.insert(name, tag);
+            }
+            for mut tr in local.tagged_ranges {
+                if !self
+                    .tagged_ranges
+                    .iter()
+                    .any(|other| other.key() == tr.key())
+                {
+                    tr.range = tr.range.start + offset..tr.range.end + offset;
+                    self.tagged_ranges.push(tr);
+                }
+            }
+        }
+        let len = self.buffer.chars().count();
+        self.bookmarks.retain(|bookmark| bookmark.at <= len);
+        self.folds.clear();
+        self.cursor = self.cursor.min(len);
+        self.select = Some(self.cursor..self.cursor);
+        info!("Joined the sync in {}", folder.display());
+        sync.base = Some(snapshot);
+        Ok(())
+    }
+
+    /// Records local changes, applies the new operations of the other devices and writes
+    /// this device's log. Returns the number of operations applied.
+    fn exchange(
+        &mut self,
+        folder: &Path,
+        sync: &mut SyncState,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let folder_name = folder.display().to_string();
+        if sync.folder != folder_name {
+            // A new folder starts from scratch, only the device and its key stay the same
+            *sync = SyncState {
+                device: std::mem::take(&mut sync.device),
+                key: sync.key.take(),
+                folder: folder_name,
+                ..Default::default()
+            };
+        }
+        if sync.base.is_none() {
+            self.join_sync(folder, sync)?;
+        }
+        // Edits are diffed into operations here rather than on every save
+        sync.record(self.sync_snapshot());
+        let key = sync.key.as_ref();
+        // Encryption set up after the folder was
+        let snapshot = folder.join(SNAPSHOT_FILE);
+        if let (Some(key), false) = (key, is_sealed_file(&snapshot)) {
+            let json = open_payload(SNAPSHOT_FILE, fs::read(&snapshot)?, None)?;
+            write_payload(folder, SNAPSHOT_FILE, &json, Some(key))?;
+        }
+
+        let own_log = format!("{}{LOG_SUFFIX}", sync.device);
+        let mut incoming = vec![];
+        let mut devices = vec![];
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !name.ends_with(LOG_SUFFIX) || name == own_log {
+                continue;
+            }
+            let json = open_payload(name, fs::read(&path)?, key)?;
+            let log = match serde_json::from_str::<DeviceLog>(&json) {
+                Ok(log) => log,
+                Err(e) => {
+                    warn!("Skipping {}: {e}", path.display());
+                    continue;
+                }
+            };
+            if log.device == sync.device {
+                continue;
+            }
+            devices.push(DeviceInfo {
+                device: log.device.clone(),
+                name: log.name,
+                updated: log.updated,
+                ops: log.ops.len(),
+            });
+            let applied = sync.applied.get(&log.device).copied().unwrap_or(0);
+            incoming.extend(log.ops.into_iter().filter(|op| op.seq > applied));
+        }
+        incoming.sort_by(|a, b| a.order().cmp(&b.order()));
+
+        // Changed here since the last sync, so other devices have not seen it yet
+        let unsent: HashSet<String> = sync.ops[sync.pushed.min(sync.ops.len())..]
+            .iter()
+            .filter_map(|op| op.change.subject())
+            .collect();
+        for op in &incoming {
+            sync.clock = sync.clock.max(op.clock);
+            sync.applied.insert(op.device.clone(), op.seq);
+            let local = op
+                .change
+                .tag_name()
+                .is_some_and(|tag| self.settings.sync.is_local(tag));
+            if local || !sync.wins(op) {
+                continue;
+            }
+            if let Some(subject) = op.change.subject() {
+                sync.versions.insert(subject, (op.clock, op.device.clone()));
+            }
+            if let Change::Tag {
+                name,
+                tag: Some(theirs),
+            } = &op.change
+            {
+                let device = devices
+                    .iter()
+                    .find(|d| d.device == op.device)
+                    .map_or(op.device.as_str(), DeviceInfo::label);
+                let collision = self
+                    .tag_collision(name, theirs, device)
+                    .filter(|_| unsent.contains(&format!("tag:{name}")));
+                if let Some(collision) = collision {
+                    self.tag_collisions.push(collision);
+                }
+            }
+            if let Some(conflict) = self.apply_op(op) {
+                warn!(
+                    line = conflict.line,
+                    "Lines changed here and on {}, keeping both", op.device
+                );
+                sync.conflicts.push(conflict);
+            }
+        }
+        if !incoming.is_empty() {
+            self.clean_invalid_ranges();
+            self.select = Some(self.cursor..self.cursor);
+        }
+        sync.base = Some(self.sync_snapshot());
+
+        let now = chrono::Local::now().naive_local();
+        let log = DeviceLog {
+            device: sync.device.clone(),
+            name: self.settings.sync.device_name.trim().to_string(),
+            updated: Some(now),
+            ops: sync.ops.clone(),
+        };
+        let json = serde_json::to_string(&log)?;
+        write_payload(folder, &own_log, &json, sync.key.as_ref())?;
+        sync.pushed = sync.ops.len();
+        devices.sort_by_key(|device| std::cmp::Reverse(device.updated));
+        sync.devices = devices;
+        sync.last_sync = Some(now);
+        info!(applied = incoming.len(), "Synced with {}", folder.display());
+        Ok(incoming.len())
+    }
+
+    /// Exchanges operations with the other devices in the sync folder. Returns the number
+    /// of their operations applied.
+    pub fn sync_now(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        let folder = PathBuf::from(self.settings.sync.folder.trim());
+        if folder.as_os_str().is_empty() {
+            return Err("no sync folder is set".into());
+        }
+        if !folder.is_dir() {
+            return Err(format!("{} is not a folder", folder.display()).into());
+        }
+        let mut sync = self.sync_state.take().unwrap_or_else(SyncState::load);
+        let conflicts = sync.conflicts.len();
+        let result = self.exchange(&folder, &mut sync);
+        let saved = sync.save();
+        let new_conflicts = sync.conflicts.len() - conflicts;
+        *self.sync_state.borrow_mut() = Some(sync);
+        let applied = result?;
+        saved?;
+        if new_conflicts > 0 {
+            self.toasts.warning(format!(
+                "{new_conflicts} edits were made here and on another device, both versions were kept"
+            ));
+        }
+        let _ = self.save_to_disk();
+        Ok(applied)
+    }
+
+    /// Syncs every minute while a sync folder is set. Failures are only logged, they are
+    /// mostly the folder being offline for a while.
+    pub fn poll_sync(&mut self, ctx: &egui::Context) {
+        if self.safe_mode || self.settings.sync.folder.trim().is_empty() {
+            return;
+        }
+        let interval = self.idle.poll_interval(SYNC_POLL);
+        ctx.request_repaint_after(interval);
+        if self
+            .sync_checked
+            .is_some_and(|checked| checked.elapsed() < interval)
+        {
+            return;
+        }
+        self.sync_checked = Some(Instant::now());
+        match self.sync_now() {
+            Ok(0) => {}
+            Ok(applied) => self
+                .toasts
+                .info(format!("Synced {applied} changes from other devices")),
+            Err(e) => warn!("Sync failed: {e}"),
+        }
+    }
+
+    /// Settings rows for the sync folder
+    pub fn sync_ui(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings.sync;
+        egui::Grid::new("settings_sync")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Sync folder");
+                ui.add(
+                    egui::TextEdit::singleline(&mut settings.folder)
+                        .hint_text("Folder shared with your other devices"),
+                )
+                .on_hover_text(
+                    "Devices exchange their edits through this folder and merge them one by \
+                     one. On the first sync, what is here is kept below the synced text.",
+                );
+                ui.end_row();
+
+                ui.label("Device name");
+                ui.add(
+                    egui::TextEdit::singleline(&mut settings.device_name)
+                        .hint_text("How other devices list this one"),
+                );
+                ui.end_row();
+
+                ui.label("Kept on this device");
+                ui.horizontal_wrapped(|ui| {
+                    if settings.local_tags.is_empty() {
+                        ui.label(RichText::new("No tags, the tag menus set them").weak());
+                    }
+                    let mut keep = None;
+                    for tag in &settings.local_tags {
+                        if ui
+                            .small_button(format!("{CLOUD_SLASH} {tag} {X}"))
+                            .on_hover_text("Sync it again")
+                            .clicked()
+                        {
+                            keep = Some(tag.clone());
+                        }
+                    }
+                    if let Some(tag) = keep {
+                        settings.set_local(&tag, false);
+                    }
+                });
+                ui.end_row();
+            });
+        ui.horizontal(|ui| {
+            self.sync_now_button(ui);
+            if self.settings.sync.folder.trim().is_empty() {
+                return;
+            }
+            let (last_sync, conflicts) =
+                self.with_sync_state(|sync| (sync.last_sync, sync.conflicts.len()));
+            if let Some(last) = last_sync {
+                ui.label(
+                    RichText::new(format!("Last synced {}", last.format("%b %d %H:%M"))).weak(),
+                );
+            }
+            if conflicts > 0 {
+                ui.label(
+                    RichText::new(format!("{conflicts} conflicts"))
+                        .color(ui.visuals().warn_fg_color),
+                )
+                .on_hover_text("Lines edited here and on another device, both versions were kept");
+            }
+        });
+        if !self.settings.sync.folder.trim().is_empty() {
+            self.sync_key_ui(ui);
+        }
+    }
+
+    fn sync_now_button(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .add_enabled(
+                !self.settings.sync.folder.trim().is_empty(),
+                egui::Button::new("Sync now"),
+            )
+            .clicked()
+        {
+            match self.sync_now() {
+                Ok(applied) => self
+                    .toasts
+                    .success(format!("Synced, {applied} changes from other devices")),
+                Err(e) => self.toasts.error(format!("Sync failed: {e}")),
+            }
+        }
+    }
+
+    /// Takes out the other device's lines a conflict added, keeping the ones edited here.
+    /// Returns false if they are not in the buffer anymore.
+    fn keep_local_lines(&mut self, conflict: &Conflict) -> bool {
+        if conflict.new.is_empty() {
+            return true;
+        }
+        let lines: Vec<&str> = self.buffer.split('\n').collect();
+        let Some(at) = find_lines(&lines, conflict.line, None, &conflict.new) else {
+            return false;
+        };
+        let mut kept = lines[..at].to_vec();
+        kept.extend(&lines[at + conflict.new.len()..]);
+        if let Some(edit) = text_edit(&self.buffer, &kept.join("\n")) {
+            self.edit_buffer(vec![edit]);
+        }
+        true
+    }
+
+    /// Char position of the start of `line`, from 0
+    fn line_start(&self, line: usize) -> usize {
+        self.buffer
+            .split('\n')
+            .take(line)
+            .map(|line| line.chars().count() + 1)
+            .sum::<usize>()
+            .min(self.buffer.chars().count())
+    }
+
+    /// The devices in the sync folder, what is waiting to be synced and the conflicts to
+    /// resolve
+    pub fn sync_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.sync_open;
+        let mut resolve = None;
+        egui::Window::new("Sync")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                let folder = self.settings.sync.folder.trim().to_string();
+                if folder.is_empty() {
+                    ui.label("No sync folder is set, the settings have it");
+                    return;
+                }
+                let sync = self.sync_state.take().unwrap_or_else(SyncState::load);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&folder).monospace());
+                    if sync.key.is_some() {
+                        ui.label(LOCK).on_hover_text("End-to-end encrypted");
+                    }
+                });
+                let local_tags = &self.settings.sync.local_tags;
+                if !local_tags.is_empty() {
+                    ui.label(format!(
+                        "{CLOUD_SLASH} Kept on this device: {}",
+                        local_tags.join(", ")
+                    ))
+                    .on_hover_text("These tags, their ranges and the lines they are on don't sync");
+                }
+                ui.horizontal(|ui| {
+                    match sync.last_sync {
+                        Some(last) => {
+                            ui.label(format!("Last synced {}", last.format("%b %d %H:%M")))
+                        }
+                        None => ui.label("Not synced yet"),
+                    };
+                    let pending = sync.ops.len().saturating_sub(sync.pushed);
+                    if pending > 0 {
+                        ui.label(RichText::new(format!("{pending} changes to send")).weak())
+                            .on_hover_text("Edits are added when syncing");
+                    }
+                });
+                ui.separator();
+                devices_ui(ui, &sync, self.settings.sync.device_name.trim());
+                if !sync.conflicts.is_empty() {
+                    ui.separator();
+                    resolve = conflicts_ui(ui, &sync, self.locked);
+                }
+                *self.sync_state.borrow_mut() = Some(sync);
+                ui.add_space(4.0);
+                self.sync_now_button(ui);
+            });
+        self.sync_open = open;
+
+        let Some((i, resolution)) = resolve else {
+            return;
+        };
+        let Some(conflict) = self.with_sync_state(|sync| sync.conflicts.get(i).cloned()) else {
+            return;
+        };
+        match resolution {
+            Resolution::GoTo => {
+                let at = self.line_start(conflict.line);
+                self.jump_to = Some(at..at);
+                return;
+            }
+            Resolution::KeepBoth => {}
+            Resolution::KeepMine => {
+                if !self.keep_local_lines(&conflict) {
+                    self.toasts.info(
+                        "The other device's lines were already changed, leaving the text as it is",
+                    );
+                }
+            }
+        }
+        let saved = self.with_sync_state(|sync| {
+            sync.conflicts.remove(i);
+            sync.save()
+        });
+        if let Err(e) = saved {
+            warn!("Could not save the sync state: {e}");
+        }
+    }
+
+    /// Conflicts not resolved yet, without loading the sync state for it
+    pub fn sync_conflicts(&self) -> usize {
+        self.sync_state
+            .borrow()
+            .as_ref()
+            .map_or(0, |sync| sync.conflicts.len())
+    }
+
+    /// Takes the sync state along after the state file moved away from `old_save_path`,
+    /// so this device keeps its id and the sync key
+    pub fn move_sync_state(&self, old_save_path: &Path) -> std::io::Result<()> {
+        let old = old_save_path.with_file_name(SYNC_FILE);
+        let new = sync_path();
+        if old == new || !old.exists() {
+            return Ok(());
+        }
+        fs::copy(&old, &new)?;
+        fs::remove_file(old)
+    }
+
+    /// Runs `f` on the sync state, loading it first
+    fn with_sync_state<R>(&self, f: impl FnOnce(&mut SyncState) -> R) -> R {
+        let mut sync = self.sync_state.borrow_mut();
+        f(sync.get_or_insert_with(SyncState::load))
+    }
+
+    /// Encrypts the sync folder with `key` from now on. If the folder is encrypted already,
+    /// the key has to be the one it is encrypted with.
+    fn use_sync_key(&mut self, key: SyncKey) -> Result<(), String> {
+        let snapshot = Path::new(self.settings.sync.folder.trim()).join(SNAPSHOT_FILE);
+        if is_sealed_file(&snapshot) {
+            let data = fs::read(&snapshot).map_err(|e| e.to_string())?;
+            key.open(SNAPSHOT_FILE, &data)
+                .map_err(|_| "this is not the recovery phrase of the sync folder".to_string())?;
+        }
+        self.with_sync_state(|sync| {
+            sync.key = Some(key);
+            sync.save()
+        })
+        .map_err(|e| e.to_string())?;
+        info!("Set up the sync key");
+        // What is in the folder gets encrypted on the next sync, right away
+        self.sync_checked = None;
+        Ok(())
+    }
+
+    /// Settings rows for the key the sync folder is encrypted with: setting up a new one,
+    /// its recovery phrase, and entering the phrase of another device
+    fn sync_key_ui(&mut self, ui: &mut egui::Ui) {
+        let shown_id = egui::Id::new("sync_phrase_shown");
+        let mut shown = ui.data(|d| d.get_temp::<bool>(shown_id)).unwrap_or(false);
+        match self.with_sync_state(|sync| sync.key.clone()) {
+            Some(key) => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{LOCK} End-to-end encrypted"))
+                        .on_hover_text("The sync folder only holds encrypted files");
+                    ui.toggle_value(&mut shown, "Recovery phrase");
+                    if ui
+                        .button("Forget key")
+                        .on_hover_text(
+                            "This device stops syncing until the recovery phrase is entered again",
+                        )
+                        .clicked()
+                    {
+                        let result = self.with_sync_state(|sync| {
+                            sync.key = None;
+                            sync.save()
+                        });
+                        if let Err(e) = result {
+                            self.toasts.error(format!("Could not forget the key: {e}"));
+                        }
+                        shown = false;
+                    }
+                });
+                if shown {
+                    let phrase = key.phrase();
+                    ui.label(RichText::new(&phrase).monospace());
+                    ui.horizontal(|ui| {
+                        if ui.button(format!("{COPY} Copy")).clicked() {
+                            ui.ctx().copy_text(phrase);
+                        }
+                        ui.label(
+                            RichText::new(
+                                "Other devices need these words to sync, and the synced notes \
+                                 can't be read back without them",
+                            )
+                            .weak(),
+                        );
+                    });
+                }
+            }
+            None => {
+                if ui
+                    .button(format!("{LOCK} Set up encryption"))
+                    .on_hover_text(
+                        "Encrypt everything in the sync folder with a key only your devices have",
+                    )
+                    .clicked()
+                {
+                    let snapshot = Path::new(self.settings.sync.folder.trim()).join(SNAPSHOT_FILE);
+                    let result = if is_sealed_file(&snapshot) {
+                        Err(
+                            "the sync folder is encrypted already, enter its recovery phrase"
+                                .to_string(),
+                        )
+                    } else {
+                        self.use_sync_key(SyncKey::generate())
+                    };
+                    match result {
+                        Ok(()) => {
+                            shown = true;
+                            self.toasts
+                                .success("Encryption set up, write down the recovery phrase");
+                        }
+                        Err(e) => self
+                            .toasts
+                            .error(format!("Could not set up encryption: {e}")),
+                    }
+                }
+                let phrase_id = egui::Id::new("sync_phrase_edit");
+                let mut phrase = ui
+                    .data(|d| d.get_temp::<String>(phrase_id))
+                    .unwrap_or_default();
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut phrase)
+                            .hint_text("Recovery phrase of your other devices"),
+                    );
+                    if ui
+                        .add_enabled(!phrase.trim().is_empty(), egui::Button::new("Use"))
+                        .clicked()
+                    {
+                        match SyncKey::from_phrase(&phrase).and_then(|key| self.use_sync_key(key)) {
+                            Ok(()) => {
+                                phrase.clear();
+                                self.toasts
+                                    .success("Recovery phrase set, syncing encrypted");
+                            }
+                            Err(e) => self.toasts.error(format!("Could not use the phrase: {e}")),
+                        }
+                    }
+                });
+                ui.data_mut(|d| d.insert_temp(phrase_id, phrase));
+            }
+        }
+        ui.data_mut(|d| d.insert_temp(shown_id, shown));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `old` with the hunks replaced by their lines of `new`
+    fn patched(old: &[&str], new: &[&str]) -> Vec<String> {
+        let mut lines: Vec<String> = old.iter().map(|line| line.to_string()).collect();
+        for (removed, added) in line_hunks(old, new).into_iter().rev() {
+            lines.splice(removed, new[added].iter().map(|line| line.to_string()));
+        }
+        lines
+    }
+
+    #[test]
+    fn same_lines_have_no_hunks() {
+        assert!(line_hunks(&["a", "b"], &["a", "b"]).is_empty());
+        assert!(line_hunks(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn hunks_of_edits_insertions_and_deletions() {
+        assert_eq!(
+            line_hunks(&["a", "b", "c"], &["a", "B", "c"]),
+            [(1..2, 1..2)]
+        );
+        assert_eq!(line_hunks(&["a", "c"], &["a", "b", "c"]), [(1..1, 1..2)]);
+        assert_eq!(line_hunks(&["a", "b", "c"], &["a", "c"]), [(1..2, 1..1)]);
+    }
+
+    #[test]
+    fn separate_edits_make_separate_hunks() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["A", "b", "c", "d", "E"];
+        assert_eq!(line_hunks(&old, &new), [(0..1, 0..1), (4..5, 4..5)]);
+    }
+
+    #[test]
+    fn hunks_turn_old_into_new() {
+        let cases: [(&[&str], &[&str]); 4] = [
+            (&["a", "b", "c", "d"], &["b", "x", "d", "e"]),
+            (&["x", "x", "y"], &["y", "x", "x"]),
+            (&[], &["a", "b"]),
+            (&["a", "b"], &[]),
+        ];
+        for (old, new) in cases {
+            assert_eq!(patched(old, new), new, "{old:?} -> {new:?}");
+        }
+    }
+
+    #[test]
+    fn find_lines_prefers_the_spot_below_the_line_above() {
+        let lines = ["x", "a", "y", "a"];
+        let old = ["a".to_string()];
+        assert_eq!(find_lines(&lines, 0, Some("y"), &old), Some(3));
+        assert_eq!(find_lines(&lines, 3, Some("x"), &old), Some(1));
+        // Without the line above the closest one will do
+        assert_eq!(find_lines(&lines, 3, Some("gone"), &old), Some(3));
+        assert_eq!(find_lines(&lines, 0, None, &["b".to_string()]), None);
+    }
+
+    #[test]
+    fn insertions_need_the_line_above() {
+        let lines = ["x", "y"];
+        assert_eq!(find_lines(&lines, 5, Some("x"), &[]), Some(1));
+        assert_eq!(find_lines(&lines, 1, None, &[]), Some(0));
+        assert_eq!(find_lines(&lines, 1, Some("gone"), &[]), None);
+    }
+
+    #[test]
+    fn text_edit_is_the_changed_middle() {
+        assert_eq!(text_edit("same", "same"), None);
+        assert_eq!(
+            text_edit("hello world", "hello big world"),
+            Some((6..6, "big ".to_string()))
+        );
+        assert_eq!(text_edit("aXXb", "ab"), Some((1..3, String::new())));
+        assert_eq!(text_edit("aaa", "aa"), Some((2..3, String::new())));
+    }
+
+    #[test]
+    fn find_text_takes_the_closest_match_in_chars() {
+        assert_eq!(find_text("äb ab ab", "ab", 4), Some(3..5));
+        assert_eq!(find_text("äb ab ab", "ab", 7), Some(6..8));
+        assert_eq!(find_text("ab", "", 0), None);
+    }
+
+    #[test]
+    fn changes_go_bottom_up_then_tags_then_ranges() {
+        let base = Snapshot {
+            buffer: "a\nb\nc\nd".to_string(),
+            tags: IndexMap::from([("x".to_string(), Tag::new([1, 2, 3]))]),
+            tagged_ranges: vec![TaggedRange::new("x".to_string(), 0..1)],
+        };
+        let mut now = base.clone();
+        now.buffer = "A\nb\nc\nD".to_string();
+        now.tags.insert("y".to_string(), Tag::new([3, 2, 1]));
+        now.tagged_ranges.clear();
+        let changes = changes(&base, &now);
+        assert_eq!(changes.len(), 4);
+        assert!(matches!(&changes[0], Change::Lines { at: 3, before: Some(b), .. } if b == "c"));
+        assert!(matches!(
+            &changes[1],
+            Change::Lines {
+                at: 0,
+                before: None,
+                ..
+            }
+        ));
+        assert!(matches!(&changes[2], Change::Tag { name, tag: Some(_) } if name == "y"));
+        assert!(
+            matches!(&changes[3], Change::Range { range: None, tag_name, .. } if tag_name == "x")
+        );
+    }
+
+    #[test]
+    fn moved_ranges_are_no_change() {
+        let mut tr = TaggedRange::new("x".to_string(), 2..3);
+        let base = Snapshot {
+            buffer: "a\nb".to_string(),
+            tags: IndexMap::new(),
+            tagged_ranges: vec![tr.clone()],
+        };
+        tr.range = 4..5;
+        tr.mark();
+        let now = Snapshot {
+            buffer: "a\nz\nb".to_string(),
+            tags: IndexMap::new(),
+            tagged_ranges: vec![tr],
+        };
+        let changes = changes(&base, &now);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0], Change::Lines { .. }));
+    }
+
+    #[test]
+    fn local_tags_and_their_lines_stay_out_of_the_snapshot() {
+        let mut app = Taskmonger {
+            buffer: "one\nsecret\ntwo\n".to_string(),
+            ..Default::default()
+        };
+        app.settings.sync.set_local("private", true);
+        app.tags.clear();
+        app.tags.insert("private".to_string(), Tag::new([1, 2, 3]));
+        app.tags
+            .insert("private/pins".to_string(), Tag::new([1, 2, 3]));
+        app.tags.insert("work".to_string(), Tag::new([1, 2, 4]));
+        app.tagged_ranges = vec![
+            TaggedRange::new("private".to_string(), 5..7),
+            TaggedRange::new("work".to_string(), 11..14),
+            TaggedRange::new("work".to_string(), 0..6),
+        ];
+        let snapshot = app.sync_snapshot();
+        assert_eq!(snapshot.buffer, "one\ntwo\n");
+        assert_eq!(snapshot.tags.keys().collect::<Vec<_>>(), ["work"]);
+        let ranges: Vec<_> = snapshot.tagged_ranges.iter().map(|tr| &tr.range).collect();
+        assert_eq!(ranges, [&(4..7)]);
+    }
+}
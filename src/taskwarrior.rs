@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::todotxt::UNFILED_TAG;
+use crate::tools::char_to_byte_range;
+use crate::{Priority, TaggedRange, Taskmonger};
+
+/// Timestamps as Taskwarrior writes them, always UTC
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TaskwarriorSettings {
+    /// The `task` executable
+    pub command: String,
+    /// Ranges whose tasks were completed or reopened in Taskwarrior follow them
+    pub read_status: bool,
+}
+
+impl Default for TaskwarriorSettings {
+    fn default() -> Self {
+        Self {
+            command: "task".to_string(),
+            read_status: true,
+        }
+    }
+}
+
+/// What a Taskwarrior sync did
+#[derive(Default)]
+pub struct TaskwarriorSync {
+    pub exported: usize,
+    pub completed: usize,
+    pub reopened: usize,
+}
+
+/// A task as far as it is read back
+#[derive(Deserialize)]
+struct Task {
+    uuid: String,
+    status: String,
+    #[serde(default)]
+    modified: Option<String>,
+}
+
+/// The same uuid for the same ranges every time, so importing again updates the tasks
+/// instead of adding them twice. Two FNV-1a hashes of the range's tag and creation time.
+fn task_uuid(tr: &TaggedRange) -> String {
+    let key = format!("{}@{}", tr.tag_name, tr.created);
+    let hash = |offset: u64| {
+        key.bytes().fold(offset, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    };
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&hash(0xcbf29ce484222325).to_be_bytes());
+    bytes[8..].copy_from_slice(&hash(0x84222325cbf29ce4).to_be_bytes());
+    // Version 8, custom, and the RFC variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Taskwarrior tags can't hold spaces
+fn task_tag(tag: &str) -> String {
+    tag.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+fn task_priority(priority: Priority) -> Option<&'static str> {
+    match priority {
+        Priority::Urgent => Some("H"),
+        Priority::High => Some("M"),
+        Priority::Normal => None,
+        Priority::Low => Some("L"),
+    }
+}
+
+fn task_date(time: NaiveDateTime) -> String {
+    time.format(DATE_FORMAT).to_string()
+}
+
+/// Due dates are days, due at their end
+fn task_due(day: NaiveDate) -> String {
+    task_date(day.and_hms_opt(23, 59, 59).unwrap_or_default())
+}
+
+/// First line of a range's text, without list marker or checkbox
+fn description(text: &str) -> String {
+    let mut line = text.trim().lines().next().unwrap_or_default().trim();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            line = rest.trim_start();
+            break;
+        }
+    }
+    for checkbox in ["[ ] ", "[x] ", "[X] "] {
+        if let Some(rest) = line.strip_prefix(checkbox) {
+            line = rest.trim_start();
+            break;
+        }
+    }
+    line.to_string()
+}
+
+/// Ranges of the same text, which make one task with all their tags, as indices into
+/// `ranges` with the one the task's uuid comes from first
+fn task_groups(buffer: &str, ranges: &[TaggedRange]) -> Vec<Vec<usize>> {
+    let len = buffer.chars().count();
+    let mut indices: Vec<usize> = (0..ranges.len())
+        .filter(|&i| !ranges[i].range.is_empty() && ranges[i].range.end <= len)
+        .collect();
+    indices.sort_by_key(|&i| {
+        let tr = &ranges[i];
+        (tr.range.start, tr.range.end, tr.created)
+    });
+    indices
+        .chunk_by(|&a, &b| ranges[a].range == ranges[b].range)
+        .map(<[usize]>::to_vec)
+        .collect()
+}
+
+/// The tasks for `task import`
+fn tasks(buffer: &str, ranges: &[TaggedRange]) -> Vec<Value> {
+    let mut tasks = vec![];
+    for group in task_groups(buffer, ranges) {
+        let first = &ranges[group[0]];
+        let text = description(&buffer[char_to_byte_range(buffer, &first.range)]);
+        if text.is_empty() {
+            continue;
+        }
+        let group: Vec<&TaggedRange> = group.iter().map(|&i| &ranges[i]).collect();
+        let done = group.iter().any(|tr| tr.done);
+        let modified = group.iter().map(|tr| tr.modified).max().unwrap_or_default();
+        let tags: Vec<String> = group
+            .iter()
+            .filter(|tr| tr.tag_name != UNFILED_TAG)
+            .map(|tr| task_tag(&tr.tag_name))
+            .collect();
+        let mut task = json!({
+            "uuid": task_uuid(first),
+            "description": text,
+            "status": if done { "completed" } else { "pending" },
+            "entry": task_date(first.created),
+            "modified": task_date(modified),
+            "tags": tags,
+        });
+        if done {
+            task["end"] = json!(task_date(modified));
+        }
+        if let Some(due) = group.iter().find_map(|tr| tr.due) {
+            task["due"] = json!(task_due(due));
+        }
+        let priority = group.iter().map(|tr| tr.priority).max().unwrap_or_default();
+        if let Some(priority) = task_priority(priority) {
+            task["priority"] = json!(priority);
+        }
+        tasks.push(task);
+    }
+    tasks
+}
+
+impl Taskmonger {
+    /// Runs `task` with `args` and rc overrides that keep it from asking anything
+    fn task_command(&self, args: &[&str], input: Option<&str>) -> Result<String, String> {
+        let command = self.settings.taskwarrior.command.trim();
+        let mut task = Command::new(if command.is_empty() { "task" } else { command });
+        task.args(["rc.confirmation=off", "rc.verbose=nothing", "rc.hooks=off"])
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = task
+            .spawn()
+            .map_err(|e| format!("could not run {command}: {e}"))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{command} {}: {}", args.join(" "), error.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Takes over the status of tasks completed or reopened in Taskwarrior since the
+    /// ranges last changed. Returns the numbers of ranges completed and reopened.
+    fn read_task_status(&mut self) -> Result<(usize, usize), String> {
+        let export = self.task_command(&["export"], None)?;
+        let tasks: Vec<Task> =
+            serde_json::from_str(&export).map_err(|e| format!("unexpected task export: {e}"))?;
+        Ok(self.take_task_status(tasks))
+    }
+
+    /// The status part of [`Self::read_task_status`], for tasks already read
+    fn take_task_status(&mut self, tasks: Vec<Task>) -> (usize, usize) {
+        let tasks: HashMap<String, Task> = tasks
+            .into_iter()
+            .map(|task| (task.uuid.clone(), task))
+            .collect();
+
+        let mut completed = 0;
+        let mut reopened = 0;
+        for group in task_groups(&self.buffer, &self.tagged_ranges) {
+            let Some(task) = tasks.get(&task_uuid(&self.tagged_ranges[group[0]])) else {
+                continue;
+            };
+            let done = match task.status.as_str() {
+                "completed" => true,
+                "pending" | "waiting" => false,
+                _ => continue,
+            };
+            let modified = task
+                .modified
+                .as_deref()
+                .and_then(|time| NaiveDateTime::parse_from_str(time, DATE_FORMAT).ok());
+            for i in group {
+                let tr = &mut self.tagged_ranges[i];
+                if tr.done == done {
+                    continue;
+                }
+                // A range changed here after the task keeps its own status
+                if modified.is_some_and(|time| time <= tr.modified) {
+                    continue;
+                }
+                tr.done = done;
+                tr.mark();
+                if done {
+                    completed += 1;
+                } else {
+                    reopened += 1;
+                }
+            }
+        }
+        (completed, reopened)
+    }
+
+    /// Sends the tagged ranges to Taskwarrior as tasks, after reading back the status of
+    /// tasks already there if the settings ask for it
+    pub fn sync_taskwarrior(&mut self) -> Result<TaskwarriorSync, String> {
+        let mut sync = TaskwarriorSync::default();
+        if self.settings.taskwarrior.read_status {
+            (sync.completed, sync.reopened) = self.read_task_status()?;
+        }
+        let tasks = tasks(&self.buffer, &self.tagged_ranges);
+        sync.exported = tasks.len();
+        if !tasks.is_empty() {
+            let json = serde_json::to_string(&tasks).map_err(|e| e.to_string())?;
+            self.task_command(&["import", "-"], Some(&json))?;
+        }
+        info!(
+            exported = sync.exported,
+            completed = sync.completed,
+            reopened = sync.reopened,
+            "Synced with Taskwarrior"
+        );
+        if sync.completed + sync.reopened > 0 {
+            let _ = self.save_to_disk();
+        }
+        Ok(sync)
+    }
+
+    /// Settings rows for Taskwarrior: the command, reading status back and syncing
+    pub fn taskwarrior_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Taskwarrior");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.taskwarrior.command)
+                    .hint_text("task")
+                    .desired_width(100.0),
+            )
+            .on_hover_text("The task executable");
+            ui.checkbox(
+                &mut self.settings.taskwarrior.read_status,
+                "Read completed tasks back",
+            );
+            if ui
+                .button("Sync tasks")
+                .on_hover_text("Tagged ranges as tasks, tags as task tags and done as completed")
+                .clicked()
+            {
+                match self.sync_taskwarrior() {
+                    Ok(sync) => {
+                        let mut message = format!("Sent {} tasks to Taskwarrior", sync.exported);
+                        if sync.completed > 0 {
+                            message.push_str(&format!(", {} completed there", sync.completed));
+                        }
+                        if sync.reopened > 0 {
+                            message.push_str(&format!(", {} reopened there", sync.reopened));
+                        }
+                        self.toasts.success(message);
+                    }
+                    Err(e) => self.toasts.error(format!("Taskwarrior sync failed: {e}")),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: "- [ ] call mom\n- [x] pay rent\nnotes".to_string(),
+            ..Default::default()
+        };
+        let mut call = TaggedRange::new("home".to_string(), 0..14);
+        call.due = NaiveDate::from_ymd_opt(2024, 5, 3);
+        call.priority = Priority::Urgent;
+        let mut rent = TaggedRange::new("home".to_string(), 15..29);
+        rent.done = true;
+        app.tagged_ranges = vec![
+            call,
+            TaggedRange::new("family and friends".to_string(), 0..14),
+            rent,
+        ];
+        app
+    }
+
+    /// The tasks of `app` as `task export` would give them back
+    fn exported(app: &Taskmonger) -> Vec<Value> {
+        let json = serde_json::to_string(&tasks(&app.buffer, &app.tagged_ranges)).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn read_back(app: &mut Taskmonger, tasks: Vec<Value>) -> (usize, usize) {
+        let tasks = serde_json::from_value(Value::Array(tasks)).unwrap();
+        app.take_task_status(tasks)
+    }
+
+    #[test]
+    fn ranges_of_the_same_text_make_one_task() {
+        let app = app();
+        let tasks = exported(&app);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0]["description"], "call mom");
+        assert_eq!(tasks[0]["tags"], json!(["home", "family_and_friends"]));
+        assert_eq!(tasks[0]["status"], "pending");
+        assert_eq!(tasks[0]["due"], "20240503T235959Z");
+        assert_eq!(tasks[0]["priority"], "H");
+        assert_eq!(tasks[1]["description"], "pay rent");
+        assert_eq!(tasks[1]["status"], "completed");
+        assert!(tasks[1].get("due").is_none());
+    }
+
+    #[test]
+    fn uuids_stay_the_same_and_differ_per_range() {
+        let app = app();
+        let first = exported(&app);
+        assert_eq!(first, exported(&app));
+        assert_eq!(first[0]["uuid"], json!(task_uuid(&app.tagged_ranges[0])));
+        assert_ne!(first[0]["uuid"], first[1]["uuid"]);
+        let uuid = first[0]["uuid"].as_str().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "8");
+    }
+
+    #[test]
+    fn reading_back_an_unchanged_export_changes_nothing() {
+        let mut app = app();
+        let tasks = exported(&app);
+        let before: Vec<bool> = app.tagged_ranges.iter().map(|tr| tr.done).collect();
+        assert_eq!(read_back(&mut app, tasks), (0, 0));
+        let after: Vec<bool> = app.tagged_ranges.iter().map(|tr| tr.done).collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn tasks_completed_or_reopened_later_change_their_ranges() {
+        let mut app = app();
+        let mut tasks = exported(&app);
+        let later = task_date(chrono::Local::now().naive_local() + chrono::Days::new(1));
+        tasks[0]["status"] = json!("completed");
+        tasks[0]["modified"] = json!(later);
+        tasks[1]["status"] = json!("pending");
+        tasks[1]["modified"] = json!(later);
+        assert_eq!(read_back(&mut app, tasks), (2, 1));
+        assert!(app.tagged_ranges.iter().take(2).all(|tr| tr.done));
+        assert!(!app.tagged_ranges[2].done);
+    }
+
+    #[test]
+    fn ranges_changed_after_the_task_keep_their_status() {
+        let mut app = app();
+        let mut tasks = exported(&app);
+        tasks[0]["status"] = json!("completed");
+        tasks[0]["modified"] = json!("20000101T000000Z");
+        tasks[1]["status"] = json!("deleted");
+        assert_eq!(read_back(&mut app, tasks), (0, 0));
+        assert!(!app.tagged_ranges[0].done);
+        assert!(app.tagged_ranges[2].done);
+    }
+}
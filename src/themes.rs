@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::PathBuf;
+
+use eframe::egui;
+use egui::Color32;
+use egui_phosphor::regular::ARROW_CLOCKWISE;
+use serde::{Deserialize, Deserializer};
+use tracing::{info, warn};
+
+use crate::Taskmonger;
+
+/// User themes are `*.toml` files in here
+fn theme_dir() -> PathBuf {
+    PathBuf::from("themes")
+}
+
+/// `#rrggbb` as in theme files
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let hex = text.trim().trim_start_matches('#');
+    match (hex.len(), u32::from_str_radix(hex, 16)) {
+        (6, Ok(rgb)) => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+        _ => Err(serde::de::Error::custom(format!(
+            "{text:?} is not a #rrggbb color"
+        ))),
+    }
+}
+
+/// Color scheme of the UI, for example in `themes/nord.toml`:
+///
+/// ```toml
+/// name = "Nord"
+/// dark = true
+/// background = "#2e3440"
+/// text = "#d8dee9"
+/// selection = "#4c566a"
+/// panel = "#3b4252"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Theme {
+    /// The file name if left out
+    #[serde(default)]
+    pub name: String,
+    /// Starts from the dark look instead of the light one, for everything not listed here
+    #[serde(default)]
+    pub dark: bool,
+    /// Behind the editor and other text fields
+    #[serde(deserialize_with = "hex_color")]
+    pub background: [u8; 3],
+    #[serde(deserialize_with = "hex_color")]
+    pub text: [u8; 3],
+    #[serde(deserialize_with = "hex_color")]
+    pub selection: [u8; 3],
+    /// Side panels, bars and windows
+    #[serde(deserialize_with = "hex_color")]
+    pub panel: [u8; 3],
+    /// Thicker borders in the text color around widgets and windows
+    #[serde(default)]
+    pub strong_borders: bool,
+}
+
+impl Theme {
+    fn builtin(name: &str, dark: bool, colors: [u32; 4], strong_borders: bool) -> Self {
+        let rgb = |c: u32| [(c >> 16) as u8, (c >> 8) as u8, c as u8];
+        Self {
+            name: name.to_string(),
+            dark,
+            background: rgb(colors[0]),
+            text: rgb(colors[1]),
+            selection: rgb(colors[2]),
+            panel: rgb(colors[3]),
+            strong_borders,
+        }
+    }
+
+    /// Themes that come with the app: background, text, selection and panel colors
+    fn builtins() -> Vec<Theme> {
+        vec![
+            Self::builtin(
+                "Solarized Light",
+                false,
+                [0xfdf6e3, 0x586e75, 0xd6cfb8, 0xeee8d5],
+                false,
+            ),
+            Self::builtin(
+                "Solarized Dark",
+                true,
+                [0x002b36, 0x93a1a1, 0x2a5a66, 0x073642],
+                false,
+            ),
+            Self::builtin(
+                "Gruvbox Dark",
+                true,
+                [0x282828, 0xebdbb2, 0x665c54, 0x3c3836],
+                false,
+            ),
+            Self::builtin(
+                "Gruvbox Light",
+                false,
+                [0xfbf1c7, 0x3c3836, 0xd5c4a1, 0xebdbb2],
+                false,
+            ),
+            Self::builtin(
+                "High Contrast",
+                true,
+                [0x000000, 0xffffff, 0x1f5fd0, 0x000000],
+                true,
+            ),
+        ]
+    }
+
+    /// The light or dark look with the theme's colors
+    pub fn visuals(&self) -> egui::Visuals {
+        let color = |[r, g, b]: [u8; 3]| Color32::from_rgb(r, g, b);
+        let mut visuals = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        visuals.extreme_bg_color = color(self.background);
+        visuals.text_edit_bg_color = Some(color(self.background));
+        visuals.override_text_color = Some(color(self.text));
+        visuals.selection.bg_fill = color(self.selection);
+        visuals.panel_fill = color(self.panel);
+        visuals.window_fill = color(self.panel);
+        visuals.faint_bg_color = color(self.panel).gamma_multiply(0.8);
+        if self.strong_borders {
+            let stroke = egui::Stroke::new(1.5, color(self.text));
+            visuals.widgets.noninteractive.bg_stroke = stroke;
+            visuals.widgets.inactive.bg_stroke = stroke;
+            visuals.window_stroke = stroke;
+            visuals.selection.stroke = stroke;
+        }
+        visuals
+    }
+}
+
+/// The built-in themes and those in the theme directory
+#[derive(Default)]
+pub struct Themes {
+    loaded: bool,
+    themes: Vec<Theme>,
+    /// Files that could not be read, with why
+    errors: Vec<String>,
+}
+
+impl Themes {
+    fn load(&mut self) {
+        self.loaded = true;
+        self.themes = Theme::builtins();
+        self.errors.clear();
+        let Ok(entries) = fs::read_dir(theme_dir()) else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let theme = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| toml::from_str::<Theme>(&text).map_err(|e| e.to_string()));
+            match theme {
+                Ok(mut theme) => {
+                    if theme.name.trim().is_empty() {
+                        theme.name = path
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                    }
+                    // A file with a built-in's name replaces it
+                    self.themes.retain(|t| t.name != theme.name);
+                    self.themes.push(theme);
+                }
+                Err(e) => {
+                    warn!("Could not load theme {}: {e}", path.display());
+                    self.errors.push(format!("{}: {e}", path.display()));
+                }
+            }
+        }
+        info!("Loaded {} themes", self.themes.len());
+    }
+
+    /// Settings row to pick light, dark or one of the themes
+    pub fn ui(&mut self, ui: &mut egui::Ui, theme: &mut Option<String>, dark_mode: &mut bool) {
+        if !self.loaded {
+            self.load();
+        }
+        let selected = match theme {
+            Some(name) => name.clone(),
+            None if *dark_mode => "Dark".to_string(),
+            None => "Light".to_string(),
+        };
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("theme")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (dark, name) in [(false, "Light"), (true, "Dark")] {
+                        if ui
+                            .selectable_label(theme.is_none() && *dark_mode == dark, name)
+                            .clicked()
+                        {
+                            *theme = None;
+                            *dark_mode = dark;
+                        }
+                    }
+                    ui.separator();
+                    for t in &self.themes {
+                        if ui
+                            .selectable_label(theme.as_ref() == Some(&t.name), &t.name)
+                            .clicked()
+                        {
+                            *theme = Some(t.name.clone());
+                            *dark_mode = t.dark;
+                        }
+                    }
+                });
+            if ui
+                .button(ARROW_CLOCKWISE)
+                .on_hover_text(format!(
+                    "Reload the themes in {}/*.toml",
+                    theme_dir().display()
+                ))
+                .clicked()
+            {
+                self.load();
+            }
+        });
+        for error in &self.errors {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<&Theme> {
+        if !self.loaded {
+            self.load();
+        }
+        self.themes.iter().find(|t| t.name == name)
+    }
+}
+
+impl Taskmonger {
+    /// Sets the visuals of the print preview, the chosen theme, or plain light or dark.
+    /// Safe mode sticks to the default one.
+    pub fn apply_theme(&mut self, ctx: &egui::Context) {
+        let visuals = if self.print_preview {
+            crate::print::print_visuals()
+        } else if self.safe_mode {
+            egui::Visuals::light()
+        } else if let Some(theme) = self
+            .settings
+            .theme
+            .as_deref()
+            .and_then(|name| self.themes.get(name))
+        {
+            theme.visuals()
+        } else if self.settings.dark_mode {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        ctx.set_visuals(visuals);
+    }
+}
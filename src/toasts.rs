@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use egui::{Color32, RichText};
+use egui_phosphor::regular::{CHECK_CIRCLE, INFO, WARNING, X, X_CIRCLE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToastKind {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastKind {
+    fn icon(&self) -> &'static str {
+        match self {
+            ToastKind::Info => INFO,
+            ToastKind::Success => CHECK_CIRCLE,
+            ToastKind::Warning => WARNING,
+            ToastKind::Error => X_CIRCLE,
+        }
+    }
+
+    fn color(&self, visuals: &egui::Visuals) -> Color32 {
+        match self {
+            ToastKind::Info => visuals.text_color(),
+            ToastKind::Success => Color32::from_rgb(80, 170, 90),
+            ToastKind::Warning => visuals.warn_fg_color,
+            ToastKind::Error => visuals.error_fg_color,
+        }
+    }
+
+    /// Problems stay longer, so they are not missed
+    fn lifetime(&self) -> Duration {
+        match self {
+            ToastKind::Info | ToastKind::Success => Duration::from_secs(3),
+            ToastKind::Warning | ToastKind::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+struct Toast {
+    kind: ToastKind,
+    text: String,
+    shown: Instant,
+}
+
+/// Short notifications in the bottom right corner that go away on their own.
+/// Uses interior mutability so errors can be reported from `&self` methods.
+#[derive(Default)]
+pub struct Toasts {
+    toasts: RefCell<Vec<Toast>>,
+}
+
+impl Toasts {
+    pub fn add(&self, kind: ToastKind, text: impl Into<String>) {
+        let text = text.into();
+        let mut toasts = self.toasts.borrow_mut();
+        // Repeated failures, e.g. saving on every keystroke, show up once
+        if let Some(toast) = toasts.iter_mut().find(|t| t.kind == kind && t.text == text) {
+            toast.shown = Instant::now();
+            return;
+        }
+        toasts.push(Toast {
+            kind,
+            text,
+            shown: Instant::now(),
+        });
+    }
+
+    pub fn info(&self, text: impl Into<String>) {
+        self.add(ToastKind::Info, text);
+    }
+
+    pub fn success(&self, text: impl Into<String>) {
+        self.add(ToastKind::Success, text);
+    }
+
+    pub fn warning(&self, text: impl Into<String>) {
+        self.add(ToastKind::Warning, text);
+    }
+
+    pub fn error(&self, text: impl Into<String>) {
+        self.add(ToastKind::Error, text);
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        let mut toasts = self.toasts.borrow_mut();
+        toasts.retain(|t| t.shown.elapsed() < t.kind.lifetime());
+        if toasts.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new("toasts".into())
+            .order(egui::Order::Foreground)
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -36.0))
+            .show(ctx, |ui| {
+                for (i, toast) in toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(320.0);
+                        ui.horizontal(|ui| {
+                            let color = toast.kind.color(ui.visuals());
+                            ui.label(RichText::new(toast.kind.icon()).color(color));
+                            ui.label(&toast.text);
+                            if ui.small_button(X).clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            toasts.remove(i);
+        }
+
+        // Wake up again when the next toast expires
+        if let Some(next) = toasts
+            .iter()
+            .map(|t| t.kind.lifetime().saturating_sub(t.shown.elapsed()))
+            .min()
+        {
+            ctx.request_repaint_after(next);
+        }
+    }
+}
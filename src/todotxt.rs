@@ -0,0 +1,365 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use eframe::egui;
+use tracing::info;
+
+use crate::tools::char_to_byte_range;
+use crate::{Priority, TaggedRange, Taskmonger};
+
+/// Tag of imported tasks without a tag of their own, which keeps their state.
+/// It is left out again on export.
+pub const UNFILED_TAG: &str = "todo";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A line of a todo.txt file
+#[derive(Debug, PartialEq)]
+struct Task {
+    done: bool,
+    priority: Priority,
+    completed: Option<NaiveDate>,
+    created: Option<NaiveDate>,
+    text: String,
+    /// `+project` as `project`, `@context` as it is
+    tags: Vec<String>,
+    due: Option<NaiveDate>,
+}
+
+fn date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, DATE_FORMAT).ok()
+}
+
+/// `(A)` is the most urgent, anything below `(B)` counts as low
+pub fn letter_priority(letter: char) -> Priority {
+    match letter {
+        'A' => Priority::Urgent,
+        'B' => Priority::High,
+        _ => Priority::Low,
+    }
+}
+
+pub fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::Urgent => Some('A'),
+        Priority::High => Some('B'),
+        Priority::Normal => None,
+        Priority::Low => Some('C'),
+    }
+}
+
+/// Reads `x 2024-05-02 2024-05-01 call mom +family @phone due:2024-05-03`, `(A) ...` and the
+/// rest of the todo.txt format
+fn parse_task(line: &str) -> Option<Task> {
+    let mut words = line.split_whitespace().peekable();
+    let done = words.next_if_eq(&"x").is_some();
+    let mut priority = Priority::Normal;
+    if let Some(word) = words.peek() {
+        let letter = word
+            .strip_prefix('(')
+            .and_then(|w| w.strip_suffix(')'))
+            .filter(|w| w.len() == 1 && w.chars().all(|c| c.is_ascii_uppercase()));
+        if let Some(letter) = letter.and_then(|l| l.chars().next()) {
+            priority = letter_priority(letter);
+            words.next();
+        }
+    }
+    // Done tasks may have a completion date before the creation date
+    let first = words.next_if(|word| date(word).is_some()).and_then(date);
+    let second = words.next_if(|word| date(word).is_some()).and_then(date);
+    let (completed, created) = match (done, first, second) {
+        (true, Some(completed), created) => (Some(completed), created),
+        (_, created, _) => (None, created),
+    };
+
+    let mut text = vec![];
+    let mut tags = vec![];
+    let mut due = None;
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            tags.push(project.to_string());
+        } else if word.len() > 1 && word.starts_with('@') {
+            tags.push(word.to_string());
+        } else if let Some(day) = word.strip_prefix("due:").and_then(date) {
+            due = Some(day);
+        } else if let Some(letter) = word.strip_prefix("pri:").and_then(|p| p.chars().next()) {
+            // Where done tasks keep their priority
+            priority = letter_priority(letter);
+        } else {
+            text.push(word);
+        }
+    }
+    if text.is_empty() && tags.is_empty() {
+        return None;
+    }
+    Some(Task {
+        done,
+        priority,
+        completed,
+        created,
+        text: text.join(" "),
+        tags,
+        due,
+    })
+}
+
+/// `tag` as a project or context word, which can't hold spaces
+fn tag_word(tag: &str) -> String {
+    let word = tag.split_whitespace().collect::<Vec<_>>().join("_");
+    if word.starts_with('@') {
+        word
+    } else {
+        format!("+{word}")
+    }
+}
+
+/// Text of a range as a task: the first line without list marker or checkbox, and whether
+/// the checkbox is ticked
+fn task_text(text: &str) -> (String, bool) {
+    let mut line = text.trim().lines().next().unwrap_or_default().trim();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            line = rest.trim_start();
+            break;
+        }
+    }
+    for (checkbox, ticked) in [("[ ] ", false), ("[x] ", true), ("[X] ", true)] {
+        if let Some(rest) = line.strip_prefix(checkbox) {
+            return (rest.trim_start().to_string(), ticked);
+        }
+    }
+    (line.to_string(), false)
+}
+
+/// A todo.txt line per tagged stretch of the buffer, ranges of several tags on the same
+/// text make one task with all of them
+fn todo_txt(buffer: &str, ranges: &[TaggedRange]) -> String {
+    let len = buffer.chars().count();
+    let mut ranges: Vec<&TaggedRange> = ranges
+        .iter()
+        .filter(|tr| !tr.range.is_empty() && tr.range.end <= len)
+        .collect();
+    ranges.sort_by_key(|tr| (tr.range.start, tr.range.end));
+
+    let mut out = String::new();
+    for group in ranges.chunk_by(|a, b| a.range == b.range) {
+        let (text, ticked) = task_text(&buffer[char_to_byte_range(buffer, &group[0].range)]);
+        if text.is_empty() {
+            continue;
+        }
+        let done = ticked || group.iter().any(|tr| tr.done);
+        let priority = group.iter().map(|tr| tr.priority).max().unwrap_or_default();
+        let created = group.iter().map(|tr| tr.created.date()).min();
+        let mut words = vec![];
+        if done {
+            words.push("x".to_string());
+            let completed = group.iter().map(|tr| tr.modified.date()).max();
+            words.extend(completed.map(|day| day.format(DATE_FORMAT).to_string()));
+        } else if let Some(letter) = priority_letter(priority) {
+            words.push(format!("({letter})"));
+        }
+        words.extend(created.map(|day| day.format(DATE_FORMAT).to_string()));
+        words.push(text);
+        for tr in group {
+            if tr.tag_name != UNFILED_TAG {
+                words.push(tag_word(&tr.tag_name));
+            }
+        }
+        if let Some(due) = group.iter().find_map(|tr| tr.due) {
+            words.push(format!("due:{}", due.format(DATE_FORMAT)));
+        }
+        if let (true, Some(letter)) = (done, priority_letter(priority)) {
+            words.push(format!("pri:{letter}"));
+        }
+        out.push_str(&words.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+impl Taskmonger {
+    /// Writes the tagged ranges as tasks of a todo.txt file
+    pub fn export_todo_txt(&self) -> std::io::Result<PathBuf> {
+        let path = PathBuf::from("todo.txt");
+        fs::write(&path, todo_txt(&self.buffer, &self.tagged_ranges))?;
+        Ok(path)
+    }
+
+    /// Appends the tasks of a todo.txt file as checkbox items. Projects and contexts
+    /// become tags, priorities, due and done states go to the ranges. Returns the number
+    /// of tasks.
+    pub fn import_todo_txt(&mut self, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.locked {
+            return Err("the buffer is locked".into());
+        }
+        let tasks: Vec<Task> = fs::read_to_string(path)?
+            .lines()
+            .filter_map(parse_task)
+            .collect();
+        if tasks.is_empty() {
+            return Err("no tasks in the file".into());
+        }
+        for task in &tasks {
+            if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+                self.buffer.push('\n');
+            }
+            self.buffer
+                .push_str(if task.done { "- [x] " } else { "- [ ] " });
+            let start = self.buffer.chars().count();
+            // Tasks of only tags still need some text to tag
+            let text = if task.text.is_empty() {
+                task.tags.join(" ")
+            } else {
+                task.text.clone()
+            };
+            self.buffer.push_str(&text);
+            let end = self.buffer.chars().count();
+            self.buffer.push('\n');
+
+            let tags = if task.tags.is_empty() {
+                vec![UNFILED_TAG.to_string()]
+            } else {
+                task.tags.clone()
+            };
+            for tag in tags {
+                let tag = self.import_tag(tag);
+                let mut tr = TaggedRange::new(tag.clone(), start..end);
+                tr.done = task.done;
+                tr.priority = task.priority;
+                tr.due = task.due;
+                // Keeping the time of day keeps ranges of the same tag and day apart
+                if let Some(created) = task.created {
+                    tr.created = created.and_time(tr.created.time());
+                }
+                if let Some(completed) = task.completed {
+                    tr.modified = completed.and_time(tr.modified.time());
+                }
+                self.tagged_ranges.push(tr);
+                self.normalize_tag_ranges(&tag);
+            }
+        }
+        info!(tasks = tasks.len(), "Imported {}", path.display());
+        let _ = self.save_to_disk();
+        Ok(tasks.len())
+    }
+
+    /// Export menu rows for todo.txt, the export button and a path to import from
+    pub fn todo_txt_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Tasks (todo.txt)")
+            .on_hover_text("Tagged ranges as todo.txt tasks, tags as +projects and @contexts")
+            .clicked()
+        {
+            match self.export_todo_txt() {
+                Ok(path) => self
+                    .toasts
+                    .success(format!("Exported tasks to {}", path.display())),
+                Err(e) => self.toasts.error(format!("todo.txt export failed: {e}")),
+            }
+            ui.close();
+        }
+        let import_id = egui::Id::new("todo_txt_import_edit");
+        let mut import = ui
+            .data(|d| d.get_temp::<String>(import_id))
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import)
+                    .hint_text("Path of a todo.txt")
+                    .desired_width(140.0),
+            );
+            if ui
+                .add_enabled(!import.trim().is_empty(), egui::Button::new("Import"))
+                .clicked()
+            {
+                match self.import_todo_txt(Path::new(import.trim())) {
+                    Ok(count) => {
+                        self.toasts.success(format!("Imported {count} tasks"));
+                        import.clear();
+                    }
+                    Err(e) => self
+                        .toasts
+                        .error(format!("Could not import the tasks: {e}")),
+                }
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(import_id, import));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parses_an_open_task() {
+        let task = parse_task("(A) 2024-05-01 call mom +family @phone due:2024-05-03").unwrap();
+        assert_eq!(
+            task,
+            Task {
+                done: false,
+                priority: Priority::Urgent,
+                completed: None,
+                created: Some(day(2024, 5, 1)),
+                text: "call mom".to_string(),
+                tags: vec!["family".to_string(), "@phone".to_string()],
+                due: Some(day(2024, 5, 3)),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_a_done_task_with_both_dates() {
+        let task = parse_task("x 2024-05-02 2024-05-01 file taxes pri:B").unwrap();
+        assert!(task.done);
+        assert_eq!(task.completed, Some(day(2024, 5, 2)));
+        assert_eq!(task.created, Some(day(2024, 5, 1)));
+        assert_eq!(task.priority, Priority::High);
+        assert_eq!(task.text, "file taxes");
+    }
+
+    #[test]
+    fn skips_blank_lines_and_keeps_lone_markers_as_text() {
+        assert_eq!(parse_task("   "), None);
+        assert_eq!(parse_task("+ @").unwrap().text, "+ @");
+    }
+
+    #[test]
+    fn exports_ranges_of_the_same_text_as_one_task() {
+        let buffer = "- [ ] buy milk\n- [x] call bob\n";
+        let mut milk = TaggedRange::new("shopping list".to_string(), 6..14);
+        milk.priority = Priority::Urgent;
+        milk.created = day(2024, 5, 1).and_hms_opt(9, 0, 0).unwrap();
+        let mut errand = milk.clone();
+        errand.tag_name = "@town".to_string();
+        errand.priority = Priority::Normal;
+        let mut bob = TaggedRange::new(UNFILED_TAG.to_string(), 15..29);
+        bob.created = day(2024, 5, 1).and_hms_opt(9, 0, 0).unwrap();
+        bob.modified = day(2024, 5, 2).and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(
+            todo_txt(buffer, &[milk, errand, bob]),
+            "(A) 2024-05-01 buy milk +shopping_list @town\n\
+             x 2024-05-02 2024-05-01 call bob\n"
+        );
+    }
+
+    #[test]
+    fn exported_tasks_read_back_the_same() {
+        let buffer = "- [ ] water plants\n";
+        let mut tr = TaggedRange::new("home".to_string(), 6..18);
+        tr.due = Some(day(2024, 6, 1));
+        tr.priority = Priority::Low;
+        let line = todo_txt(buffer, &[tr.clone()]);
+        let task = parse_task(line.trim()).unwrap();
+        assert_eq!(task.text, "water plants");
+        assert_eq!(task.tags, vec!["home".to_string()]);
+        assert_eq!(task.due, tr.due);
+        assert_eq!(task.priority, Priority::Low);
+        assert_eq!(task.created, Some(tr.created.date()));
+    }
+}
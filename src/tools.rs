@@ -1,11 +1,16 @@
+use std::borrow::Cow;
 use std::cmp::{max, min};
 use std::ops::Range;
+use std::path::Path;
 
 use egui::Color32;
+use serde::{Deserialize, Serialize};
 
-pub trait RangeExt {
+pub trait RangeExt: Sized {
     fn intersects(&self, other: &Self) -> bool;
+    fn touches(&self, other: &Self) -> bool;
     fn union(&self, other: &Self) -> Self;
+    fn subtract(&self, other: &Self) -> Vec<Self>;
 }
 
 impl RangeExt for Range<usize> {
@@ -14,14 +19,88 @@ impl RangeExt for Range<usize> {
         self.start < other.end && other.start < self.end
     }
 
+    // Check if ranges overlap or sit directly next to each other
+    fn touches(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
     // Returns the convex hull (the smallest range containing both)
     fn union(&self, other: &Self) -> Self {
         min(self.start, other.start)..max(self.end, other.end)
     }
+
+    // Returns the parts of `self` not covered by `other` (zero, one or two pieces)
+    fn subtract(&self, other: &Self) -> Vec<Self> {
+        if other.is_empty() || !self.intersects(other) {
+            return vec![self.clone()];
+        }
+        let mut pieces = vec![];
+        if self.start < other.start {
+            pieces.push(self.start..other.start);
+        }
+        if other.end < self.end {
+            pieces.push(other.end..self.end);
+        }
+        pieces
+    }
+}
+
+/// Color schemes new tags get their colors from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Warm,
+    Cool,
+    Categorical,
+    ColorblindSafe,
+}
+
+// Okabe-Ito colors, distinguishable with the common forms of color blindness
+const OKABE_ITO: [[u8; 3]; 7] = [
+    [230, 159, 0],
+    [86, 180, 233],
+    [0, 158, 115],
+    [240, 228, 66],
+    [0, 114, 178],
+    [213, 94, 0],
+    [204, 121, 167],
+];
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Warm,
+        Palette::Cool,
+        Palette::Categorical,
+        Palette::ColorblindSafe,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Warm => "Warm",
+            Palette::Cool => "Cool",
+            Palette::Categorical => "Categorical",
+            Palette::ColorblindSafe => "Colorblind safe",
+        }
+    }
+
+    /// Returns the color for the `index`th tag. The same index always gives the same color.
+    pub fn color(&self, index: usize) -> [u8; 3] {
+        match self {
+            Palette::Warm => gradient_color(&colorous::WARM, index),
+            Palette::Cool => gradient_color(&colorous::COOL, index),
+            Palette::Categorical => {
+                let c = colorous::TABLEAU10[index % colorous::TABLEAU10.len()];
+                [c.r, c.g, c.b]
+            }
+            Palette::ColorblindSafe => OKABE_ITO[index % OKABE_ITO.len()],
+        }
+    }
 }
 
-pub fn random_color(num_existing: usize) -> [u8; 3] {
-    let c = colorous::WARM.eval_rational(num_existing, 40);
+// Walk the gradient in golden ratio steps so neighbouring indices end up far apart
+fn gradient_color(gradient: &colorous::Gradient, index: usize) -> [u8; 3] {
+    let t = (index as f64 * 0.618_033_988_75).fract();
+    let c = gradient.eval_continuous(t);
     [c.r, c.g, c.b]
 }
 
@@ -53,3 +132,473 @@ pub fn mix_colors(c1: Color32, c2: Color32) -> Color32 {
         ((c1.b() as u16 + c2.b() as u16) / 2) as u8,
     )
 }
+
+/// Stronger version of a color, used for urgent ranges
+pub fn saturate(c: Color32) -> Color32 {
+    let mut hsva = egui::ecolor::Hsva::from(c);
+    hsva.s = (hsva.s * 1.6).min(1.0);
+    hsva.v = (hsva.v * 1.1).min(1.0);
+    hsva.into()
+}
+
+/// Returns the 1-based line and column of a char index in `text`.
+pub fn line_col(text: &str, char_index: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut col = 1;
+    for c in text.chars().take(char_index) {
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    (line, col)
+}
+
+/// Char ranges of the paragraphs (or lines) in `text`, without surrounding whitespace.
+/// Blank lines separate paragraphs and are left out.
+pub fn split_blocks(text: &str, per_line: bool) -> Vec<Range<usize>> {
+    let mut blocks: Vec<Range<usize>> = vec![];
+    let mut start = 0;
+    let mut previous_blank = true;
+    for line in text.split('\n') {
+        let len = line.chars().count();
+        let leading = line.chars().take_while(|c| c.is_whitespace()).count();
+        if leading == len {
+            previous_blank = true;
+        } else {
+            let trailing = line.chars().rev().take_while(|c| c.is_whitespace()).count();
+            let block = start + leading..start + len - trailing;
+            match blocks.last_mut() {
+                Some(last) if !per_line && !previous_blank => last.end = block.end,
+                _ => blocks.push(block),
+            }
+            previous_blank = false;
+        }
+        start += len + 1;
+    }
+    blocks
+}
+
+fn is_word_char(c: char, extra: &str) -> bool {
+    c.is_alphanumeric() || extra.contains(c)
+}
+
+/// Char range of the word around char `at`, None if that char is not part of a word.
+/// Words are letters, digits and the chars in `extra`.
+pub fn word_at(text: &str, at: usize, extra: &str) -> Option<Range<usize>> {
+    let mut start = None;
+    let mut count = 0;
+    for (i, c) in text.chars().enumerate() {
+        count = i + 1;
+        let word = is_word_char(c, extra);
+        if i < at {
+            start = if word { start.or(Some(i)) } else { None };
+        } else if i == at {
+            if !word {
+                return None;
+            }
+            start.get_or_insert(i);
+        } else if !word {
+            return Some(start?..i);
+        }
+    }
+    (at < count).then_some(start?..count)
+}
+
+/// Char range of the sentence around `range`, without surrounding whitespace.
+/// Sentences end with `.`, `!` or `?` before whitespace, and at line breaks.
+pub fn sentence_around(text: &str, range: &Range<usize>) -> Range<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    // A sentence ended right before char `i`
+    let ends_at = |i: usize| {
+        i > 0
+            && matches!(chars[i - 1], '.' | '!' | '?')
+            && chars.get(i).is_none_or(|c| c.is_whitespace())
+    };
+    let mut start = range.start.min(chars.len());
+    while start > 0 && chars[start - 1] != '\n' && !ends_at(start) {
+        start -= 1;
+    }
+    let mut end = range.end.min(chars.len());
+    while end < chars.len() && chars[end] != '\n' && !(end > range.start && ends_at(end)) {
+        end += 1;
+    }
+    while start < end && chars[start].is_whitespace() {
+        start += 1;
+    }
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    start.min(range.start)..end.max(range.end)
+}
+
+/// The line (or paragraph) holding all of `range`, as in [`split_blocks`]
+pub fn block_around(text: &str, range: &Range<usize>, per_line: bool) -> Option<Range<usize>> {
+    split_blocks(text, per_line)
+        .into_iter()
+        .find(|block| block.start <= range.start && range.end <= block.end)
+}
+
+/// Width of the leading whitespace, tabs count as 4
+pub fn indent(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Lines of the item at line `head` and its sub-items, the lines below indented deeper.
+/// Blank lines in between belong to it, trailing ones do not.
+pub fn subtree(lines: &[&str], head: usize) -> Range<usize> {
+    let head_indent = lines.get(head).map_or(0, |line| indent(line));
+    let mut last = head;
+    for (i, line) in lines.iter().enumerate().skip(head + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indent(line) <= head_indent {
+            break;
+        }
+        last = i;
+    }
+    head..last + 1
+}
+
+/// Char index every line of `text` starts at, the first one is always 0
+pub fn line_starts(text: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(
+        text.chars()
+            .enumerate()
+            .filter(|(_, c)| *c == '\n')
+            .map(|(i, _)| i + 1),
+    );
+    starts
+}
+
+/// Char index where the 1-based `line` starts, or None past the last line
+pub fn line_start(text: &str, line: usize) -> Option<usize> {
+    if line <= 1 {
+        return Some(0);
+    }
+    text.chars()
+        .enumerate()
+        .filter(|(_, c)| *c == '\n')
+        .nth(line - 2)
+        .map(|(i, _)| i + 1)
+}
+
+/// Rewrites relative markdown image paths (`![](img/foo.png)`) to absolute `file://` uris.
+/// Paths are looked up in `base_dir` first and then in its `attachments` directory.
+pub fn resolve_image_paths<'a>(markdown: &'a str, base_dir: &Path) -> Cow<'a, str> {
+    if !markdown.contains("![") {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut out = String::with_capacity(markdown.len());
+    let mut rest = markdown;
+    while let Some(start) = rest.find("![") {
+        let Some(uri_start) = rest[start..].find("](").map(|i| start + i + 2) else {
+            break;
+        };
+        let Some(uri_end) = rest[uri_start..].find(')').map(|i| uri_start + i) else {
+            break;
+        };
+        // An optional title follows the uri after whitespace
+        let uri = rest[uri_start..uri_end]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+
+        out.push_str(&rest[..uri_start]);
+        let raw_uri = uri;
+        let expanded = expand_vars(uri, base_dir);
+        let uri = expanded.as_ref();
+        let is_relative = !uri.is_empty()
+            && !uri.contains("://")
+            && !uri.starts_with("data:")
+            && Path::new(uri).is_relative();
+        if is_relative {
+            let mut path = base_dir.join(uri);
+            if !path.exists() {
+                let attachment = base_dir.join("attachments").join(uri);
+                if attachment.exists() {
+                    path = attachment;
+                }
+            }
+            out.push_str(&format!("file://{}", path.display()));
+        } else if Path::new(uri).is_absolute() && raw_uri != uri {
+            out.push_str(&format!("file://{uri}"));
+        } else {
+            out.push_str(uri);
+        }
+        out.push_str(&rest[uri_start + raw_uri.len()..uri_end]);
+        rest = &rest[uri_end..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Expands `~`, `$VAR`, `${VAR}` and on Windows `%VAR%` in commands and paths.
+/// `$WORKSPACE` is the folder of the document. Unknown variables are left as they are.
+pub fn expand_vars<'a>(text: &'a str, workspace: &Path) -> Cow<'a, str> {
+    if !text.contains(['~', '$', '%']) {
+        return Cow::Borrowed(text);
+    }
+    let lookup = |name: &str| -> Option<String> {
+        if name == "WORKSPACE" {
+            Some(workspace.display().to_string())
+        } else {
+            std::env::var(name).ok()
+        }
+    };
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut prev: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        let mut consumed = c.len_utf8();
+        let mut replacement = None;
+        match c {
+            // Only a `~` that starts a word is the home folder
+            '~' if prev.is_none_or(|p| p.is_whitespace() || p == '=' || p == ':')
+                && after
+                    .chars()
+                    .next()
+                    .is_none_or(|n| n == '/' || n == '\\' || n.is_whitespace()) =>
+            {
+                replacement = home_dir();
+            }
+            '$' if after.starts_with('{') => {
+                if let Some(end) = after.find('}') {
+                    replacement = lookup(&after[1..end]);
+                    consumed += end + 1;
+                }
+            }
+            '$' => {
+                let len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+                if len > 0 {
+                    replacement = lookup(&after[..len]);
+                    consumed += len;
+                }
+            }
+            '%' if cfg!(windows) => {
+                if let Some(end) = after.find('%') {
+                    if end > 0 && after[..end].chars().all(is_name_char) {
+                        replacement = lookup(&after[..end]);
+                        consumed += end + 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+        match replacement {
+            Some(value) => out.push_str(&value),
+            None => {
+                consumed = c.len_utf8();
+                out.push(c);
+            }
+        }
+        prev = rest[..consumed].chars().last();
+        rest = &rest[consumed..];
+    }
+    Cow::Owned(out)
+}
+
+fn home_dir() -> Option<String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+}
+
+/// Converts a char index range into a byte range of `text`, clamped to its length.
+pub fn char_to_byte_range(text: &str, range: &Range<usize>) -> Range<usize> {
+    let byte_at = |i: usize| {
+        text.char_indices()
+            .nth(i)
+            .map(|(b, _)| b)
+            .unwrap_or(text.len())
+    };
+    byte_at(range.start)..byte_at(range.end)
+}
+
+/// Copies task list checkbox states (`- [ ]` / `- [x]`) from `rendered` into `source`.
+/// Both texts must consist of the same lines, as produced by [`resolve_image_paths`].
+/// Returns whether anything in `source` changed.
+pub fn sync_task_checkboxes(source: &mut String, rendered: &str) -> bool {
+    let mut changed = false;
+    let mut out = String::with_capacity(source.len());
+    let mut rendered_lines = rendered.split_inclusive('\n');
+    for line in source.split_inclusive('\n') {
+        let rendered_line = rendered_lines.next().unwrap_or_default();
+        match (task_marker(line), task_marker(rendered_line)) {
+            (Some(i), Some(j)) if i == j && line.as_bytes()[i] != rendered_line.as_bytes()[j] => {
+                out.push_str(&line[..i]);
+                out.push(rendered_line.as_bytes()[j] as char);
+                out.push_str(&line[i + 1..]);
+                changed = true;
+            }
+            _ => out.push_str(line),
+        }
+    }
+    if changed {
+        *source = out;
+    }
+    changed
+}
+
+// Byte offset of the state char in a task list item such as `  - [x] call mom`
+fn task_marker(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    let after_bullet = match trimmed.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = trimmed.len()
+                - trimmed
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            if digits == 0 {
+                return None;
+            }
+            trimmed[digits..].strip_prefix(['.', ')'])?
+        }
+    };
+    let rest = after_bullet.trim_start();
+    if rest.len() == after_bullet.len() {
+        return None;
+    }
+    let b = rest.as_bytes();
+    let is_task =
+        b.len() >= 3 && b[0] == b'[' && matches!(b[1], b' ' | b'x' | b'X') && b[2] == b']';
+    is_task.then_some(line.len() - rest.len() + 1)
+}
+
+/// Wraps text in a fenced code block, with a fence longer than any backtick run inside
+pub fn fence_code_block(text: &str, language: &str) -> String {
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let newline = if text.ends_with('\n') { "" } else { "\n" };
+    format!("{fence}{language}\n{text}{newline}{fence}")
+}
+
+/// The highlighter looks languages up by file extension, so map common names to theirs
+pub fn syntax_extension(language: &str) -> &str {
+    match language {
+        "rust" => "rs",
+        "python" => "py",
+        "javascript" => "js",
+        "typescript" => "ts",
+        "bash" | "shell" | "zsh" => "sh",
+        "markdown" => "md",
+        "ruby" => "rb",
+        "csharp" | "c#" => "cs",
+        "c++" => "cpp",
+        "yml" => "yaml",
+        other => other,
+    }
+}
+
+/// Syntax highlighting color of every char of `code`
+pub fn syntax_colors(ui: &egui::Ui, code: &str, language: &str) -> Vec<Color32> {
+    let theme = egui_extras::syntax_highlighting::CodeTheme::from_memory(ui.ctx(), ui.style());
+    let job = egui_extras::syntax_highlighting::highlight(
+        ui.ctx(),
+        ui.style(),
+        &theme,
+        code,
+        syntax_extension(language),
+    );
+    job.sections
+        .iter()
+        .flat_map(|section| {
+            let chars = job.text[section.byte_range.clone()].chars().count();
+            std::iter::repeat_n(section.format.color, chars)
+        })
+        .collect()
+}
+
+/// Short human readable duration such as `2h 05m` or `45s`
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (hours, minutes) = (secs / 3600, secs / 60 % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Byte count as `512 B`, `3.4 KB` or `12.0 MB`
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Percent-encodes everything but unreserved URL characters, e.g. for `mailto:` links
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtract_without_overlap_keeps_the_range() {
+        assert_eq!((2..5).subtract(&(5..8)), vec![2..5]);
+        assert_eq!((2..5).subtract(&(0..2)), vec![2..5]);
+    }
+
+    #[test]
+    fn subtract_covering_range_leaves_nothing() {
+        assert!((2..5).subtract(&(2..5)).is_empty());
+        assert!((2..5).subtract(&(0..9)).is_empty());
+    }
+
+    #[test]
+    fn subtract_at_the_start_keeps_the_end() {
+        assert_eq!((2..8).subtract(&(0..4)), vec![4..8]);
+    }
+
+    #[test]
+    fn subtract_at_the_end_keeps_the_start() {
+        assert_eq!((2..8).subtract(&(6..10)), vec![2..6]);
+    }
+
+    #[test]
+    fn subtract_in_the_middle_leaves_two_pieces() {
+        assert_eq!((2..8).subtract(&(4..6)), vec![2..4, 6..8]);
+    }
+
+    #[test]
+    fn bytes_get_a_unit() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 + 512), "3.5 KB");
+        assert_eq!(format_bytes(12 * 1024 * 1024), "12.0 MB");
+    }
+
+    #[test]
+    fn subtract_empty_ranges() {
+        assert_eq!((2..8).subtract(&(4..4)), vec![2..8]);
+        assert!((4..4).subtract(&(2..8)).is_empty());
+        assert_eq!((4..4).subtract(&(6..8)), vec![4..4]);
+    }
+}
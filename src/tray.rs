@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::process::Command;
+use std::sync::mpsc;
+
+use eframe::egui;
+use egui::ViewportCommand;
+use tracing::{info, warn};
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+use crate::Taskmonger;
+
+const SHOW: &str = "show";
+const CAPTURE: &str = "capture";
+const QUIT: &str = "quit";
+
+/// The system tray icon with its menu
+pub struct Tray {
+    events: mpsc::Receiver<MenuEvent>,
+    shown: Shown,
+    /// Overdue count last shown in the menu
+    overdue: Option<usize>,
+    /// Quit from the menu, closing is not turned into hiding then
+    quitting: bool,
+}
+
+/// On Linux the icon lives on a GTK thread of its own, which is sent the overdue counts
+#[cfg(target_os = "linux")]
+type Shown = mpsc::Sender<usize>;
+
+/// The icon and the overdue item of its menu
+#[cfg(not(target_os = "linux"))]
+type Shown = (TrayIcon, MenuItem);
+
+fn overdue_text(count: usize) -> String {
+    match count {
+        0 => "Nothing overdue".to_string(),
+        1 => "1 range overdue".to_string(),
+        n => format!("{n} ranges overdue"),
+    }
+}
+
+/// The icon and the overdue item of its menu, which is updated later
+fn build(icon: egui::IconData) -> Result<(TrayIcon, MenuItem), Box<dyn Error>> {
+    let overdue = MenuItem::new(overdue_text(0), false, None);
+    let menu = Menu::new();
+    menu.append(&MenuItem::with_id(SHOW, "Show window", true, None))?;
+    menu.append(&MenuItem::with_id(CAPTURE, "Quick capture", true, None))?;
+    menu.append(&overdue)?;
+    menu.append(&PredefinedMenuItem::separator())?;
+    menu.append(&MenuItem::with_id(QUIT, "Quit", true, None))?;
+    let tray = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Taskmonger")
+        .with_icon(Icon::from_rgba(icon.rgba, icon.width, icon.height)?)
+        .build()?;
+    Ok((tray, overdue))
+}
+
+/// libappindicator needs a running GTK main loop, which winit doesn't have
+#[cfg(target_os = "linux")]
+fn show(icon: egui::IconData) -> Option<Shown> {
+    let (counts, receiver) = mpsc::channel::<usize>();
+    std::thread::spawn(move || {
+        if let Err(e) = gtk::init() {
+            warn!("No tray icon, GTK failed to start: {e}");
+            return;
+        }
+        let (_tray, overdue) = match build(icon) {
+            Ok(built) => built,
+            Err(e) => {
+                warn!("No tray icon: {e}");
+                return;
+            }
+        };
+        gtk::glib::timeout_add_local(std::time::Duration::from_millis(500), move || {
+            if let Some(count) = receiver.try_iter().last() {
+                overdue.set_text(overdue_text(count));
+            }
+            gtk::glib::ControlFlow::Continue
+        });
+        gtk::main();
+    });
+    Some(counts)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn show(icon: egui::IconData) -> Option<Shown> {
+    build(icon).inspect_err(|e| warn!("No tray icon: {e}")).ok()
+}
+
+impl Tray {
+    /// Puts the icon in the tray, None if there is no tray to put it in
+    pub fn start(ctx: &egui::Context, icon: egui::IconData) -> Option<Self> {
+        let shown = show(icon)?;
+        let (sender, events) = mpsc::channel();
+        let ctx = ctx.clone();
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            // The window may be hidden and not drawing, so it is shown from here
+            if event.id == SHOW {
+                ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+                ctx.send_viewport_cmd(ViewportCommand::Focus);
+            }
+            let _ = sender.send(event);
+            ctx.request_repaint();
+        }));
+        Some(Self {
+            events,
+            shown,
+            overdue: None,
+            quitting: false,
+        })
+    }
+
+    fn set_overdue(&mut self, count: usize) {
+        if self.overdue == Some(count) {
+            return;
+        }
+        self.overdue = Some(count);
+        #[cfg(target_os = "linux")]
+        let _ = self.shown.send(count);
+        #[cfg(not(target_os = "linux"))]
+        self.shown.1.set_text(overdue_text(count));
+    }
+}
+
+/// Opens the quick capture window, like the global shortcut does
+fn quick_capture() {
+    let spawned =
+        std::env::current_exe().and_then(|exe| Command::new(exe).arg("--capture").spawn());
+    if let Err(e) = spawned {
+        warn!("Could not open quick capture: {e}");
+    }
+}
+
+impl Taskmonger {
+    /// Menu clicks, the overdue count and closing to the tray
+    pub fn handle_tray(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &mut self.tray else {
+            return;
+        };
+        while let Ok(event) = tray.events.try_recv() {
+            if event.id == CAPTURE {
+                quick_capture();
+            } else if event.id == QUIT {
+                info!("Quit from the tray");
+                tray.quitting = true;
+                ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+            }
+        }
+
+        let today = chrono::Local::now().date_naive();
+        let overdue = self
+            .tagged_ranges
+            .iter()
+            .filter(|tr| tr.is_overdue(today))
+            .count();
+        tray.set_overdue(overdue);
+
+        if self.settings.minimize_to_tray
+            && !tray.quitting
+            && ctx.input(|i| i.viewport().close_requested())
+        {
+            ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(ViewportCommand::Visible(false));
+        }
+    }
+}
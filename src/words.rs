@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use eframe::egui;
+use egui::text::{CCursor, CCursorRange};
+use egui_phosphor::regular::{PLUS, TRASH};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::tools::{block_around, sentence_around, word_at};
+use crate::Taskmonger;
+
+/// What counts as a word, for double-click selection and snapping tags to words
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WordRules {
+    /// Chars that belong to a word besides letters and digits
+    pub chars: String,
+    /// Tagging a selection widens it to whole words
+    pub snap: bool,
+    /// Replaces `chars` inside ranges with this code language
+    pub by_language: BTreeMap<String, String>,
+}
+
+impl Default for WordRules {
+    fn default() -> Self {
+        Self {
+            chars: "_".to_string(),
+            snap: false,
+            by_language: BTreeMap::new(),
+        }
+    }
+}
+
+impl WordRules {
+    /// Settings rows for the chars, the snapping and the per language overrides
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Word chars");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.chars)
+                    .hint_text("_")
+                    .desired_width(80.0),
+            )
+            .on_hover_text("Besides letters and digits, e.g. -_. for file names");
+        });
+        ui.checkbox(&mut self.snap, "Snap tagged selections to whole words");
+        let mut remove = None;
+        for (language, chars) in &mut self.by_language {
+            ui.horizontal(|ui| {
+                ui.label(format!("In {language}"));
+                ui.add(egui::TextEdit::singleline(chars).desired_width(80.0));
+                if ui
+                    .small_button(TRASH)
+                    .on_hover_text("Remove override")
+                    .clicked()
+                {
+                    remove = Some(language.clone());
+                }
+            });
+        }
+        if let Some(language) = remove {
+            self.by_language.remove(&language);
+        }
+        let id = egui::Id::new("word_chars_language");
+        let mut language = ui.data(|d| d.get_temp::<String>(id)).unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut language)
+                    .hint_text("Code language")
+                    .desired_width(100.0),
+            );
+            let name = language.trim().to_lowercase();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new(PLUS))
+                .on_hover_text("Own word chars inside ranges with this language")
+                .clicked()
+            {
+                self.by_language
+                    .entry(name)
+                    .or_insert_with(|| self.chars.clone());
+                language.clear();
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(id, language));
+    }
+}
+
+impl Taskmonger {
+    /// Word chars at char `at`, those of its code language if it has an override
+    fn word_chars(&self, at: usize) -> &str {
+        let rules = &self.settings.words;
+        self.tagged_ranges
+            .iter()
+            .filter(|tr| tr.range.contains(&at))
+            .find_map(|tr| rules.by_language.get(tr.language.as_ref()?))
+            .unwrap_or(&rules.chars)
+    }
+
+    /// `range` widened to the words its ends are in, if snapping is on
+    pub fn snap_to_words(&self, range: Range<usize>) -> Range<usize> {
+        if !self.settings.words.snap || range.is_empty() {
+            return range;
+        }
+        let start = word_at(&self.buffer, range.start, self.word_chars(range.start))
+            .map_or(range.start, |word| word.start);
+        let last = range.end - 1;
+        let end =
+            word_at(&self.buffer, last, self.word_chars(last)).map_or(range.end, |word| word.end);
+        start..end
+    }
+
+    /// The smallest of word, sentence, line, paragraph and tagged range that is bigger than
+    /// the selection and contains it
+    fn grown_selection(&self) -> Option<Range<usize>> {
+        let selection = self.selection.clone();
+        let at = if selection.is_empty() {
+            // Right after a word still counts as in it
+            [Some(selection.start), selection.start.checked_sub(1)]
+                .into_iter()
+                .flatten()
+                .find_map(|at| word_at(&self.buffer, at, self.word_chars(at)))
+        } else {
+            word_at(
+                &self.buffer,
+                selection.start,
+                self.word_chars(selection.start),
+            )
+        };
+        let candidates = at
+            .into_iter()
+            .chain(Some(sentence_around(&self.buffer, &selection)))
+            .chain(block_around(&self.buffer, &selection, true))
+            .chain(block_around(&self.buffer, &selection, false))
+            .chain(self.tagged_ranges.iter().map(|tr| tr.range.clone()));
+        candidates
+            .filter(|c| {
+                c.start <= selection.start && selection.end <= c.end && c.len() > selection.len()
+            })
+            .min_by_key(|c| c.len())
+    }
+
+    /// Grows the selection to the next bigger unit of text, see [`Self::grown_selection`]
+    pub fn expand_selection(&mut self) {
+        if let Some(range) = self.grown_selection() {
+            debug!(from = ?self.selection, to = ?range, "Expanding selection");
+            self.select = Some(range);
+        }
+    }
+
+    /// Replaces the word the editor selected on double-click with one by the configured rules
+    pub fn select_word_on_double_click(
+        &mut self,
+        ctx: &egui::Context,
+        output: &egui::text_edit::TextEditOutput,
+    ) {
+        if !output.response.double_clicked() {
+            return;
+        }
+        let Some(word) = Self::hovered_char(output)
+            .and_then(|at| word_at(&self.buffer, at, self.word_chars(at)))
+        else {
+            return;
+        };
+        let mut state = output.state.clone();
+        state.cursor.set_char_range(Some(CCursorRange::two(
+            CCursor::new(word.start),
+            CCursor::new(word.end),
+        )));
+        state.store(ctx, output.response.id);
+        self.cursor = word.end;
+        self.selection = word;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaggedRange;
+
+    fn app(buffer: &str) -> Taskmonger {
+        let mut app = Taskmonger {
+            buffer: buffer.to_string(),
+            ..Default::default()
+        };
+        app.settings.words.snap = true;
+        app
+    }
+
+    #[test]
+    fn selections_snap_to_whole_words() {
+        let mut app = app("call my_func-name now");
+        // "y_f" widens to "my_func", the dash ends the word
+        assert_eq!(app.snap_to_words(6..9), 5..12);
+        app.settings.words.chars = "_-".to_string();
+        assert_eq!(app.snap_to_words(6..9), 5..17);
+        // Ends between words stay where they are
+        assert_eq!(app.snap_to_words(4..17), 4..17);
+        app.settings.words.snap = false;
+        assert_eq!(app.snap_to_words(6..9), 6..9);
+    }
+
+    #[test]
+    fn code_ranges_use_the_word_chars_of_their_language() {
+        let mut app = app("see $foo-bar here");
+        app.settings
+            .words
+            .by_language
+            .insert("shell".to_string(), "$".to_string());
+        assert_eq!(app.snap_to_words(5..6), 5..8);
+        let mut code = TaggedRange::new("code".to_string(), 4..12);
+        code.language = Some("shell".to_string());
+        app.tagged_ranges.push(code);
+        assert_eq!(app.snap_to_words(5..6), 4..8);
+    }
+
+    #[test]
+    fn selections_grow_from_word_to_sentence_to_line_to_paragraph() {
+        let mut app = app("One two. Three four.\nFive\n\nSix");
+        let mut steps = vec![];
+        app.selection = 10..10;
+        while let Some(range) = app.grown_selection() {
+            steps.push(range.clone());
+            app.selection = range;
+        }
+        assert_eq!(steps, [9..14, 9..20, 0..20, 0..25]);
+    }
+}